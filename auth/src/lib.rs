@@ -1,5 +1,6 @@
 use jsonwebtoken::decode;
 use jsonwebtoken::encode;
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::Algorithm;
 use jsonwebtoken::DecodingKey;
 use jsonwebtoken::EncodingKey;
@@ -7,12 +8,16 @@ use jsonwebtoken::Header;
 use jsonwebtoken::TokenData;
 use jsonwebtoken::Validation;
 
+use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
 use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
 use time::get_time;
 
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 use warp::filters::header::headers_cloned;
 use warp::http::header::HeaderMap;
 use warp::http::header::AUTHORIZATION;
@@ -27,6 +32,15 @@ std::compile_error!("Cannot disable authentication for non-devel environments.")
 // TODO: Generate this at first run to ensure security
 static KEY: OnceCell<[u8; 16]> = OnceCell::new();
 static ONE_WEEK: i64 = 60 * 60 * 24 * 7;
+/// Number of seconds access tokens are valid for, clients have to exchange their refresh token for
+/// a new access token once it expires.
+static ACCESS_TOKEN_LIFETIME: i64 = 60 * 60;
+/// Number of seconds refresh tokens are valid for.
+static REFRESH_TOKEN_LIFETIME: i64 = ONE_WEEK;
+
+/// Ids of the refresh tokens that were revoked mapped to when they expire. Revocations are only
+/// kept in memory, thus they are lost when dim restarts.
+static REVOKED: Lazy<Mutex<HashMap<u128, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 pub fn generate_key() -> [u8; 16] {
     rand::thread_rng().gen()
@@ -51,20 +65,40 @@ pub struct UserRolesToken {
     exp: i64,
     /// Username of the user to whom this token belongs to
     user: String,
-    /// The roles of the user, usually owner or user
+    /// The roles of the user, usually owner or user. Tokens without roles only get [`Role::User`].
     // TODO: Use a enum here maybe considering theres like two possibilities lol?
+    #[serde(default)]
     roles: Vec<String>,
+    /// Whether this is a refresh token. Refresh tokens can only be exchanged for access tokens,
+    /// tokens issued before refresh tokens existed are access tokens.
+    #[serde(default)]
+    refresh: bool,
 }
 
 #[derive(Debug)]
 pub struct Wrapper(pub TokenData<UserRolesToken>);
 
+/// Level of access a user has, roles are ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    User,
+    /// Users that can manage libraries and other users, the owner of a dim instance is an admin.
+    Admin,
+}
+
 #[derive(Clone, Debug)]
 pub enum JWTError {
     Missing,
     Invalid,
     InvalidKey,
     BadCount,
+    /// The token is well formed and was signed by us but it has expired, clients should exchange
+    /// their refresh token for a new one.
+    Expired,
+    /// The refresh token was revoked.
+    Revoked,
+    /// The user doesnt have the role required to access the route.
+    Forbidden,
 }
 
 impl warp::reject::Reject for JWTError {}
@@ -96,6 +130,20 @@ impl UserRolesToken {
         self.id
     }
 
+    /// Method returns the most privileged role the user holding this token has.
+    pub fn role(&self) -> Role {
+        if self.has_role("owner") || self.has_role("admin") {
+            Role::Admin
+        } else {
+            Role::User
+        }
+    }
+
+    /// Method returns whether this is a refresh token.
+    pub fn is_refresh(&self) -> bool {
+        self.refresh
+    }
+
     /// Method returns a clone of all roles.
     pub fn clone_roles(&self) -> Vec<String> {
         self.roles
@@ -119,12 +167,27 @@ impl UserRolesToken {
 /// ```
 pub fn jwt_generate(user: String, roles: Vec<String>) -> String {
     let now = get_time().sec;
+    generate(user, roles, false, now, now + ACCESS_TOKEN_LIFETIME)
+}
+
+/// Function generates a new refresh token and signs it with our KEY. Refresh tokens outlive access
+/// tokens and can be exchanged for a new access token with [`jwt_refresh`].
+/// # Arguments
+/// * `user` - Username for whom we want to generate a token
+/// * `roles` - vector of roles we want to give to this user.
+pub fn jwt_generate_refresh(user: String, roles: Vec<String>) -> String {
+    let now = get_time().sec;
+    generate(user, roles, true, now, now + REFRESH_TOKEN_LIFETIME)
+}
+
+fn generate(user: String, roles: Vec<String>, refresh: bool, iat: i64, exp: i64) -> String {
     let payload = UserRolesToken {
         id: uuid::Uuid::new_v4().to_u128_le(),
-        iat: now,
-        exp: now + ONE_WEEK,
+        iat,
+        exp,
         user,
         roles,
+        refresh,
     };
 
     encode(
@@ -151,11 +214,91 @@ pub fn jwt_generate(user: String, roles: Vec<String>) -> String {
 /// ```
 #[cfg(not(feature = "null_auth"))]
 pub fn jwt_check(token: String) -> Result<TokenData<UserRolesToken>, jsonwebtoken::errors::Error> {
-    decode::<UserRolesToken>(
+    let data = decode::<UserRolesToken>(
         &token,
         &DecodingKey::from_secret(get_key()),
         &Validation::new(Algorithm::HS512),
+    )?;
+
+    // refresh tokens cant be used to access routes.
+    if data.claims.refresh {
+        return Err(ErrorKind::InvalidToken.into());
+    }
+
+    Ok(data)
+}
+
+/// Function validates an access token like [`jwt_check`] but tells expired tokens apart from
+/// tokens that are invalid, clients can refresh the former but have to log in again for the latter.
+/// # Arguments
+/// * `token` - JWT token we want to validate
+pub fn jwt_verify(token: String) -> Result<TokenData<UserRolesToken>, JWTError> {
+    jwt_check(token).map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => JWTError::Expired,
+        _ => JWTError::InvalidKey,
+    })
+}
+
+/// Function validates a refresh token and returns its claims. Expired and revoked refresh tokens
+/// are rejected.
+/// # Arguments
+/// * `token` - refresh token we want to validate
+pub fn jwt_check_refresh(token: &str) -> Result<UserRolesToken, JWTError> {
+    let claims = decode::<UserRolesToken>(
+        token,
+        &DecodingKey::from_secret(get_key()),
+        &Validation::new(Algorithm::HS512),
+    )
+    .map_err(|e| match e.kind() {
+        ErrorKind::ExpiredSignature => JWTError::Expired,
+        _ => JWTError::InvalidKey,
+    })?
+    .claims;
+
+    if !claims.refresh {
+        return Err(JWTError::Invalid);
+    }
+
+    if REVOKED.lock().unwrap().contains_key(&claims.id) {
+        return Err(JWTError::Revoked);
+    }
+
+    Ok(claims)
+}
+
+/// Function exchanges a refresh token for a new access token for the same user and roles.
+/// # Arguments
+/// * `token` - refresh token to exchange
+pub fn jwt_refresh(token: &str) -> Result<String, JWTError> {
+    jwt_check_refresh(token).map(|claims| jwt_generate(claims.user, claims.roles))
+}
+
+/// Function revokes a refresh token so that it cant be exchanged for access tokens anymore, ie
+/// when a user logs out. Access tokens that were already issued stay valid until they expire.
+/// # Arguments
+/// * `token` - refresh token to revoke
+pub fn jwt_revoke(token: &str) -> Result<(), JWTError> {
+    let claims = decode::<UserRolesToken>(
+        token,
+        &DecodingKey::from_secret(get_key()),
+        &Validation {
+            validate_exp: false,
+            ..Validation::new(Algorithm::HS512)
+        },
     )
+    .map_err(|_| JWTError::InvalidKey)?
+    .claims;
+
+    let now = get_time().sec;
+    let mut lock = REVOKED.lock().unwrap();
+    // revocations of expired tokens are useless as expired tokens are rejected anyway.
+    lock.retain(|_, exp| *exp > now);
+
+    if claims.exp > now {
+        lock.insert(claims.id, claims.exp);
+    }
+
+    Ok(())
 }
 
 #[cfg(all(debug_assertions, feature = "null_auth"))]
@@ -171,6 +314,7 @@ pub fn jwt_check(_: String) -> Result<TokenData<UserRolesToken>, jsonwebtoken::e
             exp: i64::MAX,
             user: "Admin".into(),
             roles: vec!["owner".into()],
+            refresh: false,
         },
     })
 }
@@ -178,9 +322,11 @@ pub fn jwt_check(_: String) -> Result<TokenData<UserRolesToken>, jsonwebtoken::e
 pub fn with_auth() -> impl Filter<Extract = (Wrapper,), Error = Rejection> + Clone {
     headers_cloned().and_then(|x: HeaderMap| async move {
         match x.get(AUTHORIZATION) {
-            Some(k) => match k.to_str().ok().and_then(|x| jwt_check(x.into()).ok()) {
-                Some(k) => Ok(Wrapper(k)),
-                None => Err(reject::custom(JWTError::InvalidKey)),
+            Some(k) => match k.to_str() {
+                Ok(x) => jwt_verify(x.into())
+                    .map(Wrapper)
+                    .map_err(|e| reject::custom(e)),
+                Err(_) => Err(reject::custom(JWTError::InvalidKey)),
             },
             None => {
                 if cfg!(not(feature = "null_auth")) {
@@ -192,3 +338,116 @@ pub fn with_auth() -> impl Filter<Extract = (Wrapper,), Error = Rejection> + Clo
         }
     })
 }
+
+/// Filter authenticates a request like [`with_auth`] but rejects it with [`JWTError::Forbidden`]
+/// unless the user holds at least `role`. Used to guard destructive routes.
+pub fn with_role(role: Role) -> impl Filter<Extract = (Wrapper,), Error = Rejection> + Clone {
+    with_auth().and_then(move |x: Wrapper| async move {
+        if x.0.claims.role() >= role {
+            Ok(x)
+        } else {
+            Err(reject::custom(JWTError::Forbidden))
+        }
+    })
+}
+
+#[cfg(all(test, not(feature = "null_auth")))]
+mod tests {
+    use super::*;
+
+    fn init_key() {
+        KEY.get_or_init(generate_key);
+    }
+
+    #[test]
+    fn test_expired() {
+        init_key();
+        let now = get_time().sec;
+
+        let token = generate("test".into(), vec![], false, now - 7200, now - 3600);
+        assert!(matches!(jwt_verify(token), Err(JWTError::Expired)));
+
+        assert!(matches!(
+            jwt_verify("testtesttest".into()),
+            Err(JWTError::InvalidKey)
+        ));
+
+        let token = generate("test".into(), vec![], true, now - 7200, now - 3600);
+        assert!(matches!(jwt_refresh(&token), Err(JWTError::Expired)));
+    }
+
+    #[test]
+    fn test_refreshed() {
+        init_key();
+
+        let refresh = jwt_generate_refresh("test".into(), vec!["owner".into()]);
+        // refresh tokens cant be used as access tokens.
+        assert!(matches!(
+            jwt_verify(refresh.clone()),
+            Err(JWTError::InvalidKey)
+        ));
+
+        let token = jwt_refresh(&refresh).unwrap();
+        let claims = jwt_verify(token.clone()).unwrap().claims;
+        assert_eq!(claims.get_user(), "test");
+        assert!(claims.has_role("owner"));
+        assert!(!claims.is_refresh());
+
+        // access tokens cant be exchanged for other tokens.
+        assert!(matches!(jwt_refresh(&token), Err(JWTError::Invalid)));
+    }
+
+    #[test]
+    fn test_revoked() {
+        init_key();
+
+        let refresh = jwt_generate_refresh("test".into(), vec![]);
+        let other = jwt_generate_refresh("test".into(), vec![]);
+        let token = jwt_refresh(&refresh).unwrap();
+
+        jwt_revoke(&refresh).unwrap();
+        assert!(matches!(jwt_refresh(&refresh), Err(JWTError::Revoked)));
+        assert!(jwt_refresh(&other).is_ok());
+        // access tokens issued before the revocation stay valid until they expire.
+        assert!(jwt_verify(token).is_ok());
+
+        assert!(matches!(
+            jwt_revoke("testtesttest"),
+            Err(JWTError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_role() {
+        init_key();
+
+        let token = jwt_generate("test".into(), vec!["owner".into()]);
+        assert_eq!(jwt_verify(token).unwrap().claims.role(), Role::Admin);
+
+        let token = jwt_generate("test".into(), vec!["user".into()]);
+        assert_eq!(jwt_verify(token).unwrap().claims.role(), Role::User);
+
+        #[derive(Serialize)]
+        struct Legacy {
+            id: u128,
+            iat: i64,
+            exp: i64,
+            user: String,
+        }
+
+        // tokens without roles fail closed.
+        let now = get_time().sec;
+        let token = encode(
+            &Header::new(Algorithm::HS512),
+            &Legacy {
+                id: 0,
+                iat: now,
+                exp: now + 3600,
+                user: "test".into(),
+            },
+            &EncodingKey::from_secret(get_key()),
+        )
+        .unwrap();
+        assert_eq!(jwt_verify(token).unwrap().claims.role(), Role::User);
+    }
+}
@@ -120,7 +120,7 @@ fn bench(c: &mut Criterion) {
     group.sample_size(10);
     group.bench_function("scanner", move |b| {
         b.iter_custom(|iters| {
-            let logger = dim::build_logger(false);
+            let logger = dim::build_logger(false, 10);
             let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
             let (tx2, rx2) = std::sync::mpsc::channel();
 
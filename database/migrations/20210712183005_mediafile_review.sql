@@ -0,0 +1,3 @@
+-- Flag the scanner sets on mediafiles it couldnt confidently match to any metadata. Together with
+-- mediafiles that have no media_id this makes up the review queue of a library.
+ALTER TABLE mediafile ADD COLUMN needs_review BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Trailers fetched from metadata providers, usually links to youtube or vimeo.
+CREATE TABLE media_trailers (
+    id INTEGER PRIMARY KEY NOT NULL,
+    media_id INTEGER NOT NULL,
+    name TEXT,
+    url TEXT NOT NULL,
+
+    FOREIGN KEY (media_id) REFERENCES _tblmedia(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX media_trailers_idx ON media_trailers(media_id, url);
@@ -0,0 +1,3 @@
+-- Records where the duration of a mediafile came from, ie `ffprobe` if it was reported by
+-- ffprobe or `estimate` if we estimated it from the bitrate and filesize. NULL if unknown.
+ALTER TABLE mediafile ADD COLUMN duration_source TEXT;
@@ -0,0 +1,21 @@
+-- Personal labels users can attach to media, independent of provider genres.
+CREATE TABLE tag (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX tag_idx ON tag(user_id, name);
+
+CREATE TABLE media_tag (
+    id INTEGER PRIMARY KEY NOT NULL,
+    tag_id INTEGER NOT NULL,
+    media_id INTEGER NOT NULL,
+
+    FOREIGN KEY (tag_id) REFERENCES tag(id) ON DELETE CASCADE,
+    FOREIGN KEY (media_id) REFERENCES _tblmedia(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX media_tag_idx ON media_tag(tag_id, media_id);
@@ -0,0 +1,3 @@
+-- Release tags captured from the filename by the scanner, ie `{edition-Extended}` or `-GROUP`.
+ALTER TABLE mediafile ADD COLUMN edition TEXT;
+ALTER TABLE mediafile ADD COLUMN release_group TEXT;
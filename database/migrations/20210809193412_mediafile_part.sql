@@ -0,0 +1,2 @@
+-- Part number of a movie split over several files, ie `CD1`/`CD2`.
+ALTER TABLE mediafile ADD COLUMN part INTEGER;
@@ -0,0 +1,4 @@
+-- Summary of the last finished scan of a library.
+ALTER TABLE library ADD COLUMN last_scan INTEGER;
+ALTER TABLE library ADD COLUMN last_scan_duration INTEGER;
+ALTER TABLE library ADD COLUMN last_scan_items INTEGER;
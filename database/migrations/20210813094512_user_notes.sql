@@ -0,0 +1,13 @@
+-- Private notes users can attach to media.
+CREATE TABLE note (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL,
+    media_id INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    updated INTEGER NOT NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users(username) ON DELETE CASCADE,
+    FOREIGN KEY (media_id) REFERENCES _tblmedia(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX note_idx ON note(user_id, media_id);
@@ -0,0 +1,2 @@
+-- Position of a library when libraries are listed, lower comes first.
+ALTER TABLE library ADD COLUMN display_order INTEGER NOT NULL DEFAULT 0;
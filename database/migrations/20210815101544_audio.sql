@@ -0,0 +1,25 @@
+-- Albums and tracks of audio libraries.
+CREATE TABLE album (
+    id INTEGER PRIMARY KEY NOT NULL,
+    library_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    artist TEXT,
+    year INTEGER,
+    added TEXT,
+
+    FOREIGN KEY (library_id) REFERENCES library(id) ON DELETE CASCADE
+);
+
+CREATE TABLE track (
+    id INTEGER PRIMARY KEY NOT NULL,
+    album_id INTEGER NOT NULL,
+    mediafile_id INTEGER,
+    name TEXT NOT NULL,
+    track_number INTEGER,
+    duration INTEGER,
+
+    FOREIGN KEY (album_id) REFERENCES album(id) ON DELETE CASCADE,
+    FOREIGN KEY (mediafile_id) REFERENCES mediafile(id) ON DELETE SET NULL
+);
+
+CREATE INDEX track_album_idx ON track(album_id);
@@ -0,0 +1,9 @@
+-- Id of the tmdb entry a media was matched to. Separate media matched to the same entry share a
+-- name, so the unique name index only covers media that were never matched.
+ALTER TABLE _tblmedia ADD COLUMN tmdb_id INTEGER;
+
+DROP INDEX media_idx;
+CREATE UNIQUE INDEX media_idx ON _tblmedia(library_id, name, media_type)
+WHERE NOT _tblmedia.media_type = "episode" AND _tblmedia.tmdb_id IS NULL;
+
+CREATE INDEX media_tmdb_idx ON _tblmedia(library_id, tmdb_id);
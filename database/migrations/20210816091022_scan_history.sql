@@ -0,0 +1,13 @@
+-- Summaries of every finished scan of a library.
+CREATE TABLE scan_history (
+    id INTEGER PRIMARY KEY NOT NULL,
+    library_id INTEGER NOT NULL,
+    finished_at INTEGER NOT NULL,
+    duration INTEGER NOT NULL,
+    items INTEGER NOT NULL,
+    added INTEGER NOT NULL,
+
+    FOREIGN KEY (library_id) REFERENCES library(id) ON DELETE CASCADE
+);
+
+CREATE INDEX scan_history_library_idx ON scan_history(library_id, finished_at);
@@ -0,0 +1,2 @@
+-- Last episode of a file spanning several episodes, ie `2` for `S01E01-E02`.
+ALTER TABLE mediafile ADD COLUMN episode_end INTEGER;
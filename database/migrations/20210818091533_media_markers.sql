@@ -0,0 +1,16 @@
+-- Intro and credit markers of movies and episodes, used by clients to skip them. Cascade deletes
+-- dont fire with sqlite, markers are deleted explicitly alongside their media.
+CREATE TABLE marker (
+    id INTEGER PRIMARY KEY NOT NULL,
+    media_id INTEGER NOT NULL,
+    -- either intro or credits.
+    kind TEXT NOT NULL,
+    start_ms INTEGER NOT NULL,
+    end_ms INTEGER NOT NULL,
+    -- either manual or detected, detection never replaces manual markers.
+    source TEXT NOT NULL,
+
+    FOREIGN KEY (media_id) REFERENCES _tblmedia(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX marker_idx ON marker(media_id, kind);
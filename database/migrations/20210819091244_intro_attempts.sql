@@ -0,0 +1,8 @@
+-- Episodes intro detection already ran for without finding a intro, they are skipped by later
+-- passes. Cascade deletes dont fire with sqlite, attempts are deleted explicitly alongside their
+-- media.
+CREATE TABLE intro_attempt (
+    media_id INTEGER PRIMARY KEY NOT NULL,
+
+    FOREIGN KEY (media_id) REFERENCES _tblmedia(id) ON DELETE CASCADE
+);
@@ -0,0 +1,178 @@
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Album struct represents a music album of a audio library. Albums group the tracks they
+/// contain.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Album {
+    /// unique id provided by sqlite
+    pub id: i64,
+    /// id of the audio library this album belongs to.
+    pub library_id: i64,
+    /// name of the album.
+    pub name: String,
+    /// name of the artist who released the album.
+    pub artist: Option<String>,
+    /// Year in which this album was released.
+    pub year: Option<i64>,
+    /// Date when this album was inserted into the database.
+    pub added: Option<String>,
+}
+
+impl Album {
+    /// Method returns all the albums of a library sorted by artist and name.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `library_id` - id of the library we want the albums of
+    pub async fn get_all(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Album,
+            "SELECT * FROM album WHERE library_id = ? ORDER BY artist, name",
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method filters the database for a album with the id supplied and returns it.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the album
+    pub async fn get_one(conn: &crate::DbConnection, id: i64) -> Result<Self, DatabaseError> {
+        Ok(
+            sqlx::query_as!(Album, "SELECT * FROM album WHERE id = ?", id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    /// Method deletes a album and all of its tracks. Returns the number of albums deleted.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the album
+    pub async fn delete(conn: &crate::DbConnection, id: i64) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!("DELETE FROM album WHERE id = ?", id)
+            .execute(conn)
+            .await?
+            .rows_affected() as usize)
+    }
+}
+
+/// InsertableAlbum struct, same as [`Album`](Album) but without the id field.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InsertableAlbum {
+    pub library_id: i64,
+    pub name: String,
+    pub artist: Option<String>,
+    pub year: Option<i64>,
+    pub added: Option<String>,
+}
+
+impl InsertableAlbum {
+    /// Method inserts a new album into the database and returns its id.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT INTO album (library_id, name, artist, year, added) VALUES ($1, $2, $3, $4, $5)",
+            self.library_id,
+            self.name,
+            self.artist,
+            self.year,
+            self.added
+        )
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
+    }
+}
+
+/// Track struct represents a single track of a album.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Track {
+    /// unique id provided by sqlite
+    pub id: i64,
+    /// id of the album this track belongs to.
+    pub album_id: i64,
+    /// id of the mediafile holding the audio of this track, `None` if the file was removed.
+    pub mediafile_id: Option<i64>,
+    /// name of the track.
+    pub name: String,
+    /// position of the track on the album.
+    pub track_number: Option<i64>,
+    /// duration of the track in seconds.
+    pub duration: Option<i64>,
+}
+
+impl Track {
+    /// Method returns all the tracks of a album sorted by their track number.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `album_id` - id of the album we want the tracks of
+    pub async fn get_all(
+        conn: &crate::DbConnection,
+        album_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Track,
+            "SELECT * FROM track WHERE album_id = ? ORDER BY track_number, name",
+            album_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method filters the database for a track with the id supplied and returns it.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the track
+    pub async fn get_one(conn: &crate::DbConnection, id: i64) -> Result<Self, DatabaseError> {
+        Ok(
+            sqlx::query_as!(Track, "SELECT * FROM track WHERE id = ?", id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+}
+
+/// InsertableTrack struct, same as [`Track`](Track) but without the id field.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InsertableTrack {
+    pub album_id: i64,
+    pub mediafile_id: Option<i64>,
+    pub name: String,
+    pub track_number: Option<i64>,
+    pub duration: Option<i64>,
+}
+
+impl InsertableTrack {
+    /// Method inserts a new track into the database and returns its id.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT INTO track (album_id, mediafile_id, name, track_number, duration)
+            VALUES ($1, $2, $3, $4, $5)",
+            self.album_id,
+            self.mediafile_id,
+            self.name,
+            self.track_number,
+            self.duration
+        )
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
+    }
+}
@@ -65,6 +65,75 @@ impl Asset {
         .id)
     }
 
+    /// Method returns all the posters and backdrops used by media in a library.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `library_id` - id of the library
+    pub async fn get_of_library(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Asset,
+            r#"SELECT DISTINCT assets.* FROM assets
+                INNER JOIN _tblmedia
+                    ON _tblmedia.poster = assets.id OR _tblmedia.backdrop = assets.id
+                WHERE _tblmedia.library_id = ?"#,
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns the poster and backdrop assets of a media.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media
+    pub async fn get_of_media(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Asset,
+            r#"SELECT DISTINCT assets.* FROM assets
+                INNER JOIN _tblmedia
+                    ON _tblmedia.poster = assets.id OR _tblmedia.backdrop = assets.id
+                WHERE _tblmedia.id = ?"#,
+            media_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method deletes a asset if no media, season or user references it anymore. Returns whether
+    /// the asset was deleted.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `id` - id of the asset
+    pub async fn delete_if_unused(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<bool, DatabaseError> {
+        Ok(sqlx::query!(
+            "DELETE FROM assets WHERE id = ?
+                AND NOT EXISTS (SELECT 1 FROM _tblmedia WHERE poster = ? OR backdrop = ?)
+                AND NOT EXISTS (SELECT 1 FROM _tblseason WHERE poster = ?)
+                AND NOT EXISTS (SELECT 1 FROM users WHERE picture = ?)",
+            id,
+            id,
+            id,
+            id,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected()
+            > 0)
+    }
+
     pub async fn get_url_by_file(
         conn: &crate::DbConnection,
         path: &PathBuf,
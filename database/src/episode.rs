@@ -292,8 +292,7 @@ impl Episode {
         conn: &crate::DbConnection,
         episode_id: i64,
     ) -> Result<usize, DatabaseError> {
-        // NOTE: no need to manually delete the episode entry from `episode` because of the
-        // cascade delete.
+        // NOTE: `Media::delete` also deletes the episode entry and everything else referencing it.
         Ok(Media::delete(conn, episode_id).await?)
     }
 }
@@ -306,11 +305,15 @@ pub struct InsertableEpisode {
 }
 
 impl InsertableEpisode {
-    /// Method inserts a new episode into the database
+    /// Method inserts a new episode into the database. Retried if the database is locked.
     ///
     /// # Arguments
     /// * `conn` - diesel connection reference to postgres
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        crate::retry_busy(|| self.try_insert(conn)).await
+    }
+
+    async fn try_insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
         let tx = conn.begin().await?;
 
         if let Some(r) = sqlx::query!(
@@ -4,6 +4,25 @@ use err_derive::Error;
 pub enum DatabaseError {
     #[error(display = "{:?}", _0)]
     DatabaseError(sqlx::error::Error),
+    #[error(display = "Invalid library location: {}", _0)]
+    InvalidLocation(String),
+    #[error(display = "Invalid library name: {}", _0)]
+    InvalidName(String),
+}
+
+impl DatabaseError {
+    /// Returns whether the error was caused by another connection holding a lock on the
+    /// database, ie `SQLITE_BUSY` or `SQLITE_LOCKED`. These errors are transient and the
+    /// operation can be retried.
+    pub fn is_busy(&self) -> bool {
+        match self {
+            Self::DatabaseError(sqlx::error::Error::Database(e)) => {
+                matches!(e.code().as_deref(), Some("5") | Some("6"))
+                    || e.message().contains("database is locked")
+            }
+            _ => false,
+        }
+    }
 }
 
 impl From<sqlx::error::Error> for DatabaseError {
@@ -19,6 +19,16 @@ pub struct GenreMedia {
     pub media_id: i64,
 }
 
+/// Number of media of a library tagged with a genre.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct GenreCount {
+    pub id: i64,
+    /// Genre name, ie "Action"
+    pub name: String,
+    /// Number of movies and tv shows of the library tagged with the genre.
+    pub count: i64,
+}
+
 impl Genre {
     /// Method returns the entry of a genre if exists based on its name.
     ///
@@ -79,6 +89,31 @@ impl Genre {
         .await?)
     }
 
+    /// Method returns every genre used by the movies and tv shows of a library alongside the
+    /// number of media tagged with it, most used genres first.
+    ///
+    /// # Arguments
+    /// * `conn` - diesel connection reference to postgres
+    /// * `library_id` - id of the library
+    pub async fn get_distribution(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<GenreCount>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            GenreCount,
+            r#"SELECT genre.id as "id!", genre.name, COUNT(DISTINCT _tblmedia.id) as "count!: i64"
+                FROM genre
+                INNER JOIN genre_media ON genre_media.genre_id = genre.id
+                INNER JOIN _tblmedia ON _tblmedia.id = genre_media.media_id
+                WHERE _tblmedia.library_id = ? AND NOT _tblmedia.media_type = "episode"
+                GROUP BY genre.id
+                ORDER BY COUNT(DISTINCT _tblmedia.id) DESC, genre.name ASC"#,
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
     /// Method removes a genre from the genre table based on its id
     ///
     /// # Arguments
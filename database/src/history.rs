@@ -0,0 +1,47 @@
+use crate::DatabaseError;
+
+/// History groups the queries over the watch history of a user, which is kept as the
+/// [`Progress`](crate::progress::Progress) they made on each media.
+pub struct History;
+
+impl History {
+    /// Method returns the ids of the media a user has watched most recently across all libraries,
+    /// newest first. Episodes are reported as their tv show so that a show appears only once.
+    /// Every user can access every library, thus no library is filtered out.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `user_id` - username of the user
+    /// * `limit` - max number of media to return
+    pub async fn recent_across_libraries(
+        conn: &crate::DbConnection,
+        user_id: String,
+        limit: i64,
+    ) -> Result<Vec<i64>, DatabaseError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            id: i64,
+        }
+
+        // FIXME: Use query_as macro instead of query_as function when https://github.com/launchbadge/sqlx/issues/1249 is fixed.
+        Ok(sqlx::query_as::<_, Row>(
+            r#"SELECT COALESCE(_tblseason.tvshowid, _tblmedia.id) as id,
+                MAX(progress.populated) as populated
+            FROM progress
+            INNER JOIN _tblmedia ON _tblmedia.id = progress.media_id
+            LEFT OUTER JOIN episode ON episode.id = _tblmedia.id
+            LEFT OUTER JOIN _tblseason ON _tblseason.id = episode.seasonid
+            WHERE progress.user_id = ? AND progress.delta > 0
+            GROUP BY COALESCE(_tblseason.tvshowid, _tblmedia.id)
+            ORDER BY populated DESC
+            LIMIT ?"#,
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|x| x.id)
+        .collect())
+    }
+}
@@ -4,22 +4,33 @@ use once_cell::sync::OnceCell;
 use slog::Logger;
 
 use crate::utils::ffpath;
+use std::future::Future;
 use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 
+pub mod album;
 pub mod asset;
 pub mod episode;
 pub mod error;
 pub mod genre;
+pub mod history;
 pub mod library;
+pub mod marker;
 pub mod media;
 pub mod mediafile;
 pub mod movie;
+pub mod note;
 pub mod progress;
+pub mod scan_history;
 pub mod season;
+pub mod tag;
 #[cfg(test)]
 pub mod tests;
+pub mod trailer;
 pub mod tv;
 pub mod user;
 pub mod utils;
@@ -32,9 +43,11 @@ compile_error!("Features sqlite and postgres are mutually exclusive");
 cfg_if! {
     if #[cfg(feature = "sqlite")] {
         pub type DbConnection = sqlx::SqlitePool;
+        pub type DbTransaction<'a> = sqlx::Transaction<'a, sqlx::Sqlite>;
 
     } else {
         pub type DbConnection = sqlx::PgPool;
+        pub type DbTransaction<'a> = sqlx::Transaction<'a, sqlx::Postgres>;
     }
 }
 
@@ -44,6 +57,50 @@ lazy_static::lazy_static! {
 
 static __GLOBAL: OnceCell<crate::DbConnection> = OnceCell::new();
 
+/// Time in milliseconds a sqlite connection waits for a lock before failing with `SQLITE_BUSY`.
+static BUSY_TIMEOUT: AtomicU64 = AtomicU64::new(5000);
+/// Number of times operations that failed with `SQLITE_BUSY` are retried.
+static BUSY_RETRIES: AtomicU32 = AtomicU32::new(3);
+/// Delay before the first retry, every further retry waits twice as long.
+const BUSY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Function sets how long connections wait for a locked database before failing and how many
+/// times failed transactional operations are retried. The timeout only applies to connections
+/// opened after this is called, thus this must be called before the first call to `get_conn`.
+///
+/// # Arguments
+/// * `timeout` - time a connection waits for a lock
+/// * `retries` - number of times a busy operation is retried, `0` disables retrying
+pub fn set_busy_handling(timeout: Duration, retries: u32) {
+    BUSY_TIMEOUT.store(timeout.as_millis() as u64, Ordering::SeqCst);
+    BUSY_RETRIES.store(retries, Ordering::SeqCst);
+}
+
+/// Function runs `f` and retries it with an exponential backoff if it fails because the database
+/// is locked by another connection. Other errors are returned right away.
+///
+/// # Arguments
+/// * `f` - closure returning the operation to run, it is called once per attempt
+pub async fn retry_busy<T, F, Fut>(mut f: F) -> Result<T, DatabaseError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DatabaseError>>,
+{
+    let mut backoff = BUSY_BACKOFF;
+
+    for _ in 0..BUSY_RETRIES.load(Ordering::Relaxed) {
+        match f().await {
+            Err(e) if e.is_busy() => {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    f().await
+}
+
 cfg_if! {
     if #[cfg(feature = "postgres")] {
         const MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("../migrations/postgres");
@@ -86,7 +143,9 @@ pub fn try_get_conn() -> Option<&'static crate::DbConnection> {
     __GLOBAL.get()
 }
 
-#[cfg(all(feature = "sqlite", test))]
+/// Function returns a connection to a new in-memory database with every migration applied. Only
+/// meant for tests, including the tests of crates depending on this one.
+#[cfg(feature = "sqlite")]
 pub async fn get_conn_memory() -> sqlx::Result<crate::DbConnection> {
     let pool = sqlx::Pool::connect(":memory:").await?;
     let _ = dbg!(run_migrations(&pool).await);
@@ -150,6 +209,7 @@ async fn internal_get_conn(_log: Option<&Logger>) -> sqlx::Result<DbConnection>
                     sqlx::sqlite::SqliteConnectOptions::from_str(ffpath("config/dim.db"))?
                     .create_if_missing(true)
                     .synchronous(sqlx::sqlite::SqliteSynchronous::Normal)
+                    .busy_timeout(Duration::from_millis(BUSY_TIMEOUT.load(Ordering::SeqCst)))
                 ).await
         }
     }
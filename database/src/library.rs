@@ -1,7 +1,13 @@
+use crate::media::Media;
+use crate::media::SortOrder;
 use crate::DatabaseError;
 use serde::Deserialize;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 /// Enum represents a media type and can be used on a library or on a media.
 /// When returned in a http response, the fields are lowercase.
@@ -12,6 +18,11 @@ pub enum MediaType {
     Movie,
     Tv,
     Episode,
+    /// Only used on libraries, marks a library holding both movies and tv shows. Media inside of
+    /// a mixed library keep their own media type.
+    Mixed,
+    /// Music libraries, their files are grouped into [`Album`](crate::album::Album)s.
+    Audio,
 }
 
 impl fmt::Display for MediaType {
@@ -23,17 +34,124 @@ impl fmt::Display for MediaType {
                 Self::Movie => "movie",
                 Self::Tv => "tv",
                 Self::Episode => "episode",
+                Self::Mixed => "mixed",
+                Self::Audio => "audio",
             }
         )
     }
 }
 
+impl FromStr for MediaType {
+    type Err = ParseMediaTypeError;
+
+    /// Parses the lowercase form of a media type, ie `movie`, as produced by its `Display` impl.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "movie" => Ok(Self::Movie),
+            "tv" => Ok(Self::Tv),
+            "episode" => Ok(Self::Episode),
+            "mixed" => Ok(Self::Mixed),
+            "audio" => Ok(Self::Audio),
+            _ => Err(ParseMediaTypeError(s.to_string())),
+        }
+    }
+}
+
 impl Default for MediaType {
     fn default() -> Self {
         Self::Movie
     }
 }
 
+/// Error returned when a string isnt a known [`MediaType`](MediaType).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMediaTypeError(pub String);
+
+impl fmt::Display for ParseMediaTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` is not a valid media type", self.0)
+    }
+}
+
+impl std::error::Error for ParseMediaTypeError {}
+
+/// Total runtime of all movies and episodes in a library in seconds.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub struct LibraryRuntime {
+    pub library_id: i64,
+    pub runtime: i64,
+}
+
+/// Summary of the last finished scan of a library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LastScan {
+    /// Unix timestamp of when the scan finished.
+    pub finished_at: i64,
+    /// How long the scan took in seconds.
+    pub duration: i64,
+    /// Number of files found by the scan.
+    pub items: i64,
+}
+
+impl LastScan {
+    fn from_columns(
+        finished_at: Option<i64>,
+        duration: Option<i64>,
+        items: Option<i64>,
+    ) -> Option<Self> {
+        Some(Self {
+            finished_at: finished_at?,
+            duration: duration.unwrap_or_default(),
+            items: items.unwrap_or_default(),
+        })
+    }
+}
+
+/// Number of rows [`Library::delete`] removed alongside the library itself.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeletedLibrary {
+    /// Number of libraries removed, `0` if the library didnt exist.
+    pub libraries: usize,
+    /// Number of media removed, including episodes.
+    pub media: usize,
+    /// Number of mediafiles removed.
+    pub mediafiles: usize,
+    /// Number of indexed locations removed.
+    pub locations: usize,
+}
+
+/// Order in which libraries are listed by [`Library::get_paginated`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LibraryOrder {
+    /// Sort by the display order set by the user, then by name.
+    DisplayOrder,
+    Name(SortOrder),
+    Id(SortOrder),
+}
+
+impl Default for LibraryOrder {
+    fn default() -> Self {
+        Self::DisplayOrder
+    }
+}
+
+impl LibraryOrder {
+    /// Returns the `ORDER BY` clause for this order against the `library` table. Ties are broken
+    /// by id so that pages never overlap.
+    pub fn order_clause(self) -> String {
+        let direction = |order| match order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        match self {
+            Self::DisplayOrder => "ORDER BY display_order, name, id".into(),
+            Self::Name(order) => format!("ORDER BY name {0}, id {0}", direction(order)),
+            Self::Id(order) => format!("ORDER BY id {}", direction(order)),
+        }
+    }
+}
+
 /// Library struct which we can use to deserialize database queries into.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Library {
@@ -47,30 +165,252 @@ pub struct Library {
     pub locations: Vec<String>,
 
     /// Enum used to identify the media type that this library contains. At the
-    /// moment only `movie` and `tv` are supported
-    // TODO: support mixed content, music
+    /// moment `movie`, `tv`, `mixed` for libraries holding both and `audio` are supported
     pub media_type: MediaType,
+
+    /// Summary of the last finished scan, `None` if the library was never scanned.
+    pub last_scan: Option<LastScan>,
+
+    /// Position of the library when libraries are listed, lower comes first.
+    pub display_order: i64,
 }
 
 impl Library {
-    /// Method returns all libraries that exist in the database in the form of a Vec.
-    /// If no libraries are found the the Vec will just be empty.
+    /// Method returns all libraries that exist in the database in the form of a Vec sorted by
+    /// their display order. If no libraries are found the the Vec will just be empty.
     ///
-    /// This method will not return the locations indexed for this library, if you need those you
-    /// must query for them separately.
+    /// This method will not return the locations indexed for this library, if you need those use
+    /// [`get_all_with_locations`](Library::get_all_with_locations). Errors are swallowed, use
+    /// [`get_paginated`](Library::get_paginated) to tell them apart from an empty database.
     pub async fn get_all(conn: &crate::DbConnection) -> Vec<Self> {
-        sqlx::query!(r#"SELECT id, name, media_type as "media_type: MediaType" FROM library"#)
-            .fetch_all(conn)
+        Self::get_paginated(conn, 0, i64::MAX, LibraryOrder::default())
             .await
             .unwrap_or_default()
+    }
+
+    /// Method returns a page of the libraries that exist in the database. Like
+    /// [`get_all`](Library::get_all) this will not populate `locations`.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `offset` - number of libraries to skip
+    /// * `limit` - maximum number of libraries to return
+    /// * `order` - order in which the libraries are listed
+    pub async fn get_paginated(
+        conn: &crate::DbConnection,
+        offset: i64,
+        limit: i64,
+        order: LibraryOrder,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            id: i64,
+            name: String,
+            media_type: MediaType,
+            last_scan: Option<i64>,
+            last_scan_duration: Option<i64>,
+            last_scan_items: Option<i64>,
+            display_order: i64,
+        }
+
+        // NOTE: The order clause is built at runtime, thus we cant use the query macros here.
+        let query = format!(
+            "SELECT id, name, media_type, last_scan, last_scan_duration, last_scan_items,
+                display_order
+            FROM library
+            {}
+            LIMIT ? OFFSET ?",
+            order.order_clause()
+        );
+
+        Ok(sqlx::query_as::<_, Row>(&query)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(conn)
+            .await?
             .into_iter()
             .map(|x| Self {
                 id: x.id,
                 name: x.name,
                 media_type: x.media_type,
                 locations: vec![],
+                last_scan: LastScan::from_columns(
+                    x.last_scan,
+                    x.last_scan_duration,
+                    x.last_scan_items,
+                ),
+                display_order: x.display_order,
             })
-            .collect()
+            .collect())
+    }
+
+    /// Method returns all libraries that exist in the database alongside their indexed locations,
+    /// sorted by their display order. Unlike [`get_all`](Library::get_all) this will populate
+    /// `locations` using a single join against `indexed_paths`. Libraries without any locations are
+    /// returned with an empty Vec.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn get_all_with_locations(
+        conn: &crate::DbConnection,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            id: i64,
+            name: String,
+            media_type: MediaType,
+            location: Option<String>,
+            last_scan: Option<i64>,
+            last_scan_duration: Option<i64>,
+            last_scan_items: Option<i64>,
+            display_order: i64,
+        }
+
+        // FIXME: We're using the query_as function instead of macro because `LEFT OUTER JOIN`
+        // crashes the proc macro.
+        let rows = sqlx::query_as::<_, Row>(
+            r#"SELECT library.id, library.name, library.media_type, indexed_paths.location,
+                library.last_scan, library.last_scan_duration, library.last_scan_items,
+                library.display_order
+            FROM library
+            LEFT OUTER JOIN indexed_paths ON indexed_paths.library_id = library.id
+            ORDER BY library.display_order, library.name, library.id"#,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        let mut libraries: Vec<Self> = Vec::new();
+
+        // NOTE: Rows are ordered by a key that ends with the library id, thus all rows of a library
+        // are adjacent.
+        for row in rows {
+            match libraries.last_mut() {
+                Some(library) if library.id == row.id => library.locations.extend(row.location),
+                _ => libraries.push(Self {
+                    id: row.id,
+                    name: row.name,
+                    media_type: row.media_type,
+                    locations: row.location.into_iter().collect(),
+                    last_scan: LastScan::from_columns(
+                        row.last_scan,
+                        row.last_scan_duration,
+                        row.last_scan_items,
+                    ),
+                    display_order: row.display_order,
+                }),
+            }
+        }
+
+        Ok(libraries)
+    }
+
+    /// Method returns the distinct media types of the media present in a library. Episodes are
+    /// never returned as they always belong to a tv show.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    pub async fn get_media_types(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<Vec<MediaType>, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT DISTINCT media_type as "media_type: MediaType" FROM _tblmedia
+            WHERE library_id = ? AND NOT media_type = "episode"
+            ORDER BY media_type"#,
+            id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns the distinct release years of the movies and tv shows of a library, oldest
+    /// first. Media without a known release year are left out.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    pub async fn get_years(conn: &crate::DbConnection, id: i64) -> Result<Vec<i64>, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT DISTINCT year as "year!" FROM _tblmedia
+            WHERE library_id = ? AND NOT media_type = "episode" AND year IS NOT NULL
+            ORDER BY year ASC"#,
+            id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns for every library the sum of the durations of its movies and episodes in
+    /// seconds. Media with a unknown duration are excluded from the sum, when a media has several
+    /// files the longest one is used.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn get_runtimes(
+        conn: &crate::DbConnection,
+    ) -> Result<Vec<LibraryRuntime>, DatabaseError> {
+        // FIXME: Use query_as macro instead of query_as function when https://github.com/launchbadge/sqlx/issues/1249 is fixed.
+        Ok(sqlx::query_as::<_, LibraryRuntime>(
+            r#"SELECT library.id as library_id, COALESCE(SUM(m.duration), 0) as runtime
+            FROM library
+            LEFT OUTER JOIN (
+                SELECT _tblmedia.library_id, MAX(mediafile.duration) as duration
+                FROM _tblmedia
+                INNER JOIN mediafile ON mediafile.media_id = _tblmedia.id
+                WHERE _tblmedia.media_type IN ("movie", "episode")
+                    AND mediafile.duration IS NOT NULL
+                GROUP BY _tblmedia.id
+            ) m ON m.library_id = library.id
+            GROUP BY library.id
+            ORDER BY library.id"#,
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns the number of movies and tv shows in a library, episodes arent counted on
+    /// their own. Mixed libraries report the total of both.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    pub async fn media_count(conn: &crate::DbConnection, id: i64) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM _tblmedia
+            WHERE library_id = ? AND NOT media_type = "episode""#,
+            id
+        )
+        .fetch_one(conn)
+        .await?)
+    }
+
+    /// Method returns the number of movies and tv shows of every library keyed by library id,
+    /// libraries without media are reported with a count of `0`. See [`Library::media_count`].
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn media_counts_all(
+        conn: &crate::DbConnection,
+    ) -> Result<BTreeMap<i64, i64>, DatabaseError> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            id: i64,
+            count: i64,
+        }
+
+        // FIXME: Use query_as macro instead of query_as function when https://github.com/launchbadge/sqlx/issues/1249 is fixed.
+        Ok(sqlx::query_as::<_, Row>(
+            r#"SELECT library.id, COUNT(_tblmedia.id) as count FROM library
+            LEFT OUTER JOIN _tblmedia
+                ON _tblmedia.library_id = library.id AND NOT _tblmedia.media_type = "episode"
+            GROUP BY library.id"#,
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|x| (x.id, x.count))
+        .collect())
     }
 
     pub async fn get_locations(
@@ -97,7 +437,8 @@ impl Library {
         let _tx = conn.begin().await?;
 
         let library = sqlx::query!(
-            r#"SELECT id, name, media_type as "media_type: MediaType" FROM library
+            r#"SELECT id, name, media_type as "media_type: MediaType", last_scan,
+            last_scan_duration, last_scan_items, display_order FROM library
             WHERE id = ?"#,
             lib_id
         )
@@ -117,10 +458,445 @@ impl Library {
             name: library.name,
             media_type: library.media_type,
             locations,
+            last_scan: LastScan::from_columns(
+                library.last_scan,
+                library.last_scan_duration,
+                library.last_scan_items,
+            ),
+            display_order: library.display_order,
         })
     }
 
-    /// Method filters the database for a library with the id supplied and deletes it.
+    /// Method filters the database for a library with the name supplied and returns it. Library
+    /// names are assumed to be unique, returns `RowNotFound` if no library has this name.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `name` - name of the library we are trying to query
+    pub async fn get_by_name(
+        conn: &crate::DbConnection,
+        name: &str,
+    ) -> Result<Self, DatabaseError> {
+        let id = sqlx::query_scalar!("SELECT id FROM library WHERE name = ?", name)
+            .fetch_one(conn)
+            .await?;
+
+        Self::get_one(conn, id).await
+    }
+
+    /// Method stores the summary of the last finished scan of a library.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    /// * `last_scan` - summary of the scan
+    pub async fn set_last_scan(
+        conn: &crate::DbConnection,
+        id: i64,
+        last_scan: &LastScan,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE library SET last_scan = ?, last_scan_duration = ?, last_scan_items = ?
+            WHERE id = ?",
+            last_scan.finished_at,
+            last_scan.duration,
+            last_scan.items,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method updates the name, media type and locations of a library in a single transaction,
+    /// thus if any step fails nothing is changed. The name is validated like in
+    /// [`update_name`](Library::update_name). The locations are canonicalized and must exist,
+    /// must not overlap with each other nor with a location of another library. Locations which
+    /// arent in `library` anymore are removed alongside what was indexed under them like in
+    /// [`remove_location`](Library::remove_location) and new ones are added. Returns the updated
+    /// library or `RowNotFound` if there is no library with this id.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library to update
+    /// * `library` - new name, media type and locations of the library
+    pub async fn update(
+        conn: &crate::DbConnection,
+        id: i64,
+        library: InsertableLibrary,
+    ) -> Result<Self, DatabaseError> {
+        let locations = library.canonical_locations()?;
+
+        if locations.is_empty() {
+            return Err(DatabaseError::InvalidLocation(
+                "At least one location is required.".into(),
+            ));
+        }
+
+        let mut tx = conn.begin().await?;
+
+        sqlx::query_scalar!("SELECT id FROM library WHERE id = ?", id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        Self::set_name(&mut tx, id, &library.name).await?;
+
+        let others = sqlx::query!(
+            "SELECT indexed_paths.location, library.name FROM indexed_paths
+            INNER JOIN library ON library.id = indexed_paths.library_id
+            WHERE library.id != ?",
+            id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        for location in &locations {
+            let path = Path::new(location);
+            if let Some(other) = others
+                .iter()
+                .find(|x| path.starts_with(&x.location) || Path::new(&x.location).starts_with(path))
+            {
+                return Err(DatabaseError::InvalidLocation(format!(
+                    "`{}` overlaps with a location of the library `{}`.",
+                    location, other.name
+                )));
+            }
+        }
+
+        sqlx::query!(
+            "UPDATE library SET media_type = ? WHERE id = ?",
+            library.media_type,
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let current = sqlx::query_scalar!(
+            "SELECT location FROM indexed_paths WHERE library_id = ?",
+            id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        for location in current.iter().filter(|x| !locations.contains(x)) {
+            Self::purge_location(&mut tx, id, location).await?;
+        }
+
+        for location in locations.iter().filter(|x| !current.contains(x)) {
+            sqlx::query!(
+                "INSERT INTO indexed_paths(location, library_id) VALUES ($1, $2)",
+                location,
+                id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Self::get_one(conn, id).await
+    }
+
+    /// Method renames a library. The name is trimmed and must neither be empty nor already taken
+    /// by another library, names are compared case insensitively. Returns `RowNotFound` if there
+    /// is no library with this id.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library to rename
+    /// * `name` - new name of the library
+    pub async fn update_name(
+        conn: &crate::DbConnection,
+        id: i64,
+        name: &str,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = conn.begin().await?;
+        Self::set_name(&mut tx, id, name).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Method renames a library within `tx`, see [`update_name`](Library::update_name).
+    async fn set_name(
+        tx: &mut crate::DbTransaction<'_>,
+        id: i64,
+        name: &str,
+    ) -> Result<(), DatabaseError> {
+        let name = name.trim();
+
+        if name.is_empty() {
+            return Err(DatabaseError::InvalidName("Name must not be empty.".into()));
+        }
+
+        let taken = sqlx::query_scalar!(
+            "SELECT id FROM library WHERE id != ? AND lower(trim(name)) = lower(?)",
+            id,
+            name
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if taken.is_some() {
+            return Err(DatabaseError::InvalidName(
+                "A library with this name already exists.".into(),
+            ));
+        }
+
+        let updated = sqlx::query!("UPDATE library SET name = ? WHERE id = ?", name, id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        Ok(())
+    }
+
+    /// Method adds a location to a library and returns it canonicalized. Fails if the location
+    /// doesnt exist or if it is the same as or nested in another location of the library. Returns
+    /// `RowNotFound` if there is no library with this id.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    /// * `location` - path of the directory to add
+    pub async fn add_location(
+        conn: &crate::DbConnection,
+        id: i64,
+        location: &str,
+    ) -> Result<String, DatabaseError> {
+        let path = Path::new(location)
+            .canonicalize()
+            .map_err(|_| DatabaseError::InvalidLocation(format!("`{}` doesnt exist.", location)))?;
+
+        let mut tx = conn.begin().await?;
+
+        sqlx::query_scalar!("SELECT id FROM library WHERE id = ?", id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        let current = sqlx::query_scalar!(
+            "SELECT location FROM indexed_paths WHERE library_id = ?",
+            id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        if let Some(other) = current
+            .iter()
+            .find(|x| path.starts_with(x) || Path::new(x).starts_with(&path))
+        {
+            let reason = if Path::new(other) == path {
+                "is the same as"
+            } else {
+                "overlaps with"
+            };
+
+            return Err(DatabaseError::InvalidLocation(format!(
+                "`{}` {} `{}`.",
+                location, reason, other
+            )));
+        }
+
+        let path = path.to_string_lossy().to_string();
+        sqlx::query!(
+            "INSERT INTO indexed_paths(location, library_id) VALUES ($1, $2)",
+            path,
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(path)
+    }
+
+    /// Method removes a location from a library. The mediafiles indexed under the location are
+    /// purged, media left without any mediafile are [purged](Media::purge) as well alongside
+    /// everything referencing them, so are tv shows left without any episode. Media that still
+    /// have files in other locations are kept. Returns the number of mediafiles that were purged.
+    ///
+    /// The location doesnt have to exist anymore, ie when the drive it was on was removed. The
+    /// last location of a library cant be removed.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `id` - id of the library
+    /// * `location` - location to remove
+    pub async fn remove_location(
+        conn: &crate::DbConnection,
+        id: i64,
+        location: &str,
+    ) -> Result<usize, DatabaseError> {
+        let location = Path::new(location)
+            .canonicalize()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_else(|_| location.trim_end_matches('/').to_string());
+
+        let mut tx = conn.begin().await?;
+
+        let current = sqlx::query_scalar!(
+            "SELECT location FROM indexed_paths WHERE library_id = ?",
+            id
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        if !current.contains(&location) {
+            return Err(DatabaseError::InvalidLocation(format!(
+                "`{}` isnt a location of this library.",
+                location
+            )));
+        }
+
+        if current.len() == 1 {
+            return Err(DatabaseError::InvalidLocation(
+                "The last location of a library cant be removed.".into(),
+            ));
+        }
+
+        let purged = Self::purge_location(&mut tx, id, &location).await?;
+
+        tx.commit().await?;
+
+        Ok(purged)
+    }
+
+    /// Method drops a location of a library within `tx` and purges what was indexed under it, see
+    /// [`remove_location`](Library::remove_location). Returns the number of mediafiles purged.
+    async fn purge_location(
+        tx: &mut crate::DbTransaction<'_>,
+        id: i64,
+        location: &str,
+    ) -> Result<usize, DatabaseError> {
+        let prefix = format!("{}/", location);
+        let media = sqlx::query_scalar!(
+            r#"SELECT DISTINCT media_id as "media_id!" FROM mediafile
+            WHERE library_id = ? AND media_id IS NOT NULL
+            AND (target_file = ? OR substr(target_file, 1, length(?)) = ?)"#,
+            id,
+            location,
+            prefix,
+            prefix
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let shows = sqlx::query_scalar!(
+            r#"SELECT DISTINCT _tblseason.tvshowid as "tvshowid!" FROM mediafile
+            INNER JOIN episode ON episode.id = mediafile.media_id
+            INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+            WHERE mediafile.library_id = ?
+            AND (target_file = ? OR substr(target_file, 1, length(?)) = ?)"#,
+            id,
+            location,
+            prefix,
+            prefix
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let purged = sqlx::query!(
+            "DELETE FROM mediafile WHERE library_id = ?
+            AND (target_file = ? OR substr(target_file, 1, length(?)) = ?)",
+            id,
+            location,
+            prefix,
+            prefix
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() as usize;
+
+        for media_id in media {
+            let remaining = sqlx::query_scalar!(
+                "SELECT id FROM mediafile WHERE media_id = ? LIMIT 1",
+                media_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            if remaining.is_none() {
+                Media::purge(tx, media_id).await?;
+            }
+        }
+
+        // Seasons left without episodes are dropped and shows left without seasons are purged.
+        for show in shows {
+            sqlx::query!(
+                "DELETE FROM _tblseason WHERE tvshowid = ?
+                AND NOT EXISTS (SELECT id FROM episode WHERE episode.seasonid = _tblseason.id)",
+                show
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            let remaining =
+                sqlx::query_scalar!("SELECT id FROM _tblseason WHERE tvshowid = ? LIMIT 1", show)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+
+            if remaining.is_none() {
+                Media::purge(tx, show).await?;
+            }
+        }
+
+        sqlx::query!(
+            "DELETE FROM indexed_paths WHERE library_id = ? AND location = ?",
+            id,
+            location
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(purged)
+    }
+
+    /// Method sets the display order of the libraries. Libraries are ordered as they appear in
+    /// `ids`, libraries missing from `ids` keep their relative order and are placed after the
+    /// ones listed. Ids of libraries that dont exist are ignored.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    /// * `ids` - ids of the libraries in the order they should be listed in
+    pub async fn set_order(
+        conn: &crate::DbConnection,
+        ids: &[i64],
+    ) -> Result<usize, DatabaseError> {
+        let mut tx = conn.begin().await?;
+
+        let current =
+            sqlx::query_scalar!("SELECT id FROM library ORDER BY display_order, name, id")
+                .fetch_all(&mut tx)
+                .await?;
+
+        let mut order = Vec::with_capacity(current.len());
+        for id in ids.iter().chain(current.iter()) {
+            if current.contains(id) && !order.contains(id) {
+                order.push(*id);
+            }
+        }
+
+        for (idx, id) in order.iter().enumerate() {
+            let idx = idx as i64;
+            sqlx::query!("UPDATE library SET display_order = ? WHERE id = ?", idx, id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(order.len())
+    }
+
+    /// Method filters the database for a library with the id supplied and deletes it alongside
+    /// its media, mediafiles, locations, albums and scan history. Every row referencing its media
+    /// is [purged](Media::purge) too. Everything is deleted in a single transaction, thus if any
+    /// step fails nothing is deleted. Returns how many rows were removed.
     ///
     /// # Arguments
     /// * `conn` - [diesel connection](crate::DbConnection)
@@ -128,11 +904,68 @@ impl Library {
     pub async fn delete(
         conn: &crate::DbConnection,
         id_to_del: i64,
-    ) -> Result<usize, DatabaseError> {
-        Ok(sqlx::query!("DELETE FROM library WHERE id = ?", id_to_del)
-            .execute(conn)
+    ) -> Result<DeletedLibrary, DatabaseError> {
+        let mut tx = conn.begin().await?;
+
+        let mediafiles = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM mediafile WHERE library_id = ?"#,
+            id_to_del
+        )
+        .fetch_one(&mut tx)
+        .await? as usize;
+
+        // Shows are purged before episodes as purging a show already purges its episodes.
+        let ids = sqlx::query_scalar!(
+            r#"SELECT id FROM _tblmedia WHERE library_id = ? ORDER BY media_type = "episode""#,
+            id_to_del
+        )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut media = 0;
+        for id in ids {
+            media += Media::purge(&mut tx, id).await?;
+        }
+
+        // NOTE: `CASCADE DELETE` doesnt work with a sqlite backend, thus we manually delete
+        // everything else that belongs to the library.
+        sqlx::query!("DELETE FROM mediafile WHERE library_id = ?", id_to_del)
+            .execute(&mut tx)
+            .await?;
+
+        let locations = sqlx::query!("DELETE FROM indexed_paths WHERE library_id = ?", id_to_del)
+            .execute(&mut tx)
+            .await?
+            .rows_affected() as usize;
+
+        sqlx::query!(
+            "DELETE FROM track WHERE album_id IN (SELECT id FROM album WHERE library_id = ?)",
+            id_to_del
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM album WHERE library_id = ?", id_to_del)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM scan_history WHERE library_id = ?", id_to_del)
+            .execute(&mut tx)
+            .await?;
+
+        let libraries = sqlx::query!("DELETE FROM library WHERE id = ?", id_to_del)
+            .execute(&mut tx)
             .await?
-            .rows_affected() as usize)
+            .rows_affected() as usize;
+
+        tx.commit().await?;
+
+        Ok(DeletedLibrary {
+            libraries,
+            media,
+            mediafiles,
+            locations,
+        })
     }
 }
 
@@ -145,14 +978,123 @@ pub struct InsertableLibrary {
 }
 
 impl InsertableLibrary {
-    /// Method inserts a InsertableLibrary object into the database (makes a new library).
+    /// Method validates a library before it gets inserted. Returns a map of field names to error
+    /// messages, an empty map means the library is valid. Errors for a location are keyed by its
+    /// index, ie `locations.1`.
+    ///
+    /// A library is invalid if its name is empty or already taken by another library, or if any of
+    /// its locations isnt an existing directory or overlaps with a location of another library or
+    /// with another of its own locations.
+    ///
+    /// # Arguments
+    /// * `conn` - [diesel connection](crate::DbConnection)
+    pub async fn validate(
+        &self,
+        conn: &crate::DbConnection,
+    ) -> Result<BTreeMap<String, String>, DatabaseError> {
+        let mut errors = BTreeMap::new();
+        let libraries = Library::get_all_with_locations(conn).await?;
+
+        if self.name.trim().is_empty() {
+            errors.insert("name".into(), "Name must not be empty.".into());
+        } else if libraries
+            .iter()
+            .any(|x| x.name.trim().eq_ignore_ascii_case(self.name.trim()))
+        {
+            errors.insert(
+                "name".into(),
+                "A library with this name already exists.".into(),
+            );
+        }
+
+        if self.locations.is_empty() {
+            errors.insert(
+                "locations".into(),
+                "At least one location is required.".into(),
+            );
+        }
+
+        for (idx, location) in self.locations.iter().enumerate() {
+            let path = Path::new(location);
+            let overlaps = |other: &str| {
+                let other = Path::new(other);
+                path.starts_with(other) || other.starts_with(path)
+            };
+
+            let error = if !path.is_dir() {
+                Some("Location doesnt exist or isnt a directory.".to_string())
+            } else if let Some(library) = libraries
+                .iter()
+                .find(|x| x.locations.iter().any(|x| overlaps(x)))
+            {
+                Some(format!(
+                    "Location overlaps with a location of the library `{}`.",
+                    library.name
+                ))
+            } else if self.locations[..idx].iter().any(|x| overlaps(x)) {
+                Some("Location overlaps with another location of this library.".to_string())
+            } else {
+                None
+            };
+
+            if let Some(error) = error {
+                errors.insert(format!("locations.{}", idx), error);
+            }
+        }
+
+        Ok(errors)
+    }
+
+    /// Method canonicalizes the locations of the library. Fails if a location doesnt exist or if
+    /// two locations are the same directory or nested in each other.
+    fn canonical_locations(&self) -> Result<Vec<String>, DatabaseError> {
+        let mut locations: Vec<PathBuf> = Vec::new();
+
+        for location in &self.locations {
+            let path = Path::new(location).canonicalize().map_err(|_| {
+                DatabaseError::InvalidLocation(format!("`{}` doesnt exist.", location))
+            })?;
+
+            if let Some(other) = locations
+                .iter()
+                .find(|x| path.starts_with(x) || x.starts_with(&path))
+            {
+                let reason = if *other == path {
+                    "is the same as"
+                } else {
+                    "overlaps with"
+                };
+
+                return Err(DatabaseError::InvalidLocation(format!(
+                    "`{}` {} `{}`.",
+                    location,
+                    reason,
+                    other.display()
+                )));
+            }
+
+            locations.push(path);
+        }
+
+        Ok(locations
+            .into_iter()
+            .map(|x| x.to_string_lossy().to_string())
+            .collect())
+    }
+
+    /// Method inserts a InsertableLibrary object into the database (makes a new library). The
+    /// locations are stored canonicalized, nothing is written if any of them doesnt exist or if
+    /// they overlap with each other.
     ///
     /// # Arguments
     /// * `conn` - [diesel connection](crate::DbConnection)
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        let locations = self.canonical_locations()?;
+
         let tx = conn.begin().await?;
         let lib_id = sqlx::query!(
-            r#"INSERT INTO library (name, media_type) VALUES ($1, $2)"#,
+            r#"INSERT INTO library (name, media_type, display_order)
+            VALUES ($1, $2, (SELECT COALESCE(MAX(display_order) + 1, 0) FROM library))"#,
             self.name,
             self.media_type
         )
@@ -160,7 +1102,7 @@ impl InsertableLibrary {
         .await?
         .last_insert_rowid();
 
-        for location in &self.locations {
+        for location in &locations {
             sqlx::query!(
                 r#"INSERT into indexed_paths(location, library_id)
                 VALUES ($1, $2)"#,
@@ -0,0 +1,192 @@
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Part of a media a marker spans.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum MarkerKind {
+    Intro,
+    Credits,
+}
+
+impl Default for MarkerKind {
+    fn default() -> Self {
+        Self::Intro
+    }
+}
+
+/// Where a marker comes from.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum MarkerSource {
+    /// Set by a user, never replaced by detected markers.
+    Manual,
+    /// Found by analyzing the media.
+    Detected,
+}
+
+impl Default for MarkerSource {
+    fn default() -> Self {
+        Self::Manual
+    }
+}
+
+/// Struct represents the intro or the credits of a movie or episode, clients use markers to let
+/// users skip them. A media has at most one marker of every kind.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Marker {
+    pub id: i64,
+    /// id of the media this marker belongs to.
+    pub media_id: i64,
+    pub kind: MarkerKind,
+    /// Offset in milliseconds at which the marked part starts.
+    pub start_ms: i64,
+    /// Offset in milliseconds at which the marked part ends.
+    pub end_ms: i64,
+    pub source: MarkerSource,
+}
+
+impl Marker {
+    /// Method returns the markers of a media sorted by when they start.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media
+    pub async fn get_of_media(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Marker,
+            r#"SELECT id, media_id, kind as "kind: _", start_ms, end_ms, source as "source: _"
+            FROM marker WHERE media_id = ?
+            ORDER BY start_ms ASC"#,
+            media_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method deletes the marker of a kind of a media. Returns the number of markers deleted.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media
+    /// * `kind` - kind of the marker
+    pub async fn delete(
+        conn: &crate::DbConnection,
+        media_id: i64,
+        kind: MarkerKind,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "DELETE FROM marker WHERE media_id = ? AND kind = ?",
+            media_id,
+            kind
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method returns the episodes of a library that dont have a intro marker yet and that intro
+    /// detection didnt already fail for, sorted by season and episode number. Episodes spread over
+    /// several files are returned with one of their files.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `library_id` - id of the library
+    pub async fn get_missing_intros(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<IntroCandidate>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            IntroCandidate,
+            r#"SELECT episode.seasonid as season_id, episode.id as "media_id!",
+                MIN(mediafile.target_file) as "target_file!: String"
+            FROM episode
+            INNER JOIN _tblmedia ON _tblmedia.id = episode.id
+            INNER JOIN mediafile ON mediafile.media_id = episode.id
+            WHERE _tblmedia.library_id = ?
+            AND NOT EXISTS (
+                SELECT 1 FROM marker WHERE marker.media_id = episode.id AND marker.kind = 'intro'
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM intro_attempt WHERE intro_attempt.media_id = episode.id
+            )
+            GROUP BY episode.id
+            ORDER BY episode.seasonid, episode.episode_"#,
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method records that intro detection couldnt find a intro for the episode `media_id`, so
+    /// that [`get_missing_intros`](Marker::get_missing_intros) doesnt return it again. Returns the
+    /// number of newly recorded episodes.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the episode
+    pub async fn set_intro_attempted(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT OR IGNORE INTO intro_attempt (media_id) VALUES (?)",
+            media_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
+
+/// Episode without a intro marker, see [`Marker::get_missing_intros`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntroCandidate {
+    pub season_id: i64,
+    /// id of the episode.
+    pub media_id: i64,
+    /// file the intro of the episode can be detected from.
+    pub target_file: String,
+}
+
+/// InsertableMarker struct, same as [`Marker`](Marker) but without the id field.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InsertableMarker {
+    pub media_id: i64,
+    pub kind: MarkerKind,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub source: MarkerSource,
+}
+
+impl InsertableMarker {
+    /// Method sets the marker of a media, replacing the marker of the same kind if there was one.
+    /// Detected markers never replace manual markers. Returns the number of markers written.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            r#"INSERT INTO marker (media_id, kind, start_ms, end_ms, source)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (media_id, kind) DO UPDATE
+            SET start_ms = excluded.start_ms, end_ms = excluded.end_ms, source = excluded.source
+            WHERE marker.source = 'detected' OR excluded.source = 'manual'"#,
+            self.media_id,
+            self.kind,
+            self.start_ms,
+            self.end_ms,
+            self.source
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
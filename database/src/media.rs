@@ -43,6 +43,123 @@ impl PartialEq for Media {
     }
 }
 
+/// Keys a list of media can be sorted by. Every key carries its own default direction, which
+/// requests can override with a [`SortOrder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    Name,
+    Added,
+    Year,
+    Rating,
+}
+
+impl Default for SortBy {
+    fn default() -> Self {
+        Self::Name
+    }
+}
+
+/// Direction in which a list of media is sorted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortBy {
+    /// Direction used when a request doesnt specify one. Names sort alphabetically while the
+    /// date added, year and rating put the newest or best rated media first.
+    pub fn default_order(self) -> SortOrder {
+        match self {
+            Self::Name => SortOrder::Asc,
+            Self::Added | Self::Year | Self::Rating => SortOrder::Desc,
+        }
+    }
+
+    /// Returns the `ORDER BY` clause for this key against the `media` view. Ties are broken by
+    /// id so that pages of a paginated query never overlap.
+    ///
+    /// # Arguments
+    /// * `order` - direction to sort in, defaults to [`SortBy::default_order`]
+    pub fn order_clause(self, order: Option<SortOrder>) -> String {
+        let column = match self {
+            Self::Name => "name",
+            Self::Added => "added",
+            Self::Year => "year",
+            Self::Rating => "rating",
+        };
+
+        let direction = match order.unwrap_or_else(|| self.default_order()) {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        format!("ORDER BY {0} {1}, id {1}", column, direction)
+    }
+
+    /// Sorts a list of media in place the same way [`SortBy::order_clause`] would, media missing
+    /// the key sort first when ascending and last when descending.
+    ///
+    /// # Arguments
+    /// * `order` - direction to sort in, defaults to [`SortBy::default_order`]
+    /// * `media` - media to sort
+    pub fn sort(self, order: Option<SortOrder>, media: &mut [Media]) {
+        media.sort_by(|a, b| {
+            let ordering = match self {
+                Self::Name => a.name.cmp(&b.name),
+                Self::Added => a.added.cmp(&b.added),
+                Self::Year => a.year.cmp(&b.year),
+                Self::Rating => a.rating.cmp(&b.rating),
+            }
+            .then(a.id.cmp(&b.id));
+
+            match order.unwrap_or_else(|| self.default_order()) {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+    }
+}
+
+/// Set of media [`Media::shuffle_next`] picks from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShuffleScope {
+    /// Episodes of the tv show with this id.
+    Show(i64),
+    /// Movies and episodes of the library with this id.
+    Library(i64),
+}
+
+/// Filters a [`Media::search`] is narrowed down with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchFilters {
+    /// Only return media of this type.
+    pub media_type: Option<MediaType>,
+    /// Only return media of the library with this id.
+    pub library_id: Option<i64>,
+    /// Max number of results, `None` returns every match.
+    pub limit: Option<i64>,
+    /// Number of results to skip.
+    pub offset: i64,
+}
+
+/// Function escapes the `LIKE` wildcards in `query` so that they are matched literally. Patterns
+/// built from the result have to be matched with `ESCAPE '\'`.
+pub fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+
+    for c in query.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
 impl Media {
     /// Method returns all Media objects associated with a Library. Its exactly the same as
     /// [`Library::get`](Library::get) except it takes in a Library object instead of a id.
@@ -166,6 +283,153 @@ impl Media {
         ).fetch_all(conn).await?)
     }
 
+    /// Method returns a single random media of a library. Instead of sorting the whole table with
+    /// `ORDER BY RANDOM()` we count the eligible rows and pick one at a random offset.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library to pick from
+    /// * `media_type` - type of the media to pick, `mixed` picks from movies and tv shows
+    /// * `unwatched_by` - if supplied, only media this user hasnt started watching are eligible.
+    pub async fn random(
+        conn: &crate::DbConnection,
+        library_id: i64,
+        media_type: MediaType,
+        unwatched_by: Option<String>,
+    ) -> Result<Option<Self>, DatabaseError> {
+        let offset = sqlx::query_scalar!(
+            r#"SELECT ABS(RANDOM()) % MAX(COUNT(*), 1) as "offset!: i64"
+                FROM _tblmedia
+                WHERE library_id = ?
+                AND (media_type = ? OR (? = "mixed" AND media_type IN ("movie", "tv")))
+                AND (? IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM progress
+                    WHERE progress.media_id = _tblmedia.id
+                    AND progress.user_id = ? AND progress.delta > 0))
+                AND (? IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM progress
+                    INNER JOIN episode ON episode.id = progress.media_id
+                    INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+                    WHERE _tblseason.tvshowid = _tblmedia.id
+                    AND progress.user_id = ? AND progress.delta > 0))"#,
+            library_id,
+            media_type,
+            media_type,
+            unwatched_by,
+            unwatched_by,
+            unwatched_by,
+            unwatched_by
+        )
+        .fetch_one(conn)
+        .await?;
+
+        Ok(sqlx::query_as!(
+                Media,
+                r#"SELECT id, library_id, name, description, rating, year, added, poster_path, backdrop_path, media_type as "media_type: _"
+                FROM media
+                WHERE library_id = ?
+                AND (media_type = ? OR (? = "mixed" AND media_type IN ("movie", "tv")))
+                AND (? IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM progress
+                    WHERE progress.media_id = media.id
+                    AND progress.user_id = ? AND progress.delta > 0))
+                AND (? IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM progress
+                    INNER JOIN episode ON episode.id = progress.media_id
+                    INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+                    WHERE _tblseason.tvshowid = media.id
+                    AND progress.user_id = ? AND progress.delta > 0))
+                ORDER BY id
+                LIMIT 1 OFFSET ?"#,
+                library_id,
+                media_type,
+                media_type,
+                unwatched_by,
+                unwatched_by,
+                unwatched_by,
+                unwatched_by,
+                offset
+            )
+            .fetch_optional(conn)
+            .await?)
+    }
+
+    /// Method returns a random movie or episode of `scope` to play next in shuffle mode. The
+    /// `recent` media the user played last, going by their progress, are skipped so that the
+    /// same media isnt repeated right away. Scopes too small to skip that many media only skip
+    /// the last played one. `current` is never picked, not even when the scope holds nothing
+    /// else. Only media with at least one file are picked.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `scope` - show or library to pick from
+    /// * `user_id` - user whose recent plays are skipped
+    /// * `current` - id of the media the user is playing right now
+    /// * `recent` - number of recently played media to skip
+    pub async fn shuffle_next(
+        conn: &crate::DbConnection,
+        scope: ShuffleScope,
+        user_id: &str,
+        current: Option<i64>,
+        recent: i64,
+    ) -> Result<Option<Self>, DatabaseError> {
+        let (show_id, library_id) = match scope {
+            ShuffleScope::Show(id) => (Some(id), None),
+            ShuffleScope::Library(id) => (None, Some(id)),
+        };
+
+        // NOTE: The filter is shared by both queries and spliced in at runtime, thus we cant use the
+        // query macros here.
+        let filter = r#"FROM _tblmedia
+            LEFT OUTER JOIN episode ON episode.id = _tblmedia.id
+            LEFT OUTER JOIN _tblseason ON _tblseason.id = episode.seasonid
+            WHERE (_tblseason.tvshowid = ?
+                OR (_tblmedia.library_id = ? AND _tblmedia.media_type IN ("movie", "episode")))
+            AND EXISTS (SELECT 1 FROM mediafile WHERE mediafile.media_id = _tblmedia.id)
+            AND _tblmedia.id IS NOT ?
+            AND _tblmedia.id NOT IN (
+                SELECT media_id FROM progress WHERE user_id = ?
+                ORDER BY populated DESC, id DESC
+                LIMIT ?)"#;
+
+        let mut skips = vec![recent.max(0)];
+        skips.extend(if recent > 1 { vec![1, 0] } else { vec![0] });
+        skips.dedup();
+
+        for skip in skips {
+            let offset = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT ABS(RANDOM()) % MAX(COUNT(*), 1) {}",
+                filter
+            ))
+            .bind(show_id)
+            .bind(library_id)
+            .bind(current)
+            .bind(user_id)
+            .bind(skip)
+            .fetch_one(conn)
+            .await?;
+
+            let id = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT _tblmedia.id {} ORDER BY _tblmedia.id LIMIT 1 OFFSET ?",
+                filter
+            ))
+            .bind(show_id)
+            .bind(library_id)
+            .bind(current)
+            .bind(user_id)
+            .bind(skip)
+            .bind(offset)
+            .fetch_optional(conn)
+            .await?;
+
+            if let Some(id) = id {
+                return Ok(Some(Self::get(conn, id).await?));
+            }
+        }
+
+        Ok(None)
+    }
+
     pub async fn get_search(
         conn: &crate::DbConnection,
         query: &str,
@@ -185,6 +449,55 @@ impl Media {
         ).fetch_all(conn).await?)
     }
 
+    /// Method searches every library for movies and tv shows whose name contains `query`, ignoring
+    /// case. Names that are equal to `query` are returned first, followed by names starting with it
+    /// and finally names that merely contain it. An empty query returns nothing.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `query` - text to look for in the names of media
+    /// * `filters` - filters to narrow the results down with
+    pub async fn search(
+        conn: &crate::DbConnection,
+        query: &str,
+        filters: SearchFilters,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        let query = query.trim();
+
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let prefix = format!("{}%", escape_like(query));
+        let substring = format!("%{}%", escape_like(query));
+        let limit = filters.limit.unwrap_or(-1);
+
+        Ok(sqlx::query_as!(
+                Media,
+                r#"SELECT id, library_id, name, description, rating, year, added, poster_path, backdrop_path, media_type as "media_type: _"
+                FROM media
+                WHERE NOT media_type = "episode"
+                AND UPPER(name) LIKE UPPER(?) ESCAPE '\'
+                AND (? IS NULL OR media_type = ?)
+                AND (? IS NULL OR library_id = ?)
+                ORDER BY CASE
+                    WHEN UPPER(name) = UPPER(?) THEN 0
+                    WHEN UPPER(name) LIKE UPPER(?) ESCAPE '\' THEN 1
+                    ELSE 2
+                END, name, id
+                LIMIT ? OFFSET ?"#,
+                substring,
+                filters.media_type,
+                filters.media_type,
+                filters.library_id,
+                filters.library_id,
+                query,
+                prefix,
+                limit,
+                filters.offset
+        ).fetch_all(conn).await?)
+    }
+
     pub async fn get_of_genre(
         conn: &crate::DbConnection,
         genre_id: i64,
@@ -216,30 +529,226 @@ impl Media {
         ).fetch_all(conn).await?)
     }
 
-    /// Method deletes a media object based on its id.
+    /// Method returns the media before and after `media_id` in its library when the library is
+    /// sorted by `sort`, this lets clients move through a library one media at a time. Episodes
+    /// are never returned as neighbors.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library the media belongs to
+    /// * `media_id` - id of the media we want the neighbors of
+    /// * `sort` - key the library is sorted by
+    /// * `order` - direction to sort in, defaults to [`SortBy::default_order`]
+    pub async fn neighbors(
+        conn: &crate::DbConnection,
+        library_id: i64,
+        media_id: i64,
+        sort: SortBy,
+        order: Option<SortOrder>,
+    ) -> Result<(Option<Self>, Option<Self>), DatabaseError> {
+        let mut media = Self::get_all(conn, library_id).await?;
+        sort.sort(order, &mut media);
+
+        let idx = match media.iter().position(|x| x.id == media_id) {
+            Some(x) => x,
+            None => return Ok((None, None)),
+        };
+
+        let next = media.get(idx + 1).cloned();
+        let prev = idx.checked_sub(1).and_then(|x| media.get(x)).cloned();
+
+        Ok((prev, next))
+    }
+
+    /// Method returns the id of the media of a library that was matched to a tmdb id. If several
+    /// media were matched to it the one inserted first is returned.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library to look in
+    /// * `tmdb_id` - id of the tmdb entry
+    /// * `media_type` - type of the media
+    pub async fn get_id_by_tmdb_id(
+        conn: &crate::DbConnection,
+        library_id: i64,
+        tmdb_id: i64,
+        media_type: MediaType,
+    ) -> Result<Option<i64>, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            "SELECT id FROM _tblmedia
+            WHERE library_id = ? AND tmdb_id = ? AND media_type = ?
+            ORDER BY id
+            LIMIT 1",
+            library_id,
+            tmdb_id,
+            media_type
+        )
+        .fetch_optional(conn)
+        .await?)
+    }
+
+    /// Method returns the tmdb id a media was matched to, if it was matched to one.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the media
+    pub async fn get_tmdb_id(
+        conn: &crate::DbConnection,
+        id: i64,
+    ) -> Result<Option<i64>, DatabaseError> {
+        Ok(
+            sqlx::query_scalar!("SELECT tmdb_id FROM _tblmedia WHERE id = ?", id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    /// Method stores the tmdb id a media was matched to, media which already have a tmdb id keep
+    /// it.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `id` - id of the media
+    /// * `tmdb_id` - id of the tmdb entry
+    pub async fn set_tmdb_id(
+        conn: &crate::DbConnection,
+        id: i64,
+        tmdb_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE _tblmedia SET tmdb_id = ? WHERE id = ? AND tmdb_id IS NULL",
+            tmdb_id,
+            id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method deletes a media object based on its id and [purges](Media::purge) everything
+    /// referencing it, deleting a tv show deletes its seasons and episodes too. Returns how many
+    /// media objects were deleted.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
     /// * `id` - id of a media object we want to delete
     pub async fn delete(conn: &crate::DbConnection, id: i64) -> Result<usize, DatabaseError> {
-        Ok(sqlx::query!("DELETE FROM _tblmedia WHERE id = ?", id)
-            .execute(conn)
-            .await?
-            .rows_affected() as usize)
+        let mut tx = conn.begin().await?;
+        let rows = Self::purge(&mut tx, id).await?;
+        tx.commit().await?;
+
+        Ok(rows)
     }
 
     /// This function exists because for some reason `CASCADE DELETE` doesnt work with a sqlite
-    /// backend. Thus we must manually delete entries when deleting a library.
+    /// backend. Thus we must manually [purge](Media::purge) every media when deleting a library.
     pub async fn delete_by_lib_id(
         conn: &crate::DbConnection,
         library_id: i64,
     ) -> Result<usize, DatabaseError> {
-        Ok(
-            sqlx::query!("DELETE FROM _tblmedia WHERE library_id = ?", library_id)
-                .execute(conn)
-                .await?
-                .rows_affected() as usize,
+        let mut tx = conn.begin().await?;
+
+        // Shows are purged before episodes as purging a show already purges its episodes.
+        let ids = sqlx::query_scalar!(
+            r#"SELECT id FROM _tblmedia WHERE library_id = ? ORDER BY media_type = "episode""#,
+            library_id
         )
+        .fetch_all(&mut tx)
+        .await?;
+
+        let mut rows = 0;
+        for id in ids {
+            rows += Self::purge(&mut tx, id).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(rows)
+    }
+
+    /// Method deletes a media object alongside every row that references it, ie its mediafiles,
+    /// progress, notes, tags, trailers, genres, markers and artwork. Purging a tv show purges its
+    /// seasons and episodes as well. Returns how many media objects were deleted.
+    ///
+    /// # Arguments
+    /// * `tx` - transaction the deletes are run in
+    /// * `id` - id of the media object to purge
+    pub(crate) async fn purge(
+        tx: &mut crate::DbTransaction<'_>,
+        id: i64,
+    ) -> Result<usize, DatabaseError> {
+        let episodes = sqlx::query_scalar!(
+            r#"SELECT episode.id as "id!" FROM episode
+            INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+            WHERE _tblseason.tvshowid = ?"#,
+            id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut rows = 0;
+        for episode in episodes {
+            rows += Self::purge_one(tx, episode).await?;
+        }
+
+        sqlx::query!("DELETE FROM _tblseason WHERE tvshowid = ?", id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM tv_show WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+
+        Ok(rows + Self::purge_one(tx, id).await?)
+    }
+
+    async fn purge_one(tx: &mut crate::DbTransaction<'_>, id: i64) -> Result<usize, DatabaseError> {
+        // NOTE: `CASCADE DELETE` doesnt work with a sqlite backend, thus every row referencing the
+        // media has to be deleted by hand.
+        const REFERENCING: &[&str] = &[
+            "progress",
+            "note",
+            "media_tag",
+            "media_trailers",
+            "genre_media",
+            "marker",
+            "intro_attempt",
+            "media_posters",
+            "media_backdrops",
+        ];
+
+        // NOTE: The table is spliced in at runtime, thus we cant use the query macros here.
+        for table in REFERENCING {
+            sqlx::query(&format!("DELETE FROM {} WHERE media_id = ?", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE track SET mediafile_id = NULL
+            WHERE mediafile_id IN (SELECT id FROM mediafile WHERE media_id = ?)",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM mediafile WHERE media_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM episode WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM movie WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+
+        Ok(sqlx::query!("DELETE FROM _tblmedia WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() as usize)
     }
 }
 
@@ -266,11 +775,15 @@ pub struct InsertableMedia {
 }
 
 impl InsertableMedia {
-    /// Method used to insert a new media object.
+    /// Method used to insert a new media object. Retried if the database is locked.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        crate::retry_busy(|| self.try_insert(conn)).await
+    }
+
+    async fn try_insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
         let tx = conn.begin().await?;
 
         if let Some(record) = sqlx::query!(r#"SELECT id FROM media where name = ?"#, self.name)
@@ -302,6 +815,34 @@ impl InsertableMedia {
         Ok(id)
     }
 
+    /// Method inserts `self` as a new media matched to `tmdb_id`, even if a media with the same
+    /// name or tmdb id exists already. Used to keep several files of the same movie as separate
+    /// media.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `tmdb_id` - id of the tmdb entry this media was matched to
+    pub async fn insert_with_tmdb_id(
+        &self,
+        conn: &crate::DbConnection,
+        tmdb_id: i64,
+    ) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            r#"INSERT INTO _tblmedia (library_id, name, description, rating, year, added, poster, backdrop, media_type, tmdb_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+            self.library_id,
+            self.name,
+            self.description,
+            self.rating,
+            self.year,
+            self.added,
+            self.poster,
+            self.backdrop,
+            self.media_type,
+            tmdb_id
+        ).execute(conn).await?.last_insert_rowid())
+    }
+
     /// Method blindly inserts `self` into the database without checking whether a similar entry exists.
     /// This is especially useful for tv shows as they usually have similar metadata with key differences
     /// which are not indexed in the database.
@@ -36,10 +36,21 @@ pub struct MediaFile {
     pub original_resolution: Option<String>,
     /// Duration of the video file that we obtain from ffprobe
     pub duration: Option<i64>,
+    /// Where the duration came from, `ffprobe` if it was reported by ffprobe or `estimate` if it
+    /// was estimated from the bitrate and filesize of the file.
+    pub duration_source: Option<String>,
+    /// Edition of the release captured from the filename, ie `Extended` or `Directors Cut`.
+    pub edition: Option<String>,
+    /// Release group captured from the filename.
+    pub release_group: Option<String>,
+    /// Part number of a movie which is split over several files, ie `2` for `Movie.CD2.avi`.
+    pub part: Option<i64>,
 
     /// Episode number that we might get from using regex and the parse-torrent-name crate. This is
     /// specific to tv shows only.
     pub episode: Option<i64>,
+    /// Last episode number of a file which spans several episodes, ie `2` for `S01E01-E02`.
+    pub episode_end: Option<i64>,
     /// Season number that we might get from using regexa and the parse-torrent-name crate. This is
     /// specific to tv shows only.
     pub season: Option<i64>,
@@ -47,6 +58,10 @@ pub struct MediaFile {
     /// Flag which tells us if the file is corrupted or not. ie if ffprobe cant open the file and
     /// reports no metadata this flag will be set.
     pub corrupt: Option<bool>,
+
+    /// Flag which tells us that the scanner couldnt confidently match this file to any metadata
+    /// and that it should be reviewed and matched manually.
+    pub needs_review: bool,
 }
 
 impl MediaFile {
@@ -68,6 +83,23 @@ impl MediaFile {
         .await?)
     }
 
+    /// Method returns the number of mediafiles of a library.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library
+    pub async fn count_by_lib(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM mediafile WHERE library_id = ?"#,
+            library_id
+        )
+        .fetch_one(conn)
+        .await?)
+    }
+
     /// Method returns all mediafiles associated with a library and filters for those not
     /// associated with a media
     ///
@@ -87,7 +119,30 @@ impl MediaFile {
         .await?)
     }
 
-    /// Method returns all mediafiles associated with a Media object.
+    /// Method returns the review queue of a library. These are all the mediafiles which either
+    /// have no media associated or have been flagged by the scanner as needing review.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `library_id` - id of the library we want the review queue of
+    pub async fn get_review_queue(
+        conn: &crate::DbConnection,
+        library_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MediaFile,
+            "SELECT * FROM mediafile
+            WHERE library_id = ? AND (media_id IS NULL OR needs_review = 1)
+            ORDER BY raw_name ASC",
+            library_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns all mediafiles associated with a Media object. For episodes this includes
+    /// files spanning a range of episodes which covers the episode, ie `S01E01-E02` is returned
+    /// for both episode 1 and 2.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
@@ -100,7 +155,14 @@ impl MediaFile {
             MediaFile,
             "SELECT mediafile.* FROM mediafile
                 INNER JOIN media ON media.id = mediafile.media_id
-                WHERE media.id = ?",
+                WHERE media.id = ?1
+            UNION
+            SELECT mediafile.* FROM mediafile
+                INNER JOIN episode first ON first.id = mediafile.media_id
+                INNER JOIN episode ON episode.seasonid = first.seasonid
+                WHERE episode.id = ?1
+                AND episode.episode_ > first.episode_
+                AND episode.episode_ <= mediafile.episode_end",
             media_id
         )
         .fetch_all(conn)
@@ -146,6 +208,26 @@ impl MediaFile {
         .await?)
     }
 
+    /// Method returns the mediafile whose path matches `file` when ignoring case, this lets
+    /// libraries on case insensitive filesystems tell that `Movie.mkv` and `movie.mkv` are the
+    /// same file. Only ASCII letters are compared case insensitively.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `file` - path of the file
+    pub async fn get_by_file_nocase(
+        conn: &crate::DbConnection,
+        file: &str,
+    ) -> Result<Self, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MediaFile,
+            r#"SELECT * FROM mediafile WHERE target_file = ? COLLATE NOCASE"#,
+            file
+        )
+        .fetch_one(conn)
+        .await?)
+    }
+
     /// Function will return the largest duration for a media.
     pub async fn get_largest_duration(
         conn: &crate::DbConnection,
@@ -163,6 +245,75 @@ impl MediaFile {
         .duration)
     }
 
+    /// Method returns the id of the part following `part` for a media which is split over several
+    /// files. Returns `None` if `part` is the last part.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    /// * `media_id` - id of the media the parts belong to
+    /// * `part` - part number of the current file
+    pub async fn get_next_part(
+        conn: &crate::DbConnection,
+        media_id: i64,
+        part: i64,
+    ) -> Result<Option<i64>, DatabaseError> {
+        Ok(sqlx::query_scalar!(
+            "SELECT id FROM mediafile
+            WHERE media_id = ? AND part > ?
+            ORDER BY part ASC
+            LIMIT 1",
+            media_id,
+            part
+        )
+        .fetch_optional(conn)
+        .await?)
+    }
+
+    /// Method returns all mediafiles whose file is also indexed by another library, which happens
+    /// when the locations of two libraries overlap. Copies of the same file are returned next to
+    /// each other, the one indexed first leading.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    pub async fn get_cross_library_duplicates(
+        conn: &crate::DbConnection,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            MediaFile,
+            "SELECT * FROM mediafile
+            WHERE EXISTS (
+                SELECT 1 FROM mediafile other
+                WHERE other.target_file = mediafile.target_file
+                AND other.library_id != mediafile.library_id
+            )
+            ORDER BY target_file ASC, id ASC"
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method flags every copy of a file indexed by several libraries for review, except for the
+    /// copy that was indexed first. Returns the number of newly flagged mediafiles.
+    ///
+    /// # Arguments
+    /// * `conn` - postgres connection
+    pub async fn flag_cross_library_duplicates(
+        conn: &crate::DbConnection,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "UPDATE mediafile SET needs_review = 1
+            WHERE needs_review = 0 AND EXISTS (
+                SELECT 1 FROM mediafile other
+                WHERE other.target_file = mediafile.target_file
+                AND other.library_id != mediafile.library_id
+                AND other.id < mediafile.id
+            )"
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
     /// Method deletes mediafile matching the id supplied
     ///
     /// # Arguments
@@ -206,29 +357,40 @@ pub struct InsertableMediaFile {
     pub audio: Option<String>,
     pub original_resolution: Option<String>,
     pub duration: Option<i64>,
+    pub duration_source: Option<String>,
+    pub edition: Option<String>,
+    pub release_group: Option<String>,
+    pub part: Option<i64>,
 
     /***
      * Options specific to tv show scanner hence Option<T>
      ***/
     pub episode: Option<i64>,
+    pub episode_end: Option<i64>,
     pub season: Option<i64>,
     /*** ***/
     pub corrupt: Option<bool>,
 }
 
 impl InsertableMediaFile {
-    /// Method inserts a new mediafile into the database.
+    /// Method inserts a new mediafile into the database. Retried if the database is locked.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
     pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        crate::retry_busy(|| self.try_insert(conn)).await
+    }
+
+    async fn try_insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
         let tx = conn.begin().await?;
 
         let id = sqlx::query!(
             r#"
             INSERT INTO mediafile (media_id, library_id, target_file, raw_name, raw_year, quality,
-            codec, container, audio, original_resolution, duration, episode, season, corrupt)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            codec, container, audio, original_resolution, duration, duration_source, edition,
+            release_group, part, episode, episode_end, season, corrupt)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+            $18, $19)
         "#,
             self.media_id,
             self.library_id,
@@ -241,7 +403,12 @@ impl InsertableMediaFile {
             self.audio,
             self.original_resolution,
             self.duration,
+            self.duration_source,
+            self.edition,
+            self.release_group,
+            self.part,
             self.episode,
+            self.episode_end,
             self.season,
             self.corrupt
         )
@@ -269,19 +436,25 @@ pub struct UpdateMediaFile {
     pub audio: Option<String>,
     pub original_resolution: Option<String>,
     pub duration: Option<i64>,
+    pub duration_source: Option<String>,
+    pub edition: Option<String>,
+    pub release_group: Option<String>,
+    pub part: Option<i64>,
 
     /***
      * Options specific to tv show scanner hence Option<T>
      ***/
     pub episode: Option<i64>,
+    pub episode_end: Option<i64>,
     pub season: Option<i64>,
     /*** ***/
     pub corrupt: Option<bool>,
+    pub needs_review: Option<bool>,
 }
 
 impl UpdateMediaFile {
     /// Method updates the columns of a mediafile entry with what is supplied. The row is selected
-    /// based on its id.
+    /// based on its id. Retried if the database is locked.
     ///
     /// # Arguments
     /// * `conn` - postgres connection
@@ -290,6 +463,14 @@ impl UpdateMediaFile {
         &self,
         conn: &crate::DbConnection,
         id: i64,
+    ) -> Result<usize, DatabaseError> {
+        crate::retry_busy(|| self.try_update(conn, id)).await
+    }
+
+    async fn try_update(
+        &self,
+        conn: &crate::DbConnection,
+        id: i64,
     ) -> Result<usize, DatabaseError> {
         let tx = conn.begin().await?;
 
@@ -304,9 +485,15 @@ impl UpdateMediaFile {
             "UPDATE mediafile SET audio = ? WHERE id = ?" => (self.audio, id),
             "UPDATE mediafile SET original_resolution = ? WHERE id = ?" => (self.original_resolution, id),
             "UPDATE mediafile SET duration = ? WHERE id = ?" => (self.duration, id),
+            "UPDATE mediafile SET duration_source = ? WHERE id = ?" => (self.duration_source, id),
+            "UPDATE mediafile SET edition = ? WHERE id = ?" => (self.edition, id),
+            "UPDATE mediafile SET release_group = ? WHERE id = ?" => (self.release_group, id),
+            "UPDATE mediafile SET part = ? WHERE id = ?" => (self.part, id),
             "UPDATE mediafile SET episode = ? WHERE id = ?" => (self.episode, id),
+            "UPDATE mediafile SET episode_end = ? WHERE id = ?" => (self.episode_end, id),
             "UPDATE mediafile SET season = ? WHERE id = ?" => (self.season, id),
-            "UPDATE mediafile SET corrupt = ? WHERE id = ?" => (self.corrupt, id)
+            "UPDATE mediafile SET corrupt = ? WHERE id = ?" => (self.corrupt, id),
+            "UPDATE mediafile SET needs_review = ? WHERE id = ?" => (self.needs_review, id)
         );
 
         tx.commit().await?;
@@ -0,0 +1,97 @@
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::time::SystemTime;
+
+/// Struct represents a private note a user attached to a media, ie "watch the extended ending".
+/// Notes are only ever visible to the user who wrote them.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Note {
+    pub id: i64,
+    /// username of the user who wrote this note.
+    pub user_id: String,
+    /// id of the media this note is attached to.
+    pub media_id: i64,
+    pub content: String,
+    /// Unix timestamp of when the note was last changed.
+    pub updated: i64,
+}
+
+impl Note {
+    /// Method returns the note a user attached to a media, if any.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `media_id` - id of the media
+    pub async fn get(
+        conn: &crate::DbConnection,
+        uid: String,
+        media_id: i64,
+    ) -> Result<Option<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Note,
+            "SELECT * FROM note WHERE user_id = ? AND media_id = ?",
+            uid,
+            media_id
+        )
+        .fetch_optional(conn)
+        .await?)
+    }
+
+    /// Method sets the note a user attached to a media, replacing the previous note if there was
+    /// one.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `media_id` - id of the media
+    /// * `content` - content of the note
+    pub async fn set(
+        conn: &crate::DbConnection,
+        uid: String,
+        media_id: i64,
+        content: String,
+    ) -> Result<usize, DatabaseError> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        Ok(sqlx::query!(
+            "INSERT INTO note (user_id, media_id, content, updated) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, media_id)
+            DO UPDATE SET content = excluded.content, updated = excluded.updated",
+            uid,
+            media_id,
+            content,
+            timestamp
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method deletes the note a user attached to a media.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `media_id` - id of the media
+    pub async fn delete(
+        conn: &crate::DbConnection,
+        uid: String,
+        media_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "DELETE FROM note WHERE user_id = ? AND media_id = ?",
+            uid,
+            media_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+}
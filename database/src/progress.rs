@@ -5,6 +5,19 @@ use crate::DatabaseError as DieselError;
 use serde::Serialize;
 use std::time::SystemTime;
 
+/// Number of seconds before the end of a media after which it counts as finished, this skips past
+/// most credits.
+pub const FINISHED_MARGIN: i64 = 10;
+
+/// Number of movies and episodes in a library a user has watched, started or not watched yet.
+#[derive(Debug, Clone, Serialize, Default, PartialEq, sqlx::FromRow)]
+pub struct WatchStatusCounts {
+    pub library_id: i64,
+    pub watched: i64,
+    pub in_progress: i64,
+    pub unwatched: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct Progress {
     pub id: i64,
@@ -15,11 +28,22 @@ pub struct Progress {
 }
 
 impl Progress {
+    /// Method stores the progress of a user for a media. Retried if the database is locked as
+    /// clients report progress continuously during playback.
     pub async fn set(
         conn: &crate::DbConnection,
         delta: i64,
         uid: String,
         mid: i64,
+    ) -> Result<usize, DieselError> {
+        crate::retry_busy(|| Self::try_set(conn, delta, &uid, mid)).await
+    }
+
+    async fn try_set(
+        conn: &crate::DbConnection,
+        delta: i64,
+        uid: &str,
+        mid: i64,
     ) -> Result<usize, DieselError> {
         let timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -39,11 +63,94 @@ impl Progress {
         .rows_affected() as usize)
     }
 
-    pub async fn get_for_media_user(
+    /// Method marks every movie and episode of a library as watched or unwatched for a user in a
+    /// single transaction. Watched media get their progress set to their duration, media whose
+    /// duration is unknown are skipped. Unwatched media have their progress removed. Returns the
+    /// number of media that were updated.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `library_id` - id of the library
+    /// * `watched` - whether to mark the media as watched or unwatched
+    pub async fn set_for_library(
         conn: &crate::DbConnection,
-        uid: String,
+        uid: &str,
+        library_id: i64,
+        watched: bool,
+    ) -> Result<usize, DieselError> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let mut tx = conn.begin().await?;
+
+        let updated = if watched {
+            sqlx::query!(
+                r#"INSERT OR REPLACE INTO progress (delta, media_id, user_id, populated)
+                SELECT MAX(mediafile.duration), _tblmedia.id, ?, ? FROM _tblmedia
+                INNER JOIN mediafile ON mediafile.media_id = _tblmedia.id
+                WHERE _tblmedia.library_id = ?
+                AND _tblmedia.media_type IN ("movie", "episode")
+                GROUP BY _tblmedia.id
+                HAVING MAX(mediafile.duration) > 0"#,
+                uid,
+                timestamp,
+                library_id
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+        } else {
+            sqlx::query!(
+                "DELETE FROM progress WHERE user_id = ?
+                AND media_id IN (SELECT id FROM _tblmedia WHERE library_id = ?)",
+                uid,
+                library_id
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected()
+        };
+
+        tx.commit().await?;
+
+        Ok(updated as usize)
+    }
+
+    /// Method deletes the progress of every user for a media. If the media is a tv show, the
+    /// progress of all of its episodes is deleted as well.
+    pub async fn delete_for_media(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<usize, DieselError> {
+        Ok(sqlx::query!(
+            "DELETE FROM progress WHERE media_id = ?
+            OR media_id IN (
+                SELECT episode.id FROM episode
+                INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+                WHERE _tblseason.tvshowid = ?)",
+            media_id,
+            media_id
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method returns the progress of a user for a media, `None` if the user never started
+    /// watching it.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `mid` - id of the media
+    pub async fn get(
+        conn: &crate::DbConnection,
+        uid: &str,
         mid: i64,
-    ) -> Result<Self, DieselError> {
+    ) -> Result<Option<Self>, DieselError> {
         Ok(sqlx::query_as!(
             Progress,
             "SELECT progress.* FROM progress
@@ -53,14 +160,52 @@ impl Progress {
             mid
         )
         .fetch_optional(conn)
-        .await?
-        .unwrap_or(Self {
+        .await?)
+    }
+
+    pub async fn get_for_media_user(
+        conn: &crate::DbConnection,
+        uid: String,
+        mid: i64,
+    ) -> Result<Self, DieselError> {
+        Ok(Self::get(conn, &uid, mid).await?.unwrap_or(Self {
             media_id: mid,
             user_id: uid,
             ..Default::default()
         }))
     }
 
+    /// Method returns the progress of the movies and episodes a user started but didnt finish,
+    /// most recently watched first. Media within [`FINISHED_MARGIN`] seconds of their end and
+    /// media whose duration is unknown are left out.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `limit` - max number of entries to return
+    pub async fn continue_watching(
+        conn: &crate::DbConnection,
+        uid: &str,
+        limit: i64,
+    ) -> Result<Vec<Self>, DieselError> {
+        Ok(sqlx::query_as!(
+            Progress,
+            "SELECT progress.* FROM progress
+            WHERE user_id = ?
+            AND delta > 0
+            AND delta < (
+                SELECT MAX(mediafile.duration) FROM mediafile
+                WHERE mediafile.media_id = progress.media_id) - ?
+            ORDER BY populated DESC, id DESC
+            LIMIT ?",
+            uid,
+            FINISHED_MARGIN,
+            limit
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
     pub async fn get_total_time_spent_watching(
         conn: &crate::DbConnection,
         uid: String,
@@ -169,4 +314,37 @@ impl Progress {
         .fetch_all(conn)
         .await?)
     }
+
+    /// Method returns for every library the number of movies and episodes a user has watched,
+    /// started watching or not touched yet. A media counts as watched once the user watched 90% of
+    /// its duration.
+    pub async fn get_watch_status_counts(
+        conn: &crate::DbConnection,
+        uid: String,
+    ) -> Result<Vec<WatchStatusCounts>, DieselError> {
+        // FIXME: Use query_as macro instead of query_as function when https://github.com/launchbadge/sqlx/issues/1249 is fixed.
+        Ok(sqlx::query_as::<_, WatchStatusCounts>(
+            r#"SELECT library.id as library_id,
+                COALESCE(SUM(m.delta > 0 AND m.delta >= m.duration * 0.9), 0) as watched,
+                COALESCE(SUM(m.delta > 0 AND m.delta < m.duration * 0.9), 0) as in_progress,
+                COALESCE(SUM(m.delta = 0), 0) as unwatched
+            FROM library
+            LEFT OUTER JOIN (
+                SELECT _tblmedia.library_id,
+                    COALESCE(progress.delta, 0) as delta,
+                    COALESCE(MAX(mediafile.duration), 0) as duration
+                FROM _tblmedia
+                LEFT OUTER JOIN progress
+                    ON progress.media_id = _tblmedia.id AND progress.user_id = ?
+                LEFT OUTER JOIN mediafile ON mediafile.media_id = _tblmedia.id
+                WHERE _tblmedia.media_type IN ("movie", "episode")
+                GROUP BY _tblmedia.id
+            ) m ON m.library_id = library.id
+            GROUP BY library.id
+            ORDER BY library.id"#,
+        )
+        .bind(uid)
+        .fetch_all(conn)
+        .await?)
+    }
 }
@@ -0,0 +1,78 @@
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// ScanHistory struct represents the summary of a single finished scan of a library. Unlike
+/// [`LastScan`](crate::library::LastScan) every scan is kept.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct ScanHistory {
+    /// unique id provided by sqlite
+    pub id: i64,
+    /// id of the library that was scanned.
+    pub library_id: i64,
+    /// Unix timestamp of when the scan finished.
+    pub finished_at: i64,
+    /// How long the scan took in seconds.
+    pub duration: i64,
+    /// Number of files found by the scan.
+    pub items: i64,
+    /// Number of files the scan added to the library.
+    pub added: i64,
+}
+
+impl ScanHistory {
+    /// Method returns the most recent scans of a library, newest first.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `library_id` - id of the library we want the scans of
+    /// * `limit` - maximum number of scans to return
+    pub async fn get_recent(
+        conn: &crate::DbConnection,
+        library_id: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            ScanHistory,
+            "SELECT * FROM scan_history WHERE library_id = ?
+            ORDER BY finished_at DESC, id DESC
+            LIMIT ?",
+            library_id,
+            limit
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+}
+
+/// InsertableScanHistory struct, same as [`ScanHistory`](ScanHistory) but without the id field.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct InsertableScanHistory {
+    pub library_id: i64,
+    pub finished_at: i64,
+    pub duration: i64,
+    pub items: i64,
+    pub added: i64,
+}
+
+impl InsertableScanHistory {
+    /// Method appends a finished scan to the history of its library and returns its id.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<i64, DatabaseError> {
+        Ok(sqlx::query!(
+            "INSERT INTO scan_history (library_id, finished_at, duration, items, added)
+            VALUES ($1, $2, $3, $4, $5)",
+            self.library_id,
+            self.finished_at,
+            self.duration,
+            self.items,
+            self.added
+        )
+        .execute(conn)
+        .await?
+        .last_insert_rowid())
+    }
+}
@@ -134,12 +134,17 @@ pub struct InsertableSeason {
 }
 
 impl InsertableSeason {
-    /// Method inserts a new season and links it to a tv show based on the id specified.
+    /// Method inserts a new season and links it to a tv show based on the id specified. Retried if
+    /// the database is locked.
     ///
     /// # Arguments
     /// * `conn` - diesel connection reference to postgres
     /// * `id` - id of the tv show we'd like to link this season to
     pub async fn insert(&self, conn: &crate::DbConnection, id: i64) -> Result<i64, DatabaseError> {
+        crate::retry_busy(|| self.try_insert(conn, id)).await
+    }
+
+    async fn try_insert(&self, conn: &crate::DbConnection, id: i64) -> Result<i64, DatabaseError> {
         let tx = conn.begin().await?;
 
         let result = sqlx::query!(
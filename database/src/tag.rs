@@ -0,0 +1,194 @@
+use crate::media::Media;
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Struct represents a personal tag created by a user, ie "comfort watch". Tags are scoped to the
+/// user that created them and are independent of the genres fetched from metadata providers.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Tag {
+    pub id: i64,
+    /// username of the user who owns this tag.
+    pub user_id: String,
+    /// Tag name, ie "kids safe".
+    pub name: String,
+}
+
+impl Tag {
+    /// Method returns all the tags a user has created.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    pub async fn get_of_user(
+        conn: &crate::DbConnection,
+        uid: String,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Tag,
+            "SELECT * FROM tag WHERE user_id = ? ORDER BY name",
+            uid
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns all the tags a user has attached to a media.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `media_id` - id of the media
+    pub async fn get_of_media(
+        conn: &crate::DbConnection,
+        uid: String,
+        media_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Tag,
+            r#"SELECT tag.id as "id!", tag.user_id, tag.name FROM tag
+                INNER JOIN media_tag ON media_tag.tag_id = tag.id
+                WHERE tag.user_id = ? AND media_tag.media_id = ?
+                ORDER BY tag.name"#,
+            uid,
+            media_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method returns all of the medias in a library that a user has tagged with a tag.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `library_id` - id of the library we want to filter
+    /// * `tag_id` - id of the tag to filter by
+    pub async fn get_media_of_library(
+        conn: &crate::DbConnection,
+        uid: String,
+        library_id: i64,
+        tag_id: i64,
+    ) -> Result<Vec<Media>, DatabaseError> {
+        Ok(sqlx::query_as!(
+                Media,
+                r#"SELECT media.id, media.library_id, media.name, description, rating, year, added, poster_path, backdrop_path, media_type as "media_type: _"
+                FROM media
+                INNER JOIN media_tag ON media_tag.media_id = media.id
+                INNER JOIN tag ON tag.id = media_tag.tag_id
+                WHERE media.library_id = ? AND tag.user_id = ? AND tag.id = ?
+                ORDER BY media.name"#,
+                library_id,
+                uid,
+                tag_id,
+            )
+            .fetch_all(conn)
+            .await?)
+    }
+
+    /// Method attaches this tag to a media. Tagging a media twice with the same tag is a no-op.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media to tag
+    pub async fn add_to_media(
+        &self,
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO media_tag (tag_id, media_id) VALUES ($1, $2)",
+            self.id,
+            media_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Method removes a tag from a media. The tag must belong to the user passed in.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `tag_id` - id of the tag to remove
+    /// * `media_id` - id of the media to remove the tag from
+    pub async fn remove_from_media(
+        conn: &crate::DbConnection,
+        uid: String,
+        tag_id: i64,
+        media_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(sqlx::query!(
+            "DELETE FROM media_tag
+            WHERE media_id = ?
+            AND tag_id IN (SELECT id FROM tag WHERE id = ? AND user_id = ?)",
+            media_id,
+            tag_id,
+            uid
+        )
+        .execute(conn)
+        .await?
+        .rows_affected() as usize)
+    }
+
+    /// Method deletes a tag owned by a user, removing it from every media it was attached to.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `uid` - username of the user
+    /// * `id` - tag id
+    pub async fn delete(
+        conn: &crate::DbConnection,
+        uid: String,
+        id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(
+            sqlx::query!("DELETE FROM tag WHERE id = ? AND user_id = ?", id, uid)
+                .execute(conn)
+                .await?
+                .rows_affected() as usize,
+        )
+    }
+}
+
+/// Tag entry that can be inserted into the db.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct InsertableTag {
+    pub user_id: String,
+    pub name: String,
+}
+
+impl InsertableTag {
+    /// Method inserts a new tag for a user, if the user already has a tag with the same name the
+    /// existing tag is returned instead.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<Tag, DatabaseError> {
+        let tx = conn.begin().await?;
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO tag (user_id, name) VALUES ($1, $2)",
+            self.user_id,
+            self.name
+        )
+        .execute(conn)
+        .await?;
+
+        let tag = sqlx::query_as!(
+            Tag,
+            "SELECT * FROM tag WHERE user_id = ? AND name = ?",
+            self.user_id,
+            self.name
+        )
+        .fetch_one(conn)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(tag)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::album;
+use crate::get_conn_memory;
+use crate::library;
+
+use super::library_tests::test_location;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_get_and_delete() {
+    let ref conn = get_conn_memory().await.unwrap();
+
+    let library_id = library::InsertableLibrary {
+        name: "music".into(),
+        locations: vec![test_location("music")],
+        media_type: library::MediaType::Audio,
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    let result = library::Library::get_one(conn, library_id).await.unwrap();
+    assert_eq!(result.media_type, library::MediaType::Audio);
+    assert_eq!(result.media_type.to_string(), "audio");
+
+    let album_id = album::InsertableAlbum {
+        library_id,
+        name: "TestAlbum".into(),
+        artist: Some("TestArtist".into()),
+        year: Some(2020),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    for (track_number, name) in &[(2, "Second"), (1, "First")] {
+        album::InsertableTrack {
+            album_id,
+            name: name.to_string(),
+            track_number: Some(*track_number),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    let result = album::Album::get_all(conn, library_id).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].name, "TestAlbum");
+
+    let result = album::Album::get_one(conn, album_id).await.unwrap();
+    assert_eq!(result.artist.as_deref(), Some("TestArtist"));
+
+    let tracks = album::Track::get_all(conn, album_id).await.unwrap();
+    let names = tracks.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["First", "Second"]);
+
+    let result = album::Track::get_one(conn, tracks[0].id).await.unwrap();
+    assert_eq!(result, tracks[0]);
+
+    let rows = album::Album::delete(conn, album_id).await.unwrap();
+    assert_eq!(rows, 1);
+
+    assert!(album::Album::get_one(conn, album_id).await.is_err());
+    assert!(album::Track::get_all(conn, album_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -77,3 +77,57 @@ async fn test_delete() {
     let result = genre::Genre::get_by_id(conn, id).await;
     assert!(result.is_err());
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_distribution() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let other = create_test_library(conn).await;
+
+    let action = insert_genre(conn, "Action".into()).await;
+    let drama = insert_genre(conn, "Drama".into()).await;
+
+    let insert = |library_id: i64, name: &'static str, genres: Vec<i64>| async move {
+        let media_id = media::InsertableMedia {
+            library_id,
+            name: name.into(),
+            added: "Test".into(),
+            media_type: library::MediaType::Movie,
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        for genre_id in genres {
+            genre::InsertableGenreMedia::insert_pair(genre_id, media_id, conn)
+                .await
+                .unwrap();
+        }
+    };
+
+    insert(library, "Movie1", vec![action, drama]).await;
+    insert(library, "Movie2", vec![action]).await;
+    insert(other, "Movie3", vec![drama]).await;
+
+    let result = genre::Genre::get_distribution(conn, library).await.unwrap();
+    assert_eq!(
+        result,
+        &[
+            genre::GenreCount {
+                id: action,
+                name: "Action".into(),
+                count: 2,
+            },
+            genre::GenreCount {
+                id: drama,
+                name: "Drama".into(),
+                count: 1,
+            },
+        ]
+    );
+
+    let result = genre::Genre::get_distribution(conn, other).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].count, 1);
+}
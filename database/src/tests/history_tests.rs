@@ -0,0 +1,41 @@
+use crate::get_conn_memory;
+use crate::history;
+use crate::media;
+use crate::progress;
+
+use super::library_tests::create_test_library;
+use super::media_tests;
+use super::user_tests::insert_user;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_recent_across_libraries() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+
+    media_tests::insert_many(conn, 3).await;
+    let medias = media::Media::get_all(conn, library).await.unwrap();
+
+    let result = history::History::recent_across_libraries(conn, user.clone(), 10)
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+
+    progress::Progress::set(conn, 10, user.clone(), medias[0].id)
+        .await
+        .unwrap();
+    progress::Progress::set(conn, 20, user.clone(), medias[1].id)
+        .await
+        .unwrap();
+
+    let mut result = history::History::recent_across_libraries(conn, user.clone(), 10)
+        .await
+        .unwrap();
+    result.sort();
+    assert_eq!(result, &[medias[0].id, medias[1].id]);
+
+    let result = history::History::recent_across_libraries(conn, user, 1)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+}
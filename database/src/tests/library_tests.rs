@@ -1,13 +1,38 @@
+use crate::episode;
 use crate::get_conn_memory;
 use crate::library;
+use crate::media;
+use crate::mediafile;
+use crate::note;
+use crate::progress;
+use crate::season;
+use crate::trailer;
+use crate::tv;
+
+use super::media_tests::count_dependents;
+use super::media_tests::insert_dependents;
+use super::media_tests::insert_many;
+use super::media_tests::insert_media;
+use super::user_tests::insert_user;
+
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 
+/// Creates a directory for a library location under the temp dir and returns its canonical path.
+pub fn test_location(name: &str) -> String {
+    let path = std::env::temp_dir().join("dim_test_locations").join(name);
+    std::fs::create_dir_all(&path).unwrap();
+    path.canonicalize().unwrap().to_string_lossy().to_string()
+}
+
 pub async fn create_test_library(conn: &crate::DbConnection) -> i64 {
     static _LIB: AtomicU64 = AtomicU64::new(0);
     let lib = library::InsertableLibrary {
         name: format!("test{}", _LIB.load(Ordering::Relaxed)),
-        location: format!("/dev/null{}", _LIB.load(Ordering::Relaxed)),
+        locations: vec![test_location(&format!(
+            "null{}",
+            _LIB.load(Ordering::Relaxed)
+        ))],
         media_type: library::MediaType::Movie,
     };
 
@@ -22,6 +47,66 @@ async fn test_insert() {
     assert_eq!(id, 1);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_invalid_locations() {
+    let conn = get_conn_memory().await.unwrap();
+
+    let invalid = [
+        vec![test_location("dup"), test_location("dup")],
+        vec![test_location("nested"), test_location("nested/4k")],
+        vec![test_location("nested/4k"), test_location("nested")],
+        vec![test_location("exists"), "/dev/null/missing".into()],
+    ];
+
+    for locations in invalid.iter() {
+        let lib = library::InsertableLibrary {
+            name: "test_invalid".into(),
+            locations: locations.clone(),
+            media_type: library::MediaType::Movie,
+        };
+
+        let result = lib.insert(&conn).await;
+        assert!(matches!(
+            result,
+            Err(crate::DatabaseError::InvalidLocation(_))
+        ));
+    }
+
+    // nothing was written for the rejected libraries.
+    assert!(library::Library::get_all(&conn).await.is_empty());
+
+    let lib = library::InsertableLibrary {
+        name: "test_canonical".into(),
+        locations: vec![format!("{}/../canonical", test_location("canonical"))],
+        media_type: library::MediaType::Movie,
+    };
+
+    let id = lib.insert(&conn).await.unwrap();
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.locations, &[test_location("canonical")]);
+}
+
+#[test]
+fn test_media_type_from_str() {
+    use library::MediaType;
+    use std::str::FromStr;
+
+    let types = [
+        MediaType::Movie,
+        MediaType::Tv,
+        MediaType::Episode,
+        MediaType::Mixed,
+        MediaType::Audio,
+    ];
+
+    for t in types {
+        assert_eq!(MediaType::from_str(&t.to_string()), Ok(t));
+    }
+
+    assert!(MediaType::from_str("Movie").is_err());
+    assert!(MediaType::from_str("show").is_err());
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_get_one() {
     let conn = get_conn_memory().await.unwrap();
@@ -32,6 +117,178 @@ async fn test_get_one() {
     assert_eq!(result.media_type, library::MediaType::Movie);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_by_name() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    let name = library::Library::get_one(&conn, id).await.unwrap().name;
+
+    let result = library::Library::get_by_name(&conn, &name).await.unwrap();
+    assert_eq!(result.id, id);
+    assert_eq!(result.name, name);
+
+    let result = library::Library::get_by_name(&conn, "doesnt exist").await;
+    assert!(matches!(
+        result,
+        Err(crate::DatabaseError::DatabaseError(
+            sqlx::Error::RowNotFound
+        ))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_update_name() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    let other = create_test_library(&conn).await;
+    let taken = library::Library::get_one(&conn, other).await.unwrap().name;
+
+    library::Library::update_name(&conn, id, " Movies ")
+        .await
+        .unwrap();
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.name, "Movies");
+
+    for name in ["", "   ", taken.to_uppercase().as_str()] {
+        let result = library::Library::update_name(&conn, id, name).await;
+        assert!(matches!(result, Err(crate::DatabaseError::InvalidName(_))));
+    }
+
+    // renaming a library to its own name in another case is allowed.
+    library::Library::update_name(&conn, id, "movies")
+        .await
+        .unwrap();
+
+    let result = library::Library::update_name(&conn, 1234, "Shows").await;
+    assert!(matches!(
+        result,
+        Err(crate::DatabaseError::DatabaseError(
+            sqlx::Error::RowNotFound
+        ))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_add_remove_location() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    let first = library::Library::get_one(&conn, id)
+        .await
+        .unwrap()
+        .locations[0]
+        .clone();
+    let second = test_location("add_remove_location");
+
+    let result = library::Library::add_location(&conn, id, &second)
+        .await
+        .unwrap();
+    assert_eq!(result, second);
+
+    let nested = test_location("add_remove_location/nested");
+    for location in [
+        first.as_str(),
+        second.as_str(),
+        nested.as_str(),
+        "/doesnt/exist",
+    ] {
+        let result = library::Library::add_location(&conn, id, location).await;
+        assert!(matches!(
+            result,
+            Err(crate::DatabaseError::InvalidLocation(_))
+        ));
+    }
+
+    insert_many(&conn, 2).await;
+
+    let tv = insert_media(&conn).await;
+    tv::TVShow::insert(&conn, tv).await.unwrap();
+    let season = season::InsertableSeason {
+        season_number: 1,
+        ..Default::default()
+    }
+    .insert(&conn, tv)
+    .await
+    .unwrap();
+    let episode = episode::InsertableEpisode {
+        media: media::InsertableMedia {
+            library_id: id,
+            name: "TestEpisode".into(),
+            ..Default::default()
+        },
+        seasonid: season,
+        episode: 1,
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    for (media_id, location) in [(1, &first), (1, &second), (2, &second), (episode, &second)] {
+        let mfile = mediafile::InsertableMediaFile {
+            library_id: id,
+            media_id: Some(media_id),
+            target_file: format!("{}/file{}.mkv", location, media_id),
+            raw_name: "Test".into(),
+            ..Default::default()
+        };
+
+        mfile.insert(&conn).await.unwrap();
+    }
+
+    let user = insert_user(&conn).await;
+    for media_id in [1, 2, episode] {
+        insert_dependents(&conn, &user, media_id).await;
+    }
+
+    let result = library::Library::remove_location(&conn, id, &second)
+        .await
+        .unwrap();
+    assert_eq!(result, 3);
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.locations, vec![first.clone()]);
+
+    // media 1 still has a file in the first location.
+    assert!(media::Media::get(&conn, 1).await.is_ok());
+    assert_eq!(count_dependents(&conn, 1).await, 7);
+
+    assert!(media::Media::get(&conn, 2).await.is_err());
+    assert_eq!(count_dependents(&conn, 2).await, 0);
+
+    // the show lost its only episode, thus its season and the show itself are purged.
+    assert!(episode::Episode::get_by_id(&conn, episode).await.is_err());
+    assert_eq!(count_dependents(&conn, episode).await, 0);
+    assert!(season::Season::get_by_id(&conn, season).await.is_err());
+    assert!(media::Media::get(&conn, tv).await.is_err());
+
+    for location in [first.as_str(), second.as_str()] {
+        let result = library::Library::remove_location(&conn, id, location).await;
+        assert!(matches!(
+            result,
+            Err(crate::DatabaseError::InvalidLocation(_))
+        ));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_media_count() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    let empty = create_test_library(&conn).await;
+
+    insert_many(&conn, 4).await;
+
+    assert_eq!(library::Library::media_count(&conn, id).await.unwrap(), 4);
+    assert_eq!(
+        library::Library::media_count(&conn, empty).await.unwrap(),
+        0
+    );
+
+    let result = library::Library::media_counts_all(&conn).await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[&id], 4);
+    assert_eq!(result[&empty], 0);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_get_all() {
     let conn = get_conn_memory().await.unwrap();
@@ -44,6 +301,34 @@ async fn test_get_all() {
     assert_eq!(result.len(), 10);
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_paginated() {
+    use crate::media::SortOrder;
+    use library::LibraryOrder;
+
+    let conn = get_conn_memory().await.unwrap();
+    for _ in 0..5 {
+        create_test_library(&conn).await;
+    }
+
+    let ids = |x: Vec<library::Library>| x.into_iter().map(|x| x.id).collect::<Vec<_>>();
+
+    let result = library::Library::get_paginated(&conn, 1, 2, LibraryOrder::Id(SortOrder::Desc))
+        .await
+        .unwrap();
+    assert_eq!(ids(result), vec![4, 3]);
+
+    let result = library::Library::get_paginated(&conn, 3, 10, LibraryOrder::Id(SortOrder::Asc))
+        .await
+        .unwrap();
+    assert_eq!(ids(result), vec![4, 5]);
+
+    let result = library::Library::get_paginated(&conn, 10, 10, LibraryOrder::default())
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_delete() {
     let conn = get_conn_memory().await.unwrap();
@@ -51,6 +336,446 @@ async fn test_delete() {
 
     library::Library::get_one(&conn, id).await.unwrap();
 
-    let rows = library::Library::delete(&conn, id).await.unwrap();
+    let result = library::Library::delete(&conn, id).await.unwrap();
+    assert_eq!(result.libraries, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_report() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+    insert_many(&conn, 3).await;
+
+    for media in media::Media::get_all(&conn, id).await.unwrap() {
+        mediafile::InsertableMediaFile {
+            library_id: id,
+            target_file: format!("/dev/null/delete_report/{}", media.id),
+            raw_name: "Test".into(),
+            media_id: Some(media.id),
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    let result = library::Library::delete(&conn, id).await.unwrap();
+    assert_eq!(
+        result,
+        library::DeletedLibrary {
+            libraries: 1,
+            media: 3,
+            mediafiles: 3,
+            locations: 1,
+        }
+    );
+
+    assert!(library::Library::get_one(&conn, id).await.is_err());
+    assert!(media::Media::get_all(&conn, id).await.unwrap().is_empty());
+    assert!(mediafile::MediaFile::get_by_lib(&conn, id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let result = library::Library::delete(&conn, id).await.unwrap();
+    assert_eq!(result, library::DeletedLibrary::default());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_user_data() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+    insert_many(conn, 1).await;
+
+    let media = media::Media::get_all(conn, id).await.unwrap().remove(0);
+
+    progress::Progress::set(conn, 100, user.clone(), media.id)
+        .await
+        .unwrap();
+    note::Note::set(conn, user.clone(), media.id, "Test".into())
+        .await
+        .unwrap();
+    trailer::InsertableTrailer {
+        media_id: media.id,
+        name: None,
+        url: "https://example.com/trailer".into(),
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    library::Library::delete(conn, id).await.unwrap();
+
+    assert!(progress::Progress::get(conn, &user, media.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(note::Note::get(conn, user, media.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(trailer::Trailer::get_of_media(conn, media.id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_all_with_locations() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let lib = library::InsertableLibrary {
+        name: "test_multiple_locations".into(),
+        locations: vec![test_location("a"), test_location("b"), test_location("c")],
+        media_type: library::MediaType::Tv,
+    };
+
+    let multi_id = lib.insert(&conn).await.unwrap();
+
+    let lib = library::InsertableLibrary {
+        name: "test_no_locations".into(),
+        locations: vec![],
+        media_type: library::MediaType::Movie,
+    };
+
+    let empty_id = lib.insert(&conn).await.unwrap();
+
+    let result = library::Library::get_all_with_locations(&conn)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 3);
+
+    let find = |id| result.iter().find(|x| x.id == id).unwrap();
+    assert_eq!(find(id).locations.len(), 1);
+    assert_eq!(find(multi_id).locations.len(), 3);
+    assert!(find(empty_id).locations.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_media_types() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let result = library::Library::get_media_types(&conn, id).await.unwrap();
+    assert!(result.is_empty());
+
+    insert_many(&conn, 3).await;
+
+    let result = library::Library::get_media_types(&conn, id).await.unwrap();
+    assert_eq!(result, &[library::MediaType::Movie]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_years() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(conn).await;
+
+    let result = library::Library::get_years(conn, id).await.unwrap();
+    assert!(result.is_empty());
+
+    let medias = [
+        (Some(2020), library::MediaType::Movie),
+        (Some(1999), library::MediaType::Movie),
+        (Some(2020), library::MediaType::Tv),
+        (None, library::MediaType::Movie),
+        (Some(2005), library::MediaType::Episode),
+    ];
+
+    for (i, (year, media_type)) in medias.iter().enumerate() {
+        media::InsertableMedia {
+            library_id: id,
+            name: format!("TestMedia{}", i),
+            year: *year,
+            media_type: *media_type,
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    let result = library::Library::get_years(conn, id).await.unwrap();
+    assert_eq!(result, &[1999, 2020]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_runtimes() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(conn).await;
+
+    insert_many(conn, 3).await;
+    let medias = media::Media::get_all(conn, id).await.unwrap();
+
+    let durations = [Some(100), Some(50), None];
+    for (i, (media, duration)) in medias.iter().zip(durations.iter()).enumerate() {
+        mediafile::InsertableMediaFile {
+            library_id: id,
+            media_id: Some(media.id),
+            target_file: format!("/dev/null/{}", i),
+            raw_name: media.name.clone(),
+            duration: *duration,
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    let result = library::Library::get_runtimes(conn).await.unwrap();
+    assert_eq!(
+        result,
+        &[library::LibraryRuntime {
+            library_id: id,
+            runtime: 150,
+        }]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_update() {
+    let conn = get_conn_memory().await.unwrap();
+
+    let lib = library::InsertableLibrary {
+        name: "test_update".into(),
+        locations: vec![test_location("a"), test_location("b")],
+        media_type: library::MediaType::Movie,
+    };
+
+    let id = lib.insert(&conn).await.unwrap();
+
+    let media = insert_media(&conn).await;
+    mediafile::InsertableMediaFile {
+        library_id: id,
+        media_id: Some(media),
+        target_file: format!("{}/file.mkv", test_location("a")),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    let update = library::InsertableLibrary {
+        name: " test_update2 ".into(),
+        locations: vec![test_location("b"), test_location("c")],
+        media_type: library::MediaType::Tv,
+    };
+
+    let result = library::Library::update(&conn, id, update.clone())
+        .await
+        .unwrap();
+    assert_eq!(result.id, id);
+    assert_eq!(result.name, "test_update2");
+    assert_eq!(result.media_type, library::MediaType::Tv);
+
+    let mut locations = result.locations;
+    locations.sort();
+    assert_eq!(locations, &[test_location("b"), test_location("c")]);
+
+    // the media only had a file in the removed location.
+    assert!(media::Media::get(&conn, media).await.is_err());
+
+    let other = library::InsertableLibrary {
+        name: "test_update_other".into(),
+        locations: vec![test_location("d")],
+        media_type: library::MediaType::Movie,
+    };
+
+    other.insert(&conn).await.unwrap();
+
+    let invalid = [
+        library::InsertableLibrary {
+            name: "Test_Update_Other".into(),
+            locations: vec![test_location("e")],
+            media_type: library::MediaType::Movie,
+        },
+        library::InsertableLibrary {
+            name: "test_update3".into(),
+            locations: vec![test_location("d/nested")],
+            media_type: library::MediaType::Movie,
+        },
+        library::InsertableLibrary {
+            name: "test_update3".into(),
+            locations: vec![test_location("e"), "/doesnt/exist".into()],
+            media_type: library::MediaType::Movie,
+        },
+    ];
+
+    for update in invalid {
+        let result = library::Library::update(&conn, id, update).await;
+        assert!(matches!(
+            result,
+            Err(crate::DatabaseError::InvalidName(_))
+                | Err(crate::DatabaseError::InvalidLocation(_))
+        ));
+    }
+
+    // failed updates dont change anything.
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.name, "test_update2");
+    assert_eq!(result.media_type, library::MediaType::Tv);
+    assert_eq!(result.locations.len(), 2);
+
+    let result = library::Library::update(&conn, 1234, update).await;
+    assert!(matches!(
+        result,
+        Err(crate::DatabaseError::DatabaseError(
+            sqlx::Error::RowNotFound
+        ))
+    ));
+
+    let locations =
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM indexed_paths WHERE library_id = 1234")
+            .fetch_one(&conn)
+            .await
+            .unwrap();
+    assert_eq!(locations, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_last_scan() {
+    let conn = get_conn_memory().await.unwrap();
+    let id = create_test_library(&conn).await;
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert!(result.last_scan.is_none());
+
+    let last_scan = library::LastScan {
+        finished_at: 1628697600,
+        duration: 42,
+        items: 128,
+    };
+
+    let rows = library::Library::set_last_scan(&conn, id, &last_scan)
+        .await
+        .unwrap();
     assert_eq!(rows, 1);
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.last_scan, Some(last_scan));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_validate() {
+    let conn = get_conn_memory().await.unwrap();
+
+    let root = std::env::temp_dir().join(format!("dim_test_validate_{}", std::process::id()));
+    std::fs::create_dir_all(root.join("a").join("b")).unwrap();
+    std::fs::create_dir_all(root.join("c")).unwrap();
+
+    let path = |x: &str| root.join(x).to_string_lossy().to_string();
+
+    library::InsertableLibrary {
+        name: "Movies".into(),
+        locations: vec![path("a")],
+        media_type: library::MediaType::Movie,
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    let lib = library::InsertableLibrary {
+        name: "movies".into(),
+        locations: vec![path("a/b"), path("c"), path("c"), path("missing")],
+        media_type: library::MediaType::Movie,
+    };
+
+    let errors = lib.validate(&conn).await.unwrap();
+    let fields = errors.keys().map(String::as_str).collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        &["locations.0", "locations.2", "locations.3", "name"]
+    );
+
+    let lib = library::InsertableLibrary {
+        name: "Shows".into(),
+        locations: vec![path("c")],
+        media_type: library::MediaType::Tv,
+    };
+
+    assert!(lib.validate(&conn).await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_order() {
+    let conn = get_conn_memory().await.unwrap();
+    let first = create_test_library(&conn).await;
+    let second = create_test_library(&conn).await;
+    let third = create_test_library(&conn).await;
+
+    let ids = |x: Vec<library::Library>| x.into_iter().map(|x| x.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(library::Library::get_all(&conn).await),
+        &[first, second, third]
+    );
+
+    let rows = library::Library::set_order(&conn, &[third, 1337, first])
+        .await
+        .unwrap();
+    assert_eq!(rows, 3);
+
+    assert_eq!(
+        ids(library::Library::get_all(&conn).await),
+        &[third, first, second]
+    );
+    assert_eq!(
+        ids(library::Library::get_all_with_locations(&conn)
+            .await
+            .unwrap()),
+        &[third, first, second]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mixed_library() {
+    let conn = get_conn_memory().await.unwrap();
+
+    let lib = library::InsertableLibrary {
+        name: "test_mixed".into(),
+        locations: vec![test_location("mixed")],
+        media_type: library::MediaType::Mixed,
+    };
+
+    let id = lib.insert(&conn).await.unwrap();
+
+    let result = library::Library::get_one(&conn, id).await.unwrap();
+    assert_eq!(result.media_type, library::MediaType::Mixed);
+
+    let result = library::Library::get_all(&conn).await;
+    assert_eq!(result[0].media_type, library::MediaType::Mixed);
+
+    for media_type in &[library::MediaType::Movie, library::MediaType::Tv] {
+        media::InsertableMedia {
+            library_id: id,
+            name: media_type.to_string(),
+            media_type: *media_type,
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+    }
+
+    let result = library::Library::get_media_types(&conn, id).await.unwrap();
+    assert_eq!(result, &[library::MediaType::Movie, library::MediaType::Tv]);
+
+    let result = media::Media::random(&conn, id, library::MediaType::Mixed, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(result.media_type, library::MediaType::Mixed);
+
+    assert_eq!(library::MediaType::Mixed.to_string(), "mixed");
+    assert_eq!(
+        serde_json::to_string(&library::MediaType::Mixed).unwrap(),
+        "\"mixed\""
+    );
+    assert_eq!(
+        serde_json::from_str::<library::MediaType>("\"movie\"").unwrap(),
+        library::MediaType::Movie
+    );
 }
@@ -0,0 +1,245 @@
+use crate::episode;
+use crate::get_conn_memory;
+use crate::library;
+use crate::marker;
+use crate::media;
+use crate::mediafile;
+use crate::season;
+use crate::tv;
+
+use super::library_tests::create_test_library;
+use super::media_tests::insert_media;
+
+use marker::MarkerKind;
+use marker::MarkerSource;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_get_and_delete() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(conn).await;
+    let media_id = insert_media(conn).await;
+
+    let result = marker::Marker::get_of_media(conn, media_id).await.unwrap();
+    assert!(result.is_empty());
+
+    for (kind, start_ms, end_ms) in [
+        (MarkerKind::Credits, 1_300_000, 1_400_000),
+        (MarkerKind::Intro, 30_000, 90_000),
+        (MarkerKind::Intro, 35_000, 95_000),
+    ] {
+        let rows = marker::InsertableMarker {
+            media_id,
+            kind,
+            start_ms,
+            end_ms,
+            source: MarkerSource::Manual,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    let result = marker::Marker::get_of_media(conn, media_id).await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].kind, MarkerKind::Intro);
+    assert_eq!((result[0].start_ms, result[0].end_ms), (35_000, 95_000));
+    assert_eq!(result[1].kind, MarkerKind::Credits);
+
+    let rows = marker::Marker::delete(conn, media_id, MarkerKind::Credits)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let result = marker::Marker::get_of_media(conn, media_id).await.unwrap();
+    assert_eq!(result.len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_detected_keeps_manual() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(conn).await;
+    let media_id = insert_media(conn).await;
+
+    let detected = marker::InsertableMarker {
+        media_id,
+        kind: MarkerKind::Intro,
+        start_ms: 10_000,
+        end_ms: 40_000,
+        source: MarkerSource::Detected,
+    };
+
+    assert_eq!(detected.insert(conn).await.unwrap(), 1);
+    // detected markers replace each other.
+    let redetected = marker::InsertableMarker {
+        start_ms: 12_000,
+        ..detected.clone()
+    };
+    assert_eq!(redetected.insert(conn).await.unwrap(), 1);
+
+    let manual = marker::InsertableMarker {
+        start_ms: 0,
+        source: MarkerSource::Manual,
+        ..detected.clone()
+    };
+    assert_eq!(manual.insert(conn).await.unwrap(), 1);
+    assert_eq!(detected.insert(conn).await.unwrap(), 0);
+
+    let result = marker::Marker::get_of_media(conn, media_id).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].start_ms, 0);
+    assert_eq!(result[0].source, MarkerSource::Manual);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_missing_intros() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+
+    let season = season::InsertableSeason {
+        season_number: 1,
+        ..Default::default()
+    }
+    .insert(conn, tv)
+    .await
+    .unwrap();
+
+    let mut episodes = vec![];
+    for i in 1..=3 {
+        let id = episode::InsertableEpisode {
+            media: media::InsertableMedia {
+                library_id: library,
+                name: format!("TestEpisode{}", i),
+                ..Default::default()
+            },
+            seasonid: season,
+            episode: i,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        mediafile::InsertableMediaFile {
+            library_id: library,
+            media_id: Some(id),
+            target_file: format!("/episode{}.mkv", i),
+            raw_name: "Test".into(),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        episodes.push(id);
+    }
+
+    let result = marker::Marker::get_missing_intros(conn, library)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 3);
+    assert_eq!(result[0].season_id, season);
+    assert_eq!(result[0].media_id, episodes[0]);
+    assert_eq!(result[2].target_file, "/episode3.mkv".to_string());
+
+    marker::InsertableMarker {
+        media_id: episodes[1],
+        kind: MarkerKind::Intro,
+        start_ms: 0,
+        end_ms: 30_000,
+        source: MarkerSource::Detected,
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    // credit markers dont count.
+    marker::InsertableMarker {
+        media_id: episodes[2],
+        kind: MarkerKind::Credits,
+        start_ms: 1_300_000,
+        end_ms: 1_400_000,
+        source: MarkerSource::Manual,
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    let result = marker::Marker::get_missing_intros(conn, library)
+        .await
+        .unwrap();
+    assert_eq!(
+        result.iter().map(|x| x.media_id).collect::<Vec<_>>(),
+        vec![episodes[0], episodes[2]]
+    );
+
+    let rows = marker::Marker::set_intro_attempted(conn, episodes[0])
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    // attempts are only recorded once.
+    let rows = marker::Marker::set_intro_attempted(conn, episodes[0])
+        .await
+        .unwrap();
+    assert_eq!(rows, 0);
+
+    let result = marker::Marker::get_missing_intros(conn, library)
+        .await
+        .unwrap();
+    assert_eq!(
+        result.iter().map(|x| x.media_id).collect::<Vec<_>>(),
+        vec![episodes[2]]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_with_media() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let lib = create_test_library(conn).await;
+    let mut ids = vec![];
+
+    for name in ["Deleted", "DeletedWithLibrary"] {
+        let media_id = media::InsertableMedia {
+            library_id: lib,
+            name: name.into(),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        marker::InsertableMarker {
+            media_id,
+            kind: MarkerKind::Intro,
+            start_ms: 0,
+            end_ms: 30_000,
+            source: MarkerSource::Manual,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        ids.push(media_id);
+    }
+
+    media::Media::delete(conn, ids[0]).await.unwrap();
+    assert!(marker::Marker::get_of_media(conn, ids[0])
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        marker::Marker::get_of_media(conn, ids[1])
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    library::Library::delete(conn, lib).await.unwrap();
+    assert!(marker::Marker::get_of_media(conn, ids[1])
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -1,10 +1,20 @@
+use crate::episode;
+use crate::genre;
 use crate::get_conn_memory;
 use crate::library;
+use crate::marker;
 use crate::media;
 use crate::mediafile;
+use crate::note;
+use crate::progress;
+use crate::season;
+use crate::tag;
+use crate::trailer;
+use crate::tv;
 
 use super::library_tests::create_test_library;
 use super::mediafile_tests::insert_mediafile_with_mediaid;
+use super::user_tests::insert_user;
 
 pub async fn insert_media(conn: &crate::DbConnection) -> i64 {
     let media = media::InsertableMedia {
@@ -22,6 +32,79 @@ pub async fn insert_media(conn: &crate::DbConnection) -> i64 {
     media.insert(conn).await.unwrap()
 }
 
+/// Inserts progress, a note, a tag, a trailer, a genre and a marker for a media.
+pub async fn insert_dependents(conn: &crate::DbConnection, user: &str, media_id: i64) {
+    progress::Progress::set(conn, 100, user.into(), media_id)
+        .await
+        .unwrap();
+    note::Note::set(conn, user.into(), media_id, "Test".into())
+        .await
+        .unwrap();
+    tag::InsertableTag {
+        user_id: user.into(),
+        name: "Test".into(),
+    }
+    .insert(conn)
+    .await
+    .unwrap()
+    .add_to_media(conn, media_id)
+    .await
+    .unwrap();
+    trailer::InsertableTrailer {
+        media_id,
+        name: None,
+        url: "https://example.com/trailer".into(),
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    let genre_id = genre::InsertableGenre {
+        name: "Test".into(),
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    genre::InsertableGenreMedia { genre_id, media_id }
+        .insert(conn)
+        .await;
+
+    marker::InsertableMarker {
+        media_id,
+        end_ms: 1000,
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+}
+
+/// Returns how many rows reference a media across the mediafiles and the tables filled by
+/// [`insert_dependents`].
+pub async fn count_dependents(conn: &crate::DbConnection, media_id: i64) -> i64 {
+    let mut count = 0;
+    for table in [
+        "progress",
+        "note",
+        "media_tag",
+        "media_trailers",
+        "genre_media",
+        "marker",
+        "mediafile",
+    ] {
+        count += sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM {} WHERE media_id = ?",
+            table
+        ))
+        .bind(media_id)
+        .fetch_one(conn)
+        .await
+        .unwrap();
+    }
+
+    count
+}
+
 pub async fn insert_many(conn: &crate::DbConnection, n: usize) {
     for i in 0..n {
         let media = media::InsertableMedia {
@@ -109,6 +192,78 @@ async fn test_delete() {
     assert!(result.is_err());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_dependents() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+
+    let movie = insert_media(conn).await;
+    mediafile::InsertableMediaFile {
+        library_id,
+        media_id: Some(movie),
+        target_file: "/dev/null/movie".into(),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    insert_dependents(conn, &user, movie).await;
+
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+    insert_dependents(conn, &user, tv).await;
+
+    let season = season::InsertableSeason {
+        season_number: 1,
+        ..Default::default()
+    }
+    .insert(conn, tv)
+    .await
+    .unwrap();
+
+    let episode = episode::InsertableEpisode {
+        media: media::InsertableMedia {
+            library_id,
+            name: "TestEpisode".into(),
+            ..Default::default()
+        },
+        seasonid: season,
+        episode: 1,
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    mediafile::InsertableMediaFile {
+        library_id,
+        media_id: Some(episode),
+        target_file: "/dev/null/episode".into(),
+        raw_name: "Test".into(),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+    insert_dependents(conn, &user, episode).await;
+
+    let result = media::Media::delete(conn, movie).await.unwrap();
+    assert_eq!(result, 1);
+    assert_eq!(count_dependents(conn, movie).await, 0);
+
+    // the episode of the show is untouched by deleting the movie.
+    assert_eq!(count_dependents(conn, episode).await, 7);
+
+    let result = media::Media::delete(conn, tv).await.unwrap();
+    assert_eq!(result, 2);
+    assert_eq!(count_dependents(conn, tv).await, 0);
+    assert_eq!(count_dependents(conn, episode).await, 0);
+
+    assert!(media::Media::get(conn, episode).await.is_err());
+    assert!(episode::Episode::get_by_id(conn, episode).await.is_err());
+    assert!(season::Season::get_by_id(conn, season).await.is_err());
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_delete_by_lib() {
     let ref conn = get_conn_memory().await.unwrap();
@@ -182,3 +337,317 @@ async fn test_update() {
     assert_eq!(result.name, "TestMedia2".to_string());
     assert_eq!(result.rating, Some(5));
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_random() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+
+    let result = media::Media::random(conn, library_id, library::MediaType::Movie, None)
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    insert_many(conn, 5).await;
+
+    let result = media::Media::random(conn, library_id, library::MediaType::Movie, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.library_id, library_id);
+
+    let result = media::Media::random(conn, library_id, library::MediaType::Tv, None)
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    let uid = insert_user(conn).await;
+    let all = media::Media::get_all(conn, library_id).await.unwrap();
+
+    for media in &all[1..] {
+        progress::Progress::set(conn, 100, uid.clone(), media.id)
+            .await
+            .unwrap();
+    }
+
+    let result = media::Media::random(conn, library_id, library::MediaType::Movie, Some(uid))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.id, all[0].id);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_shuffle_next() {
+    use media::ShuffleScope;
+
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let uid = insert_user(conn).await;
+    let scope = ShuffleScope::Library(library_id);
+
+    let result = media::Media::shuffle_next(conn, scope, &uid, None, 3)
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    insert_many(conn, 5).await;
+
+    // media without files cant be played.
+    let result = media::Media::shuffle_next(conn, scope, &uid, None, 3)
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    let all = media::Media::get_all(conn, library_id).await.unwrap();
+    for (i, media) in all.iter().enumerate() {
+        let mfile = mediafile::InsertableMediaFile {
+            library_id,
+            target_file: format!("/dev/null/{}", i),
+            raw_name: "Test".into(),
+            media_id: Some(media.id),
+            ..Default::default()
+        };
+        mfile.insert(conn).await.unwrap();
+    }
+
+    for media in &all[1..] {
+        progress::Progress::set(conn, 100, uid.clone(), media.id)
+            .await
+            .unwrap();
+    }
+
+    for _ in 0..10 {
+        let result = media::Media::shuffle_next(conn, scope, &uid, None, 4)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result.id, all[0].id);
+    }
+
+    // every media was played recently, thus only the last one is skipped.
+    progress::Progress::set(conn, 100, uid.clone(), all[0].id)
+        .await
+        .unwrap();
+
+    for _ in 0..10 {
+        let result = media::Media::shuffle_next(conn, scope, &uid, None, 5)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(result.id, all[0].id);
+    }
+
+    // the media being played is never picked, even when no recent plays are skipped.
+    for _ in 0..10 {
+        let result = media::Media::shuffle_next(conn, scope, &uid, Some(all[1].id), 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(result.id, all[1].id);
+    }
+}
+
+#[test]
+fn test_sort() {
+    let mut all = vec![
+        media::Media {
+            id: 1,
+            name: "B".into(),
+            added: Some("2021-01-01".into()),
+            ..Default::default()
+        },
+        media::Media {
+            id: 2,
+            name: "A".into(),
+            added: Some("2021-03-01".into()),
+            ..Default::default()
+        },
+        media::Media {
+            id: 3,
+            name: "C".into(),
+            added: Some("2021-02-01".into()),
+            ..Default::default()
+        },
+    ];
+
+    let ids = |x: &[media::Media]| x.iter().map(|x| x.id).collect::<Vec<_>>();
+
+    media::SortBy::Name.sort(None, &mut all);
+    assert_eq!(ids(&all), vec![2, 1, 3]);
+
+    media::SortBy::Added.sort(None, &mut all);
+    assert_eq!(ids(&all), vec![2, 3, 1]);
+
+    media::SortBy::Added.sort(Some(media::SortOrder::Asc), &mut all);
+    assert_eq!(ids(&all), vec![1, 3, 2]);
+
+    assert_eq!(
+        media::SortBy::Added.order_clause(None),
+        "ORDER BY added DESC, id DESC"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_neighbors() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    insert_many(conn, 3).await;
+
+    let all = media::Media::get_all(conn, library_id).await.unwrap();
+    let id_of = |name: &str| all.iter().find(|x| x.name == name).unwrap().id;
+
+    let (prev, next) = media::Media::neighbors(
+        conn,
+        library_id,
+        id_of("TestMedia1"),
+        media::SortBy::Name,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(prev.unwrap().name, "TestMedia0");
+    assert_eq!(next.unwrap().name, "TestMedia2");
+
+    let (prev, next) = media::Media::neighbors(
+        conn,
+        library_id,
+        id_of("TestMedia0"),
+        media::SortBy::Name,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(prev.is_none());
+    assert_eq!(next.unwrap().name, "TestMedia1");
+
+    let (prev, next) = media::Media::neighbors(
+        conn,
+        library_id,
+        id_of("TestMedia0"),
+        media::SortBy::Name,
+        Some(media::SortOrder::Desc),
+    )
+    .await
+    .unwrap();
+    assert_eq!(prev.unwrap().name, "TestMedia1");
+    assert!(next.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_tmdb_id() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let id = insert_media(conn).await;
+
+    let result = media::Media::get_id_by_tmdb_id(conn, library_id, 603, library::MediaType::Movie)
+        .await
+        .unwrap();
+    assert!(result.is_none());
+    assert_eq!(media::Media::get_tmdb_id(conn, id).await.unwrap(), None);
+
+    media::Media::set_tmdb_id(conn, id, 603).await.unwrap();
+    let rows = media::Media::set_tmdb_id(conn, id, 604).await.unwrap();
+    assert_eq!(rows, 0);
+    assert_eq!(
+        media::Media::get_tmdb_id(conn, id).await.unwrap(),
+        Some(603)
+    );
+
+    let copy = media::InsertableMedia {
+        library_id,
+        name: "TestMedia".into(),
+        media_type: library::MediaType::Movie,
+        ..Default::default()
+    }
+    .insert_with_tmdb_id(conn, 603)
+    .await
+    .unwrap();
+    assert_ne!(copy, id);
+
+    let result = media::Media::get_id_by_tmdb_id(conn, library_id, 603, library::MediaType::Movie)
+        .await
+        .unwrap();
+    assert_eq!(result, Some(id));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_search() {
+    use media::SearchFilters;
+
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let other_library = create_test_library(conn).await;
+
+    for (library_id, name, media_type) in [
+        (library_id, "The Batman", library::MediaType::Movie),
+        (library_id, "Batman", library::MediaType::Movie),
+        (library_id, "Batman Begins", library::MediaType::Movie),
+        (
+            other_library,
+            "Batman: The Animated Series",
+            library::MediaType::Tv,
+        ),
+        (library_id, "100% Batman", library::MediaType::Movie),
+        (library_id, "Superman", library::MediaType::Movie),
+    ] {
+        media::InsertableMedia {
+            library_id,
+            name: name.into(),
+            added: "Test".into(),
+            media_type,
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    let names = |x: Vec<media::Media>| x.into_iter().map(|x| x.name).collect::<Vec<_>>();
+
+    let result = media::Media::search(conn, "batman", Default::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        names(result),
+        vec![
+            "Batman",
+            "Batman Begins",
+            "Batman: The Animated Series",
+            "100% Batman",
+            "The Batman"
+        ]
+    );
+
+    let filters = SearchFilters {
+        media_type: Some(library::MediaType::Tv),
+        ..Default::default()
+    };
+    let result = media::Media::search(conn, "BATMAN", filters).await.unwrap();
+    assert_eq!(names(result), vec!["Batman: The Animated Series"]);
+
+    let filters = SearchFilters {
+        library_id: Some(library_id),
+        limit: Some(2),
+        offset: 1,
+        ..Default::default()
+    };
+    let result = media::Media::search(conn, "batman", filters).await.unwrap();
+    assert_eq!(names(result), vec!["Batman Begins", "100% Batman"]);
+
+    let result = media::Media::search(conn, "%", Default::default())
+        .await
+        .unwrap();
+    assert_eq!(names(result), vec!["100% Batman"]);
+
+    let result = media::Media::search(conn, "  ", Default::default())
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn test_escape_like() {
+    assert_eq!(media::escape_like("batman"), "batman");
+    assert_eq!(media::escape_like("100%_\\"), "100\\%\\_\\\\");
+}
@@ -111,6 +111,35 @@ async fn test_get_by_file() {
     assert_eq!(result.target_file, "/dev/null".to_string());
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_by_file_nocase() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+    let _ = mediafile::InsertableMediaFile {
+        library_id: 1,
+        target_file: "/media/Movie.mkv".into(),
+        raw_name: "Movie".into(),
+        ..Default::default()
+    }
+    .insert(&conn)
+    .await
+    .unwrap();
+
+    let result = mediafile::MediaFile::get_by_file_nocase(&conn, "/MEDIA/movie.MKV")
+        .await
+        .unwrap();
+    assert_eq!(result.target_file, "/media/Movie.mkv".to_string());
+
+    assert!(mediafile::MediaFile::get_by_file(&conn, "/media/movie.mkv")
+        .await
+        .is_err());
+    assert!(
+        mediafile::MediaFile::get_by_file_nocase(&conn, "/media/movie2.mkv")
+            .await
+            .is_err()
+    );
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_deletes() {
     let conn = get_conn_memory().await.unwrap();
@@ -160,3 +189,166 @@ async fn test_get_of_media() {
     assert_eq!(result[0].media_id, Some(media_id));
     assert_eq!(result[0].id, mfile);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_of_media_episode_range() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let lib = create_test_library(conn).await;
+    let tv = super::media_tests::insert_media(conn).await;
+    crate::tv::TVShow::insert(conn, tv).await.unwrap();
+
+    let season = crate::season::InsertableSeason {
+        season_number: 1,
+        ..Default::default()
+    }
+    .insert(conn, tv)
+    .await
+    .unwrap();
+
+    let mut episodes = vec![];
+    for episode in 1..=3 {
+        let id = crate::episode::InsertableEpisode {
+            media: crate::media::InsertableMedia {
+                library_id: lib,
+                name: format!("Episode{}", episode),
+                ..Default::default()
+            },
+            seasonid: season,
+            episode,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+
+        episodes.push(id);
+    }
+
+    let mfile = mediafile::InsertableMediaFile {
+        library_id: lib,
+        target_file: "/dev/null/S01E01-E02".into(),
+        raw_name: "Test".into(),
+        media_id: Some(episodes[0]),
+        episode: Some(1),
+        episode_end: Some(2),
+        season: Some(1),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    for episode in &episodes[..2] {
+        let result = mediafile::MediaFile::get_of_media(conn, *episode)
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, mfile);
+    }
+
+    let result = mediafile::MediaFile::get_of_media(conn, episodes[2])
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_review_queue() {
+    let conn = get_conn_memory().await.unwrap();
+    let lib_id = create_test_library(&conn).await;
+    let media_id = super::media_tests::insert_media(&conn).await;
+    let mfile = insert_mediafile_with_mediaid(&conn, media_id).await;
+    insert_many_mediafile(&conn, 1).await;
+
+    let result = mediafile::MediaFile::get_review_queue(&conn, lib_id)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].media_id, None);
+
+    let update = mediafile::UpdateMediaFile {
+        needs_review: Some(true),
+        ..Default::default()
+    };
+
+    update.update(&conn, mfile).await.unwrap();
+
+    let result = mediafile::MediaFile::get_review_queue(&conn, lib_id)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_next_part() {
+    let conn = get_conn_memory().await.unwrap();
+    let _ = create_test_library(&conn).await;
+    let media_id = super::media_tests::insert_media(&conn).await;
+
+    let mut parts = vec![];
+    for part in 1..=2 {
+        let mfile = mediafile::InsertableMediaFile {
+            library_id: 1,
+            media_id: Some(media_id),
+            target_file: format!("/dev/null/cd{}", part),
+            raw_name: "Test".into(),
+            part: Some(part),
+            ..Default::default()
+        };
+
+        parts.push(mfile.insert(&conn).await.unwrap());
+    }
+
+    let result = mediafile::MediaFile::get_next_part(&conn, media_id, 1)
+        .await
+        .unwrap();
+    assert_eq!(result, Some(parts[1]));
+
+    let result = mediafile::MediaFile::get_next_part(&conn, media_id, 2)
+        .await
+        .unwrap();
+    assert_eq!(result, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cross_library_duplicates() {
+    let conn = get_conn_memory().await.unwrap();
+    let first = create_test_library(&conn).await;
+    let second = create_test_library(&conn).await;
+
+    let mut ids = vec![];
+    for (library_id, target_file) in &[
+        (first, "/media/a.mkv"),
+        (first, "/media/b.mkv"),
+        (second, "/media/a.mkv"),
+    ] {
+        let mfile = mediafile::InsertableMediaFile {
+            library_id: *library_id,
+            target_file: target_file.to_string(),
+            raw_name: "Test".into(),
+            ..Default::default()
+        };
+
+        ids.push(mfile.insert(&conn).await.unwrap());
+    }
+
+    let result = mediafile::MediaFile::get_cross_library_duplicates(&conn)
+        .await
+        .unwrap();
+    let result = result.iter().map(|x| x.id).collect::<Vec<_>>();
+    assert_eq!(result, vec![ids[0], ids[2]]);
+
+    let rows = mediafile::MediaFile::flag_cross_library_duplicates(&conn)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let rows = mediafile::MediaFile::flag_cross_library_duplicates(&conn)
+        .await
+        .unwrap();
+    assert_eq!(rows, 0);
+
+    let result = mediafile::MediaFile::get_one(&conn, ids[0]).await.unwrap();
+    assert!(!result.needs_review);
+    let result = mediafile::MediaFile::get_one(&conn, ids[2]).await.unwrap();
+    assert!(result.needs_review);
+}
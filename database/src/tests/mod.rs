@@ -1,10 +1,16 @@
+pub mod album_tests;
 pub mod episode_tests;
 pub mod genre_tests;
+pub mod history_tests;
 pub mod library_tests;
+pub mod marker_tests;
 pub mod media_tests;
 pub mod mediafile_tests;
 pub mod movie_tests;
+pub mod note_tests;
 pub mod progress_tests;
+pub mod scan_history_tests;
 pub mod season_tests;
+pub mod tag_tests;
 pub mod tv_tests;
 pub mod user_tests;
@@ -0,0 +1,49 @@
+use crate::get_conn_memory;
+use crate::note;
+
+use super::library_tests::create_test_library;
+use super::media_tests::insert_media;
+use super::user_tests::insert_user;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_get_and_delete() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(conn).await;
+    let uid = insert_user(conn).await;
+    let media_id = insert_media(conn).await;
+
+    let result = note::Note::get(conn, uid.clone(), media_id).await.unwrap();
+    assert!(result.is_none());
+
+    note::Note::set(conn, uid.clone(), media_id, "first".into())
+        .await
+        .unwrap();
+    note::Note::set(
+        conn,
+        uid.clone(),
+        media_id,
+        "watch the extended ending".into(),
+    )
+    .await
+    .unwrap();
+
+    let result = note::Note::get(conn, uid.clone(), media_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.content, "watch the extended ending");
+    assert_eq!(result.media_id, media_id);
+
+    let other = note::Note::get(conn, "someone else".into(), media_id)
+        .await
+        .unwrap();
+    assert!(other.is_none());
+
+    let rows = note::Note::delete(conn, uid.clone(), media_id)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let result = note::Note::get(conn, uid, media_id).await.unwrap();
+    assert!(result.is_none());
+}
@@ -1,11 +1,13 @@
 use crate::episode;
 use crate::get_conn_memory;
 use crate::media;
+use crate::mediafile;
 use crate::progress;
 use crate::season;
 use crate::tv;
 
 use super::library_tests::create_test_library;
+use super::media_tests;
 use super::media_tests::insert_media;
 use super::user_tests::insert_user;
 
@@ -186,3 +188,194 @@ async fn test_get_continue_watching() {
     assert_eq!(result.len(), 2);
     assert_eq!(result[0].id, 2);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_delete_for_media() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+    let media = insert_media(conn).await;
+
+    progress::Progress::set(conn, 100, user.clone(), media)
+        .await
+        .unwrap();
+
+    let rows = progress::Progress::delete_for_media(conn, media)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let result = progress::Progress::get_for_media_user(conn, user, media)
+        .await
+        .unwrap();
+    assert_eq!(result.delta, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_watch_status_counts() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+
+    media_tests::insert_many(conn, 3).await;
+    let medias = media::Media::get_all(conn, library).await.unwrap();
+
+    for (i, media) in medias.iter().enumerate() {
+        mediafile::InsertableMediaFile {
+            library_id: library,
+            media_id: Some(media.id),
+            target_file: format!("/dev/null/{}", i),
+            raw_name: media.name.clone(),
+            duration: Some(100),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    progress::Progress::set(conn, 95, user.clone(), medias[0].id)
+        .await
+        .unwrap();
+    progress::Progress::set(conn, 20, user.clone(), medias[1].id)
+        .await
+        .unwrap();
+
+    let result = progress::Progress::get_watch_status_counts(conn, user)
+        .await
+        .unwrap();
+    assert_eq!(
+        result,
+        &[progress::WatchStatusCounts {
+            library_id: library,
+            watched: 1,
+            in_progress: 1,
+            unwatched: 1,
+        }]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_for_library() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+
+    media_tests::insert_many(conn, 3).await;
+    let medias = media::Media::get_all(conn, library).await.unwrap();
+
+    // the last media has no duration and cant be marked as watched.
+    for (i, media) in medias.iter().enumerate() {
+        mediafile::InsertableMediaFile {
+            library_id: library,
+            media_id: Some(media.id),
+            target_file: format!("/dev/null/{}", i),
+            raw_name: media.name.clone(),
+            duration: Some(100).filter(|_| i < 2),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    progress::Progress::set(conn, 20, user.clone(), medias[0].id)
+        .await
+        .unwrap();
+
+    let result = progress::Progress::set_for_library(conn, &user, library, true)
+        .await
+        .unwrap();
+    assert_eq!(result, 2);
+
+    let result = progress::Progress::get_watch_status_counts(conn, user.clone())
+        .await
+        .unwrap();
+    assert_eq!(result[0].watched, 2);
+    assert_eq!(result[0].unwatched, 1);
+
+    let result = progress::Progress::set_for_library(conn, &user, library, false)
+        .await
+        .unwrap();
+    assert_eq!(result, 2);
+
+    let result = progress::Progress::get_watch_status_counts(conn, user)
+        .await
+        .unwrap();
+    assert_eq!(result[0].watched, 0);
+    assert_eq!(result[0].unwatched, 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let _library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+    let media = insert_media(conn).await;
+
+    let result = progress::Progress::get(conn, &user, media).await.unwrap();
+    assert!(result.is_none());
+
+    progress::Progress::set(conn, 100, user.clone(), media)
+        .await
+        .unwrap();
+    progress::Progress::set(conn, 150, user.clone(), media)
+        .await
+        .unwrap();
+
+    let result = progress::Progress::get(conn, &user, media)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.delta, 150);
+    assert_eq!(result.media_id, media);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_continue_watching() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library = create_test_library(conn).await;
+    let user = insert_user(conn).await;
+
+    media_tests::insert_many(conn, 5).await;
+    let medias = media::Media::get_all(conn, library).await.unwrap();
+
+    // the last media has no duration, we cant tell whether it was finished.
+    for (i, media) in medias.iter().enumerate() {
+        mediafile::InsertableMediaFile {
+            library_id: library,
+            media_id: Some(media.id),
+            target_file: format!("/dev/null/{}", i),
+            raw_name: media.name.clone(),
+            duration: Some(100).filter(|_| i < 4),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    for (media, delta) in medias.iter().zip([20, 95, 50, 0, 20]) {
+        progress::Progress::set(conn, delta, user.clone(), media.id)
+            .await
+            .unwrap();
+    }
+
+    // updating the progress moves the media to the front.
+    progress::Progress::set(conn, 30, user.clone(), medias[0].id)
+        .await
+        .unwrap();
+
+    let result = progress::Progress::continue_watching(conn, &user, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| (x.media_id, x.delta))
+        .collect::<Vec<_>>();
+    assert_eq!(result, vec![(medias[0].id, 30), (medias[2].id, 50)]);
+
+    let result = progress::Progress::continue_watching(conn, &user, 1)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+}
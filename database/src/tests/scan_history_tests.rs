@@ -0,0 +1,43 @@
+use crate::get_conn_memory;
+use crate::scan_history;
+
+use super::library_tests::create_test_library;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_and_get_recent() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+
+    let result = scan_history::ScanHistory::get_recent(conn, library_id, 10)
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+
+    for (finished_at, added) in [(100, 40), (200, 3), (150, 0)].iter() {
+        scan_history::InsertableScanHistory {
+            library_id,
+            finished_at: *finished_at,
+            duration: 5,
+            items: 43,
+            added: *added,
+        }
+        .insert(conn)
+        .await
+        .unwrap();
+    }
+
+    let result = scan_history::ScanHistory::get_recent(conn, library_id, 2)
+        .await
+        .unwrap();
+    let summary = result
+        .iter()
+        .map(|x| (x.finished_at, x.added))
+        .collect::<Vec<_>>();
+    assert_eq!(summary, &[(200, 3), (150, 0)]);
+
+    let other = create_test_library(conn).await;
+    let result = scan_history::ScanHistory::get_recent(conn, other, 10)
+        .await
+        .unwrap();
+    assert!(result.is_empty());
+}
@@ -0,0 +1,64 @@
+use crate::get_conn_memory;
+use crate::tag;
+
+use super::library_tests::create_test_library;
+use super::media_tests::insert_media;
+use super::user_tests::insert_user;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_insert_and_get_of_user() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let uid = insert_user(conn).await;
+
+    let tag = tag::InsertableTag {
+        user_id: uid.clone(),
+        name: "comfort watch".into(),
+    };
+
+    let first = tag.insert(conn).await.unwrap();
+    let second = tag.insert(conn).await.unwrap();
+    assert_eq!(first, second);
+
+    let tags = tag::Tag::get_of_user(conn, uid).await.unwrap();
+    assert_eq!(tags, &[first]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_filter_by_tag() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let uid = insert_user(conn).await;
+    let media_id = insert_media(conn).await;
+
+    let tag = tag::InsertableTag {
+        user_id: uid.clone(),
+        name: "kids safe".into(),
+    }
+    .insert(conn)
+    .await
+    .unwrap();
+
+    tag.add_to_media(conn, media_id).await.unwrap();
+    tag.add_to_media(conn, media_id).await.unwrap();
+
+    let tags = tag::Tag::get_of_media(conn, uid.clone(), media_id)
+        .await
+        .unwrap();
+    assert_eq!(tags, &[tag.clone()]);
+
+    let media = tag::Tag::get_media_of_library(conn, uid.clone(), library_id, tag.id)
+        .await
+        .unwrap();
+    assert_eq!(media.len(), 1);
+    assert_eq!(media[0].id, media_id);
+
+    let rows = tag::Tag::remove_from_media(conn, uid.clone(), tag.id, media_id)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+
+    let media = tag::Tag::get_media_of_library(conn, uid, library_id, tag.id)
+        .await
+        .unwrap();
+    assert!(media.is_empty());
+}
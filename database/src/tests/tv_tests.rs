@@ -1,10 +1,14 @@
+use crate::episode;
 use crate::get_conn_memory;
 use crate::library;
 use crate::media;
+use crate::progress;
+use crate::season;
 use crate::tv;
 
 use super::library_tests::create_test_library;
 use super::media_tests::insert_media;
+use super::user_tests::insert_user;
 
 pub async fn insert_tv(conn: &crate::DbConnection) -> i64 {
     let media = media::InsertableMedia {
@@ -53,3 +57,56 @@ async fn test_upgrade() {
 
     assert!(result.is_ok());
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_get_all_episodes() {
+    let ref conn = get_conn_memory().await.unwrap();
+    let library_id = create_test_library(conn).await;
+    let tv = insert_media(conn).await;
+    tv::TVShow::insert(conn, tv).await.unwrap();
+    let uid = insert_user(conn).await;
+
+    let mut episodes = vec![];
+
+    for season_number in &[2, 1] {
+        let season = season::InsertableSeason {
+            season_number: *season_number,
+            ..Default::default()
+        }
+        .insert(conn, tv)
+        .await
+        .unwrap();
+
+        for episode in &[2, 1] {
+            let id = episode::InsertableEpisode {
+                media: media::InsertableMedia {
+                    library_id,
+                    name: format!("S{}E{}", season_number, episode),
+                    ..Default::default()
+                },
+                seasonid: season,
+                episode: *episode,
+            }
+            .insert(conn)
+            .await
+            .unwrap();
+
+            episodes.push(id);
+        }
+    }
+
+    progress::Progress::set(conn, 120, uid.clone(), episodes[0])
+        .await
+        .unwrap();
+
+    let result = tv::TVShow::get_all_episodes(conn, tv, &uid).await.unwrap();
+    let names = result.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["S1E1", "S1E2", "S2E1", "S2E2"]);
+    assert_eq!(result[3].progress, 120);
+    assert!(result[..3].iter().all(|x| x.progress == 0));
+
+    let result = tv::TVShow::get_all_episodes(conn, tv, "nobody")
+        .await
+        .unwrap();
+    assert!(result.iter().all(|x| x.progress == 0));
+}
@@ -0,0 +1,83 @@
+use crate::DatabaseError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Trailer struct represents a trailer for a media fetched from a metadata provider. These are
+/// usually links to external sites like youtube.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Trailer {
+    /// unique id.
+    pub id: i64,
+    /// id of the media this trailer belongs to.
+    pub media_id: i64,
+    /// name of the trailer as provided by the metadata provider.
+    pub name: Option<String>,
+    /// url at which the trailer can be watched.
+    pub url: String,
+}
+
+impl Trailer {
+    /// Method returns all the trailers for a media.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media we want the trailers of
+    pub async fn get_of_media(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<Vec<Self>, DatabaseError> {
+        Ok(sqlx::query_as!(
+            Trailer,
+            "SELECT * FROM media_trailers WHERE media_id = ?",
+            media_id
+        )
+        .fetch_all(conn)
+        .await?)
+    }
+
+    /// Method deletes all the trailers of a media.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `media_id` - id of the media whose trailers we want to delete
+    pub async fn delete_of_media(
+        conn: &crate::DbConnection,
+        media_id: i64,
+    ) -> Result<usize, DatabaseError> {
+        Ok(
+            sqlx::query!("DELETE FROM media_trailers WHERE media_id = ?", media_id)
+                .execute(conn)
+                .await?
+                .rows_affected() as usize,
+        )
+    }
+}
+
+/// Same as [`Trailer`](Trailer) but without the id field.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct InsertableTrailer {
+    pub media_id: i64,
+    pub name: Option<String>,
+    pub url: String,
+}
+
+impl InsertableTrailer {
+    /// Method inserts a new trailer into the database. If the media already has a trailer with
+    /// the same url, nothing is inserted.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    pub async fn insert(&self, conn: &crate::DbConnection) -> Result<(), DatabaseError> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO media_trailers (media_id, name, url) VALUES ($1, $2, $3)",
+            self.media_id,
+            self.name,
+            self.url
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -11,6 +11,28 @@ pub struct TVShow {
     pub id: i64,
 }
 
+/// Episode of a tv show along with the progress a user has made watching it. Returned by
+/// [`TVShow::get_all_episodes`].
+#[derive(Clone, Serialize, Debug, PartialEq, sqlx::FromRow)]
+pub struct ShowEpisode {
+    /// id of the episode.
+    pub id: i64,
+    /// season number of the season this episode belongs to.
+    pub season: i64,
+    /// episode number.
+    pub episode: i64,
+    /// name of the episode.
+    pub name: String,
+    /// description of the episode.
+    pub description: Option<String>,
+    /// path to the backdrop of the episode.
+    pub thumbnail_url: Option<String>,
+    /// duration in seconds of the longest file for this episode.
+    pub duration: Option<i64>,
+    /// seconds of this episode the user has watched.
+    pub progress: i64,
+}
+
 impl TVShow {
     /// Method returns all the tv shows in the database.
     ///
@@ -102,6 +124,38 @@ impl TVShow {
         .total)
     }
 
+    /// Method returns every episode of a tv show along with the progress a user has made on each
+    /// of them in a single query. Episodes are sorted by season and episode number.
+    ///
+    /// # Arguments
+    /// * `conn` - database connection
+    /// * `id` - id of the tv show.
+    /// * `user_id` - id of the user whose progress should be returned.
+    pub async fn get_all_episodes(
+        conn: &crate::DbConnection,
+        id: i64,
+        user_id: &str,
+    ) -> Result<Vec<ShowEpisode>, DatabaseError> {
+        Ok(sqlx::query_as::<_, ShowEpisode>(
+            r#"SELECT episode.id, _tblseason.season_number as season, episode.episode_ as episode,
+                _tblmedia.name, _tblmedia.description, assets.local_path as thumbnail_url,
+                (SELECT MAX(mediafile.duration) FROM mediafile
+                    WHERE mediafile.media_id = episode.id) as duration,
+                COALESCE(progress.delta, 0) as progress
+            FROM episode
+            INNER JOIN _tblseason ON _tblseason.id = episode.seasonid
+            INNER JOIN _tblmedia ON _tblmedia.id = episode.id
+            LEFT OUTER JOIN assets ON assets.id = _tblmedia.backdrop
+            LEFT OUTER JOIN progress ON progress.media_id = episode.id AND progress.user_id = ?
+            WHERE _tblseason.tvshowid = ?
+            ORDER BY _tblseason.season_number, episode.episode_"#,
+        )
+        .bind(user_id)
+        .bind(id)
+        .fetch_all(conn)
+        .await?)
+    }
+
     /// Method inserts a new tv show in the database.
     ///
     /// # Arguments
@@ -1,4 +1,5 @@
 use crate::balanced_or_tree;
+use crate::logger::RequestId;
 use crate::logger::RequestLogger;
 use crate::routes;
 use crate::scanners;
@@ -7,12 +8,23 @@ use crate::websocket;
 
 use once_cell::sync::OnceCell;
 
+use std::convert::Infallible;
+use std::time::Duration;
+use std::time::Instant;
+
+use slog::error;
 use slog::info;
 use slog::Logger;
 
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::mpsc::UnboundedSender;
 
+use hyper::server::conn::AddrStream;
+use hyper::service::make_service_fn;
+use hyper::service::service_fn;
+use hyper::service::Service;
+
+use warp::http::header::HeaderValue;
 use warp::http::status::StatusCode;
 use warp::Filter;
 
@@ -61,6 +73,87 @@ pub async fn run_scanners(log: Logger, tx: EventTx) {
     }
 }
 
+/// Function periodically kills stream sessions that havent been requested by a client for longer
+/// than `stream_session_timeout` and transcodes none of whose segments were requested for longer
+/// than `transcode_idle_timeout`. The interval is read from `stream_cleanup_interval`.
+async fn reap_inactive_sessions(
+    logger: slog::Logger,
+    state: StateManager,
+    stream_tracking: StreamTracking,
+) {
+    loop {
+        let settings = crate::get_global_settings();
+        tokio::time::sleep(Duration::from_secs(settings.stream_cleanup_interval.max(1))).await;
+
+        if settings.stream_session_timeout > 0 {
+            let timeout = Duration::from_secs(settings.stream_session_timeout);
+            for gid in stream_tracking.reap_inactive(&state, timeout).await {
+                info!(logger, "Killed inactive stream session"; "gid" => gid.to_string());
+            }
+        }
+
+        if settings.transcode_idle_timeout > 0 {
+            let timeout = Duration::from_secs(settings.transcode_idle_timeout);
+            for gid in stream_tracking
+                .reap_idle_transcodes(&state, Instant::now(), timeout)
+                .await
+            {
+                info!(logger, "Killed idle transcode"; "gid" => gid.to_string());
+            }
+        }
+    }
+}
+
+/// Function periodically matches files that were scanned while tmdb was unreachable. The interval
+/// in seconds is read from `provider_retry_interval`. Files left unmatched before a restart are
+/// queued again first.
+async fn retry_unmatched(logger: slog::Logger, event_tx: EventTx) {
+    match scanners::requeue_unmatched().await {
+        Ok(0) => {}
+        Ok(x) => info!(logger, "Queued unmatched files to be matched again"; "files" => x),
+        Err(e) => error!(logger, "Failed to queue unmatched files"; "reason" => e.to_string()),
+    }
+
+    loop {
+        let interval = crate::get_global_settings().provider_retry_interval.max(1);
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        if let Err(e) = scanners::retry_unmatched(&logger, &event_tx).await {
+            error!(logger, "Failed to match queued files"; "reason" => e.to_string());
+        }
+    }
+}
+
+/// Function periodically refreshes the metadata of tv shows that are still airing. The interval
+/// in seconds is read from `show_refresh_interval`, `0` disables the refresh.
+async fn refresh_ongoing_shows(logger: slog::Logger, event_tx: EventTx) {
+    loop {
+        let interval = match crate::get_global_settings().show_refresh_interval {
+            0 => 60,
+            x => x,
+        };
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        // the setting might have changed while we were sleeping.
+        if crate::get_global_settings().show_refresh_interval == 0 {
+            continue;
+        }
+
+        if let Err(e) = scanners::refresh_ongoing_shows(&logger, &event_tx).await {
+            error!(logger, "Failed to refresh ongoing shows"; "reason" => e.to_string());
+        }
+    }
+}
+
+/// Function periodically sends the new card events that were batched by the scanners.
+async fn flush_new_cards(event_tx: EventTx) {
+    loop {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        scanners::flush_new_cards(&event_tx);
+    }
+}
+
 pub async fn warp_core(
     logger: slog::Logger,
     event_tx: EventTx,
@@ -77,10 +170,22 @@ pub async fn warp_core(
 
     let request_logger = RequestLogger::new(logger.clone());
 
+    tokio::spawn(reap_inactive_sessions(
+        logger.clone(),
+        state.clone(),
+        stream_tracking.clone(),
+    ));
+
+    tokio::spawn(refresh_ongoing_shows(logger.clone(), event_tx.clone()));
+    tokio::spawn(flush_new_cards(event_tx.clone()));
+    tokio::spawn(retry_unmatched(logger.clone(), event_tx.clone()));
+
     let api_routes = balanced_or_tree![
         /* NOTE: v1 REST API routes start HERE */
         /* /api/v1/auth and /user routes */
         auth::filters::login(conn.clone()),
+        auth::filters::refresh(conn.clone()),
+        auth::filters::logout(),
         auth::filters::whoami(conn.clone()),
         auth::filters::admin_exists(conn.clone()),
         auth::filters::register(conn.clone()),
@@ -94,25 +199,63 @@ pub async fn warp_core(
         /* general routes */
         routes::general::filters::search(conn.clone()),
         routes::general::filters::get_directory_structure(),
+        routes::general::filters::parse_filename(logger.clone()),
         /* library routes */
         routes::library::filters::library_get(conn.clone()),
         routes::library::filters::library_post(conn.clone(), logger.clone(), event_tx.clone()),
-        routes::library::filters::library_delete(conn.clone(), event_tx.clone()),
+        routes::library::filters::library_update(conn.clone(), logger.clone(), event_tx.clone()),
+        routes::library::filters::library_set_order(conn.clone()),
+        routes::library::filters::library_duplicates(conn.clone()),
+        routes::library::filters::library_flag_duplicates(conn.clone()),
+        routes::library::filters::library_delete(
+            conn.clone(),
+            event_tx.clone(),
+            state.clone(),
+            stream_tracking.clone()
+        ),
         routes::library::filters::library_get_self(conn.clone()),
         routes::library::filters::get_all_of_library(conn.clone()),
         routes::library::filters::get_all_unmatched_media(conn.clone()),
+        routes::library::filters::get_ignore_rules(conn.clone(), logger.clone()),
+        routes::library::filters::get_review_queue(conn.clone()),
+        routes::library::filters::get_scan_history(conn.clone()),
+        routes::library::filters::get_random_media(conn.clone()),
+        routes::library::filters::shuffle_next(conn.clone()),
+        routes::library::filters::get_media_types(conn.clone()),
+        routes::library::filters::get_genres(conn.clone()),
+        routes::library::filters::get_years(conn.clone()),
+        routes::library::filters::set_watched(conn.clone()),
+        routes::library::filters::get_watch_status_counts(conn.clone()),
+        routes::library::filters::get_runtimes(conn.clone()),
+        routes::library::filters::analyze_library(conn.clone(), logger.clone(), event_tx.clone()),
         /* dashboard routes */
         routes::dashboard::filters::dashboard(conn.clone(), rt.clone()),
         routes::dashboard::filters::banners(conn.clone()),
         /* media routes */
         routes::media::filters::get_media_by_id(conn.clone()),
         routes::media::filters::get_media_files(conn.clone()),
-        routes::media::filters::update_media_by_id(conn.clone()),
+        routes::media::filters::get_media_neighbors(conn.clone()),
+        routes::media::filters::update_media_by_id(conn.clone(), event_tx.clone()),
         routes::media::filters::delete_media_by_id(conn.clone()),
         routes::media::filters::tmdb_search(),
         routes::media::filters::map_progress(conn.clone()),
+        /* tag routes */
+        routes::tag::filters::get_user_tags(conn.clone()),
+        routes::tag::filters::delete_user_tag(conn.clone()),
+        routes::tag::filters::get_media_tags(conn.clone()),
+        routes::tag::filters::add_media_tag(conn.clone()),
+        routes::tag::filters::remove_media_tag(conn.clone()),
+        /* note routes */
+        routes::note::filters::get_media_note(conn.clone()),
+        routes::note::filters::set_media_note(conn.clone()),
+        routes::note::filters::delete_media_note(conn.clone()),
+        /* marker routes */
+        routes::marker::filters::get_media_markers(conn.clone()),
+        routes::marker::filters::set_media_marker(conn.clone()),
+        routes::marker::filters::delete_media_marker(conn.clone()),
         /* tv routes */
         routes::tv::filters::get_tv_seasons(conn.clone()),
+        routes::tv::filters::get_tv_episodes(conn.clone()),
         routes::tv::filters::patch_episode_by_id(conn.clone()),
         routes::tv::filters::delete_season_by_id(conn.clone()),
         routes::tv::filters::get_season_episodes(conn.clone()),
@@ -120,12 +263,20 @@ pub async fn warp_core(
         routes::tv::filters::delete_episode_by_id(conn.clone()),
         /* mediafile routes */
         routes::mediafile::filters::get_mediafile_info(conn.clone()),
-        routes::mediafile::filters::rematch_mediafile(conn.clone(), logger.clone()),
+        routes::mediafile::filters::rematch_mediafile(
+            conn.clone(),
+            logger.clone(),
+            event_tx.clone()
+        ),
+        routes::mediafile::filters::download_mediafile(conn.clone()),
+        routes::mediafile::filters::direct_play(conn.clone()),
+        routes::mediafile::filters::get_bif_thumbnails(conn.clone()),
         /* settings routes */
         routes::settings::filters::get_user_settings(conn.clone()),
         routes::settings::filters::post_user_settings(conn.clone()),
         routes::settings::filters::get_global_settings(),
         routes::settings::filters::set_global_settings(),
+        routes::settings::filters::clear_cache(conn.clone()),
         /* stream routes */
         routes::stream::filters::return_virtual_manifest(
             conn.clone(),
@@ -138,13 +289,16 @@ pub async fn warp_core(
             state.clone(),
             stream_tracking.clone()
         ),
-        routes::stream::filters::get_init(state.clone())
+        routes::stream::filters::get_stream_profiles(conn.clone()),
+        routes::stream::filters::return_hls_master(stream_tracking.clone()),
+        routes::stream::filters::return_hls_playlist(stream_tracking.clone()),
+        routes::stream::filters::get_init(state.clone(), stream_tracking.clone())
             .recover(routes::global_filters::handle_rejection),
         routes::stream::filters::should_client_hard_seek(state.clone(), stream_tracking.clone()),
         routes::stream::filters::session_get_stderr(state.clone(), stream_tracking.clone()),
         routes::stream::filters::kill_session(state.clone(), stream_tracking.clone()),
         routes::stream::filters::get_subtitle(state.clone()),
-        routes::stream::filters::get_chunk(state.clone())
+        routes::stream::filters::get_chunk(state.clone(), stream_tracking.clone())
             .recover(routes::global_filters::handle_rejection),
         warp::path!("api" / "stream" / ..)
             .and(warp::any())
@@ -163,17 +317,19 @@ pub async fn warp_core(
         /* NOTE: This is a barrier to 404 any rest api calls that dont match till here */
         routes::global_filters::api_not_found(),
         /* websocket route */
-        websocket::event_socket(tokio::runtime::Handle::current(), event_rx)
-            .recover(routes::global_filters::handle_rejection),
+        websocket::event_socket(
+            tokio::runtime::Handle::current(),
+            event_rx,
+            state.clone(),
+            stream_tracking.clone()
+        )
+        .recover(routes::global_filters::handle_rejection),
         /* static routes */
         routes::statik::filters::dist_static(),
         routes::statik::filters::get_image(conn.clone(), logger.clone()),
         routes::statik::filters::react_routes(),
     ]
     .recover(routes::global_filters::handle_rejection)
-    .with(warp::filters::log::custom(move |x| {
-        request_logger.on_response(x);
-    }))
     .with(warp::cors().allow_any_origin());
 
     cfg_if::cfg_if! {
@@ -182,10 +338,57 @@ pub async fn warp_core(
         }
     }
 
+    // every request is tagged with a id and the address of its peer before it reaches the routes,
+    // filters read them back from the extensions of the request, see `with_logger` and
+    // `with_remote_addr`.
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote_addr = conn.remote_addr();
+        let service = service.clone();
+        let request_logger = request_logger.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req: hyper::Request<hyper::Body>| {
+                let id = RequestId::new();
+                let method = req.method().clone();
+                let path = req.uri().path().to_string();
+                let started = Instant::now();
+
+                req.extensions_mut().insert(id);
+                req.extensions_mut()
+                    .insert(routes::global_filters::RemoteAddr(remote_addr));
+
+                let mut service = service.clone();
+                let request_logger = request_logger.clone();
+
+                async move {
+                    let mut resp = service.call(req).await?;
+
+                    if let Ok(value) = HeaderValue::from_str(&id.to_string()) {
+                        resp.headers_mut().insert("x-request-id", value);
+                    }
+
+                    request_logger.on_response(
+                        id,
+                        &method,
+                        &path,
+                        resp.status(),
+                        remote_addr,
+                        started.elapsed(),
+                    );
+
+                    Ok::<_, Infallible>(resp)
+                }
+            }))
+        }
+    });
+
+    let server = hyper::Server::bind(&([0, 0, 0, 0], port).into()).serve(make_service);
+
     info!(logger, "Webserver is listening on 0.0.0.0:{}", port);
 
     tokio::select! {
-        _ = warp::serve(routes).run(([0, 0, 0, 0], port)) => {},
+        _ = server => {},
         _ = tokio::signal::ctrl_c() => {
             std::process::exit(0);
         }
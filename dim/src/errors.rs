@@ -4,10 +4,13 @@ use serde::Serialize;
 use serde_json::json;
 
 use crate::scanners::base::ScannerError;
+use crate::streaming::ffprobe::FFProbeError;
 use nightfall::error::NightfallError;
 
 use http::StatusCode;
 
+use std::collections::BTreeMap;
+
 #[derive(Clone, Debug, Error, Serialize)]
 #[serde(tag = "error")]
 pub enum DimError {
@@ -25,12 +28,16 @@ pub enum DimError {
     NotFoundError,
     #[error(display = "Authentication is required for this route.")]
     AuthRequired,
+    #[error(display = "The token has expired, exchange the refresh token for a new one.")]
+    TokenExpired,
     #[error(display = "Invalid media_type supplied, options are [movie, tv].")]
     InvalidMediaType,
     #[error(display = "A error in the streaming library has occured")]
     StreamingError(#[error(source)] StreamingErrors),
     #[error(display = "You do not have permission to access this route")]
     Unauthorized,
+    #[error(display = "Admin role required to access this route")]
+    Forbidden,
     #[error(display = "A error has occured when matching.")]
     ScannerError(#[error(source)] ScannerError),
     #[error(display = "Upload failed.")]
@@ -39,6 +46,14 @@ pub enum DimError {
     MissingFieldInBody { description: String },
     #[error(display = "Unsupported file type.")]
     UnsupportedFile,
+    #[error(display = "The library contains media, pass `force` to change its media type.")]
+    LibraryNotEmpty,
+    #[error(display = "Downloads are disabled for this library.")]
+    DownloadsDisabled,
+    #[error(display = "The library is invalid, see `fields` for details.")]
+    InvalidLibrary { errors: BTreeMap<String, String> },
+    #[error(display = "The marker has to start before it ends.")]
+    InvalidMarker,
 }
 
 impl warp::reject::Reject for DimError {}
@@ -54,17 +69,26 @@ impl warp::Reply for DimError {
             | Self::InternalServerError
             | Self::ScannerError(_)
             | Self::UploadFailed => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::AuthRequired | Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::AuthRequired | Self::TokenExpired | Self::Unauthorized => {
+                StatusCode::UNAUTHORIZED
+            }
             Self::UnsupportedFile | Self::InvalidMediaType | Self::MissingFieldInBody { .. } => {
                 StatusCode::NOT_ACCEPTABLE
             }
+            Self::LibraryNotEmpty => StatusCode::CONFLICT,
+            Self::DownloadsDisabled | Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::InvalidLibrary { .. } | Self::InvalidMarker => StatusCode::UNPROCESSABLE_ENTITY,
         };
 
-        let resp = json!({
+        let mut resp = json!({
             "error": json!(&self)["error"],
             "messsage": self.to_string(),
         });
 
+        if let Self::InvalidLibrary { errors } = &self {
+            resp["fields"] = json!(errors);
+        }
+
         warp::http::Response::builder()
             .status(status)
             .header("ContentType", "application/json")
@@ -136,8 +160,16 @@ pub enum StreamingErrors {
     NoMediaFileFound(String),
     #[error(display = "Failed to create a ffprobe context")]
     FFProbeCtxFailed,
+    #[error(display = "The ffprobe binary could not be found")]
+    FFProbeNotFound,
+    #[error(display = "ffprobe timed out while probing the file")]
+    FFProbeTimedOut,
     #[error(display = "Could not parse the gid")]
     GidParseError,
+    #[error(display = "Maximum number of concurrent streams reached")]
+    TooManyStreams,
+    #[error(display = "Transcoding failed after {} retries", _0)]
+    TranscodeFailed(u32),
 }
 
 impl warp::reject::Reject for StreamingErrors {}
@@ -147,6 +179,9 @@ impl warp::Reply for StreamingErrors {
         let status = match self {
             Self::OtherNightfall(NightfallError::ChunkNotDone) => StatusCode::PROCESSING,
             Self::NoMediaFileFound(_) => StatusCode::NOT_FOUND,
+            Self::TooManyStreams => StatusCode::FORBIDDEN,
+            Self::FileIsCorrupt => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::FFProbeTimedOut => StatusCode::GATEWAY_TIMEOUT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -169,6 +204,17 @@ impl From<std::io::Error> for StreamingErrors {
     }
 }
 
+impl From<FFProbeError> for StreamingErrors {
+    fn from(e: FFProbeError) -> Self {
+        match e {
+            FFProbeError::NotFound => Self::FFProbeNotFound,
+            FFProbeError::TimedOut(_) => Self::FFProbeTimedOut,
+            FFProbeError::Unparseable(_) => Self::FileIsCorrupt,
+            FFProbeError::Io(_) => Self::FFProbeCtxFailed,
+        }
+    }
+}
+
 use database::DatabaseError;
 impl From<DatabaseError> for DimError {
     fn from(_: DatabaseError) -> Self {
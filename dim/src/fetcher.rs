@@ -7,10 +7,14 @@ use slog::Logger;
 use priority_queue::PriorityQueue;
 use tokio::sync::Mutex;
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
 use std::fs::File;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::io::copy;
 use std::io::Cursor;
+use std::io::Write;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -33,6 +37,12 @@ pub async fn insert_into_queue(log: &Logger, poster: String, priority: usize) {
     }
 }
 
+/// Function forgets which posters have already been queued for caching, thus allowing them to be
+/// fetched again.
+pub async fn clear_cache() {
+    POSTER_CACHE.lock().await.clear();
+}
+
 pub async fn bump_priority(log: &Logger, poster: String, priority: usize) {
     debug!(log, "Bumping priority of {:?} to {}", &poster, priority);
     let mut lock = PROCESSING_QUEUE.lock().await;
@@ -88,3 +98,87 @@ async fn process_queue(log: Logger) {
 pub async fn tmdb_poster_fetcher(log: Logger) {
     tokio::spawn(process_queue(log.clone()));
 }
+
+/// Function returns `poster` if its set, otherwise it returns a generated title card for `title`
+/// if `generate_title_cards` is enabled.
+pub fn poster_or_title_card(poster: Option<String>, title: &str) -> Option<String> {
+    if poster.is_some() || !crate::get_global_settings().generate_title_cards {
+        return poster;
+    }
+
+    get_title_card(title)
+}
+
+/// Function returns the path to a title card for `title`, which is the title written on a
+/// background whose color is derived from the hash of the title. Title cards are cached in the
+/// metadata directory and are only generated the first time they are requested.
+pub fn get_title_card(title: &str) -> Option<String> {
+    let mut hasher = DefaultHasher::new();
+    title.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let fname = format!("title_card_{:016x}.svg", hash);
+    let mut out_path = PathBuf::from(METADATA_PATH.get()?);
+    out_path.push(&fname);
+
+    if !out_path.exists() {
+        let content = render_title_card(title, hash);
+        File::create(out_path)
+            .ok()?
+            .write_all(content.as_bytes())
+            .ok()?;
+    }
+
+    Some(format!("images/{}", fname))
+}
+
+fn render_title_card(title: &str, hash: u64) -> String {
+    const LINE_WIDTH: usize = 16;
+    const MAX_LINES: usize = 5;
+
+    let mut lines: Vec<String> = vec![];
+
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + word.chars().count() < LINE_WIDTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    lines.truncate(MAX_LINES);
+
+    let first_line = 375 - (lines.len() as i64 - 1) * 30;
+    let text = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            format!(
+                r#"<tspan x="250" y="{}">{}</tspan>"#,
+                first_line + i as i64 * 60,
+                escape_xml(line)
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="500" height="750" viewBox="0 0 500 750">"#,
+            r#"<rect width="500" height="750" fill="hsl({}, 45%, 30%)"/>"#,
+            r#"<text fill="white" font-family="sans-serif" font-size="48" text-anchor="middle">{}</text>"#,
+            "</svg>"
+        ),
+        hash % 360,
+        text
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
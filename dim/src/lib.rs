@@ -26,15 +26,20 @@ use chrono::Utc;
 use slog::o;
 use slog::Drain;
 use slog::Duplicate;
+use slog::Level;
+use slog::LevelFilter;
 use slog::Logger;
 
 use slog_async::Async;
 use slog_json::Json as slog_json_default;
+use slog_term::Decorator;
 use slog_term::FullFormat;
 use slog_term::TermDecorator;
 
 use std::fs::create_dir_all;
 use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 
 /// Module contains our core initialization logic.
 pub mod core;
@@ -63,14 +68,49 @@ pub use routes::settings::set_global_settings;
 pub use routes::settings::GlobalSettings;
 
 /// Function builds a logger drain that drains to a json file located in logs/ and also to stdout.
-pub fn build_logger(_debug: bool) -> slog::Logger {
+/// Every call creates a new log file, only the `max_files` most recent ones are kept. A
+/// `max_files` of `0` keeps every log file. Both outputs only log records of level info and
+/// above unless `debug` is set.
+pub fn build_logger(debug: bool, max_files: usize) -> slog::Logger {
     let date_now = Utc::now();
 
-    let decorator = TermDecorator::new().build();
+    let _ = create_dir_all("logs");
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            // NOTE: colons arent allowed in filenames on windows.
+            let file = File::create(format!(
+                "./logs/dim-log-{}.log",
+                date_now.format("%Y-%m-%dT%H-%M-%S%.3f")
+            ))
+            .expect("Couldnt open log file");
+        } else {
+            let file = File::create(format!("./logs/dim-log-{}.log", date_now.to_rfc3339()))
+                .expect("Couldnt open log file");
+        }
+    }
+
+    if max_files > 0 {
+        rotate_logs(Path::new("./logs"), max_files);
+    }
+
+    logger_with(TermDecorator::new().build(), file, debug)
+}
+
+/// Function builds a logger that drains to `decorator` as text and to `json` as json. Both only
+/// log records of level info and above unless `debug` is set.
+fn logger_with<D, W>(decorator: D, json: W, debug: bool) -> slog::Logger
+where
+    D: Decorator + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let level = if debug { Level::Trace } else { Level::Info };
+
     let drain = FullFormat::new(decorator)
         .use_original_order()
         .build()
         .fuse();
+    let drain = LevelFilter::new(drain, level).fuse();
 
     let drain = Async::new(drain)
         .chan_size(2048)
@@ -78,19 +118,8 @@ pub fn build_logger(_debug: bool) -> slog::Logger {
         .build()
         .fuse();
 
-    let _ = create_dir_all("logs");
-
-    cfg_if::cfg_if! {
-        if #[cfg(target_os = "windows")] {
-            let file = File::create("./logs/dim-log.log")
-                .expect("Couldnt open log file");
-        } else {
-            let file = File::create(format!("./logs/dim-log-{}.log", date_now.to_rfc3339()))
-                .expect("Couldnt open log file");
-        }
-    }
-
-    let json_drain = Async::new(slog_json_default::default(file).fuse())
+    let json_drain = LevelFilter::new(slog_json_default::default(json).fuse(), level).fuse();
+    let json_drain = Async::new(json_drain)
         .chan_size(2048)
         .overflow_strategy(slog_async::OverflowStrategy::Block)
         .build()
@@ -98,3 +127,72 @@ pub fn build_logger(_debug: bool) -> slog::Logger {
 
     Logger::root(Duplicate::new(drain, json_drain).fuse(), o!())
 }
+
+/// Function deletes all but the `max_files` most recently modified log files in `dir`. Files that
+/// werent created by [`build_logger`] are left alone.
+fn rotate_logs(dir: &Path, max_files: usize) {
+    let mut logs = match std::fs::read_dir(dir) {
+        Ok(x) => x
+            .filter_map(Result::ok)
+            .filter(|x| {
+                let name = x.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with("dim-log") && name.ends_with(".log")
+            })
+            .filter_map(|x| Some((x.metadata().ok()?.modified().ok()?, x.path())))
+            .collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+
+    // newest first, timestamped names break ties between files modified at the same time.
+    logs.sort_by(|a, b| b.cmp(a));
+
+    for (_, path) in logs.into_iter().skip(max_files) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use slog_term::PlainDecorator;
+
+    use std::io;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_logger_levels() {
+        for &debug in &[false, true] {
+            let term = Buffer::default();
+            let json = Buffer::default();
+            let log = logger_with(PlainDecorator::new(term.clone()), json.clone(), debug);
+
+            slog::info!(log, "info record");
+            slog::debug!(log, "debug record");
+
+            // dropping the logger flushes the async drains.
+            drop(log);
+
+            for buffer in &[term, json] {
+                let out = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+                assert!(out.contains("info record"), "{}", out);
+                assert_eq!(out.contains("debug record"), debug, "{}", out);
+            }
+        }
+    }
+}
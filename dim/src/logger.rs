@@ -1,7 +1,37 @@
 use slog::info;
 use slog::o;
 
-use warp::filters::log::Info;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use warp::http::Method;
+use warp::http::StatusCode;
+
+/// Id assigned to every incoming request. It is stored in the extensions of the request, echoed
+/// back in the `X-Request-Id` header and attached to every log line of the request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestId(pub Uuid);
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
 #[derive(Clone)]
 pub struct RequestLogger {
@@ -17,19 +47,28 @@ impl RequestLogger {
 }
 
 impl RequestLogger {
-    pub fn on_response(&self, info: Info<'_>) {
-        let (tag, duration) = if info.elapsed().as_millis() > 0 {
-            ("ms", info.elapsed().as_millis())
+    pub fn on_response(
+        &self,
+        id: RequestId,
+        method: &Method,
+        path: &str,
+        status: StatusCode,
+        remote_addr: SocketAddr,
+        elapsed: Duration,
+    ) {
+        let (tag, duration) = if elapsed.as_millis() > 0 {
+            ("ms", elapsed.as_millis())
         } else {
-            ("us", info.elapsed().as_micros())
+            ("us", elapsed.as_micros())
         };
 
         info!(
             self.logger,
-            "{}", info.method();
-            "route" => info.path().to_string(),
-            "status" => info.status().to_string(),
-            "ip" => info.remote_addr().map(|x| x.to_string()).unwrap_or("???.???.???.???".into()),
+            "{}", method;
+            "request_id" => id.to_string(),
+            "route" => path.to_string(),
+            "status" => status.to_string(),
+            "ip" => remote_addr.to_string(),
             "duration" => duration,
             "duration_tag" => tag,
         );
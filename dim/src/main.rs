@@ -54,11 +54,16 @@ fn main() {
 
     auth::set_jwt_key(secret_key);
 
+    database::set_busy_handling(
+        Duration::from_millis(global_settings.db_busy_timeout),
+        global_settings.db_busy_retries,
+    );
+
     core::METADATA_PATH
         .set(global_settings.metadata_dir.clone())
         .expect("Failed to set METADATA_PATH");
 
-    let logger = build_logger(global_settings.verbose);
+    let logger = build_logger(global_settings.verbose, global_settings.max_log_files);
 
     {
         let failed = streaming::ffcheck()
@@ -1,6 +1,6 @@
 use crate::core::DbConnection;
 use crate::errors;
-use auth::{jwt_generate, Wrapper as Auth};
+use auth::{jwt_generate, jwt_generate_refresh, Wrapper as Auth};
 use bytes::BufMut;
 
 use database::asset::Asset;
@@ -46,6 +46,43 @@ pub mod filters {
             })
     }
 
+    pub fn refresh(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        pub struct Params {
+            refresh_token: String,
+        }
+
+        warp::path!("api" / "v1" / "auth" / "refresh")
+            .and(warp::post())
+            .and(warp::body::json::<Params>())
+            .and(with_db(conn))
+            .and_then(
+                |Params { refresh_token }: Params, conn: DbConnection| async move {
+                    super::refresh(refresh_token, conn)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn logout() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        pub struct Params {
+            refresh_token: String,
+        }
+
+        warp::path!("api" / "v1" / "auth" / "logout")
+            .and(warp::post())
+            .and(warp::body::json::<Params>())
+            .and_then(|Params { refresh_token }: Params| async move {
+                super::logout(refresh_token)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
     pub fn whoami(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
@@ -229,16 +266,57 @@ pub async fn login(
         user.password.clone(),
         new_login.password.clone(),
     ) {
-        let token = jwt_generate(user.username, user.roles.clone());
+        let token = jwt_generate(user.username.clone(), user.roles.clone());
+        let refresh_token = jwt_generate_refresh(user.username, user.roles.clone());
 
         return Ok(reply::json(&json!({
             "token": token,
+            "refresh_token": refresh_token,
         })));
     }
 
     Err(errors::AuthError::WrongPassword)
 }
 
+/// Method mapped to `POST /api/v1/auth/refresh` exchanges a refresh token for a new access token.
+/// The roles of the new token are read from the database, users that were deleted cant refresh.
+///
+/// # Arguments
+/// * `refresh_token` - refresh token handed out on login
+///
+/// # Return Schema
+/// ```text
+/// {
+///   "token": string,
+/// }
+/// ```
+pub async fn refresh(
+    refresh_token: String,
+    conn: DbConnection,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let claims =
+        auth::jwt_check_refresh(&refresh_token).map_err(|_| errors::DimError::AuthRequired)?;
+
+    let user = User::get(&conn, &claims.get_user())
+        .await
+        .map_err(|_| errors::DimError::AuthRequired)?;
+
+    Ok(reply::json(&json!({
+        "token": jwt_generate(user.username, user.roles),
+    })))
+}
+
+/// Method mapped to `POST /api/v1/auth/logout` revokes a refresh token so that it cant be
+/// exchanged for access tokens anymore.
+///
+/// # Arguments
+/// * `refresh_token` - refresh token handed out on login
+pub async fn logout(refresh_token: String) -> Result<impl warp::Reply, errors::DimError> {
+    auth::jwt_revoke(&refresh_token).map_err(|_| errors::DimError::AuthRequired)?;
+
+    Ok(StatusCode::OK)
+}
+
 pub async fn whoami(user: Auth, conn: DbConnection) -> Result<impl warp::Reply, Infallible> {
     let username = user.0.claims.get_user();
 
@@ -6,6 +6,7 @@ use auth::Wrapper as Auth;
 
 use database::episode::Episode;
 use database::genre::*;
+use database::history::History;
 use database::library::MediaType;
 use database::media::Media;
 use database::mediafile::MediaFile;
@@ -124,6 +125,24 @@ pub async fn dashboard(
         }));
     }
 
+    let mut recently_watched = Vec::new();
+    for media in History::recent_across_libraries(&conn, user.0.claims.get_user(), 10).await? {
+        let item = match sqlx::query!(
+            "SELECT name, assets.local_path FROM _tblmedia LEFT JOIN assets ON assets.id = _tblmedia.poster
+            WHERE _tblmedia.id = ?",
+            media
+        ).fetch_one(&conn).await {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        recently_watched.push(json!({
+            "id": media,
+            "poster_path": item.local_path,
+            "name": item.name
+        }));
+    }
+
     let continue_watching = if !continue_watching.is_empty() {
         Some(json!({
             "CONTINUE WATCHING": continue_watching,
@@ -132,8 +151,17 @@ pub async fn dashboard(
         None
     };
 
+    let recently_watched = if !recently_watched.is_empty() {
+        Some(json!({
+            "RECENTLY WATCHED": recently_watched,
+        }))
+    } else {
+        None
+    };
+
     Ok(reply::json(&json!({
         ..?continue_watching,
+        ..?recently_watched,
         "TOP RATED": top_rated,
         "FRESHLY ADDED": recently_added,
     })))
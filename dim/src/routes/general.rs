@@ -1,15 +1,23 @@
 use crate::core::DbConnection;
 use crate::errors;
+use crate::scanners;
+use crate::utils::page_size;
 
 use auth::Wrapper as Auth;
 use serde::Serialize;
 
 use database::genre::*;
+use database::library::MediaType;
+use database::media::Media;
+use database::media::SearchFilters;
+use database::media::SortBy;
+use database::media::SortOrder;
 
 use tokio::task::spawn_blocking;
 
 use std::fs;
 use std::io;
+use std::path::Path;
 use std::path::PathBuf;
 
 use warp::reply;
@@ -23,6 +31,7 @@ pub mod filters {
     use warp::Filter;
     use warp::Rejection;
 
+    use super::super::global_filters::with_logger;
     use super::super::global_filters::with_state;
     use serde::Deserialize;
 
@@ -38,6 +47,36 @@ pub mod filters {
             })
     }
 
+    pub fn parse_filename(
+        log: slog::Logger,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct RouteArgs {
+            filename: String,
+            strip_tags: Option<Vec<String>>,
+            join_multipart: Option<bool>,
+        }
+
+        warp::path!("api" / "v1" / "filename" / "parse")
+            .and(warp::post())
+            .and(warp::body::json::<RouteArgs>())
+            .and(auth::with_auth())
+            .and(with_logger(log))
+            .and_then(
+                |RouteArgs {
+                     filename,
+                     strip_tags,
+                     join_multipart,
+                 }: RouteArgs,
+                 auth: Auth,
+                 log: slog::Logger| async move {
+                    super::parse_filename(log, filename, strip_tags, join_multipart, auth)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn search(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
@@ -45,9 +84,16 @@ pub mod filters {
         struct SearchArgs {
             query: Option<String>,
             year: Option<i32>,
+            year_from: Option<i32>,
+            year_to: Option<i32>,
             library_id: Option<i32>,
+            media_type: Option<database::library::MediaType>,
             genre: Option<String>,
             quick: Option<bool>,
+            sort: Option<database::media::SortBy>,
+            order: Option<database::media::SortOrder>,
+            limit: Option<i64>,
+            offset: Option<i64>,
         }
 
         warp::path!("api" / "v1" / "search")
@@ -61,9 +107,16 @@ pub mod filters {
                         conn,
                         args.query,
                         args.year,
+                        args.year_from,
+                        args.year_to,
                         args.library_id,
+                        args.media_type,
                         args.genre,
                         args.quick,
+                        args.sort,
+                        args.order,
+                        args.limit,
+                        args.offset,
                         auth,
                     )
                     .await
@@ -73,6 +126,61 @@ pub mod filters {
     }
 }
 
+/// Method mapped to `POST /api/v1/filename/parse` parses a filename the same way the scanner
+/// does and returns the result, which makes it possible to preview parsing rules before they are
+/// saved. Rules that arent supplied default to the current settings.
+///
+/// # Arguments
+/// * `log` - logger
+/// * `filename` - filename to parse, a video extension is stripped if present
+/// * `strip_tags` - release tags to strip from the filename
+/// * `join_multipart` - whether multi-part tags like `CD1` should be captured
+/// * `_user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "title": string,
+///     "year": int | null,
+///     "season": int | null,
+///     "episode": int | null,
+///     "part": int | null,
+///     "quality": string | null,
+///     "edition": string | null,
+///     "release_group": string | null,
+/// }
+/// ```
+pub async fn parse_filename(
+    log: slog::Logger,
+    filename: String,
+    strip_tags: Option<Vec<String>>,
+    join_multipart: Option<bool>,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let settings = crate::get_global_settings();
+
+    let path = Path::new(&filename);
+    let filename = match path.extension().and_then(|x| x.to_str()) {
+        Some(ext) if scanners::SUPPORTED_EXTS.contains(&ext) => path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        _ => filename.clone(),
+    };
+
+    let parsed = scanners::base::parse_filename(
+        &log,
+        filename,
+        strip_tags.unwrap_or(settings.filename_strip_tags),
+        join_multipart.unwrap_or(settings.join_multipart_movies),
+    )
+    .await
+    .map_err(errors::DimError::ScannerError)?;
+
+    Ok(reply::json(&parsed))
+}
+
 pub fn enumerate_directory<T: AsRef<std::path::Path>>(path: T) -> io::Result<Vec<String>> {
     let mut dirs: Vec<String> = fs::read_dir(path)?
         .into_iter()
@@ -126,94 +234,113 @@ pub async fn get_directory_structure(
     ))
 }
 
+/// Method mapped to `GET /api/v1/search` searches for media by name, genre or release year. The
+/// first supplied filter out of `query`, `genre`, `year` and `year_from`/`year_to` is used.
+///
+/// # Arguments
+/// * `query` - text to look for in the names of media, an empty query returns nothing
+/// * `library_id` - when supplied only media of this library are returned
+/// * `media_type` - when supplied only media of this type are returned, only used with `query`
+/// * `sort` - key to sort the results by, not used with `query` whose results are ranked by
+/// relevance. Defaults to the year for `year_from`/`year_to` and to the name otherwise.
+/// * `order` - direction to sort in, defaults to the direction of the sort key, ascending for
+/// year ranges.
+/// * `limit` - max number of results to return
+/// * `offset` - number of results to skip
 pub async fn search(
     conn: DbConnection,
     query: Option<String>,
     year: Option<i32>,
-    _library_id: Option<i32>,
+    year_from: Option<i32>,
+    year_to: Option<i32>,
+    library_id: Option<i32>,
+    media_type: Option<MediaType>,
     genre: Option<String>,
     _quick: Option<bool>,
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    limit: Option<i64>,
+    offset: Option<i64>,
     _user: Auth,
 ) -> Result<warp::reply::Json, errors::DimError> {
-    if let Some(query_string) = query {
-        let query_string = query_string
-            .split(' ')
-            .map(|x| format!("%{}%", x))
-            .collect::<Vec<_>>()
-            .as_slice()
-            .join(" ");
-
-        return search_by_name(&conn, &query_string, 15).await;
+    let limit = page_size(limit);
+    let offset = offset.unwrap_or(0).max(0);
+    let library_id = library_id.map(|x| x as i64);
+
+    if let Some(query) = query {
+        let filters = SearchFilters {
+            media_type,
+            library_id,
+            limit: Some(limit),
+            offset,
+        };
+
+        return Ok(reply::json(&Media::search(&conn, &query, filters).await?));
     }
 
     if let Some(x) = genre {
         let genre_id = Genre::get_by_name(&conn, x).await?.id;
-        return search_by_genre(&conn, genre_id).await;
+        let order_by = sort.unwrap_or_default().order_clause(order);
+        return search_by_genre(&conn, genre_id, library_id, limit, offset, order_by).await;
     }
 
     if let Some(x) = year {
-        return search_by_release_year(&conn, x as i64).await;
+        let order_by = sort.unwrap_or_default().order_clause(order);
+        return search_by_release_year(&conn, x as i64, library_id, limit, offset, order_by).await;
     }
 
-    Err(errors::DimError::NotFoundError)
-}
+    if year_from.is_some() || year_to.is_some() {
+        let from = year_from.map(|x| x as i64).unwrap_or(i64::MIN);
+        let to = year_to.map(|x| x as i64).unwrap_or(i64::MAX);
+        let order_by = match sort {
+            Some(x) => x.order_clause(order),
+            None => SortBy::Year.order_clause(Some(order.unwrap_or(SortOrder::Asc))),
+        };
 
-async fn search_by_name(
-    conn: &DbConnection,
-    query: &str,
-    limit: i64,
-) -> Result<warp::reply::Json, errors::DimError> {
-    #[derive(Serialize)]
-    struct Record {
-        id: i64,
-        library_id: i64,
-        name: String,
-        poster_path: Option<String>,
+        return search_by_year_range(&conn, from, to, library_id, limit, offset, order_by).await;
     }
 
-    let data = sqlx::query_as!(
-        Record,
-        r#"SELECT _tblmedia.id, library_id, name, assets.local_path as poster_path FROM _tblmedia
-           LEFT JOIN assets on _tblmedia.poster = assets.id
-           WHERE NOT media_type = "episode"
-           AND UPPER(name) LIKE ?
-           LIMIT ?"#,
-        query,
-        limit
-    )
-    .fetch_all(conn)
-    .await
-    .map_err(|_| errors::DimError::NotFoundError)?;
+    Err(errors::DimError::NotFoundError)
+}
 
-    Ok(reply::json(&data))
+#[derive(Serialize, sqlx::FromRow)]
+struct SearchRecord {
+    id: i64,
+    library_id: i64,
+    name: String,
+    poster_path: Option<String>,
 }
 
 async fn search_by_genre(
     conn: &DbConnection,
     genre_id: i64,
+    library_id: Option<i64>,
+    limit: i64,
+    offset: i64,
+    order_by: String,
 ) -> Result<warp::reply::Json, errors::DimError> {
-    #[derive(Serialize)]
-    struct Record {
-        id: i64,
-        library_id: i64,
-        name: String,
-        poster_path: Option<String>,
-    }
-
-    let data = sqlx::query_as!(
-        Record,
-        r#"SELECT _tblmedia.id, library_id, name, assets.local_path as poster_path
-                FROM _tblmedia
-                LEFT JOIN assets on _tblmedia.poster = assets.id
-                INNER JOIN genre_media ON genre_media.media_id = _tblmedia.id
+    // NOTE: The order clause is built at runtime, thus we cant use the query macros here.
+    let query = format!(
+        r#"SELECT id, library_id, name, poster_path
+                FROM media
                 WHERE NOT media_type = "episode"
-                AND genre_media.genre_id = ?
+                AND id IN (SELECT media_id FROM genre_media WHERE genre_id = ?)
+                AND (? IS NULL OR library_id = ?)
+                {}
+                LIMIT ? OFFSET ?
                 "#,
-        genre_id,
-    )
-    .fetch_all(conn)
-    .await
-    .map_err(|_| errors::DimError::NotFoundError)?;
+        order_by
+    );
+
+    let data = sqlx::query_as::<_, SearchRecord>(&query)
+        .bind(genre_id)
+        .bind(library_id)
+        .bind(library_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(conn)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
 
     Ok(reply::json(&data))
 }
@@ -221,28 +348,70 @@ async fn search_by_genre(
 async fn search_by_release_year(
     conn: &DbConnection,
     year: i64,
+    library_id: Option<i64>,
+    limit: i64,
+    offset: i64,
+    order_by: String,
 ) -> Result<warp::reply::Json, errors::DimError> {
-    #[derive(Serialize)]
-    struct Record {
-        id: i64,
-        library_id: i64,
-        name: String,
-        poster_path: Option<String>,
-    }
-
-    let data = sqlx::query_as!(
-        Record,
-        r#"SELECT _tblmedia.id, library_id, name, assets.local_path as poster_path
-                FROM _tblmedia
-            LEFT JOIN assets on _tblmedia.poster = assets.id
+    let query = format!(
+        r#"SELECT id, library_id, name, poster_path
+                FROM media
                 WHERE NOT media_type = "episode"
                 AND year = ?
+                AND (? IS NULL OR library_id = ?)
+                {}
+                LIMIT ? OFFSET ?
                 "#,
-        year,
-    )
-    .fetch_all(conn)
-    .await
-    .map_err(|_| errors::DimError::NotFoundError)?;
+        order_by
+    );
+
+    let data = sqlx::query_as::<_, SearchRecord>(&query)
+        .bind(year)
+        .bind(library_id)
+        .bind(library_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(conn)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    Ok(warp::reply::json(&data))
+}
+
+/// Returns media released between `from` and `to` inclusive. Media without a year are never
+/// returned.
+async fn search_by_year_range(
+    conn: &DbConnection,
+    from: i64,
+    to: i64,
+    library_id: Option<i64>,
+    limit: i64,
+    offset: i64,
+    order_by: String,
+) -> Result<warp::reply::Json, errors::DimError> {
+    let query = format!(
+        r#"SELECT id, library_id, name, poster_path
+                FROM media
+                WHERE NOT media_type = "episode"
+                AND year IS NOT NULL
+                AND year BETWEEN ? AND ?
+                AND (? IS NULL OR library_id = ?)
+                {}
+                LIMIT ? OFFSET ?
+                "#,
+        order_by
+    );
+
+    let data = sqlx::query_as::<_, SearchRecord>(&query)
+        .bind(from)
+        .bind(to)
+        .bind(library_id)
+        .bind(library_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(conn)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
 
     Ok(warp::reply::json(&data))
 }
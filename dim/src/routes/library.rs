@@ -1,20 +1,34 @@
 use crate::core::DbConnection;
 use crate::core::EventTx;
+use crate::core::StateManager;
 use crate::errors;
+use crate::fetcher;
+use crate::routes::settings::UntitledFiles;
 use crate::scanners;
+use crate::scanners::base::parse_filename;
+use crate::scanners::release_tags;
+use crate::stream_tracking::StreamTracking;
 
 use auth::Wrapper as Auth;
 
+use database::genre::Genre;
 use database::library::InsertableLibrary;
 use database::library::Library;
+use database::library::MediaType;
 use database::media::Media;
+use database::media::ShuffleScope;
+use database::media::SortBy;
+use database::media::SortOrder;
 use database::mediafile::MediaFile;
+use database::progress::Progress;
+use database::scan_history::ScanHistory;
+use database::tag::Tag;
+use database::DatabaseError;
 
 use events::Message;
 use events::PushEventType;
 
 use std::collections::HashMap;
-use std::convert::Infallible;
 use std::path::Path;
 
 use slog::Logger;
@@ -23,6 +37,7 @@ use warp::http::StatusCode;
 use warp::reply;
 
 use serde::Serialize;
+use serde_json::json;
 
 pub mod filters {
     use warp::reject;
@@ -31,9 +46,11 @@ pub mod filters {
     use super::super::global_filters::with_db;
 
     use auth::Wrapper as Auth;
+    use serde::Deserialize;
 
     use database::DbConnection;
 
+    use super::super::global_filters::with_logger;
     use super::super::global_filters::with_state;
     use super::*;
 
@@ -46,7 +63,11 @@ pub mod filters {
             .and(warp::get())
             .and(with_db(conn))
             .and(auth::with_auth())
-            .and_then(super::library_get)
+            .and_then(|conn: DbConnection, user: Auth| async move {
+                super::library_get(conn, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
     }
 
     pub fn library_post(
@@ -57,9 +78,9 @@ pub mod filters {
         warp::path!("api" / "v1" / "library")
             .and(warp::post())
             .and(warp::body::json::<InsertableLibrary>())
-            .and(auth::with_auth())
+            .and(auth::with_role(auth::Role::Admin))
             .and(with_state::<EventTx>(event_tx))
-            .and(with_state::<slog::Logger>(logger))
+            .and(with_logger(logger))
             .and(with_state::<DbConnection>(conn))
             .and_then(
                 |new_library: InsertableLibrary,
@@ -74,18 +95,135 @@ pub mod filters {
             )
     }
 
+    pub fn library_update(
+        conn: DbConnection,
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            force: Option<bool>,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64)
+            .and(warp::patch())
+            .and(warp::body::json::<InsertableLibrary>())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and(with_logger(logger))
+            .and(with_state::<EventTx>(event_tx))
+            .and_then(
+                |id: i64,
+                 library: InsertableLibrary,
+                 QueryArgs { force }: QueryArgs,
+                 user: Auth,
+                 conn: DbConnection,
+                 logger: slog::Logger,
+                 event_tx: EventTx| async move {
+                    super::library_update(
+                        conn,
+                        id,
+                        library,
+                        force.unwrap_or(false),
+                        logger,
+                        event_tx,
+                        user,
+                    )
+                    .await
+                    .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn library_set_order(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "order")
+            .and(warp::put())
+            .and(warp::body::json::<Vec<i64>>())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|ids: Vec<i64>, user: Auth, conn: DbConnection| async move {
+                super::library_set_order(conn, ids, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_duplicates(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "duplicates")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|user: Auth, conn: DbConnection| async move {
+                super::library_duplicates(conn, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn library_flag_duplicates(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "duplicates")
+            .and(warp::post())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|user: Auth, conn: DbConnection| async move {
+                super::library_flag_duplicates(conn, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
     pub fn library_delete(
         conn: DbConnection,
         event_tx: EventTx,
+        state: StateManager,
+        stream_tracking: StreamTracking,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("api" / "v1" / "library" / i64)
             .and(warp::delete())
-            .and(auth::with_auth())
+            .and(auth::with_role(auth::Role::Admin))
             .and(with_state::<DbConnection>(conn))
             .and(with_state::<EventTx>(event_tx))
+            .and(with_state::<StateManager>(state))
+            .and(with_state::<StreamTracking>(stream_tracking))
             .and_then(
-                |id: i64, user: Auth, conn: DbConnection, event_tx: EventTx| async move {
-                    super::library_delete(id, user, conn, event_tx)
+                |id: i64,
+                 user: Auth,
+                 conn: DbConnection,
+                 event_tx: EventTx,
+                 state: StateManager,
+                 stream_tracking: StreamTracking| async move {
+                    super::library_delete(id, user, conn, event_tx, state, stream_tracking)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn analyze_library(
+        conn: DbConnection,
+        logger: slog::Logger,
+        event_tx: EventTx,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "analyze")
+            .and(warp::post())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and(with_logger(logger))
+            .and(with_state::<EventTx>(event_tx))
+            .and_then(
+                |id: i64,
+                 _user: Auth,
+                 conn: DbConnection,
+                 logger: slog::Logger,
+                 event_tx: EventTx| async move {
+                    super::analyze_library(id, conn, logger, event_tx)
                         .await
                         .map_err(|e| reject::custom(e))
                 },
@@ -109,15 +247,60 @@ pub mod filters {
     pub fn get_all_of_library(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            tag: Option<i64>,
+            sort: Option<SortBy>,
+            order: Option<SortOrder>,
+        }
+
         warp::path!("api" / "v1" / "library" / i64 / "media")
             .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
             .and(auth::with_auth())
             .and(with_state::<DbConnection>(conn))
-            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
-                super::get_all_library(conn, id, user)
+            .and_then(
+                |id: i64, args: QueryArgs, user: Auth, conn: DbConnection| async move {
+                    super::get_all_library(
+                        conn,
+                        id,
+                        args.tag,
+                        args.sort.unwrap_or_default(),
+                        args.order,
+                        user,
+                    )
                     .await
                     .map_err(|e| reject::custom(e))
-            })
+                },
+            )
+    }
+
+    pub fn get_ignore_rules(
+        conn: DbConnection,
+        log: slog::Logger,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            path: Option<String>,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64 / "ignore_rules")
+            .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and(with_logger(log))
+            .and_then(
+                |id: i64,
+                 QueryArgs { path }: QueryArgs,
+                 user: Auth,
+                 conn: DbConnection,
+                 log: slog::Logger| async move {
+                    super::get_ignore_rules(conn, log, id, path, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
     }
 
     pub fn get_all_unmatched_media(
@@ -133,26 +316,312 @@ pub mod filters {
                     .map_err(|e| reject::custom(e))
             })
     }
+
+    pub fn get_runtimes(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "runtime")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|_user: Auth, conn: DbConnection| async move {
+                super::get_runtimes(conn)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn get_watch_status_counts(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / "watch_status")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|user: Auth, conn: DbConnection| async move {
+                super::get_watch_status_counts(conn, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn set_watched(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct Body {
+            watched: bool,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64 / "watched")
+            .and(warp::post())
+            .and(warp::body::json::<Body>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, Body { watched }: Body, user: Auth, conn: DbConnection| async move {
+                    super::set_watched(conn, id, watched, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn get_media_types(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "media_types")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_media_types(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn get_genres(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "genres")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_genres(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn get_years(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "years")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_years(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn get_random_media(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            media_type: Option<MediaType>,
+            #[serde(default)]
+            unwatched: bool,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64 / "random")
+            .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64,
+                 QueryArgs {
+                     media_type,
+                     unwatched,
+                 }: QueryArgs,
+                 user: Auth,
+                 conn: DbConnection| async move {
+                    super::get_random_media(conn, id, media_type, unwatched, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn shuffle_next(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            show: Option<i64>,
+            current: Option<i64>,
+            recent: Option<i64>,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64 / "shuffle")
+            .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64,
+                 QueryArgs {
+                     show,
+                     current,
+                     recent,
+                 }: QueryArgs,
+                 user: Auth,
+                 conn: DbConnection| async move {
+                    super::shuffle_next(conn, id, show, current, recent, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn get_scan_history(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            limit: Option<i64>,
+        }
+
+        warp::path!("api" / "v1" / "library" / i64 / "scans")
+            .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, QueryArgs { limit }: QueryArgs, user: Auth, conn: DbConnection| async move {
+                    super::get_scan_history(conn, id, limit, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn get_review_queue(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "library" / i64 / "review")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_review_queue(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
 }
 
-/// Method maps to `GET /api/v1/library` and returns a list of all libraries in te database.
-/// This method can only be accessed by authenticated users.
+/// Method maps to `GET /api/v1/library` and returns a list of all libraries in te database,
+/// including the locations they index, sorted by their display order. This method can only be
+/// accessed by authenticated users.
 ///
 /// # Arguments
 /// * `conn` - database connection
 /// * `_log` - logger
 /// * `_user` - Authentication middleware
-pub async fn library_get(conn: DbConnection, _user: Auth) -> Result<impl warp::Reply, Infallible> {
-    Ok(reply::json(&{
-        let mut x = Library::get_all(&conn).await;
-        x.sort_by(|a, b| a.name.cmp(&b.name));
-        x
-    }))
+pub async fn library_get(
+    conn: DbConnection,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&Library::get_all_with_locations(&conn).await?))
+}
+
+/// Method mapped to `PUT /api/v1/library/order` sets the order libraries are listed in. The body
+/// is a list of library ids in the order they should be listed in, libraries missing from the list
+/// are placed after the ones listed.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `ids` - ids of the libraries in their new order
+/// * `_user` - Auth middleware
+pub async fn library_set_order(
+    conn: DbConnection,
+    ids: Vec<i64>,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Library::set_order(&conn, &ids).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Method mapped to `GET /api/v1/library/duplicates` is an integrity check which lists the files
+/// that are indexed by more than one library, which happens when the locations of libraries
+/// overlap. The copy indexed first is listed first. Copies are flagged for review through
+/// [`POST /api/v1/library/duplicates`](library_flag_duplicates).
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `_user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// [
+///     {
+///         "target_file": string,
+///         "files": [
+///             {
+///                 "id": int,
+///                 "library_id": int,
+///                 "media_id": int | null,
+///                 "needs_review": bool,
+///             }
+///         ]
+///     }
+/// ]
+/// ```
+pub async fn library_duplicates(
+    conn: DbConnection,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    // copies of the same file are returned next to each other.
+    let mut result: Vec<(String, Vec<serde_json::Value>)> = vec![];
+    for file in MediaFile::get_cross_library_duplicates(&conn).await? {
+        let entry = json!({
+            "id": file.id,
+            "library_id": file.library_id,
+            "media_id": file.media_id,
+            "needs_review": file.needs_review,
+        });
+
+        match result.last_mut() {
+            Some((target_file, files)) if *target_file == file.target_file => files.push(entry),
+            _ => result.push((file.target_file, vec![entry])),
+        }
+    }
+
+    Ok(reply::json(
+        &result
+            .into_iter()
+            .map(|(target_file, files)| json!({ "target_file": target_file, "files": files }))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Method mapped to `POST /api/v1/library/duplicates` flags every copy of a file indexed by more
+/// than one library for review, except for the copy that was indexed first. Method can only be
+/// accessed by admins.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `_user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "flagged": int,
+/// }
+/// ```
+pub async fn library_flag_duplicates(
+    conn: DbConnection,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let flagged = MediaFile::flag_cross_library_duplicates(&conn).await?;
+
+    Ok(reply::json(&json!({ "flagged": flagged })))
 }
 
 /// Method maps to `POST /api/v1/library`, it adds a new library to the database, starts a new
 /// scanner for it, then dispatches a event to all clients notifying them that a new library has
-/// been created. This method can only be accessed by authenticated users. Method returns 200 OK
+/// been created. This method can only be accessed by admins. Method returns 200 OK
+///
+/// If the library fails validation nothing is created and `422` is returned with a map of the
+/// invalid fields to their errors under `fields`, ie `{"name": "...", "locations.0": "..."}`.
 ///
 /// # Arguments
 /// * `conn` - database connection
@@ -166,6 +635,12 @@ pub async fn library_post(
     event_tx: EventTx,
     _user: Auth,
 ) -> Result<impl warp::Reply, errors::DimError> {
+    let errors = new_library.validate(&conn).await?;
+
+    if !errors.is_empty() {
+        return Err(errors::DimError::InvalidLibrary { errors });
+    }
+
     let id = new_library.insert(&conn).await?;
     let tx_clone = event_tx.clone();
     let log_clone = log.clone();
@@ -198,26 +673,109 @@ pub async fn library_post(
     Ok(StatusCode::CREATED)
 }
 
+/// Method mapped to `PATCH /api/v1/library/<id>` updates the name, media type and locations of a
+/// library in one go. Locations missing from the body are removed and new locations are scanned.
+/// Media only indexed under removed locations are purged. A `EventUpdateLibrary` event is
+/// dispatched if the library was renamed. Empty names, names of other libraries and locations
+/// which dont exist or overlap with other locations are rejected with `InvalidLibrary`, in which
+/// case nothing is changed.
+/// Changing the media type of a library which already contains media requires `?force=true`.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library to update
+/// * `library` - new name, media type and locations
+/// * `force` - whether to change the media type of a library which has media
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+/// * `_user` - Auth middleware
+pub async fn library_update(
+    conn: DbConnection,
+    id: i64,
+    library: InsertableLibrary,
+    force: bool,
+    log: Logger,
+    event_tx: EventTx,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let current = Library::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    if current.media_type != library.media_type
+        && !force
+        && !Library::get_media_types(&conn, id).await?.is_empty()
+    {
+        return Err(errors::DimError::LibraryNotEmpty);
+    }
+
+    let updated = Library::update(&conn, id, library)
+        .await
+        .map_err(|e| match e {
+            DatabaseError::InvalidName(x) => errors::DimError::InvalidLibrary {
+                errors: std::iter::once(("name".to_string(), x)).collect(),
+            },
+            DatabaseError::InvalidLocation(x) => errors::DimError::InvalidLibrary {
+                errors: std::iter::once(("locations".to_string(), x)).collect(),
+            },
+            e => e.into(),
+        })?;
+
+    if updated.name != current.name {
+        let event = Message {
+            id,
+            event_type: PushEventType::EventUpdateLibrary,
+        };
+
+        let _ = event_tx.send(event.to_string());
+    }
+
+    if updated
+        .locations
+        .iter()
+        .any(|x| !current.locations.contains(x))
+    {
+        tokio::spawn(async move {
+            let _ = scanners::start(id, log, event_tx).await;
+        });
+    }
+
+    Ok(reply::json(&updated))
+}
+
 /// Method mapped to `DELETE /api/v1/library/<id>` is used to delete a library from the database.
-/// It deletes the database based on the parameter `id`, then dispatches a event notifying all
-/// clients that the database with this id has been removed. Method can only be accessed by
-/// authenticated users.
+/// It deletes the database based on the parameter `id` alongside its media, files and locations,
+/// kills every stream of a file of the library, then dispatches a event notifying all clients
+/// that the database with this id has been removed. Method can only be accessed by admins.
 ///
 /// # Arguments:
 /// * `conn` - database connection
 /// * `id` - id of the library we want to delete
 /// * `event_tx` - channel over which to dispatch events
+/// * `state` - stream manager
+/// * `stream_tracking` - tracked stream sessions
 /// * `_user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "libraries": int,
+///     "media": int,
+///     "mediafiles": int,
+///     "locations": int,
+/// }
+/// ```
 // NOTE: Should we only allow the owner to add/remove libraries?
 pub async fn library_delete(
     id: i64,
     _user: Auth,
     conn: DbConnection,
     event_tx: EventTx,
+    state: StateManager,
+    stream_tracking: StreamTracking,
 ) -> Result<impl warp::Reply, errors::DimError> {
-    Media::delete_by_lib_id(&conn, id).await?;
-    MediaFile::delete_by_lib_id(&conn, id).await?;
-    Library::delete(&conn, id).await?;
+    let deleted = Library::delete(&conn, id).await?;
+    stream_tracking.kill_library(&state, id).await;
 
     let event = Message {
         id,
@@ -226,7 +784,34 @@ pub async fn library_delete(
 
     let _ = event_tx.send(serde_json::to_string(&event).unwrap());
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(reply::json(&deleted))
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/analyze` probes the files of a library that are
+/// missing technical metadata such as the resolution, codec or duration and fills it in. The
+/// filesystem isnt walked again and no metadata is fetched from providers. The analysis runs in
+/// the background.
+///
+/// # Arguments
+/// * `id` - id of the library to analyze
+/// * `conn` - database connection
+/// * `log` - logger
+/// * `event_tx` - channel over which to dispatch events
+pub async fn analyze_library(
+    id: i64,
+    conn: DbConnection,
+    log: Logger,
+    event_tx: EventTx,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Library::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    tokio::spawn(async move {
+        let _ = scanners::analyze(id, log, event_tx).await;
+    });
+
+    Ok(StatusCode::ACCEPTED)
 }
 
 /// Method mapped to `GET /api/v1/library/<id>` returns info about the library with the supplied
@@ -250,11 +835,19 @@ pub async fn get_self(
 /// # Arguments
 /// * `conn` - database connection
 /// * `id` - id of the library we want media of
-/// * `_user` - Auth middleware
+/// * `tag` - optional id of a personal tag, if supplied only media the user tagged with it are
+/// returned.
+/// * `sort` - key to sort the media by
+/// * `order` - direction to sort in, defaults to the direction of the sort key, ascending for
+/// names and descending for everything else.
+/// * `user` - Auth middleware
 pub async fn get_all_library(
     conn: DbConnection,
     id: i64,
-    _user: Auth,
+    tag: Option<i64>,
+    sort: SortBy,
+    order: Option<SortOrder>,
+    user: Auth,
 ) -> Result<impl warp::Reply, errors::DimError> {
     let mut result = HashMap::new();
     let lib = Library::get_one(&conn, id).await?;
@@ -266,24 +859,107 @@ pub async fn get_all_library(
         poster_path: Option<String>,
     }
 
-    let mut data = sqlx::query_as!(
-        Record,
-        r#"SELECT _tblmedia.id, name, assets.local_path as poster_path FROM _tblmedia
-        LEFT JOIN assets ON _tblmedia.poster = assets.id
-        WHERE library_id = ? AND NOT media_type = "episode""#,
-        id
-    )
-    .fetch_all(&conn)
-    .await
-    .map_err(|_| errors::DimError::NotFoundError)?;
+    let mut media = if let Some(tag_id) = tag {
+        Tag::get_media_of_library(&conn, user.0.claims.get_user(), id, tag_id).await?
+    } else {
+        Media::get_all(&conn, id).await?
+    };
+
+    sort.sort(order, &mut media);
 
-    data.sort_by(|a, b| a.name.cmp(&b.name));
+    let data = media
+        .into_iter()
+        .map(|x| Record {
+            id: x.id,
+            poster_path: fetcher::poster_or_title_card(x.poster_path, &x.name),
+            name: x.name,
+        })
+        .collect::<Vec<_>>();
 
     result.insert(lib.name, data);
 
     Ok(reply::json(&result))
 }
 
+/// Method mapped to `GET /api/v1/library/<id>/ignore_rules` returns the rules the scanner uses to
+/// decide which files of a library are skipped. If `path` is supplied the route also reports
+/// whether that file would be skipped and why.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `log` - logger
+/// * `id` - id of the library
+/// * `path` - optional path of a file to check against the rules
+/// * `_user` - auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "locations": [string],
+///     "extensions": [string],
+///     "ignore_hidden": bool,
+///     "max_depth": int | null,
+///     "network": bool,
+///     "untitled_files": "skip" | "unmatched" | "review",
+///     "path": string | null,
+///     "ignored": bool | null,
+///     "reason": string | null,
+/// }
+/// ```
+pub async fn get_ignore_rules(
+    conn: DbConnection,
+    log: Logger,
+    id: i64,
+    path: Option<String>,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let library = Library::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+    let settings = crate::get_global_settings();
+
+    let mut reason = None;
+    if let Some(path) = path.as_ref() {
+        let path = Path::new(path);
+        reason = scanners::ignore_reason(id, &library.locations, path);
+
+        if reason.is_none() && settings.untitled_files == UntitledFiles::Skip {
+            let stem = path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or_default()
+                .to_string();
+
+            let multipart = matches!(library.media_type, MediaType::Movie | MediaType::Mixed)
+                && settings.join_multipart_movies;
+
+            let untitled =
+                match parse_filename(&log, stem, settings.filename_strip_tags, multipart).await {
+                    Ok(x) => release_tags::is_generic_title(&x.title),
+                    Err(_) => true,
+                };
+
+            if untitled {
+                reason = Some(
+                    "the file has no recognizable title and `untitled_files` is `skip`".into(),
+                );
+            }
+        }
+    }
+
+    Ok(reply::json(&json!({
+        "locations": library.locations,
+        "extensions": scanners::SUPPORTED_EXTS,
+        "ignore_hidden": settings.ignore_hidden_files,
+        "max_depth": settings.max_scan_depth.get(&id),
+        "network": settings.network_libraries.contains(&id),
+        "untitled_files": settings.untitled_files,
+        "path": path,
+        "ignored": path.as_ref().map(|_| reason.is_some()),
+        "reason": reason,
+    })))
+}
+
 /// Method mapped to `GET` /api/v1/library/<id>/unmatched` returns a list of all unmatched medias
 /// to be displayed in the library pages.
 ///
@@ -305,10 +981,10 @@ pub async fn get_all_unmatched_media(
         name: String,
         duration: Option<i64>,
         target_file: String,
+        poster_path: Option<String>,
     }
 
-    sqlx::query_as!(
-        Record,
+    sqlx::query!(
         r#"SELECT id, raw_name as name, duration, target_file FROM mediafile
         WHERE library_id = ? AND media_id IS NULL"#,
         id
@@ -317,6 +993,13 @@ pub async fn get_all_unmatched_media(
     .await
     .map_err(|_| errors::DimError::NotFoundError)?
     .into_iter()
+    .map(|x| Record {
+        id: x.id,
+        poster_path: fetcher::poster_or_title_card(None, &x.name),
+        name: x.name,
+        duration: x.duration,
+        target_file: x.target_file,
+    })
     .map(|x| {
         let mut path = Path::new(&x.target_file).to_path_buf();
         let file_name = path.file_name().unwrap().to_string_lossy().to_string();
@@ -333,3 +1016,300 @@ pub async fn get_all_unmatched_media(
 
     Ok(reply::json(&result))
 }
+
+/// Method mapped to `GET /api/v1/library/<id>/scans` returns the summaries of the most recent
+/// scans of a library, newest first.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `limit` - maximum number of scans to return, defaults to 20
+/// * `_user` - auth middleware
+///
+/// # Return Schema
+/// ```text
+/// [
+///     {
+///         "id": int,
+///         "library_id": int,
+///         "finished_at": int,
+///         "duration": int,
+///         "items": int,
+///         "added": int,
+///     }
+/// ]
+/// ```
+pub async fn get_scan_history(
+    conn: DbConnection,
+    id: i64,
+    limit: Option<i64>,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Library::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    Ok(reply::json(
+        &ScanHistory::get_recent(&conn, id, limit.unwrap_or(20)).await?,
+    ))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/review` returns the review queue of a library. These
+/// are all the files the scanner couldnt confidently match, alongside the title and year parsed
+/// from their filenames, so that they can be matched manually.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - auth middleware
+pub async fn get_review_queue(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    #[derive(Serialize)]
+    struct Record {
+        id: i64,
+        media_id: Option<i64>,
+        raw_name: String,
+        raw_year: Option<i64>,
+        season: Option<i64>,
+        episode: Option<i64>,
+        duration: Option<i64>,
+        target_file: String,
+    }
+
+    let queue = MediaFile::get_review_queue(&conn, id)
+        .await?
+        .into_iter()
+        .map(|x| Record {
+            id: x.id,
+            media_id: x.media_id,
+            raw_name: x.raw_name,
+            raw_year: x.raw_year,
+            season: x.season,
+            episode: x.episode,
+            duration: x.duration,
+            target_file: x.target_file,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(reply::json(&queue))
+}
+
+/// Method mapped to `GET /api/v1/library/runtime` returns the total runtime of the movies and
+/// episodes in every library and across all libraries in seconds. Media with a unknown duration
+/// are not counted.
+///
+/// # Arguments
+/// * `conn` - database connection
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "total": int,
+///     "libraries": [{
+///         "library_id": int,
+///         "runtime": int,
+///     }]
+/// }
+/// ```
+pub async fn get_runtimes(conn: DbConnection) -> Result<impl warp::Reply, errors::DimError> {
+    let libraries = Library::get_runtimes(&conn).await?;
+    let total: i64 = libraries.iter().map(|x| x.runtime).sum();
+
+    Ok(reply::json(&json!({
+        "total": total,
+        "libraries": libraries,
+    })))
+}
+
+/// Method mapped to `GET /api/v1/library/watch_status` returns for every library the number of
+/// movies and episodes the user has watched, started watching or not watched yet.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `user` - auth middleware
+///
+/// # Return Schema
+/// ```text
+/// [{
+///     "library_id": int,
+///     "watched": int,
+///     "in_progress": int,
+///     "unwatched": int,
+/// }]
+/// ```
+pub async fn get_watch_status_counts(
+    conn: DbConnection,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(
+        &Progress::get_watch_status_counts(&conn, user.0.claims.get_user()).await?,
+    ))
+}
+
+/// Method mapped to `POST /api/v1/library/<id>/watched` marks every movie and episode of a library
+/// as watched or unwatched for the user. Media whose duration is unknown cant be marked as
+/// watched and are skipped.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `watched` - whether to mark the media as watched or unwatched
+/// * `user` - auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "updated": int,
+/// }
+/// ```
+pub async fn set_watched(
+    conn: DbConnection,
+    id: i64,
+    watched: bool,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Library::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    let updated = Progress::set_for_library(&conn, &user.0.claims.get_user(), id, watched).await?;
+
+    Ok(reply::json(&json!({ "updated": updated })))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/media_types` returns the distinct media types of
+/// the media present in a library, ie `["movie", "tv"]`.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - auth middleware
+pub async fn get_media_types(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&Library::get_media_types(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/genres` returns every genre used by the movies and
+/// tv shows of a library alongside the number of media tagged with it, most used genres first.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - auth middleware
+///
+/// # Return Schema
+/// ```text
+/// [
+///     {
+///         "id": int,
+///         "name": string,
+///         "count": int,
+///     }
+/// ]
+/// ```
+pub async fn get_genres(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&Genre::get_distribution(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/years` returns the distinct release years of the
+/// movies and tv shows of a library oldest first, ie `[1999, 2020]`. Used to only offer years
+/// that have media when filtering by year.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `_user` - auth middleware
+pub async fn get_years(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&Library::get_years(&conn, id).await?))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/random` returns a random movie or tv show from a
+/// library.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `media_type` - optional media type to pick, defaults to the media type of the library
+/// * `unwatched` - if true, only media the user hasnt started watching are picked
+/// * `user` - auth middleware
+pub async fn get_random_media(
+    conn: DbConnection,
+    id: i64,
+    media_type: Option<MediaType>,
+    unwatched: bool,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let media_type = match media_type {
+        Some(x) => x,
+        None => Library::get_one(&conn, id).await?.media_type,
+    };
+
+    let unwatched_by = if unwatched {
+        Some(user.0.claims.get_user())
+    } else {
+        None
+    };
+
+    let media = Media::random(&conn, id, media_type, unwatched_by)
+        .await?
+        .ok_or(errors::DimError::NotFoundError)?;
+
+    Ok(reply::json(&media))
+}
+
+/// Method mapped to `GET /api/v1/library/<id>/shuffle` returns a random movie or episode to play
+/// next in shuffle mode. The media the user played most recently are skipped so that shuffle
+/// mode doesnt repeat itself.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the library
+/// * `show` - if supplied, only episodes of this tv show are picked
+/// * `current` - id of the media being played, it is never picked
+/// * `recent` - number of recently played media to skip, defaults to 5
+/// * `user` - auth middleware
+pub async fn shuffle_next(
+    conn: DbConnection,
+    id: i64,
+    show: Option<i64>,
+    current: Option<i64>,
+    recent: Option<i64>,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let scope = match show {
+        Some(show) => {
+            if Media::get(&conn, show).await?.library_id != id {
+                return Err(errors::DimError::NotFoundError);
+            }
+
+            ShuffleScope::Show(show)
+        }
+        None => ShuffleScope::Library(id),
+    };
+
+    let media = Media::shuffle_next(
+        &conn,
+        scope,
+        &user.0.claims.get_user(),
+        current,
+        recent.unwrap_or(5),
+    )
+    .await?
+    .ok_or(errors::DimError::NotFoundError)?;
+
+    Ok(reply::json(&media))
+}
@@ -0,0 +1,172 @@
+use crate::core::DbConnection;
+use crate::errors;
+
+use database::marker::InsertableMarker;
+use database::marker::Marker;
+use database::marker::MarkerKind;
+use database::marker::MarkerSource;
+
+use warp::http::status::StatusCode;
+use warp::reply;
+
+pub mod filters {
+    use warp::reject;
+    use warp::Filter;
+
+    use super::super::global_filters::with_state;
+    use auth::Wrapper as Auth;
+    use serde::Deserialize;
+
+    use database::marker::MarkerKind;
+    use database::DbConnection;
+
+    #[derive(Deserialize)]
+    struct MarkerArgs {
+        kind: MarkerKind,
+        start_ms: i64,
+        end_ms: i64,
+    }
+
+    #[derive(Deserialize)]
+    struct KindArgs {
+        kind: MarkerKind,
+    }
+
+    pub fn get_media_markers(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "markers")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, _user: Auth, conn: DbConnection| async move {
+                super::get_media_markers(conn, id)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn set_media_marker(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "markers")
+            .and(warp::put())
+            .and(warp::body::json::<MarkerArgs>())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64,
+                 MarkerArgs {
+                     kind,
+                     start_ms,
+                     end_ms,
+                 }: MarkerArgs,
+                 _user: Auth,
+                 conn: DbConnection| async move {
+                    super::set_media_marker(conn, id, kind, start_ms, end_ms)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn delete_media_marker(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "markers")
+            .and(warp::delete())
+            .and(warp::query::query::<KindArgs>())
+            .and(auth::with_role(auth::Role::Admin))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, KindArgs { kind }: KindArgs, _user: Auth, conn: DbConnection| async move {
+                    super::delete_media_marker(conn, id, kind)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+}
+
+/// Method mapped to `GET /api/v1/media/<id>/markers` returns the intro and credit markers of a
+/// media sorted by when they start.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+///
+/// # Return Schema
+/// ```text
+/// [
+///     {
+///         "id": int,
+///         "media_id": int,
+///         "kind": "intro" | "credits",
+///         "start_ms": int,
+///         "end_ms": int,
+///         "source": "manual" | "detected",
+///     }
+/// ]
+/// ```
+pub async fn get_media_markers(
+    conn: DbConnection,
+    id: i64,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(&Marker::get_of_media(&conn, id).await?))
+}
+
+/// Method mapped to `PUT /api/v1/media/<id>/markers` sets a marker of a media, replacing the
+/// marker of the same kind. Markers set through this route are never replaced by detected ones.
+/// Only admins can set markers.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `kind` - kind of the marker
+/// * `start_ms` - offset in milliseconds at which the marker starts
+/// * `end_ms` - offset in milliseconds at which the marker ends
+pub async fn set_media_marker(
+    conn: DbConnection,
+    id: i64,
+    kind: MarkerKind,
+    start_ms: i64,
+    end_ms: i64,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if start_ms < 0 || start_ms >= end_ms {
+        return Err(errors::DimError::InvalidMarker);
+    }
+
+    // make sure the media actually exists before attaching a marker to it.
+    let _ = database::media::Media::get(&conn, id).await?;
+
+    InsertableMarker {
+        media_id: id,
+        kind,
+        start_ms,
+        end_ms,
+        source: MarkerSource::Manual,
+    }
+    .insert(&conn)
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Method mapped to `DELETE /api/v1/media/<id>/markers?kind=<kind>` deletes a marker of a media.
+/// Only admins can delete markers.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `kind` - kind of the marker
+pub async fn delete_media_marker(
+    conn: DbConnection,
+    id: i64,
+    kind: MarkerKind,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if Marker::delete(&conn, id, kind).await? == 0 {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,17 +1,30 @@
 use crate::core::DbConnection;
+use crate::core::EventTx;
 use crate::errors;
+use crate::fetcher;
 use crate::json;
 
 use auth::Wrapper as Auth;
 use std::convert::Infallible;
+use std::fs;
+use std::path::PathBuf;
 
+use database::asset::Asset;
 use database::episode::Episode;
 use database::genre::Genre;
+use database::library::Library;
 use database::library::MediaType;
 use database::media::Media;
+use database::media::SortBy;
+use database::media::SortOrder;
 use database::media::UpdateMedia;
 use database::mediafile::MediaFile;
+use database::note::Note;
 use database::progress::Progress;
+use database::trailer::Trailer;
+
+use events::Message;
+use events::PushEventType;
 
 use warp::http::status::StatusCode;
 use warp::reply;
@@ -20,10 +33,14 @@ pub mod filters {
     use warp::reject;
     use warp::Filter;
 
+    use crate::core::EventTx;
+
     use super::super::global_filters::with_state;
     use auth::Wrapper as Auth;
     use serde::Deserialize;
 
+    use database::media::SortBy;
+    use database::media::SortOrder;
     use database::media::UpdateMedia;
     use database::DbConnection;
 
@@ -55,29 +72,72 @@ pub mod filters {
             })
     }
 
+    pub fn get_media_neighbors(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct RouteArgs {
+            sort: Option<SortBy>,
+            order: Option<SortOrder>,
+        }
+
+        warp::path!("api" / "v1" / "media" / i64 / "neighbors")
+            .and(warp::get())
+            .and(warp::query::query::<RouteArgs>())
+            .and(with_state::<DbConnection>(conn))
+            .and(auth::with_auth())
+            .and_then(
+                |id: i64, args: RouteArgs, conn: DbConnection, user: Auth| async move {
+                    super::get_media_neighbors(
+                        conn,
+                        id,
+                        args.sort.unwrap_or_default(),
+                        args.order,
+                        user,
+                    )
+                    .await
+                    .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn update_media_by_id(
         conn: DbConnection,
+        event_tx: EventTx,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("api" / "v1" / "media" / i64)
             .and(warp::patch())
             .and(warp::body::json::<UpdateMedia>())
             .and(auth::with_auth())
             .and(with_state::<DbConnection>(conn))
+            .and(with_state::<EventTx>(event_tx))
             .and_then(super::update_media_by_id)
     }
 
     pub fn delete_media_by_id(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct RouteArgs {
+            #[serde(default)]
+            delete_file: bool,
+        }
+
         warp::path!("api" / "v1" / "media" / i64)
             .and(warp::delete())
+            .and(warp::query::query::<RouteArgs>())
             .and(auth::with_auth())
             .and(with_state::<DbConnection>(conn))
-            .and_then(|id: i64, auth: Auth, conn: DbConnection| async move {
-                super::delete_media_by_id(conn, id, auth)
-                    .await
-                    .map_err(|e| reject::custom(e))
-            })
+            .and_then(
+                |id: i64,
+                 RouteArgs { delete_file }: RouteArgs,
+                 auth: Auth,
+                 conn: DbConnection| async move {
+                    super::delete_media_by_id(conn, id, delete_file, auth)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
     }
 
     pub fn tmdb_search() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
@@ -150,8 +210,10 @@ pub mod filters {
 ///     "backdrop_path": string | uri_path,
 ///     "media_type": string | enum,
 ///     "genres": [string],
+///     "trailers": [{"name": string, "url": string | uri}],
 ///     "duration": int,
 ///     "duration_pretty": string,
+///     "note": string | null,
 /// }
 /// ```
 ///
@@ -165,8 +227,8 @@ pub async fn get_media_by_id(
     let media = Media::get(&conn, id).await?;
 
     let media_id = match media.media_type {
-        MediaType::Movie | MediaType::Episode => id,
         MediaType::Tv => Episode::get_first_for_show(&conn, id).await?.id,
+        _ => id,
     };
 
     // TODO: at some point we want to issue a warning to the UI that none of the mediafiles with
@@ -187,6 +249,8 @@ pub async fn get_media_by_id(
         .map(|x| x.name)
         .collect::<Vec<String>>();
 
+    let trailers = Trailer::get_of_media(&conn, id).await.unwrap_or_default();
+
     let progress = match media.media_type {
         MediaType::Episode | MediaType::Movie => {
             Progress::get_for_media_user(&conn, user.0.claims.get_user(), id)
@@ -237,8 +301,14 @@ pub async fn get_media_by_id(
                 }))
             }
         }
+        MediaType::Mixed | MediaType::Audio => None,
     };
 
+    let note = Note::get(&conn, user.0.claims.get_user(), id)
+        .await
+        .unwrap_or_default()
+        .map(|x| x.content);
+
     let season_episode_tag = match media.media_type {
         MediaType::Episode => {
             let result = Episode::get_season_episode_by_id(&conn, id).await?;
@@ -264,6 +334,11 @@ pub async fn get_media_by_id(
         "media_type": media.media_type,
         "genres": genres,
         "duration": duration,
+        "trailers": trailers.iter().map(|x| json!({
+            "name": x.name,
+            "url": x.url,
+        })).collect::<Vec<_>>(),
+        "note": note,
         ..?season_episode_tag,
         ..?progress
     })))
@@ -277,45 +352,169 @@ pub async fn get_media_files(
     Ok(reply::json(&mediafiles))
 }
 
+/// Method mapped to `GET /api/v1/media/<id>/neighbors` returns the media before and after a media
+/// in its library, used by the prev/next arrows of the detail page. The library is sorted the same
+/// way as `GET /api/v1/library/<id>/media`.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media we want the neighbors of
+/// * `sort` - key the library is sorted by
+/// * `order` - direction to sort in, defaults to the direction of the sort key
+/// * `_user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "prev": { "id": int, "name": string, "poster_path": string | null } | null,
+///     "next": { "id": int, "name": string, "poster_path": string | null } | null,
+/// }
+/// ```
+pub async fn get_media_neighbors(
+    conn: DbConnection,
+    id: i64,
+    sort: SortBy,
+    order: Option<SortOrder>,
+    _user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let media = Media::get(&conn, id).await?;
+    let (prev, next) = Media::neighbors(&conn, media.library_id, id, sort, order).await?;
+
+    let record = |x: Media| {
+        json!({
+            "id": x.id,
+            "poster_path": fetcher::poster_or_title_card(x.poster_path, &x.name),
+            "name": x.name,
+        })
+    };
+
+    Ok(reply::json(&json!({
+        "prev": prev.map(record),
+        "next": next.map(record),
+    })))
+}
+
 /// Method mapped to `PATCH /api/v1/media/<id>` is used to edit information about a media entry
-/// manually. It is used in the web ui to manually edit metadata of a media.
+/// manually. It is used in the web ui to manually edit metadata of a media. A `EventUpdateCard`
+/// event is dispatched if the media was updated.
 ///
 /// # Arguments
 /// * `conn` - database connection
 /// * `id` - id of the media we want to edit
 /// * `data` - the info that we changed about the media entry
 /// * `_user` - Auth middleware
+/// * `event_tx` - channel over which to dispatch events
 pub async fn update_media_by_id(
     id: i64,
     data: UpdateMedia,
     _user: Auth,
     conn: DbConnection,
+    event_tx: EventTx,
 ) -> Result<impl warp::Reply, Infallible> {
-    let status = if data.update(&conn, id).await.is_ok() {
-        StatusCode::NO_CONTENT
-    } else {
-        StatusCode::NOT_MODIFIED
+    if data.update(&conn, id).await.is_err() {
+        return Ok(StatusCode::NOT_MODIFIED);
+    }
+
+    let event = Message {
+        id,
+        event_type: PushEventType::EventUpdateCard,
     };
 
-    Ok(status)
+    let _ = event_tx.send(event.to_string());
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// Method mapped to `DELETE /api/v1/media/<id>` is used to delete a media entry for the library.
-/// ONly authenticated users can query this.
+/// The progress users have on the media and its artwork are removed as well. ONly authenticated
+/// users can query this.
+///
+/// The backing files are left untouched unless `delete_file` is set, in which case they are
+/// removed from disk too. Only the owner can delete files and only files inside the locations
+/// indexed by the library of the media are ever removed. Files are removed before the media, if
+/// one of them cant be removed the media is kept.
 ///
 /// # Arguments
 /// * `conn` - database connection
 /// * `id` - id of the media we want to delete
-/// * `_user` - auth middleware
+/// * `delete_file` - whether to also delete the files backing this media
+/// * `user` - auth middleware
 pub async fn delete_media_by_id(
     conn: DbConnection,
     id: i64,
-    _user: Auth,
+    delete_file: bool,
+    user: Auth,
 ) -> Result<impl warp::Reply, errors::DimError> {
+    if delete_file && !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    let media = Media::get(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    // mediafiles get removed alongside the media so we have to collect them beforehand.
+    let files = if delete_file {
+        get_files_in_library(&conn, &media).await?
+    } else {
+        vec![]
+    };
+
+    for file in files {
+        fs::remove_file(file)?;
+    }
+
+    let assets = Asset::get_of_media(&conn, id).await?;
+
     Media::delete(&conn, id).await?;
+
+    let meta_path = PathBuf::from(crate::core::METADATA_PATH.get().unwrap());
+
+    for asset in assets {
+        if Asset::delete_if_unused(&conn, asset.id).await? {
+            let _ = fs::remove_file(meta_path.join(asset.local_path.trim_start_matches("images/")));
+        }
+    }
+
     Ok(StatusCode::OK)
 }
 
+/// Function returns the paths of all files backing a media, for tv shows these are the files of
+/// all of its episodes. Files that dont resolve to a path inside one of the locations indexed by
+/// the library of the media are left out.
+async fn get_files_in_library(
+    conn: &DbConnection,
+    media: &Media,
+) -> Result<Vec<PathBuf>, errors::DimError> {
+    let media_ids = match media.media_type {
+        MediaType::Tv => Episode::get_all_of_tv(conn, media.id)
+            .await?
+            .into_iter()
+            .map(|x| x.id)
+            .collect(),
+        _ => vec![media.id],
+    };
+
+    let locations = Library::get_locations(conn, media.library_id)
+        .await?
+        .into_iter()
+        .filter_map(|x| fs::canonicalize(x).ok())
+        .collect::<Vec<_>>();
+
+    let mut files = vec![];
+
+    for media_id in media_ids {
+        for mediafile in MediaFile::get_of_media(conn, media_id).await? {
+            match fs::canonicalize(&mediafile.target_file) {
+                Ok(path) if locations.iter().any(|x| path.starts_with(x)) => files.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 /// Method mapped to `GET /api/v1/media/tmdb_search` is used to quickly search TMDB based on 3
 /// params, one of which is optional. This is used client side in the rematch utility
 ///
@@ -331,9 +530,8 @@ pub async fn tmdb_search(
 ) -> Result<impl warp::Reply, errors::DimError> {
     use crate::scanners::tmdb::Tmdb;
 
-    let media_type = match media_type.as_ref() {
-        "movie" => MediaType::Movie,
-        "tv" => MediaType::Tv,
+    let media_type = match media_type.parse() {
+        Ok(x @ MediaType::Movie) | Ok(x @ MediaType::Tv) => x,
         _ => return Err(errors::DimError::InvalidMediaType),
     };
 
@@ -341,7 +539,7 @@ pub async fn tmdb_search(
 
     Ok(reply::json(
         &tmdb_session
-            .search_by_name(query, year, None)
+            .search_by_name(query, year)
             .await
             .map_err(|_| errors::DimError::NotFoundError)?
             .into_iter()
@@ -1,18 +1,31 @@
 use crate::core::DbConnection;
+use crate::core::EventTx;
 use crate::errors;
+use crate::streaming::bif;
+use crate::utils::reply_with_range;
 
 use auth::Wrapper as Auth;
 use database::mediafile::MediaFile;
 
+use events::Message;
+use events::PushEventType;
+
 use serde_json::json;
 use warp::http::status::StatusCode;
 use warp::reply;
 
+use std::path::Path;
+use std::path::PathBuf;
+
+use tokio::task::spawn_blocking;
+
 pub mod filters {
     use warp::reject;
     use warp::Filter;
 
+    use super::super::global_filters::with_logger;
     use super::super::global_filters::with_state;
+    use crate::core::EventTx;
     use auth::Wrapper as Auth;
     use database::DbConnection;
 
@@ -32,9 +45,61 @@ pub mod filters {
             })
     }
 
+    pub fn download_mediafile(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "mediafile" / i64 / "download")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(warp::header::optional::<String>("range"))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, auth: Auth, range: Option<String>, conn: DbConnection| async move {
+                    super::download_mediafile(conn, id, auth, range)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn direct_play(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "mediafile" / i64 / "direct")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(warp::header::optional::<String>("range"))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, auth: Auth, range: Option<String>, conn: DbConnection| async move {
+                    super::direct_play(conn, id, auth, range)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn get_bif_thumbnails(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "mediafile" / i64 / "thumbnails.bif")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(warp::header::optional::<String>("range"))
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, auth: Auth, range: Option<String>, conn: DbConnection| async move {
+                    super::get_bif_thumbnails(conn, id, auth, range)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn rematch_mediafile(
         conn: DbConnection,
         log: slog::Logger,
+        event_tx: EventTx,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         #[derive(Deserialize)]
         struct RouteArgs {
@@ -46,18 +111,20 @@ pub mod filters {
             .and(warp::patch())
             .and(auth::with_auth())
             .and(with_state::<DbConnection>(conn))
-            .and(with_state::<slog::Logger>(log))
+            .and(with_logger(log))
+            .and(with_state::<EventTx>(event_tx))
             .and(warp::query::query::<RouteArgs>())
             .and_then(
                 |id: i64,
                  _auth: Auth,
                  conn: DbConnection,
                  log: slog::Logger,
+                 event_tx: EventTx,
                  RouteArgs {
                      tmdb_id,
                      media_type,
                  }: RouteArgs| async move {
-                    super::rematch_mediafile(conn, log, id, tmdb_id, media_type)
+                    super::rematch_mediafile(conn, log, event_tx, id, tmdb_id, media_type)
                         .await
                         .map_err(|e| reject::custom(e))
                 },
@@ -86,20 +153,150 @@ pub async fn get_mediafile_info(
     })))
 }
 
+/// Function returns `DownloadsDisabled` if the library of `mediafile` is listed in
+/// `download_disabled_libraries`. Owners can always download.
+fn check_download(user: &Auth, mediafile: &MediaFile) -> Result<(), errors::DimError> {
+    if !user.0.claims.has_role("owner")
+        && crate::get_global_settings()
+            .download_disabled_libraries
+            .contains(&mediafile.library_id)
+    {
+        return Err(errors::DimError::DownloadsDisabled);
+    }
+
+    Ok(())
+}
+
+/// Method mapped to `GET /api/v1/mediafile/<id>/download` returns the original file as a download.
+/// Range requests are supported so interrupted downloads can be resumed.
+///
+/// # Arguments
+/// * `id` - id of the mediafile to download
+/// * `range` - value of the `Range` header if the client sent one
+///
+/// Libraries listed in `download_disabled_libraries` reject downloads with `403` unless the user
+/// is an owner.
+pub async fn download_mediafile(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+    range: Option<String>,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let mediafile = MediaFile::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    check_download(&user, &mediafile)?;
+
+    let path = PathBuf::from(&mediafile.target_file);
+    let filename = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or(mediafile.raw_name);
+
+    Ok(reply_with_range(path, range, "application/octet-stream", Some(filename)).await)
+}
+
+/// Method mapped to `GET /api/v1/mediafile/<id>/direct` serves the original file without
+/// transcoding it, ie for direct play of music. Range requests are answered with `206 Partial
+/// Content` so clients can seek within the file.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the mediafile
+/// * `user` - Auth middleware
+/// * `range` - value of the `Range` header if the client sent one
+///
+/// Serving the original file is a download as well, so libraries listed in
+/// `download_disabled_libraries` reject direct play with `403` unless the user is an owner.
+pub async fn direct_play(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+    range: Option<String>,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let mediafile = MediaFile::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    check_download(&user, &mediafile)?;
+
+    let path = PathBuf::from(&mediafile.target_file);
+    let content_type = match path.extension().and_then(|x| x.to_str()) {
+        Some("mp3") => "audio/mpeg",
+        Some("flac") => "audio/flac",
+        Some("m4a") | Some("aac") => "audio/mp4",
+        Some("ogg") | Some("opus") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mkv") => "video/x-matroska",
+        _ => "application/octet-stream",
+    };
+
+    Ok(reply_with_range(path, range, content_type, None).await)
+}
+
+/// Method mapped to `GET /api/v1/mediafile/<id>/thumbnails.bif` returns Roku style BIF trick-play
+/// thumbnails for a mediafile. The BIF file is generated on the first request and cached, the
+/// route returns 404 unless the `bif_thumbnails` setting is enabled.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the mediafile
+/// * `_user` - Auth middleware
+/// * `range` - optional range header
+pub async fn get_bif_thumbnails(
+    conn: DbConnection,
+    id: i64,
+    _user: Auth,
+    range: Option<String>,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let settings = crate::get_global_settings();
+    if !settings.bif_thumbnails {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    let mediafile = MediaFile::get_one(&conn, id)
+        .await
+        .map_err(|_| errors::DimError::NotFoundError)?;
+
+    let dir = PathBuf::from(&settings.cache_dir).join("bif");
+    let path = dir.join(format!("{}_{}.bif", id, settings.bif_interval));
+
+    if !path.exists() {
+        std::fs::create_dir_all(&dir)?;
+
+        let target = path.clone();
+        spawn_blocking(move || {
+            bif::generate(
+                Path::new(&mediafile.target_file),
+                &target,
+                settings.bif_interval,
+            )
+        })
+        .await
+        .map_err(|_| errors::DimError::InternalServerError)??;
+    }
+
+    Ok(reply_with_range(path, range, "application/octet-stream", None).await)
+}
+
 /// Method mapped to `PATCH /api/v1/mediafile/<id>/match` used to match a unmatched(orphan)
 /// mediafile to a tmdb id.
 ///
 /// # Arguments
 /// * `conn` - database connection
 /// * `log` - logger
-/// * `event_tx` - websocket channel over which we dispatch a event notifying other clients of the
-/// new metadata
+/// * `event_tx` - websocket channel over which we dispatch a `EventUpdateCard` event notifying
+/// other clients of the new metadata
 ///
 /// * `id` - id of the orphan mediafile we want to rematch
 /// * `tmdb_id` - the tmdb id of the proper metadata we want to fetch for the media
 pub async fn rematch_mediafile(
     conn: DbConnection,
     _log: slog::Logger,
+    event_tx: EventTx,
     id: i64,
     tmdb_id: i32,
     media_type: String,
@@ -110,26 +307,36 @@ pub async fn rematch_mediafile(
     let mediafile = MediaFile::get_one(&conn, id).await?;
     let matcher = crate::scanners::get_matcher_unchecked();
 
-    let mut tmdb = match media_type.to_lowercase().as_ref() {
-        "movie" => Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Movie),
-        "tv" => Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Tv),
+    let media_type = match media_type.to_lowercase().parse() {
+        Ok(x @ MediaType::Movie) | Ok(x @ MediaType::Tv) => x,
         _ => return Err(errors::DimError::InvalidMediaType),
     };
 
+    let mut tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), media_type);
+
     let result = tmdb
         .search_by_id(tmdb_id)
         .await
         .map_err(|_| errors::DimError::NotFoundError)?;
 
-    match media_type.to_lowercase().as_ref() {
-        "movie" => {
+    match media_type {
+        MediaType::Movie => {
             matcher
                 .match_movie_to_result(mediafile, result.into())
                 .await?
         }
-        "tv" => matcher.match_tv_to_result(mediafile, result.into()).await?,
+        MediaType::Tv => matcher.match_tv_to_result(mediafile, result.into()).await?,
         _ => unreachable!(),
     }
 
+    if let Some(media_id) = MediaFile::get_one(&conn, id).await?.media_id {
+        let event = Message {
+            id: media_id,
+            event_type: PushEventType::EventUpdateCard,
+        };
+
+        let _ = event_tx.send(event.to_string());
+    }
+
     Ok(StatusCode::OK)
 }
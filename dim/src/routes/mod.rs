@@ -2,19 +2,26 @@ pub mod auth;
 pub mod dashboard;
 pub mod general;
 pub mod library;
+pub mod marker;
 pub mod media;
 pub mod mediafile;
+pub mod note;
 pub mod settings;
 pub mod statik;
 pub mod stream;
+pub mod tag;
 pub mod tv;
 
 pub mod global_filters {
     use crate::errors;
+    use crate::logger::RequestId;
     use database::DbConnection;
 
+    use slog::o;
+
     use std::convert::Infallible;
     use std::error::Error;
+    use std::net::SocketAddr;
     use warp::Filter;
     use warp::Reply;
 
@@ -30,6 +37,28 @@ pub mod global_filters {
         warp::any().map(move || state.clone())
     }
 
+    /// Peer address of a request. Requests are served through `warp::service` which doesnt hand
+    /// the address of the connection to warp, so it is stored in the extensions of the request.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct RemoteAddr(pub SocketAddr);
+
+    /// Filter extracts the peer address of a request, replaces `warp::addr::remote`.
+    pub fn with_remote_addr(
+    ) -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Clone {
+        warp::ext::optional::<RemoteAddr>().map(|x: Option<RemoteAddr>| x.map(|x| x.0))
+    }
+
+    /// Filter hands out a child of `logger` tagged with the id of the request, so that every log
+    /// line of a request can be correlated.
+    pub fn with_logger(
+        logger: slog::Logger,
+    ) -> impl Filter<Extract = (slog::Logger,), Error = Infallible> + Clone {
+        warp::ext::optional::<RequestId>().map(move |id: Option<RequestId>| match id {
+            Some(id) => logger.new(o!("request_id" => id.to_string())),
+            None => logger.clone(),
+        })
+    }
+
     pub async fn handle_rejection(
         err: warp::reject::Rejection,
     ) -> Result<impl warp::Reply, warp::reject::Rejection> {
@@ -37,6 +66,10 @@ pub mod global_filters {
             return Ok(e.clone().into_response());
         } else if let Some(e) = err.find::<errors::DimError>() {
             return Ok(e.clone().into_response());
+        } else if let Some(auth::JWTError::Expired) = err.find::<auth::JWTError>() {
+            return Ok(errors::DimError::TokenExpired.into_response());
+        } else if let Some(auth::JWTError::Forbidden) = err.find::<auth::JWTError>() {
+            return Ok(errors::DimError::Forbidden.into_response());
         } else if let Some(_) = err.find::<auth::JWTError>() {
             return Ok(errors::DimError::AuthRequired.into_response());
         } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
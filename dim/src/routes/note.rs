@@ -0,0 +1,144 @@
+use crate::core::DbConnection;
+use crate::errors;
+
+use auth::Wrapper as Auth;
+
+use database::note::Note;
+
+use serde_json::json;
+use warp::http::status::StatusCode;
+use warp::reply;
+
+pub mod filters {
+    use warp::reject;
+    use warp::Filter;
+
+    use super::super::global_filters::with_state;
+    use auth::Wrapper as Auth;
+    use serde::Deserialize;
+
+    use database::DbConnection;
+
+    #[derive(Deserialize)]
+    struct NoteArgs {
+        content: String,
+    }
+
+    pub fn get_media_note(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "note")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_media_note(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn set_media_note(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "note")
+            .and(warp::put())
+            .and(warp::body::json::<NoteArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, NoteArgs { content }: NoteArgs, user: Auth, conn: DbConnection| async move {
+                    super::set_media_note(conn, id, content, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn delete_media_note(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "note")
+            .and(warp::delete())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::delete_media_note(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+}
+
+/// Method mapped to `GET /api/v1/media/<id>/note` returns the private note the user attached to a
+/// media.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// {
+///     "content": string | null,
+///     "updated": int | null,
+/// }
+/// ```
+pub async fn get_media_note(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let note = Note::get(&conn, user.0.claims.get_user(), id).await?;
+
+    Ok(reply::json(&json!({
+        "content": note.as_ref().map(|x| x.content.clone()),
+        "updated": note.map(|x| x.updated),
+    })))
+}
+
+/// Method mapped to `PUT /api/v1/media/<id>/note` sets the private note the user attached to a
+/// media, replacing the previous one. Setting an empty note deletes it.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `content` - content of the note
+/// * `user` - Auth middleware
+pub async fn set_media_note(
+    conn: DbConnection,
+    id: i64,
+    content: String,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    // make sure the media actually exists before attaching a note to it.
+    let _ = database::media::Media::get(&conn, id).await?;
+
+    if content.trim().is_empty() {
+        Note::delete(&conn, user.0.claims.get_user(), id).await?;
+    } else {
+        Note::set(&conn, user.0.claims.get_user(), id, content).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Method mapped to `DELETE /api/v1/media/<id>/note` deletes the private note the user attached
+/// to a media.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `user` - Auth middleware
+pub async fn delete_media_note(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if Note::delete(&conn, user.0.claims.get_user(), id).await? == 0 {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
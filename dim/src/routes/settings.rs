@@ -2,6 +2,7 @@ use crate::core::DbConnection;
 use crate::errors;
 use crate::utils::ffpath;
 
+use database::asset::Asset;
 use database::user::UpdateableUser;
 use database::user::User;
 use database::user::UserSettings;
@@ -10,19 +11,182 @@ use auth::Wrapper as Auth;
 use serde::Deserialize;
 use serde::Serialize;
 
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::Read;
 use std::io::Write;
+use std::path::PathBuf;
 use std::sync::Mutex;
 
 use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
 
+use serde_json::json;
 use warp::reply;
 
+/// Behavior of the scanner when ffprobe cant determine the duration of a file.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingDuration {
+    /// Estimate the duration from the bitrate and filesize of the file.
+    Estimate,
+    /// Index the file with an unknown duration.
+    Unknown,
+    /// Dont index the file at all.
+    Skip,
+}
+
+impl Default for MissingDuration {
+    fn default() -> Self {
+        Self::Estimate
+    }
+}
+
+/// Behavior of the scanner for files whose name has no recognizable title, ie `video1.mp4`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UntitledFiles {
+    /// Dont index the file at all.
+    Skip,
+    /// Index the file as unmatched with the raw filename as its title.
+    Unmatched,
+    /// Index the file like `unmatched` and flag it for review.
+    Review,
+}
+
+impl Default for UntitledFiles {
+    fn default() -> Self {
+        Self::Unmatched
+    }
+}
+
+/// What happens to a file that is indexed by more than one library because their locations
+/// overlap.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateFiles {
+    /// Only list the copies in the integrity check.
+    Report,
+    /// Keep the copy indexed first and flag every other copy for review.
+    Flag,
+}
+
+impl Default for DuplicateFiles {
+    fn default() -> Self {
+        Self::Report
+    }
+}
+
+/// What the scanner does with files it couldnt match because the metadata provider is down.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderOutage {
+    /// Leave the file unmatched and flag it for review.
+    Review,
+    /// Leave the file unmatched and match it again once the provider is reachable.
+    Retry,
+}
+
+impl Default for ProviderOutage {
+    fn default() -> Self {
+        Self::Retry
+    }
+}
+
+/// What the scanner does when the files of a movie or tv library look like the other media type,
+/// ie a tv library pointed at movies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaTypeMismatch {
+    /// Log a warning and notify clients but scan the library as its media type.
+    Warn,
+    /// Log a warning and notify clients and scan the library like a mixed library.
+    Mixed,
+}
+
+impl Default for MediaTypeMismatch {
+    fn default() -> Self {
+        Self::Warn
+    }
+}
+
+/// Whether paths that only differ in case point at the same file, this has to match the filesystem
+/// the libraries live on.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathCase {
+    /// `Movie.mkv` and `movie.mkv` are two files, ie on ext4.
+    Sensitive,
+    /// `Movie.mkv` and `movie.mkv` are the same file, ie on NTFS, APFS or most SMB shares.
+    Insensitive,
+}
+
+impl Default for PathCase {
+    fn default() -> Self {
+        if cfg!(any(target_os = "windows", target_os = "macos")) {
+            Self::Insensitive
+        } else {
+            Self::Sensitive
+        }
+    }
+}
+
+/// Image format artwork is converted to when it is served.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtworkFormat {
+    /// Serve WebP to clients that accept it and JPEG to every other client.
+    Webp,
+    /// Always serve JPEG.
+    Jpeg,
+}
+
+impl Default for ArtworkFormat {
+    fn default() -> Self {
+        Self::Webp
+    }
+}
+
+/// What happens when a movie is matched to a tmdb id another movie of the same library was
+/// matched to already, ie when a library holds the same movie twice.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateProviderIds {
+    /// Attach the file to the existing movie as another version.
+    Merge,
+    /// Create a separate movie for the file.
+    Separate,
+    /// Leave the file unmatched and flag it for review.
+    Flag,
+}
+
+impl Default for DuplicateProviderIds {
+    fn default() -> Self {
+        Self::Merge
+    }
+}
+
+/// What segment urls are named after.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentNaming {
+    /// Index of the segment, ie `42.m4s`.
+    Number,
+    /// Start of the segment in milliseconds, ie `210000.m4s`.
+    Time,
+}
+
+impl Default for SegmentNaming {
+    fn default() -> Self {
+        Self::Number
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct GlobalSettings {
     pub enable_ssl: bool,
     pub port: u16,
@@ -36,7 +200,159 @@ pub struct GlobalSettings {
     pub disable_auth: bool,
 
     pub verbose: bool,
+    /// Number of log files kept in `logs/`, older ones are deleted on startup. `0` keeps every
+    /// log file.
+    pub max_log_files: usize,
     pub secret_key: Option<[u8; 16]>,
+
+    /// Number of media segments that must be ready before the init segment of a stream is
+    /// returned to the client. Higher values trade startup latency for a smoother start.
+    pub transcode_prebuffer_segments: u32,
+    /// Number of times a segment request is retried when the underlying transcode fails before an
+    /// error is returned to the client.
+    pub transcode_retries: u32,
+    /// Number of seconds ffprobe may spend on a file before it is killed, so that a probe which
+    /// hangs on a broken file cant wedge a stream request or a scan.
+    pub ffprobe_timeout: u64,
+    /// Codec audio streams are transcoded to, ie `aac` or `ac3`. Clients can override it per
+    /// stream.
+    pub transcode_audio_codec: String,
+    /// Audio codecs that are passed through as is instead of being transcoded, ie `["ac3"]`.
+    pub audio_passthrough_codecs: Vec<String>,
+    /// Maximum video bitrate in bits per second for each network type a client can report, ie
+    /// `{"mobile": 2000000}`. Network types without an entry are not capped.
+    pub network_bitrate_caps: HashMap<String, u64>,
+    /// Number of digits segment names are zero padded to in segment urls, ie `5` yields
+    /// `00042.m4s`. `0` disables padding.
+    pub segment_number_padding: usize,
+    /// Whether segment urls are named after the index or the start time of the segment.
+    pub segment_naming: SegmentNaming,
+
+    /// Page size used by paginated routes when the client doesnt request one.
+    pub default_page_size: i64,
+    /// Largest page size a client can request from paginated routes.
+    pub max_page_size: i64,
+
+    /// What the scanner should do with files for which ffprobe cant determine a duration.
+    pub missing_duration: MissingDuration,
+    /// What the scanner should do with files whose name has no recognizable title. These files
+    /// are never matched automatically.
+    pub untitled_files: UntitledFiles,
+    /// Release tags the scanner strips from filenames before parsing them, ie `PROPER`. Tags are
+    /// matched regardless of case.
+    pub filename_strip_tags: Vec<String>,
+    /// Whether movies split over several files, ie `Movie.CD1.avi` and `Movie.CD2.avi`, are
+    /// joined into a single media with ordered parts.
+    pub join_multipart_movies: bool,
+    /// Minimum confidence from `0.0` to `1.0` a search result needs to be matched to a file. The
+    /// confidence is based on the similarity of the titles and years, files without a confident
+    /// match are queued for review.
+    pub min_match_confidence: f64,
+    /// What happens to a movie file matched to the same tmdb id as another movie in its library.
+    pub duplicate_provider_ids: DuplicateProviderIds,
+    /// What happens to files that couldnt be matched because tmdb was unreachable. Files are
+    /// always indexed with the title parsed from their name.
+    pub provider_outage: ProviderOutage,
+    /// Interval in seconds at which files queued while tmdb was unreachable are matched again.
+    pub provider_retry_interval: u64,
+    /// Number of times a request tmdb rejects because of its rate limit is retried before the
+    /// file is treated as if tmdb was unreachable.
+    pub provider_rate_limit_retries: u32,
+    /// Longest number of seconds we wait before retrying a rate limited request, even if tmdb asks
+    /// for a longer delay.
+    pub provider_rate_limit_max_wait: u64,
+    /// What happens when the files of a movie or tv library dont look like its media type.
+    pub media_type_mismatch: MediaTypeMismatch,
+
+    /// Maximum number of streams a single user can run at once, `0` means no limit. The owner is
+    /// exempt from this limit.
+    pub max_streams_per_user: usize,
+    /// Number of seconds after which a stream session that received no requests is killed, `0`
+    /// disables the cleanup.
+    pub stream_session_timeout: u64,
+    /// Number of seconds after which a transcode none of whose segments were requested is killed
+    /// and its files are removed, `0` disables the cleanup.
+    pub transcode_idle_timeout: u64,
+    /// Interval in seconds at which inactive stream sessions are looked for.
+    pub stream_cleanup_interval: u64,
+    /// Number of seconds a client has to reconnect its websocket before the stream sessions it
+    /// started are killed, `0` kills them as soon as the websocket drops.
+    pub stream_reconnect_grace: u64,
+
+    /// Maximum number of files the scanner processes at once, the next file is started as soon as
+    /// any of them is done. This bounds memory and load during large scans. Files arent committed
+    /// in batches, every file is written to the database as soon as it has been processed, so an
+    /// interrupted scan keeps every file it got to.
+    pub scan_batch_size: usize,
+    /// Maximum number of libraries that are scanned at the same time, `0` means no limit. Scans
+    /// over the limit are queued until a running scan finishes. Changes require a restart.
+    pub max_concurrent_scans: usize,
+    /// Number of images that are resized at the same time when a client requests artwork in a
+    /// size that isnt cached yet. Changes require a restart.
+    pub artwork_resize_workers: usize,
+    /// Format posters and backdrops are served in. Converted images are cached next to the
+    /// resized ones.
+    pub artwork_format: ArtworkFormat,
+    /// Time in milliseconds a database connection waits for a lock held by another connection
+    /// before giving up. Changes require a restart.
+    pub db_busy_timeout: u64,
+    /// Number of times writes that failed because the database was locked are retried with an
+    /// exponential backoff, `0` disables retrying. Changes require a restart.
+    pub db_busy_retries: u32,
+    /// Ids of libraries whose locations are on a network filesystem like SMB or NFS. These
+    /// libraries are walked without calling `stat` on every file.
+    pub network_libraries: Vec<i64>,
+    /// Maximum depth the scanner descends to below the locations of a library, keyed by library
+    /// id, ie `{"1": 3}`. Files directly inside a location are at depth `1`. Libraries without an
+    /// entry are walked fully.
+    pub max_scan_depth: HashMap<i64, usize>,
+    /// Whether the scanner skips hidden files and directories, ie `.trash`, below the locations of
+    /// a library.
+    pub ignore_hidden_files: bool,
+    /// Ids of libraries whose files cannot be downloaded or direct played through
+    /// `/api/v1/mediafile/<id>`. Owners are exempt.
+    pub download_disabled_libraries: Vec<i64>,
+    /// What happens to files indexed by more than one library after every scan. Admins can flag
+    /// duplicates at any time with `POST /api/v1/library/duplicates`.
+    pub duplicate_files: DuplicateFiles,
+    /// Whether the filesystems libraries live on treat paths that only differ in case as the same
+    /// file. Defaults to insensitive on windows and macos and to sensitive everywhere else. Only
+    /// ASCII letters are compared case insensitively.
+    pub path_case: PathCase,
+
+    /// Interval in seconds at which the metadata of tv shows that are still airing is refreshed,
+    /// `0` disables the refresh.
+    pub show_refresh_interval: u64,
+
+    /// Whether to generate title cards for media without a poster instead of leaving the poster
+    /// empty.
+    pub generate_title_cards: bool,
+    /// Base url artwork from tmdb is downloaded from, can point at a mirror or a regional CDN.
+    /// Image sizes and paths are appended to it, ie `<base>/original/<path>`.
+    pub tmdb_image_base_url: String,
+    /// Whether BIF trick-play thumbnails are served through
+    /// `GET /api/v1/mediafile/<id>/thumbnails.bif`. Files are generated on first request and
+    /// cached.
+    pub bif_thumbnails: bool,
+    /// Number of seconds between two thumbnails of a BIF file.
+    pub bif_interval: u32,
+    /// Whether intro markers are detected for new episodes after a library is scanned, by
+    /// matching the audio of neighbouring episodes of a season. Detected markers never replace
+    /// markers set by hand.
+    pub intro_detection: bool,
+    /// Number of seconds from the start of a episode searched for its intro.
+    pub intro_detection_window: u64,
+    /// Number of seconds intro detection pauses after fingerprinting a episode, keeps detection
+    /// from hogging the cpu and disks.
+    pub intro_detection_throttle: u64,
+
+    /// Maximum size of a websocket message in bytes. Batch events that would exceed it are split
+    /// into several messages, `0` disables the limit.
+    pub ws_max_message_size: usize,
+
+    /// Window in milliseconds during which new card events are collected and sent as a single
+    /// batch event, `0` sends every card on its own.
+    pub card_batch_window: u64,
 }
 
 impl Default for GlobalSettings {
@@ -59,7 +375,68 @@ impl Default for GlobalSettings {
             quiet_boot: false,
             disable_auth: false,
             verbose: false,
+            max_log_files: 10,
             secret_key: None,
+            transcode_prebuffer_segments: 0,
+            transcode_retries: 2,
+            ffprobe_timeout: 30,
+            transcode_audio_codec: "aac".into(),
+            audio_passthrough_codecs: vec![],
+            network_bitrate_caps: {
+                let mut caps = HashMap::new();
+                caps.insert("mobile".into(), 2_000_000);
+                caps
+            },
+            segment_number_padding: 0,
+            segment_naming: Default::default(),
+            default_page_size: 15,
+            max_page_size: 100,
+            missing_duration: Default::default(),
+            untitled_files: Default::default(),
+            filename_strip_tags: vec![
+                "PROPER".into(),
+                "REPACK".into(),
+                "RERIP".into(),
+                "INTERNAL".into(),
+                "LIMITED".into(),
+                "UNRATED".into(),
+                "REMASTERED".into(),
+            ],
+            join_multipart_movies: true,
+            min_match_confidence: 0.0,
+            duplicate_provider_ids: Default::default(),
+            provider_outage: Default::default(),
+            provider_retry_interval: 5 * 60,
+            provider_rate_limit_retries: 10,
+            provider_rate_limit_max_wait: 60,
+            media_type_mismatch: Default::default(),
+            max_streams_per_user: 0,
+            stream_session_timeout: 600,
+            transcode_idle_timeout: 60,
+            stream_cleanup_interval: 60,
+            stream_reconnect_grace: 30,
+            scan_batch_size: 100,
+            max_concurrent_scans: 0,
+            artwork_resize_workers: 4,
+            artwork_format: Default::default(),
+            db_busy_timeout: 5000,
+            db_busy_retries: 3,
+            network_libraries: vec![],
+            max_scan_depth: HashMap::new(),
+            ignore_hidden_files: true,
+            download_disabled_libraries: vec![],
+            duplicate_files: Default::default(),
+            path_case: Default::default(),
+            show_refresh_interval: 24 * 60 * 60,
+            generate_title_cards: true,
+            tmdb_image_base_url: "https://image.tmdb.org/t/p".into(),
+            bif_thumbnails: false,
+            bif_interval: 10,
+            intro_detection: false,
+            intro_detection_window: 600,
+            intro_detection_throttle: 5,
+            ws_max_message_size: 64 * 1024,
+            card_batch_window: 500,
         }
     }
 }
@@ -124,6 +501,7 @@ pub mod filters {
     use warp::Rejection;
 
     use super::super::global_filters::with_state;
+    use serde::Deserialize;
 
     pub fn get_user_settings(
         conn: DbConnection,
@@ -173,6 +551,42 @@ pub mod filters {
             })
     }
 
+    pub fn clear_cache(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            library_id: Option<i64>,
+            metadata: Option<bool>,
+            artwork: Option<bool>,
+        }
+
+        warp::path!("api" / "v1" / "host" / "cache")
+            .and(warp::delete())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |QueryArgs {
+                     library_id,
+                     metadata,
+                     artwork,
+                 }: QueryArgs,
+                 auth: Auth,
+                 conn: DbConnection| async move {
+                    super::clear_cache(
+                        conn,
+                        auth,
+                        library_id,
+                        metadata.unwrap_or(true),
+                        artwork.unwrap_or(true),
+                    )
+                    .await
+                    .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
     pub fn set_global_settings(
     ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
         warp::path!("api" / "v1" / "host" / "settings")
@@ -225,3 +639,63 @@ pub async fn http_set_global_settings(
 
     Err(errors::DimError::Unauthorized)
 }
+
+/// Method mapped to `DELETE /api/v1/host/cache` clears the metadata and/or artwork caches so that
+/// the next scan or rematch pulls fresh data from the metadata providers. Artwork that has been
+/// cleared will be fetched again the next time it is requested. Only the owner can access this
+/// route.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `user` - auth middleware
+/// * `library_id` - if supplied, only the artwork of this library is cleared.
+/// * `metadata` - whether to clear the in-memory metadata cache. This cache isnt tracked per
+/// library, thus it is always cleared as a whole.
+/// * `artwork` - whether to clear the on-disk artwork cache.
+pub async fn clear_cache(
+    conn: DbConnection,
+    user: Auth,
+    library_id: Option<i64>,
+    metadata: bool,
+    artwork: bool,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if !user.0.claims.has_role("owner") {
+        return Err(errors::DimError::Unauthorized);
+    }
+
+    if metadata {
+        crate::scanners::tmdb::clear_cache().await;
+    }
+
+    let mut artwork_removed = 0usize;
+
+    if artwork {
+        let meta_path = PathBuf::from(crate::core::METADATA_PATH.get().unwrap());
+
+        let files = match library_id {
+            Some(id) => Asset::get_of_library(&conn, id)
+                .await?
+                .into_iter()
+                .map(|x| meta_path.join(x.local_path.trim_start_matches("images/")))
+                .collect::<Vec<_>>(),
+            None => fs::read_dir(&meta_path)?
+                .filter_map(Result::ok)
+                .map(|x| x.path())
+                .filter(|x| x.is_file())
+                .collect::<Vec<_>>(),
+        };
+
+        for file in files {
+            if fs::remove_file(file).is_ok() {
+                artwork_removed += 1;
+            }
+        }
+
+        crate::fetcher::clear_cache().await;
+    }
+
+    Ok(reply::json(&json!({
+        "metadata_cleared": metadata,
+        "artwork_removed": artwork_removed,
+    })))
+}
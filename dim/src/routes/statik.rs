@@ -1,15 +1,32 @@
 use database::asset;
 use http::StatusCode;
+use once_cell::sync::Lazy;
 use rust_embed::RustEmbed;
+use slog::warn;
+use tokio::sync::Semaphore;
+use tokio::task::spawn_blocking;
 use warp::path;
 use warp::Reply;
 
+use std::io;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
 
 use crate::fetcher::bump_priority;
+use crate::routes::settings::ArtworkFormat;
+use crate::streaming::FFMPEG_BIN;
+
+/// Largest width or height images can be resized to.
+const MAX_RESIZE: u32 = 4096;
+
+/// Permits limiting how many images are resized at once, see `artwork_resize_workers`.
+static RESIZE_PERMITS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(crate::get_global_settings().artwork_resize_workers.max(1)));
 
 pub mod filters {
+    use super::super::global_filters::with_logger;
     use super::super::global_filters::with_state;
     use rust_embed::RustEmbed;
     use serde::Deserialize;
@@ -37,12 +54,18 @@ pub mod filters {
             .and(warp::get())
             .and(warp::path::tail())
             .and(warp::query::query::<QueryArgs>())
+            .and(warp::header::optional::<String>("accept"))
             .and(with_state(metadata_path.clone()))
             .and(with_state(conn))
-            .and(with_state(log))
+            .and(with_logger(log))
             .and_then(
-                |x, QueryArgs { w, h }: QueryArgs, meta_path, conn, log| async move {
-                    super::get_image(x, w, h, meta_path, conn, log).await
+                |x,
+                 QueryArgs { w, h }: QueryArgs,
+                 accept: Option<String>,
+                 meta_path,
+                 conn,
+                 log| async move {
+                    super::get_image(x, w, h, accept, meta_path, conn, log).await
                 },
             )
     }
@@ -108,10 +131,14 @@ pub async fn react_routes() -> Result<impl warp::Reply, warp::Rejection> {
     }
 }
 
+/// Method mapped to `GET /images/<path>` serves cached artwork. Posters and backdrops are resized
+/// to fit within `w`x`h` if both are supplied, and are converted to WebP if `artwork_format` is
+/// `webp` and the `Accept` header of the client lists `image/webp`. Every other client gets JPEG.
 pub async fn get_image(
     path: path::Tail,
-    _resize_w: Option<u32>,
-    _resize_h: Option<u32>,
+    resize_w: Option<u32>,
+    resize_h: Option<u32>,
+    accept: Option<String>,
     meta_path: String,
     conn: database::DbConnection,
     log: slog::Logger,
@@ -122,29 +149,120 @@ pub async fn get_image(
     let mut url_path = PathBuf::from("images/");
     url_path.push(path.as_str());
 
-    /*
-    let image = if let (Some(w), Some(h)) = (resize_w, resize_h) {
-        spawn_blocking(move ||  { resize_image(file_path, w, h).ok() }).await.unwrap()
-    } else {
-        tokio::fs::read(file_path).await.ok()
-    };
-    */
-
     if !Path::new(&file_path).exists() {
         if let Ok(x) = dbg!(asset::Asset::get_url_by_file(&conn, &url_path).await) {
             bump_priority(&log, x, 5).await;
         }
     }
 
-    let image = tokio::fs::read(file_path).await.ok();
+    // NOTE: generated title cards are svgs, everything else we cache is a jpeg.
+    let mime = match file_path.extension().and_then(|x| x.to_str()) {
+        Some("svg") => "image/svg+xml",
+        _ => "image/jpeg",
+    };
+
+    let webp = crate::get_global_settings().artwork_format == ArtworkFormat::Webp
+        && accept.map_or(false, |x| x.contains("image/webp"));
+
+    let size = resize_w.zip(resize_h);
+    let converted = if mime == "image/jpeg" && (size.is_some() || webp) && file_path.exists() {
+        match resize_image(file_path.clone(), path.as_str(), size, webp).await {
+            Ok(data) => Some(data),
+            Err(e) => {
+                warn!(log, "Failed to resize image"; "path" => path.as_str(), "reason" => e.to_string());
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let (image, mime) = match converted {
+        Some(data) if webp => (Some(data), "image/webp"),
+        Some(data) => (Some(data), mime),
+        None => (tokio::fs::read(file_path).await.ok(), mime),
+    };
 
     if let Some(data) = image {
         return warp::http::Response::builder()
             .status(StatusCode::OK)
-            .header("ContentType", "image/jpeg")
+            .header("Content-Type", mime)
+            .header("Vary", "Accept")
             .body(data)
             .map_err(|_| warp::reject::not_found());
     }
 
     Err(warp::reject::not_found())
 }
+
+/// Function resizes `file` to fit within `size` while keeping its aspect ratio and converts it to
+/// WebP if `webp` is set, otherwise the image stays a JPEG. Images are cached under `cache_dir` by
+/// size, format and `name`, so every variant is only created once. At most
+/// `artwork_resize_workers` images are resized at the same time.
+async fn resize_image(
+    file: PathBuf,
+    name: &str,
+    size: Option<(u32, u32)>,
+    webp: bool,
+) -> io::Result<Vec<u8>> {
+    let size = size.map(|(w, h)| (w.clamp(1, MAX_RESIZE), h.clamp(1, MAX_RESIZE)));
+    let target = PathBuf::from(&crate::get_global_settings().cache_dir)
+        .join("images")
+        .join(size.map_or("original".to_string(), |(w, h)| format!("{}x{}", w, h)))
+        .join(name);
+
+    let (target, extension) = if webp {
+        (target.with_extension("webp"), "part.webp")
+    } else {
+        (target, "part.jpg")
+    };
+
+    if let Ok(data) = tokio::fs::read(&target).await {
+        return Ok(data);
+    }
+
+    let _permit = RESIZE_PERMITS
+        .acquire()
+        .await
+        .expect("Resize semaphore was closed");
+
+    // another request might have resized this image while we were waiting for a permit.
+    if let Ok(data) = tokio::fs::read(&target).await {
+        return Ok(data);
+    }
+
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    // ffmpeg writes to a temporary file so that concurrent requests never read a partial image.
+    let partial = target.with_extension(extension);
+    let output = partial.clone();
+
+    let status = spawn_blocking(move || {
+        let mut cmd = Command::new(*FFMPEG_BIN);
+        cmd.arg("-v").arg("quiet").arg("-y").arg("-i").arg(file);
+
+        if let Some((w, h)) = size {
+            cmd.arg("-vf").arg(format!(
+                "scale={}:{}:force_original_aspect_ratio=decrease",
+                w, h
+            ));
+        }
+
+        cmd.arg(output)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    })
+    .await
+    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+    if !status.success() {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(io::Error::new(io::ErrorKind::Other, "ffmpeg failed"));
+    }
+
+    tokio::fs::rename(&partial, &target).await?;
+    tokio::fs::read(&target).await
+}
@@ -6,10 +6,17 @@ use crate::errors;
 use crate::stream_tracking::ContentType;
 use crate::stream_tracking::StreamTracking;
 use crate::stream_tracking::VirtualManifest;
+use crate::streaming::audio_codec_tag;
+use crate::streaming::bitrate_ceiling;
 use crate::streaming::ffprobe::FFProbeCtx;
 use crate::streaming::get_avc1_tag;
-use crate::streaming::get_qualities;
+use crate::streaming::get_ladder;
 use crate::streaming::level_to_tag;
+use crate::streaming::pick_audio_codec;
+use crate::streaming::segment_template;
+use crate::streaming::stream_path_id;
+use crate::streaming::vtt;
+use crate::streaming::Quality;
 use crate::utils::quality_to_label;
 
 use database::mediafile::MediaFile;
@@ -49,6 +56,7 @@ pub mod filters {
     use auth::Wrapper as Auth;
     use uuid::Uuid;
 
+    use super::super::global_filters::with_logger;
     use super::super::global_filters::with_state;
     use serde::Deserialize;
 
@@ -61,6 +69,11 @@ pub mod filters {
         #[derive(Deserialize)]
         struct QueryArgs {
             gid: Option<String>,
+            audio_codec: Option<String>,
+            network: Option<String>,
+            max_bitrate: Option<u64>,
+            subtitle_offset: Option<f64>,
+            connection_id: Option<String>,
         }
 
         warp::path!("api" / "v1" / "stream" / i64 / "manifest")
@@ -70,16 +83,25 @@ pub mod filters {
             .and(with_state::<DbConnection>(conn))
             .and(with_state::<StateManager>(state))
             .and(with_state::<StreamTracking>(stream_tracking))
-            .and(with_state::<slog::Logger>(log))
+            .and(with_logger(log))
             .and_then(
                 |id: i64,
-                 QueryArgs { gid }: QueryArgs,
+                 QueryArgs {
+                     gid,
+                     audio_codec,
+                     network,
+                     max_bitrate,
+                     subtitle_offset,
+                     connection_id,
+                 }: QueryArgs,
                  auth: Auth,
                  conn: DbConnection,
                  state: StateManager,
                  stream_tracking: StreamTracking,
                  log: slog::Logger| async move {
                     let gid = gid.and_then(|x| Uuid::parse_str(x.as_str()).ok());
+                    let connection_id =
+                        connection_id.and_then(|x| Uuid::parse_str(x.as_str()).ok());
 
                     warp_unwrap!(
                         super::return_virtual_manifest(
@@ -89,7 +111,12 @@ pub mod filters {
                             conn,
                             log,
                             id,
-                            gid
+                            gid,
+                            audio_codec,
+                            network,
+                            max_bitrate,
+                            subtitle_offset,
+                            connection_id
                         )
                         .await
                     )
@@ -148,8 +175,78 @@ pub mod filters {
             )
     }
 
+    pub fn get_stream_profiles(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            network: Option<String>,
+            max_bitrate: Option<u64>,
+        }
+
+        warp::path!("api" / "v1" / "stream" / i64 / "profiles")
+            .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64,
+                 QueryArgs {
+                     network,
+                     max_bitrate,
+                 }: QueryArgs,
+                 auth: Auth,
+                 conn: DbConnection| async move {
+                    super::get_stream_profiles(conn, auth, id, network, max_bitrate)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn return_hls_master(
+        stream_tracking: StreamTracking,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "stream" / String / "master.m3u8")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<StreamTracking>(stream_tracking))
+            .and_then(
+                |id: String, auth: Auth, stream_tracking: StreamTracking| async move {
+                    let gid = match Uuid::parse_str(id.as_str()) {
+                        Ok(x) => x,
+                        Err(_) => return Err(reject::custom(StreamingErrors::GidParseError)),
+                    };
+
+                    super::return_hls_master(stream_tracking, auth, gid)
+                        .await
+                        .map_or_else(|e| Ok(e.into_response()), |x| Ok(x.into_response()))
+                },
+            )
+    }
+
+    pub fn return_hls_playlist(
+        stream_tracking: StreamTracking,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "stream" / String / "playlist.m3u8")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<StreamTracking>(stream_tracking))
+            .and_then(
+                |id: String, auth: Auth, stream_tracking: StreamTracking| async move {
+                    super::return_hls_playlist(stream_tracking, auth, id)
+                        .await
+                        .map_or_else(
+                            |e| Ok::<_, warp::Rejection>(e.into_response()),
+                            |x| Ok(x.into_response()),
+                        )
+                },
+            )
+    }
+
     pub fn get_init(
         state: StateManager,
+        stream_tracking: StreamTracking,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         #[derive(Deserialize)]
         struct QueryArgs {
@@ -160,9 +257,13 @@ pub mod filters {
             .and(warp::get())
             .and(warp::query::query::<QueryArgs>())
             .and(with_state::<StateManager>(state))
+            .and(with_state::<StreamTracking>(stream_tracking))
             .and_then(
-                |id: String, QueryArgs { start_num }: QueryArgs, state: StateManager| async move {
-                    super::get_init(state, id, start_num)
+                |id: String,
+                 QueryArgs { start_num }: QueryArgs,
+                 state: StateManager,
+                 stream_tracking: StreamTracking| async move {
+                    super::get_init(state, stream_tracking, id, start_num)
                         .await
                         .map_err(|e| reject::custom(e))
                 },
@@ -171,14 +272,19 @@ pub mod filters {
 
     pub fn get_chunk(
         state: StateManager,
+        stream_tracking: StreamTracking,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
         warp::path!("api" / "v1" / "stream" / String / "data" / ..)
             .and(warp::get())
             .and(warp::filters::path::tail())
             .and(with_state::<StateManager>(state))
+            .and(with_state::<StreamTracking>(stream_tracking))
             .and_then(
-                |id: String, chunk: warp::filters::path::Tail, state: StateManager| async move {
-                    super::get_chunk(state, id, chunk.as_str().into())
+                |id: String,
+                 chunk: warp::filters::path::Tail,
+                 state: StateManager,
+                 stream_tracking: StreamTracking| async move {
+                    super::get_chunk(state, stream_tracking, id, chunk.as_str().into())
                         .await
                         .map_err(|e| reject::custom(e))
                 },
@@ -188,14 +294,22 @@ pub mod filters {
     pub fn get_subtitle(
         state: StateManager,
     ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        #[derive(Deserialize)]
+        struct QueryArgs {
+            offset: Option<f64>,
+        }
+
         warp::path!("api" / "v1" / "stream" / String / "data" / "stream.vtt")
             .and(warp::get())
+            .and(warp::query::query::<QueryArgs>())
             .and(with_state::<StateManager>(state))
-            .and_then(|id: String, state: StateManager| async move {
-                super::get_subtitle(state, id)
-                    .await
-                    .map_err(|e| reject::custom(e))
-            })
+            .and_then(
+                |id: String, QueryArgs { offset }: QueryArgs, state: StateManager| async move {
+                    super::get_subtitle(state, id, offset)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
     }
 
     pub fn should_client_hard_seek(
@@ -267,38 +381,241 @@ pub mod filters {
     }
 }
 
+/// Method mapped to `GET /api/v1/stream/<id>/profiles` returns the video qualities a manifest for
+/// the file `id` would offer, so that clients only let users pick qualities that make sense for
+/// the file. Renditions are never taller than the source, ie a 1080p file has no 4K profile.
+///
+/// # Arguments
+/// * `id` - id of the mediafile
+/// * `network` - network the client is on, caps the profiles like it caps a manifest
+/// * `max_bitrate` - bitrate the profiles are capped to
+///
+/// # Return Schema
+/// ```text
+/// {
+///   // null if the file has no video or its bitrate is over the cap.
+///   "native": {
+///     "height": i64,
+///     "width": i64,
+///     "bitrate": i64,
+///     "codec": string,
+///     "direct_play": bool,
+///     "label": string,
+///   },
+///   "profiles": [{
+///     "height": i64,
+///     "width": i64,
+///     "bitrate": i64,
+///     "label": string,
+///   }]
+/// }
+/// ```
+pub async fn get_stream_profiles(
+    conn: DbConnection,
+    _auth: Auth,
+    id: i64,
+    network: Option<String>,
+    max_bitrate: Option<u64>,
+) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    let media = MediaFile::get_one(&conn, id)
+        .await
+        .map_err(|e| errors::StreamingErrors::NoMediaFileFound(e.to_string()))?;
+
+    let info = spawn_blocking(move || {
+        FFProbeCtx::new(crate::streaming::FFPROBE_BIN.as_ref())
+            .get_meta(&std::path::PathBuf::from(media.target_file))
+    })
+    .await
+    .unwrap()?;
+
+    let video_stream = match info.get_primary("video") {
+        Some(x) => x,
+        None => return Ok(reply::json(&json!({ "native": null, "profiles": [] }))),
+    };
+
+    let source = Quality {
+        height: video_stream.height.unwrap_or(1080) as u64,
+        bitrate: video_stream
+            .get_bitrate()
+            .or(info.get_container_bitrate())
+            .unwrap_or(10_000_000),
+    };
+    let source_width = video_stream.width.unwrap_or(1920) as u64;
+
+    let ceiling = bitrate_ceiling(
+        network.as_deref(),
+        max_bitrate,
+        &crate::get_global_settings().network_bitrate_caps,
+    );
+
+    let native = if ceiling.map_or(true, |x| source.bitrate <= x) {
+        json!({
+            "height": source.height,
+            "width": source_width,
+            "bitrate": source.bitrate,
+            "codec": video_stream.codec_name,
+            "direct_play": video_stream.codec_name == "h264",
+            "label": format!("{} (Native)", quality_to_label(&source)),
+        })
+    } else {
+        json!(null)
+    };
+
+    let profiles = get_ladder(source, ceiling)
+        .into_iter()
+        .map(|quality| {
+            let width = source_width as f64 * quality.height as f64 / source.height as f64;
+
+            json!({
+                "height": quality.height,
+                "width": width.round() as u64,
+                "bitrate": ceiling.map_or(quality.bitrate, |x| quality.bitrate.min(x)),
+                "label": quality_to_label(&quality),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(reply::json(&json!({
+        "native": native,
+        "profiles": profiles,
+    })))
+}
+
 /// Method mapped to `GET /api/v1/stream/<id>/manifest?<gid>` returns or creates a virtual
 /// manifest.
+///
+/// If `max_streams_per_user` is set, users other than the owner cannot create more sessions than
+/// the limit allows.
+///
+/// When the file is one part of a movie split over several files, the tracks of the following parts
+/// are added to the session too. Each part is compiled into its own period of the manifest, the
+/// `period` of a track being the index of its part.
+///
+/// Audio streams are transcoded to `audio_codec` if supplied or `transcode_audio_codec`
+/// otherwise, unless their codec is listed in `audio_passthrough_codecs`. Clients that can play
+/// the source codec, ie FLAC, can pass it as `audio_codec` to avoid transcoding.
+///
+/// Video bitrates are capped to the lower of `max_bitrate` and the cap configured in
+/// `network_bitrate_caps` for `network`, ie `mobile`. The native track is left out if it exceeds
+/// the cap.
+///
+/// Files without a video stream, like music, only get audio tracks. Each track is streamed as
+/// one continuous session, gapless playback between tracks is left to the client.
+///
+/// Subtitles are shifted by `subtitle_offset` seconds, clients can change the offset during
+/// playback by requesting the subtitle track with a different `offset`.
+///
+/// Sessions created with the `connection_id` a client was handed over the websocket are killed
+/// once that websocket drops and isnt reconnected within `stream_reconnect_grace` seconds.
 pub async fn return_virtual_manifest(
     state: StateManager,
     stream_tracking: StreamTracking,
-    _auth: Auth,
+    auth: Auth,
     conn: DbConnection,
     log: slog::Logger,
     id: i64,
     gid: Option<Uuid>,
+    audio_codec: Option<String>,
+    network: Option<String>,
+    max_bitrate: Option<u64>,
+    subtitle_offset: Option<f64>,
+    connection_id: Option<Uuid>,
 ) -> Result<impl warp::Reply, errors::StreamingErrors> {
     if let Some(gid) = gid {
+        stream_tracking.touch(&gid).await;
+
         return Ok(reply::json(&json!({
             "tracks": stream_tracking.get_for_gid(&gid).await,
             "gid": gid.to_hyphenated().to_string(),
         })));
     }
 
+    let user = auth.0.claims.get_user();
+    let max_streams = crate::get_global_settings().max_streams_per_user;
+
+    if max_streams > 0
+        && !auth.0.claims.has_role("owner")
+        && stream_tracking.count_for_user(&user).await >= max_streams
+    {
+        return Err(errors::StreamingErrors::TooManyStreams);
+    }
+
     let gid = uuid::Uuid::new_v4();
 
-    let media = MediaFile::get_one(&conn, id)
+    let mut media = MediaFile::get_one(&conn, id)
         .await
         .map_err(|e| errors::StreamingErrors::NoMediaFileFound(e.to_string()))?;
+    let library_id = media.library_id;
+    let mut period = 0;
 
+    // movies split over several files are played from one manifest, each part in its own period.
+    loop {
+        insert_tracks(
+            &state,
+            &stream_tracking,
+            &log,
+            gid,
+            &media,
+            period,
+            audio_codec.clone(),
+            network.as_deref(),
+            max_bitrate,
+            subtitle_offset,
+        )
+        .await?;
+
+        let next_part = match (media.media_id, media.part) {
+            (Some(media_id), Some(part)) => MediaFile::get_next_part(&conn, media_id, part)
+                .await
+                .unwrap_or_default(),
+            _ => None,
+        };
+
+        match next_part {
+            Some(id) => {
+                media = MediaFile::get_one(&conn, id)
+                    .await
+                    .map_err(|e| errors::StreamingErrors::NoMediaFileFound(e.to_string()))?;
+                period += 1;
+            }
+            None => break,
+        }
+    }
+
+    stream_tracking.set_owner(&gid, user).await;
+    stream_tracking.set_library(&gid, library_id).await;
+
+    if let Some(connection_id) = connection_id {
+        stream_tracking.set_connection(&gid, connection_id).await;
+    }
+
+    Ok(reply::json(&json!({
+        "tracks": stream_tracking.get_for_gid(&gid).await,
+        "gid": gid.to_hyphenated().to_string(),
+    })))
+}
+
+/// Function probes `media` and inserts a track for each of its streams into the session `gid`,
+/// tagged with `period`.
+async fn insert_tracks(
+    state: &StateManager,
+    stream_tracking: &StreamTracking,
+    log: &slog::Logger,
+    gid: Uuid,
+    media: &MediaFile,
+    period: u64,
+    audio_codec: Option<String>,
+    network: Option<&str>,
+    max_bitrate: Option<u64>,
+    subtitle_offset: Option<f64>,
+) -> Result<(), errors::StreamingErrors> {
     let target_file = media.target_file.clone();
     let info = spawn_blocking(move || {
         FFProbeCtx::new(crate::streaming::FFPROBE_BIN.as_ref())
             .get_meta(&std::path::PathBuf::from(target_file))
     })
     .await
-    .unwrap()
-    .map_err(|_| errors::StreamingErrors::FFProbeCtxFailed)?;
+    .unwrap()?;
 
     let mut ms = info
         .get_ms()
@@ -307,184 +624,235 @@ pub async fn return_virtual_manifest(
 
     ms.truncate(4);
 
-    let video_stream = info
-        .get_primary("video")
-        .cloned()
-        .ok_or(errors::StreamingErrors::FileIsCorrupt)?;
-
-    let ctx = ProfileContext {
-        file: media.target_file.clone(),
-        input_ctx: video_stream.clone().into(),
-        output_ctx: OutputCtx {
-            codec: "h264".into(),
-            start_num: 0,
-            ..Default::default()
-        },
-        ..Default::default()
-    };
-
-    let profile_chain = get_profile_for(&log, StreamType::Video, &ctx);
-    let video = state.create(profile_chain, ctx).await?;
-
-    // FIXME: Stop hardcoding a fps of 24
-    let video_avc = video_stream
-        .level
-        .and_then(|x| level_to_tag(x))
-        .unwrap_or(get_avc1_tag(
-            video_stream.width.clone().unwrap_or(1920) as u64,
-            video_stream.height.clone().unwrap_or(1080) as u64,
-            video_stream
-                .get_bitrate()
-                .or(info.get_container_bitrate())
-                .expect("Failed to pick bitrate for video stream"),
-            24,
-        ));
-
-    let bitrate = video_stream
-        .get_bitrate()
-        .or(info.get_container_bitrate())
-        .unwrap_or(10_000_000);
-
-    let label = {
-        let (ident, bitrate_norm) = if bitrate > 1_000_000 {
-            ("MB", bitrate / 1_000_000)
-        } else {
-            ("KB", bitrate / 1_000)
-        };
+    let video_stream = info.get_primary("video").cloned();
 
-        format!(
-            "{}p@{}{} (Native)",
-            video_stream.height.clone().unwrap(),
-            bitrate_norm,
-            ident
-        )
-    };
+    // files without a video stream, ie music, are streamed as audio only.
+    if video_stream.is_none() && info.get_primary("audio").is_none() {
+        return Err(errors::StreamingErrors::FileIsCorrupt);
+    }
 
     let mut set_id = 1;
 
-    stream_tracking
-        .insert(
-            &gid,
-            VirtualManifest {
-                id: video.clone(),
-                is_direct: true,
-                mime: "video/mp4".into(),
-                duration: info.get_duration(),
-                content_type: ContentType::Video,
-                chunk_path: format!("{}/data/$Number$.m4s", video.clone()),
-                init_seg: Some(format!("{}/data/init.mp4", video.clone())),
-                codecs: video_avc.to_string(),
-                bandwidth: video_stream
-                    .get_bitrate()
-                    .or(info.get_container_bitrate())
-                    .unwrap_or(10_000_000), // lol rip
-                args: {
-                    let mut x = HashMap::new();
-                    x.insert(
-                        "height".to_string(),
-                        video_stream.height.clone().unwrap().to_string(),
-                    );
-                    x
-                },
-                is_default: true,
-                label,
-                lang: None,
-                set_id: NonZeroU64::new(set_id).unwrap(),
-            },
-        )
-        .await;
+    if let Some(video_stream) = video_stream {
+        // Direct play is decided for every file on its own, episodes of the same show can have
+        // different codecs and resolutions.
+        let is_direct = video_stream.codec_name == "h264";
 
-    let qualities = get_qualities(
-        video_stream.height.unwrap_or(1080) as u64,
-        video_stream
+        let bitrate = video_stream
             .get_bitrate()
             .or(info.get_container_bitrate())
-            .unwrap_or(10_000_000),
-    );
-
-    for quality in qualities {
-        let ctx = ProfileContext {
-            file: media.target_file.clone(),
-            input_ctx: video_stream.clone().into(),
-            output_ctx: OutputCtx {
-                codec: "h264".into(),
-                start_num: 0,
-                bitrate: Some(quality.bitrate),
-                height: Some(quality.height as i64),
+            .unwrap_or(10_000_000);
+
+        let ceiling = bitrate_ceiling(
+            network,
+            max_bitrate,
+            &crate::get_global_settings().network_bitrate_caps,
+        );
+
+        let native = ceiling.map_or(true, |x| bitrate <= x);
+
+        if native {
+            let ctx = ProfileContext {
+                file: media.target_file.clone(),
+                input_ctx: video_stream.clone().into(),
+                output_ctx: OutputCtx {
+                    codec: "h264".into(),
+                    start_num: 0,
+                    ..Default::default()
+                },
                 ..Default::default()
-            },
-            ..Default::default()
+            };
+
+            let profile_chain = get_profile_for(&log, StreamType::Video, &ctx);
+            let video = state.create(profile_chain, ctx).await?;
+
+            // FIXME: Stop hardcoding a fps of 24
+            let video_avc =
+                video_stream
+                    .level
+                    .and_then(|x| level_to_tag(x))
+                    .unwrap_or(get_avc1_tag(
+                        video_stream.width.clone().unwrap_or(1920) as u64,
+                        video_stream.height.clone().unwrap_or(1080) as u64,
+                        video_stream
+                            .get_bitrate()
+                            .or(info.get_container_bitrate())
+                            .expect("Failed to pick bitrate for video stream"),
+                        24,
+                    ));
+
+            let label = {
+                let (ident, bitrate_norm) = if bitrate > 1_000_000 {
+                    ("MB", bitrate / 1_000_000)
+                } else {
+                    ("KB", bitrate / 1_000)
+                };
+
+                format!(
+                    "{}p@{}{} (Native)",
+                    video_stream.height.clone().unwrap(),
+                    bitrate_norm,
+                    ident
+                )
+            };
+
+            let path_id = stream_path_id(&gid, media.id, "video-native");
+
+            stream_tracking
+                .insert(
+                    &gid,
+                    VirtualManifest {
+                        id: video.clone(),
+                        is_direct,
+                        mime: "video/mp4".into(),
+                        duration: info.get_duration(),
+                        content_type: ContentType::Video,
+                        chunk_path: segment_template(&path_id),
+                        init_seg: Some(format!("{}/data/init.mp4", path_id)),
+                        codecs: video_avc.to_string(),
+                        bandwidth: video_stream
+                            .get_bitrate()
+                            .or(info.get_container_bitrate())
+                            .unwrap_or(10_000_000), // lol rip
+                        args: {
+                            let mut x = HashMap::new();
+                            x.insert(
+                                "height".to_string(),
+                                video_stream.height.clone().unwrap().to_string(),
+                            );
+                            if let Some(width) = video_stream.width {
+                                x.insert("width".to_string(), width.to_string());
+                            }
+                            x
+                        },
+                        is_default: true,
+                        label,
+                        lang: None,
+                        set_id: NonZeroU64::new(set_id).unwrap(),
+                        period,
+                    },
+                )
+                .await;
+        }
+
+        let source = Quality {
+            height: video_stream.height.unwrap_or(1080) as u64,
+            bitrate,
         };
 
-        // FIXME: remove this panic
-        let profile_chain = get_profile_for(&log, StreamType::Video, &ctx);
-        debug_assert!(!profile_chain.is_empty());
+        for (idx, quality) in get_ladder(source, ceiling).into_iter().enumerate() {
+            let bitrate = ceiling.map_or(quality.bitrate, |x| quality.bitrate.min(x));
+            let ctx = ProfileContext {
+                file: media.target_file.clone(),
+                input_ctx: video_stream.clone().into(),
+                output_ctx: OutputCtx {
+                    codec: "h264".into(),
+                    start_num: 0,
+                    bitrate: Some(bitrate),
+                    height: Some(quality.height as i64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
 
-        let video = state.create(profile_chain, ctx).await?;
+            // FIXME: remove this panic
+            let profile_chain = get_profile_for(&log, StreamType::Video, &ctx);
+            debug_assert!(!profile_chain.is_empty());
 
-        let video_stream_height = video_stream.height.unwrap_or(1080) as u64;
-        let ratio = video_stream_height as f64 / quality.height as f64;
-        let width = video_stream.width.unwrap_or(1920) as f64 / ratio;
+            let video = state.create(profile_chain, ctx).await?;
 
-        let video_avc = video_stream
-            .level
-            .and_then(|x| level_to_tag(x))
-            .unwrap_or(get_avc1_tag(
-                width as u64,
-                quality.height,
-                quality.bitrate,
-                24,
-            ));
+            let video_stream_height = video_stream.height.unwrap_or(1080) as u64;
+            let ratio = video_stream_height as f64 / quality.height as f64;
+            let width = video_stream.width.unwrap_or(1920) as f64 / ratio;
 
-        let label = quality_to_label(quality);
+            let video_avc =
+                video_stream
+                    .level
+                    .and_then(|x| level_to_tag(x))
+                    .unwrap_or(get_avc1_tag(
+                        width as u64,
+                        quality.height,
+                        quality.bitrate,
+                        24,
+                    ));
 
-        stream_tracking
-            .insert(
+            let label = quality_to_label(&quality);
+
+            let path_id = stream_path_id(
                 &gid,
-                VirtualManifest {
-                    id: video.clone(),
-                    is_direct: false,
-                    mime: "video/mp4".into(),
-                    duration: info.get_duration(),
-                    content_type: ContentType::Video,
-                    chunk_path: format!("{}/data/$Number$.m4s", video.clone()),
-                    init_seg: Some(format!("{}/data/init.mp4", video.clone())),
-                    codecs: video_avc.to_string(),
-                    bandwidth: quality.bitrate,
-                    args: {
-                        let mut x = HashMap::new();
-                        x.insert("height".to_string(), quality.height.to_string());
-                        x
+                media.id,
+                &format!("video-{}p{}", quality.height, bitrate),
+            );
+
+            stream_tracking
+                .insert(
+                    &gid,
+                    VirtualManifest {
+                        id: video.clone(),
+                        is_direct: false,
+                        mime: "video/mp4".into(),
+                        duration: info.get_duration(),
+                        content_type: ContentType::Video,
+                        chunk_path: segment_template(&path_id),
+                        init_seg: Some(format!("{}/data/init.mp4", path_id)),
+                        codecs: video_avc.to_string(),
+                        bandwidth: bitrate,
+                        args: {
+                            let mut x = HashMap::new();
+                            x.insert("height".to_string(), quality.height.to_string());
+                            x.insert("width".to_string(), (width.round() as u64).to_string());
+                            x
+                        },
+                        // the best capped quality is the default if the native track was left out.
+                        is_default: !native && idx == 0,
+                        label,
+                        lang: None,
+                        set_id: NonZeroU64::new(set_id).unwrap(),
+                        period,
                     },
-                    is_default: false,
-                    label,
-                    lang: None,
-                    set_id: NonZeroU64::new(set_id).unwrap(),
-                },
-            )
-            .await;
-    }
+                )
+                .await;
+        }
 
-    set_id += 1; // video streams are all wrapped in one adaptationset, so we reuse the same id.
+        set_id += 1; // video streams are all wrapped in one adaptationset, so we reuse the same id.
+    }
 
     let audio_streams = info.find_by_type("audio");
+    let settings = crate::get_global_settings();
+    let audio_target = audio_codec.unwrap_or(settings.transcode_audio_codec);
 
     for stream in audio_streams {
         let is_default = info.get_primary("audio") == Some(stream);
-        let ctx = ProfileContext {
+        let audio_ctx = |codec: &str| ProfileContext {
             file: media.target_file.clone(),
             input_ctx: stream.clone().into(),
             output_ctx: OutputCtx {
-                codec: "aac".into(),
+                codec: codec.into(),
                 start_num: 0,
                 ..Default::default()
             },
             ..Default::default()
         };
 
-        let profile = get_profile_for(&log, StreamType::Audio, &ctx);
+        let mut codec = pick_audio_codec(
+            &stream.codec_name,
+            &audio_target,
+            &settings.audio_passthrough_codecs,
+        );
+        let mut ctx = audio_ctx(&codec);
+        let mut profile = get_profile_for(&log, StreamType::Audio, &ctx);
+
+        // fall back to transcoding if nightfall cant pass the stream through.
+        if profile.is_empty() && codec != audio_target {
+            codec = audio_target.clone();
+            ctx = audio_ctx(&codec);
+            profile = get_profile_for(&log, StreamType::Audio, &ctx);
+        }
+
         let audio = state.create(profile, ctx).await?;
 
+        let path_id = stream_path_id(&gid, media.id, &format!("audio-{}-{}", stream.index, codec));
+
         stream_tracking
             .insert(
                 &gid,
@@ -493,16 +861,21 @@ pub async fn return_virtual_manifest(
                     is_direct: false,
                     mime: "audio/mp4".into(),
                     duration: info.get_duration(),
-                    codecs: "mp4a.40.2".into(),
-                    bandwidth: 120_000,
+                    codecs: audio_codec_tag(&codec).into(),
+                    bandwidth: if codec == stream.codec_name {
+                        stream.get_bitrate().unwrap_or(120_000)
+                    } else {
+                        120_000
+                    },
                     content_type: ContentType::Audio,
-                    chunk_path: format!("{}/data/$Number$.m4s", audio.clone()),
-                    init_seg: Some(format!("{}/data/init.mp4", audio.clone())),
+                    chunk_path: segment_template(&path_id),
+                    init_seg: Some(format!("{}/data/init.mp4", path_id)),
                     args: HashMap::new(),
                     is_default,
                     label: stream.get_language().unwrap_or_default(),
                     lang: stream.get_language(),
                     set_id: NonZeroU64::new(set_id).unwrap(),
+                    period,
                 },
             )
             .await;
@@ -573,7 +946,12 @@ pub async fn return_virtual_manifest(
                     codecs: codec.into(), //ignored
                     bandwidth: 1024,      // ignored
                     duration: None,
-                    chunk_path: format!("{}/data/stream.vtt", subtitle.clone()),
+                    chunk_path: match subtitle_offset {
+                        Some(offset) => {
+                            format!("{}/data/stream.vtt?offset={}", subtitle.clone(), offset)
+                        }
+                        None => format!("{}/data/stream.vtt", subtitle.clone()),
+                    },
                     init_seg: None,
                     args: {
                         let mut x = HashMap::new();
@@ -590,16 +968,14 @@ pub async fn return_virtual_manifest(
                         .unwrap_or_default(),
                     lang: stream.get_language(),
                     set_id: NonZeroU64::new(set_id).unwrap(),
+                    period,
                 },
             )
             .await;
         set_id += 1;
     }
 
-    Ok(reply::json(&json!({
-        "tracks": stream_tracking.get_for_gid(&gid).await,
-        "gid": gid.to_hyphenated().to_string(),
-    })))
+    Ok(())
 }
 
 /// Method mapped to `/api/v1/stream/<gid>/manifest.mpd` compiles a virtual manifest into a
@@ -620,6 +996,8 @@ pub async fn return_manifest(
     should_kill: Option<bool>,
     includes: Option<String>,
 ) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    stream_tracking.touch(&gid).await;
+
     if should_kill.unwrap_or(true) {
         let ids = stream_tracking
             .get_for_gid(&gid)
@@ -655,63 +1033,157 @@ pub async fn return_manifest(
     ))
 }
 
+/// Method mapped to `/api/v1/stream/<gid>/master.m3u8` compiles a virtual manifest into a HLS
+/// master playlist. Every video stream of the session is listed as a variant so that the player
+/// can adapt to the bandwidth available.
+pub async fn return_hls_master(
+    stream_tracking: StreamTracking,
+    _auth: Auth,
+    gid: Uuid,
+) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    stream_tracking.touch(&gid).await;
+
+    let playlist = stream_tracking
+        .compile_hls(&gid)
+        .await
+        .ok_or(errors::StreamingErrors::SessionDoesntExist)?;
+
+    Ok(warp::reply::with_header(
+        playlist,
+        "Content-Type",
+        "application/vnd.apple.mpegurl",
+    ))
+}
+
+/// Method mapped to `/api/v1/stream/<id>/playlist.m3u8` returns the HLS media playlist of the
+/// audio or video stream `id`.
+pub async fn return_hls_playlist(
+    stream_tracking: StreamTracking,
+    _auth: Auth,
+    id: String,
+) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    stream_tracking.touch_stream(&id).await;
+
+    let playlist = stream_tracking
+        .get_stream(&id)
+        .await
+        .and_then(|x| x.compile_hls())
+        .ok_or(errors::StreamingErrors::SessionDoesntExist)?;
+
+    Ok(warp::reply::with_header(
+        playlist,
+        "Content-Type",
+        "application/vnd.apple.mpegurl",
+    ))
+}
+
 /// Repeatedly invoke a nightfall routine until a timeout occurs waiting for a chunk to be "ready".
 ///
-/// `tick_dur` will the the duration amount that gets passed into `std::thread::sleep` and it will
-/// block for AT MOST `tick_limit` ticks. When a the tick limit has been hit `None` is returned
-/// otherwise `Some(Result<T, NightfallError>)` is returned.
+/// `tick_dur` is how long to sleep between invocations while the chunk isnt ready, after
+/// `tick_limit` ticks `ChunkNotDone` is returned.
 ///
+/// Requests for sessions that dont exist fail right away. Any other error is treated as a failed
+/// transcode, ffmpeg sometimes dies early because of transient errors. Requesting the chunk again
+/// makes nightfall respawn the process so the routine is retried up to `transcode_retries` times
+/// before `TranscodeFailed` is returned.
 async fn timeout_segment<F, T>(
     f: impl Fn() -> F,
     tick_dur: Duration,
     tick_limit: usize,
-) -> Result<T, NightfallError>
+) -> Result<T, errors::StreamingErrors>
 where
     F: Future<Output = Result<T, NightfallError>>,
 {
+    let max_retries = crate::get_global_settings().transcode_retries;
     let mut ticks = 0usize;
+    let mut retries = 0u32;
 
     loop {
         if ticks >= tick_limit {
             return Err(NightfallError::ChunkNotDone.into());
         }
 
-        let result = f().await;
-
-        if let Err(NightfallError::ChunkNotDone) = result {
-            ticks += 1;
-            tokio::time::sleep(tick_dur).await;
-        } else {
-            break result;
+        match f().await {
+            Err(NightfallError::ChunkNotDone) => {
+                ticks += 1;
+                tokio::time::sleep(tick_dur).await;
+            }
+            // retrying cant bring back a session that was killed or never existed.
+            Err(NightfallError::SessionDoesntExist) => {
+                break Err(errors::StreamingErrors::SessionDoesntExist)
+            }
+            Err(_) if retries < max_retries => {
+                retries += 1;
+                tokio::time::sleep(tick_dur).await;
+            }
+            Err(e) if max_retries == 0 => break Err(e.into()),
+            Err(_) => break Err(errors::StreamingErrors::TranscodeFailed(retries)),
+            Ok(x) => break Ok(x),
         }
     }
 }
 
+/// Function resolves the id of a stream url to the id nightfall assigned to the stream. Urls are
+/// built from the session, file and profile of a stream, the nightfall id is accepted as well.
+async fn resolve_stream(stream_tracking: &StreamTracking, id: String) -> String {
+    stream_tracking
+        .get_stream(&id)
+        .await
+        .map(|x| x.id)
+        .unwrap_or(id)
+}
+
 /// Method mapped to `/api/v1/stream/<id>/data/init.mp4` returns the init chunk of the stream `id`.
+/// If `transcode_prebuffer_segments` is set, this will also wait for that many media segments to
+/// be produced before returning.
 ///
 /// # Query args
 /// * `start_num` - first chunk index
 pub async fn get_init(
     state: StateManager,
+    stream_tracking: StreamTracking,
     id: String,
     start_num: Option<u32>,
 ) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    let start_num = start_num.unwrap_or(0);
+    let id = resolve_stream(&stream_tracking, id).await;
+
     let path: String = timeout_segment(
-        || state.chunk_init_request(id.clone(), start_num.unwrap_or(0)),
+        || state.chunk_init_request(id.clone(), start_num),
         Duration::from_millis(100),
         100,
     )
     .await?;
 
+    let prebuffer = crate::get_global_settings().transcode_prebuffer_segments;
+
+    if prebuffer > 0 {
+        // NOTE: Segments are produced sequentially so we only have to wait for the last one. If
+        // it times out we still return the init segment and let the client catch up.
+        let _ = timeout_segment(
+            || state.chunk_request(id.clone(), start_num + prebuffer - 1),
+            Duration::from_millis(100),
+            100 * prebuffer as usize,
+        )
+        .await;
+    }
+
     Ok(reply_with_file(path, ("Content-Type", "video/mp4")).await)
 }
 
-/// Method mapped to `/api/v1/stream/<id>/data/<chunk..>` returns a chunk for stream `id`.
+/// Method mapped to `/api/v1/stream/<id>/data/<chunk..>` returns a chunk for stream `id`. Seeking
+/// beyond the produced segments restarts the transcode at the requested chunk.
 pub async fn get_chunk(
     state: StateManager,
+    stream_tracking: StreamTracking,
     id: String,
     chunk: PathBuf,
 ) -> Result<impl warp::Reply, errors::StreamingErrors> {
+    stream_tracking.touch_segment(&id).await;
+
+    let stream = stream_tracking.get_stream(&id).await;
+    let id = resolve_stream(&stream_tracking, id).await;
+
     let extension = chunk
         .extension()
         .ok_or(errors::StreamingErrors::InvalidRequest)?
@@ -725,15 +1197,29 @@ pub async fn get_chunk(
 
     // Parse the chunk filename into a u64, we unwrap_or because sometimes it can be a init chunk,
     // if its a init chunk we assume a chunk index of 0 because we are fetching the first few
-    // chunks.
-    let chunk_num = chunk
+    // chunks. Zero padded chunk names (`segment_number_padding`) parse the same way, names of
+    // `$Time$` templates are converted back into the chunk index.
+    let chunk_name = chunk
         .file_stem()
         .ok_or(errors::StreamingErrors::InvalidRequest)?
         .to_string_lossy()
         .into_owned()
-        .parse::<u32>()
+        .parse::<u64>()
         .unwrap_or(0);
 
+    let chunk_num = stream.map_or(chunk_name, |x| x.segment_number(chunk_name)) as u32;
+
+    // If the chunk is far ahead of what has been produced so far, waiting for ffmpeg to catch up
+    // would stall playback. Instead we restart the session at the requested chunk the same way a
+    // client side hard seek would.
+    if state
+        .should_hard_seek(id.clone(), chunk_num)
+        .await
+        .unwrap_or(false)
+    {
+        let _ = state.chunk_init_request(id.clone(), chunk_num).await;
+    }
+
     let path: String = timeout_segment(
         || state.chunk_request(id.clone(), chunk_num),
         Duration::from_millis(100),
@@ -749,9 +1235,11 @@ pub async fn get_chunk(
 ///
 /// # Arguments
 /// * `id` - id of the underlying stream (Must be a subtitle stream of non-bitmap format).
+/// * `offset` - seconds to shift every cue by, negative values make subtitles show up earlier
 pub async fn get_subtitle(
     state: StateManager,
     id: String,
+    offset: Option<f64>,
 ) -> Result<impl warp::Reply, errors::StreamingErrors> {
     let path: String = timeout_segment(
         || state.get_sub(id.clone(), "stream".into()),
@@ -760,7 +1248,21 @@ pub async fn get_subtitle(
     )
     .await?;
 
-    Ok(reply_with_file(path, ("Content-Type", "text/vtt")).await)
+    let offset = (offset.unwrap_or_default() * 1000.0).round() as i64;
+    if offset == 0 {
+        return Ok(reply_with_file(path, ("Content-Type", "text/vtt")).await);
+    }
+
+    let body = match tokio::fs::read_to_string(&path).await {
+        Ok(x) => vtt::shift(&x, offset),
+        Err(_) => return Ok(reply_with_file(path, ("Content-Type", "text/vtt")).await),
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "text/vtt")
+        .status(StatusCode::OK)
+        .body(Body::from(body))
+        .unwrap())
 }
 
 /// Method mapped to `/api/v1/stream/<gid>/state/should_hard_seek/<chunk_num>` returns whether the
@@ -811,6 +1313,8 @@ pub async fn kill_session(
         let _ = state.die(manifest.id).await;
     }
 
+    stream_tracking.remove(&gid).await;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -0,0 +1,208 @@
+use crate::core::DbConnection;
+use crate::errors;
+
+use auth::Wrapper as Auth;
+
+use database::tag::InsertableTag;
+use database::tag::Tag;
+
+use warp::http::status::StatusCode;
+use warp::reply;
+
+pub mod filters {
+    use warp::reject;
+    use warp::Filter;
+
+    use super::super::global_filters::with_state;
+    use auth::Wrapper as Auth;
+    use serde::Deserialize;
+
+    use database::DbConnection;
+
+    #[derive(Deserialize)]
+    struct TagArgs {
+        name: String,
+    }
+
+    pub fn get_user_tags(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "user" / "tags")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|user: Auth, conn: DbConnection| async move {
+                super::get_user_tags(conn, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn delete_user_tag(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "user" / "tags" / i64)
+            .and(warp::delete())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::delete_user_tag(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn get_media_tags(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "tags")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, user: Auth, conn: DbConnection| async move {
+                super::get_media_tags(conn, id, user)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
+    pub fn add_media_tag(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "tags")
+            .and(warp::post())
+            .and(warp::body::json::<TagArgs>())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, TagArgs { name }: TagArgs, user: Auth, conn: DbConnection| async move {
+                    super::add_media_tag(conn, id, name, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+
+    pub fn remove_media_tag(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("api" / "v1" / "media" / i64 / "tags" / i64)
+            .and(warp::delete())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(
+                |id: i64, tag_id: i64, user: Auth, conn: DbConnection| async move {
+                    super::remove_media_tag(conn, id, tag_id, user)
+                        .await
+                        .map_err(|e| reject::custom(e))
+                },
+            )
+    }
+}
+
+/// Method mapped to `GET /api/v1/user/tags` returns all the personal tags the user has created.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `user` - Auth middleware
+pub async fn get_user_tags(
+    conn: DbConnection,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(
+        &Tag::get_of_user(&conn, user.0.claims.get_user()).await?,
+    ))
+}
+
+/// Method mapped to `DELETE /api/v1/user/tags/<id>` deletes a personal tag, removing it from all
+/// the media it was attached to.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the tag
+/// * `user` - Auth middleware
+pub async fn delete_user_tag(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if Tag::delete(&conn, user.0.claims.get_user(), id).await? == 0 {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Method mapped to `GET /api/v1/media/<id>/tags` returns the personal tags the user attached to a
+/// media.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `user` - Auth middleware
+pub async fn get_media_tags(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    Ok(reply::json(
+        &Tag::get_of_media(&conn, user.0.claims.get_user(), id).await?,
+    ))
+}
+
+/// Method mapped to `POST /api/v1/media/<id>/tags` tags a media with a personal tag. If the user
+/// doesnt have a tag with the supplied name yet, it gets created. Returns the tag.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `name` - name of the tag
+/// * `user` - Auth middleware
+pub async fn add_media_tag(
+    conn: DbConnection,
+    id: i64,
+    name: String,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let name = name.trim().to_string();
+
+    if name.is_empty() {
+        return Err(errors::DimError::MissingFieldInBody {
+            description: "Tag name must not be empty".into(),
+        });
+    }
+
+    // make sure the media actually exists before tagging it.
+    let _ = database::media::Media::get(&conn, id).await?;
+
+    let tag = InsertableTag {
+        user_id: user.0.claims.get_user(),
+        name,
+    }
+    .insert(&conn)
+    .await?;
+
+    tag.add_to_media(&conn, id).await?;
+
+    Ok(reply::json(&tag))
+}
+
+/// Method mapped to `DELETE /api/v1/media/<id>/tags/<tag_id>` removes a personal tag from a media.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `id` - id of the media
+/// * `tag_id` - id of the tag
+/// * `user` - Auth middleware
+pub async fn remove_media_tag(
+    conn: DbConnection,
+    id: i64,
+    tag_id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    if Tag::remove_from_media(&conn, user.0.claims.get_user(), tag_id, id).await? == 0 {
+        return Err(errors::DimError::NotFoundError);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
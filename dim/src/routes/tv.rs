@@ -5,6 +5,9 @@ use auth::Wrapper as Auth;
 
 use database::episode::{Episode, UpdateEpisode};
 use database::season::{Season, UpdateSeason};
+use database::tv::{ShowEpisode, TVShow};
+
+use serde_json::json;
 
 use warp::http::status::StatusCode;
 use warp::reply;
@@ -34,6 +37,20 @@ pub mod filters {
             })
     }
 
+    pub fn get_tv_episodes(
+        conn: DbConnection,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        warp::path!("api" / "v1" / "tv" / i64 / "episodes")
+            .and(warp::get())
+            .and(auth::with_auth())
+            .and(with_state::<DbConnection>(conn))
+            .and_then(|id: i64, auth: Auth, conn: DbConnection| async move {
+                super::get_tv_episodes(conn, id, auth)
+                    .await
+                    .map_err(|e| reject::custom(e))
+            })
+    }
+
     pub fn get_season_by_id(
         conn: DbConnection,
     ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
@@ -138,6 +155,57 @@ pub async fn get_tv_seasons(
     Ok(reply::json(&Season::get_all(&conn, id).await?))
 }
 
+/// Method mapped to `GET /api/v1/tv/<id>/episodes` returns every episode of a tv show grouped by
+/// season, along with the progress the user has made on each of them.
+///
+/// # Arguments
+/// * `id` - id of the tv show we want episodes of
+/// * `user` - Auth middleware
+///
+/// # Return Schema
+/// ```text
+/// [
+///     {
+///         "season": int,
+///         "episodes": [
+///             {
+///                 "id": int,
+///                 "season": int,
+///                 "episode": int,
+///                 "name": string,
+///                 "description": string | null,
+///                 "thumbnail_url": string | null,
+///                 "duration": int | null,
+///                 "progress": int,
+///             }
+///         ]
+///     }
+/// ]
+/// ```
+pub async fn get_tv_episodes(
+    conn: DbConnection,
+    id: i64,
+    user: Auth,
+) -> Result<impl warp::Reply, errors::DimError> {
+    let episodes = TVShow::get_all_episodes(&conn, id, &user.0.claims.get_user()).await?;
+
+    // episodes are sorted by season so every season is a consecutive run.
+    let mut seasons: Vec<(i64, Vec<ShowEpisode>)> = vec![];
+    for episode in episodes {
+        match seasons.last_mut() {
+            Some((season, list)) if *season == episode.season => list.push(episode),
+            _ => seasons.push((episode.season, vec![episode])),
+        }
+    }
+
+    let result = seasons
+        .into_iter()
+        .map(|(season, episodes)| json!({ "season": season, "episodes": episodes }))
+        .collect::<Vec<_>>();
+
+    Ok(reply::json(&result))
+}
+
 /// Method mapped to `GET /api/v1/tv/<id>/season/<season_num>` returns info about the season
 /// <season_num> for tv show by <id>
 ///
@@ -9,12 +9,18 @@ use database::mediafile::UpdateMediaFile;
 use database::DbConnection;
 
 use crate::core::EventTx;
+use crate::routes::settings::MissingDuration;
+use crate::routes::settings::ProviderOutage;
+use crate::routes::settings::UntitledFiles;
 use crate::scanners::movie::MovieMatcher;
 use crate::scanners::tmdb::Tmdb;
 use crate::scanners::tv_show::TvShowMatcher;
+use crate::streaming::ffprobe::FFPWrapper;
 use crate::streaming::ffprobe::FFProbeCtx;
+use crate::streaming::ffprobe::FFProbeError;
 use crate::streaming::FFPROBE_BIN;
 
+use super::release_tags;
 use super::ApiMedia;
 
 use torrent_name_parser::Metadata;
@@ -45,6 +51,10 @@ pub enum ScannerError {
     FilenameParserError,
     #[error(display = "Something happened to ffprobe")]
     FFProbeError,
+    #[error(display = "ffprobe couldnt determine the duration of the file")]
+    MissingDuration,
+    #[error(display = "The filename contains no recognizable title")]
+    UntitledFile,
     #[error(display = "An unknown error has occured")]
     UnknownError,
     #[error(display = "Database error why={}", _0)]
@@ -55,10 +65,90 @@ impl From<database::DatabaseError> for ScannerError {
     fn from(e: database::DatabaseError) -> Self {
         match e {
             database::DatabaseError::DatabaseError(e) => Self::DatabaseError(e.to_string()),
+            e @ database::DatabaseError::InvalidLocation(_) => Self::DatabaseError(e.to_string()),
+            e @ database::DatabaseError::InvalidName(_) => Self::DatabaseError(e.to_string()),
         }
     }
 }
 
+/// Information the scanner extracts from a filename.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ParsedFilename {
+    pub title: String,
+    pub year: Option<i64>,
+    pub season: Option<i64>,
+    pub episode: Option<i64>,
+    /// Last episode of a file spanning several episodes, ie `2` for `S01E01-E02`.
+    pub episode_end: Option<i64>,
+    /// Part number of a movie split over several files.
+    pub part: Option<i64>,
+    pub quality: Option<String>,
+    pub edition: Option<String>,
+    pub release_group: Option<String>,
+}
+
+/// Function parses a filename the same way the scanner does when a file is mounted. Release tags
+/// in `strip_tags` are removed before parsing, episode ranges such as `S01E01-E02` are captured
+/// and trailing `CD1`/`CD2` tags are captured if `multipart` is set.
+///
+/// # Arguments
+/// * `log` - logger
+/// * `file_name` - filename without the extension
+/// * `strip_tags` - release tags to strip from the filename
+/// * `multipart` - whether to capture multi-part tags
+pub async fn parse_filename(
+    log: &slog::Logger,
+    file_name: String,
+    strip_tags: Vec<String>,
+    multipart: bool,
+) -> Result<ParsedFilename, ScannerError> {
+    // `S01E01-E02` has to be collapsed first, otherwise `-E02` is taken for a release group.
+    let (clean_name, episode_end) = release_tags::extract_episode_range(&file_name);
+
+    // strip release tags like `[1080p]` or `{edition-Extended}` before parsing the filename.
+    let (clean_name, release_tags) = release_tags::extract(&clean_name, &strip_tags);
+
+    // `Show.Name.S01E02` and `Show Name - S01E02` should yield the same title.
+    let clean_name = release_tags::normalize_separators(&clean_name);
+
+    // strip `CD1`/`CD2` so that every part of a movie is matched to the same media.
+    let (clean_name, part) = if multipart {
+        release_tags::extract_part(&clean_name)
+    } else {
+        (clean_name, None)
+    };
+
+    let clone = clean_name.replace(|c: char| !c.is_ascii(), "");
+
+    // closure needs to be bound because of a lifetime bug where the closure passed to
+    // `spawn_blocking` lives more than the data moved into it thus we cant pass a reference to
+    // `Metadata::from` directly.
+    let meta_from_string =
+        move || Metadata::from(&clone).map_err(|_| ScannerError::FilenameParserError);
+
+    let metadata = match spawn_blocking(meta_from_string).await {
+        Ok(x) => x?,
+        Err(e) => {
+            error!(log, "Metadata::from possibly panic'd"; "e" => e.to_string());
+            return Err(ScannerError::UnknownError);
+        }
+    };
+
+    let episode = metadata.episode().map(|x| x as i64);
+
+    Ok(ParsedFilename {
+        title: metadata.title().to_owned(),
+        year: metadata.year().map(|x| x as i64),
+        season: metadata.season().map(|x| x as i64),
+        episode,
+        episode_end: episode_end.filter(|x| episode.map_or(false, |ep| *x > ep)),
+        part,
+        quality: release_tags.quality,
+        edition: release_tags.edition,
+        release_group: release_tags.release_group,
+    })
+}
+
 /// `MetadataExtractor` is an actor that processes files on the local filesystem. It parses the
 /// filename to extract basic information such as title, year, episode/season. This actor will also
 /// run ffprobe on the files to extract other metadata like format and codec.
@@ -80,12 +170,15 @@ impl MetadataExtractor {
         }
     }
 
+    /// Method parses and probes a file and inserts it into the database. Files whose name has no
+    /// recognizable title are handled according to `untitled_files`, if they are indexed this
+    /// still returns [`ScannerError::UntitledFile`] so that they are never passed to a matcher.
     #[handler]
     pub async fn mount_file(
         &mut self,
         file: PathBuf,
         library_id: i64,
-        _media_type: MediaType,
+        media_type: MediaType,
     ) -> Result<MediaFile, ScannerError> {
         let target_file = file.to_str().unwrap().to_owned();
 
@@ -100,8 +193,8 @@ impl MetadataExtractor {
             return Err(ScannerError::UnknownError);
         };
 
-        let target_file_clone = target_file.clone();
-        let res = MediaFile::get_by_file(&self.conn, &target_file_clone).await;
+        // on case insensitive filesystems `Movie.mkv` and `movie.mkv` are the same file.
+        let res = super::get_mediafile_by_path(&self.conn, &target_file).await;
 
         if let Ok(_media_file) = res {
             debug!(
@@ -113,6 +206,7 @@ impl MetadataExtractor {
             return Err(ScannerError::UnknownError);
         }
 
+        let settings = crate::get_global_settings();
         let ctx = FFProbeCtx::new(&FFPROBE_BIN);
 
         // we clone so that we can strip the extension.
@@ -126,31 +220,74 @@ impl MetadataExtractor {
             .unwrap()
             .to_owned();
 
-        let clone = file_name_clone.clone().replace(|c: char| !c.is_ascii(), "");
+        // multi-part naming like `CD1`/`CD2` is only joined for movies and mixed libraries.
+        let parsed = parse_filename(
+            &self.logger,
+            file_name_clone.clone(),
+            settings.filename_strip_tags,
+            matches!(media_type, MediaType::Movie | MediaType::Mixed)
+                && settings.join_multipart_movies,
+        )
+        .await;
+
+        // files like `video1.mp4` are either skipped or indexed with the raw filename as title.
+        let untitled = match &parsed {
+            Ok(x) => release_tags::is_generic_title(&x.title),
+            Err(e) => matches!(e, ScannerError::FilenameParserError),
+        };
 
-        // closure needs to be bound because of a lifetime bug where the closure passed to
-        // `spawn_blocking` lives more than the data moved into it thus we cant pass a reference to
-        // `Metadata::from` directly.
-        let meta_from_string =
-            move || Metadata::from(&clone).map_err(|_| ScannerError::FilenameParserError);
+        let parsed = match parsed {
+            Ok(x) if !untitled => x,
+            Err(e) if !untitled => return Err(e),
+            parsed => {
+                if settings.untitled_files == UntitledFiles::Skip {
+                    warn!(
+                        self.logger,
+                        "Skipping file as its name has no recognizable title";
+                        "file" => &target_file,
+                    );
+                    return Err(ScannerError::UntitledFile);
+                }
+
+                ParsedFilename {
+                    title: file_name_clone,
+                    ..parsed.unwrap_or_default()
+                }
+            }
+        };
 
-        let metadata = match spawn_blocking(meta_from_string).await {
-            Ok(x) => x?,
+        // files ffprobe cant make sense of are still indexed but flagged as corrupt.
+        let ffprobe_data = match ctx.get_meta(&file) {
+            Ok(data) => data,
+            Err(FFProbeError::Unparseable(_)) => FFPWrapper::corrupt(),
             Err(e) => {
-                error!(self.logger, "Metadata::from possibly panic'd"; "e" => e.to_string());
-                return Err(ScannerError::UnknownError);
+                error!(
+                    self.logger,
+                    "Couldnt extract media information with ffprobe";
+                    "file" => file.to_string_lossy().to_string(),
+                    "reason" => e.to_string(),
+                );
+                return Err(ScannerError::FFProbeError);
             }
         };
 
-        let ffprobe_data = if let Ok(data) = ctx.get_meta(&file) {
-            data
-        } else {
-            error!(
-                self.logger,
-                "Couldnt extract media information with ffprobe";
-                "file" => file.to_string_lossy().to_string(),
-            );
-            return Err(ScannerError::FFProbeError);
+        let (duration, duration_source) = match ffprobe_data.get_duration() {
+            Some(x) => (Some(x), Some("ffprobe")),
+            None => match crate::get_global_settings().missing_duration {
+                MissingDuration::Estimate => {
+                    let duration = ffprobe_data.estimate_duration();
+                    (duration, duration.map(|_| "estimate"))
+                }
+                MissingDuration::Unknown => (None, None),
+                MissingDuration::Skip => {
+                    warn!(
+                        self.logger,
+                        "Skipping file as ffprobe couldnt determine its duration";
+                        "file" => &target_file,
+                    );
+                    return Err(ScannerError::MissingDuration);
+                }
+            },
         };
 
         let media_file = InsertableMediaFile {
@@ -158,19 +295,30 @@ impl MetadataExtractor {
             media_id: None,
             target_file: target_file.to_string(),
 
-            raw_name: metadata.title().to_owned(),
-            raw_year: metadata.year().map(|x| x as i64),
-            season: metadata.season().map(|x| x as i64),
-            episode: metadata.episode().map(|x| x as i64),
+            raw_name: parsed.title,
+            raw_year: parsed.year,
+            season: parsed.season,
+            episode: parsed.episode,
+            episode_end: parsed.episode_end,
 
-            quality: ffprobe_data.get_height().map(|x| x.to_string()),
+            quality: ffprobe_data
+                .get_height()
+                .map(|x| x.to_string())
+                .or(parsed.quality),
             codec: ffprobe_data.get_video_codec(),
             container: ffprobe_data.get_container(),
             audio: ffprobe_data
                 .get_primary_codec("audio")
                 .map(ToOwned::to_owned),
-            original_resolution: Default::default(),
-            duration: ffprobe_data.get_duration().map(|x| x as i64),
+            original_resolution: ffprobe_data
+                .get_width()
+                .zip(ffprobe_data.get_height())
+                .map(|(width, height)| format!("{}x{}", width, height)),
+            duration: duration.map(|x| x as i64),
+            duration_source: duration_source.map(ToOwned::to_owned),
+            edition: parsed.edition,
+            release_group: parsed.release_group,
+            part: parsed.part,
             corrupt: ffprobe_data.is_corrupt(),
         };
 
@@ -187,12 +335,102 @@ impl MetadataExtractor {
             "library_id" => library_id,
             "id" => file_id,
             "2nd_pass_id" => id.id,
-            "season" => metadata.season().unwrap_or(0),
-            "episode" => metadata.episode().unwrap_or(0),
+            "season" => parsed.season.unwrap_or(0),
+            "episode" => parsed.episode.unwrap_or(0),
         );
 
+        // untitled files stay unmatched, matching them would only yield wrong metadata.
+        if untitled {
+            if settings.untitled_files == UntitledFiles::Review {
+                flag_for_review(&self.conn, &self.logger, &id).await;
+            }
+
+            return Err(ScannerError::UntitledFile);
+        }
+
         Ok(id)
     }
+
+    /// Method probes a file that is already in the database and fills in the technical metadata
+    /// that is missing, ie for files scanned before the metadata was stored. Fields that are
+    /// already set are left untouched. Returns whether the file was updated.
+    #[handler]
+    pub async fn analyze_file(&mut self, media_file: MediaFile) -> Result<bool, ScannerError> {
+        let file = PathBuf::from(&media_file.target_file);
+        let ctx = FFProbeCtx::new(&FFPROBE_BIN);
+
+        let ffprobe_data = match ctx.get_meta(&file) {
+            Ok(data) => data,
+            Err(FFProbeError::Unparseable(_)) => FFPWrapper::corrupt(),
+            Err(e) => {
+                error!(
+                    self.logger,
+                    "Couldnt extract media information with ffprobe";
+                    "file" => &media_file.target_file,
+                    "reason" => e.to_string(),
+                );
+                return Err(ScannerError::FFProbeError);
+            }
+        };
+
+        let (duration, duration_source) = match ffprobe_data.get_duration() {
+            Some(x) => (Some(x), Some("ffprobe")),
+            None => match crate::get_global_settings().missing_duration {
+                MissingDuration::Estimate => {
+                    let duration = ffprobe_data.estimate_duration();
+                    (duration, duration.map(|_| "estimate"))
+                }
+                _ => (None, None),
+            },
+        };
+
+        let missing_duration = media_file.duration.is_none();
+
+        let update = UpdateMediaFile {
+            quality: ffprobe_data
+                .get_height()
+                .map(|x| x.to_string())
+                .filter(|_| media_file.quality.is_none()),
+            codec: ffprobe_data
+                .get_video_codec()
+                .filter(|_| media_file.codec.is_none()),
+            container: ffprobe_data
+                .get_container()
+                .filter(|_| media_file.container.is_none()),
+            audio: ffprobe_data
+                .get_primary_codec("audio")
+                .map(ToOwned::to_owned)
+                .filter(|_| media_file.audio.is_none()),
+            original_resolution: ffprobe_data
+                .get_width()
+                .zip(ffprobe_data.get_height())
+                .map(|(width, height)| format!("{}x{}", width, height))
+                .filter(|_| media_file.original_resolution.is_none()),
+            duration: duration.map(|x| x as i64).filter(|_| missing_duration),
+            duration_source: duration_source
+                .map(ToOwned::to_owned)
+                .filter(|_| missing_duration),
+            corrupt: ffprobe_data
+                .is_corrupt()
+                .filter(|_| media_file.corrupt.is_none()),
+            ..Default::default()
+        };
+
+        if update == UpdateMediaFile::default() {
+            return Ok(false);
+        }
+
+        update.update(&self.conn, media_file.id).await?;
+
+        debug!(
+            self.logger,
+            "Analyzed file";
+            "file" => &media_file.target_file,
+            "id" => media_file.id,
+        );
+
+        Ok(true)
+    }
 }
 
 #[actor]
@@ -224,12 +462,17 @@ impl MetadataMatcher {
             .await
         {
             Ok(v) => v,
+            Err(e) if e.is_outage() => {
+                provider_outage(&self.conn, &self.log, &media, e).await;
+                return Err(ScannerError::UnknownError);
+            }
             Err(e) => {
                 error!(
                     self.log,
                     "Could not match movie to tmdb";
                     "reason" => e.to_string(),
                 );
+                flag_for_review(&self.conn, &self.log, &media).await;
                 return Err(ScannerError::UnknownError);
             }
         };
@@ -243,6 +486,17 @@ impl MetadataMatcher {
         media: MediaFile,
         result: ApiMedia,
     ) -> Result<(), ScannerError> {
+        let mut result = result;
+
+        result.trailers = self
+            .movie_tmdb
+            .get_trailers_for(result.id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
         let matcher = MovieMatcher {
             conn: &self.conn,
             log: &self.log,
@@ -313,12 +567,17 @@ impl MetadataMatcher {
 
         let result = match result {
             Ok(v) => v,
+            Err(e) if e.is_outage() => {
+                provider_outage(&self.conn, &self.log, &media, e).await;
+                return Err(ScannerError::UnknownError);
+            }
             Err(e) => {
                 error!(
                     self.log,
                     "Could not match tv show to tmdb";
                     "reason" => e.to_string(),
                 );
+                flag_for_review(&self.conn, &self.log, &media).await;
                 return Err(ScannerError::UnknownError);
             }
         };
@@ -407,6 +666,14 @@ impl MetadataMatcher {
         }
 
         result.seasons = seasons;
+        result.trailers = self
+            .tv_tmdb
+            .get_trailers_for(result.id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(Into::into)
+            .collect();
 
         let matcher = TvShowMatcher {
             conn: &self.conn,
@@ -418,3 +685,51 @@ impl MetadataMatcher {
         Ok(())
     }
 }
+
+/// Function handles a mediafile that couldnt be matched because tmdb is unreachable, depending on
+/// `provider_outage` it is either flagged for review or queued to be matched again later.
+async fn provider_outage(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    media: &MediaFile,
+    e: super::tmdb::TmdbError,
+) {
+    match crate::get_global_settings().provider_outage {
+        ProviderOutage::Retry => {
+            warn!(
+                log,
+                "Tmdb is unreachable, queued file to be matched later";
+                "id" => media.id,
+                "reason" => e.to_string(),
+            );
+            super::queue_for_retry(media.id);
+        }
+        ProviderOutage::Review => {
+            error!(
+                log,
+                "Tmdb is unreachable";
+                "id" => media.id,
+                "reason" => e.to_string(),
+            );
+            flag_for_review(conn, log, media).await;
+        }
+    }
+}
+
+/// Function flags a mediafile as needing review so that it shows up in the review queue of its
+/// library.
+async fn flag_for_review(conn: &DbConnection, log: &slog::Logger, media: &MediaFile) {
+    let updated_mediafile = UpdateMediaFile {
+        needs_review: Some(true),
+        ..Default::default()
+    };
+
+    if let Err(e) = updated_mediafile.update(conn, media.id).await {
+        warn!(
+            log,
+            "Failed to flag mediafile for review";
+            "id" => media.id,
+            "reason" => e.to_string(),
+        );
+    }
+}
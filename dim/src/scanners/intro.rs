@@ -0,0 +1,165 @@
+//! Intro detection pass run after tv libraries are scanned, see `intro_detection`.
+use crate::streaming::intro;
+
+use database::get_conn;
+use database::marker::InsertableMarker;
+use database::marker::IntroCandidate;
+use database::marker::Marker;
+use database::marker::MarkerKind;
+use database::marker::MarkerSource;
+
+use slog::debug;
+use slog::info;
+use slog::warn;
+
+use once_cell::sync::Lazy;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::spawn_blocking;
+
+/// Only one library is analyzed at a time as detection runs ffmpeg over every episode.
+static DETECTION_PERMITS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(1));
+
+/// Function detects the intros of the episodes of a library that dont have a intro marker yet and
+/// stores them as detected markers. Every episode is compared with its neighbours in the same
+/// season, so seasons where only a single episode is missing its intro are skipped. Episodes no
+/// intro was found for are recorded and skipped by later passes. Returns the number of markers
+/// stored.
+pub async fn detect_library(log: slog::Logger, library_id: i64) -> usize {
+    let _permit = DETECTION_PERMITS
+        .acquire()
+        .await
+        .expect("Intro detection semaphore was closed");
+
+    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    let settings = crate::get_global_settings();
+    let throttle = Duration::from_secs(settings.intro_detection_throttle);
+
+    let candidates = match Marker::get_missing_intros(&conn, library_id).await {
+        Ok(x) => x,
+        Err(e) => {
+            warn!(
+                log,
+                "Failed to fetch episodes without intro markers";
+                "mod" => "intro",
+                "library_id" => library_id,
+                "reason" => e.to_string(),
+            );
+            return 0;
+        }
+    };
+
+    let mut seasons: Vec<Vec<IntroCandidate>> = vec![];
+    for candidate in candidates {
+        match seasons.last_mut() {
+            Some(season) if season[0].season_id == candidate.season_id => season.push(candidate),
+            _ => seasons.push(vec![candidate]),
+        }
+    }
+
+    let mut found = 0;
+    let mut failed = 0;
+
+    for season in seasons.into_iter().filter(|x| x.len() > 1) {
+        let mut prints = Vec::with_capacity(season.len());
+
+        for episode in season.iter() {
+            let file = PathBuf::from(&episode.target_file);
+            let window = settings.intro_detection_window;
+
+            let print = match spawn_blocking(move || intro::fingerprint(&file, window)).await {
+                Ok(Ok(x)) => x,
+                _ => {
+                    failed += 1;
+                    vec![]
+                }
+            };
+
+            prints.push(print);
+            tokio::time::sleep(throttle).await;
+        }
+
+        // matches between every episode and the episode right after it.
+        let pairs = spawn_blocking(move || {
+            prints
+                .windows(2)
+                .map(|x| intro::find_common(&x[0], &x[1]).filter(intro::Match::is_intro))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        for (i, episode) in season.iter().enumerate() {
+            let previous = i
+                .checked_sub(1)
+                .and_then(|x| pairs.get(x).copied().flatten())
+                .map(|x| x.range_b());
+
+            let range = pairs
+                .get(i)
+                .copied()
+                .flatten()
+                .map(|x| x.range_a())
+                .or(previous);
+
+            let (start_ms, end_ms) = match range {
+                Some(x) => x,
+                None => {
+                    if let Err(e) = Marker::set_intro_attempted(&conn, episode.media_id).await {
+                        debug!(
+                            log,
+                            "Failed to record intro detection attempt";
+                            "mod" => "intro",
+                            "media_id" => episode.media_id,
+                            "reason" => e.to_string(),
+                        );
+                    }
+
+                    continue;
+                }
+            };
+
+            let marker = InsertableMarker {
+                media_id: episode.media_id,
+                kind: MarkerKind::Intro,
+                start_ms,
+                end_ms,
+                source: MarkerSource::Detected,
+            };
+
+            match marker.insert(&conn).await {
+                Ok(x) => found += x,
+                Err(e) => debug!(
+                    log,
+                    "Failed to store detected intro";
+                    "mod" => "intro",
+                    "media_id" => episode.media_id,
+                    "reason" => e.to_string(),
+                ),
+            }
+        }
+    }
+
+    if failed > 0 {
+        warn!(
+            log,
+            "Failed to fingerprint episodes, ffmpeg might be built without chromaprint";
+            "mod" => "intro",
+            "library_id" => library_id,
+            "files" => failed,
+        );
+    }
+
+    info!(
+        log,
+        "Finished intro detection";
+        "mod" => "intro",
+        "library_id" => library_id,
+        "markers" => found,
+    );
+
+    found
+}
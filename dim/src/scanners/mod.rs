@@ -1,23 +1,59 @@
 pub mod base;
+pub mod intro;
 pub mod movie;
+pub mod release_tags;
 pub mod scanner_daemon;
 pub mod tmdb;
 pub mod tv_show;
 
+use database::episode::Episode;
+use database::episode::InsertableEpisode;
 use database::get_conn;
+use database::library::LastScan;
 use database::library::Library;
 use database::library::MediaType;
+use database::media::InsertableMedia;
+use database::media::Media;
+use database::media::UpdateMedia;
+use database::mediafile::MediaFile;
+use database::scan_history::InsertableScanHistory;
+use database::season::InsertableSeason;
+use database::season::Season;
+use database::tv::TVShow;
+use database::DatabaseError;
+use database::DbConnection;
+
+use async_trait::async_trait;
+use chrono::prelude::Utc;
+use futures::stream;
+use futures::StreamExt;
 
 use crate::core::EventTx;
+use crate::routes::settings::DuplicateFiles;
+use crate::routes::settings::MediaTypeMismatch;
+use crate::routes::settings::PathCase;
+use crate::routes::settings::ProviderOutage;
+use crate::scanners::tmdb::Tmdb;
 
+use slog::debug;
 use slog::info;
+use slog::warn;
 
+use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
 use walkdir::WalkDir;
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
 use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use tokio::sync::Semaphore;
 
 use serde::Deserialize;
 use serde::Serialize;
@@ -35,6 +71,13 @@ pub struct ApiMedia {
     pub genres: Vec<String>,
     pub rating: Option<i32>,
     pub seasons: Vec<ApiSeason>,
+    pub trailers: Vec<ApiTrailer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ApiTrailer {
+    pub name: Option<String>,
+    pub url: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -61,6 +104,180 @@ pub(super) static METADATA_EXTRACTOR: OnceCell<base::MetadataExtractor> = OnceCe
 pub(super) static METADATA_MATCHER: OnceCell<base::MetadataMatcher> = OnceCell::new();
 pub(super) static SUPPORTED_EXTS: &[&str] = &["mp4", "mkv", "avi", "webm"];
 
+/// Permits limiting how many libraries are scanned at once, see `max_concurrent_scans`.
+static SCAN_PERMITS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(crate::get_global_settings().max_concurrent_scans));
+
+/// Progress of all running scans keyed by library id, holds `(processed, total)`.
+static SCAN_PROGRESS: Lazy<Mutex<HashMap<i64, (usize, usize)>>> = Lazy::new(Default::default);
+
+/// Ids of mediafiles that couldnt be matched because tmdb was unreachable, see
+/// `provider_outage`. The queue only lives in memory, [`requeue_unmatched`] restores it on
+/// startup.
+static RETRY_QUEUE: Lazy<Mutex<Vec<i64>>> = Lazy::new(Default::default);
+
+/// New card events waiting to be sent, see `card_batch_window`.
+static CARD_BATCHER: Lazy<Mutex<events::CardBatcher>> =
+    Lazy::new(|| Mutex::new(events::CardBatcher::new(Duration::from_millis(0))));
+
+/// Function queues a new card event for media `id` of library `lib_id`. Cards are coalesced into
+/// batch events over `card_batch_window` milliseconds, if batching is disabled the event is sent
+/// right away.
+pub fn push_new_card(tx: &EventTx, lib_id: i64, id: i64) {
+    let window = crate::get_global_settings().card_batch_window;
+
+    let messages = if window == 0 {
+        vec![events::Message {
+            id,
+            event_type: events::PushEventType::EventNewCard { lib_id },
+        }]
+    } else {
+        let mut batcher = CARD_BATCHER.lock().unwrap();
+        batcher.window = Duration::from_millis(window);
+        batcher.push(lib_id, id, Instant::now())
+    };
+
+    send_messages(tx, messages);
+}
+
+/// Function sends the queued card events whose batch window elapsed.
+pub fn flush_new_cards(tx: &EventTx) {
+    let messages = CARD_BATCHER.lock().unwrap().poll(Instant::now());
+    send_messages(tx, messages);
+}
+
+fn send_messages(tx: &EventTx, messages: Vec<events::Message>) {
+    let max_size = crate::get_global_settings().ws_max_message_size;
+
+    for message in messages {
+        for chunk in message.to_chunked_strings(max_size) {
+            let _ = tx.send(chunk);
+        }
+    }
+}
+
+/// Function queues the mediafile `id` to be matched again by [`retry_unmatched`].
+pub fn queue_for_retry(id: i64) {
+    let mut queue = RETRY_QUEUE.lock().unwrap();
+
+    if !queue.contains(&id) {
+        queue.push(id);
+    }
+}
+
+/// Function queues every unmatched mediafile that wasnt flagged for review to be matched again.
+/// Files whose match failed for any other reason than a tmdb outage are flagged for review, so
+/// this restores the retry queue after a restart. Does nothing unless `provider_outage` is set to
+/// `retry`. Returns the number of queued files.
+pub async fn requeue_unmatched() -> Result<usize, self::base::ScannerError> {
+    if crate::get_global_settings().provider_outage != ProviderOutage::Retry {
+        return Ok(0);
+    }
+
+    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    let mut queued = 0;
+
+    for library in Library::get_all(&conn).await {
+        // audio files arent matched against tmdb.
+        if library.media_type == MediaType::Audio {
+            continue;
+        }
+
+        for file in MediaFile::get_by_lib_null_media(&conn, library.id).await? {
+            if !file.needs_review {
+                queue_for_retry(file.id);
+                queued += 1;
+            }
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Function matches the mediafiles that were queued while tmdb was unreachable. Files are matched
+/// one by one, if one of them gets queued again tmdb is still down and the remaining files stay
+/// queued for the next pass. Returns the number of files that were matched.
+pub async fn retry_unmatched(
+    log: &slog::Logger,
+    tx: &EventTx,
+) -> Result<usize, self::base::ScannerError> {
+    let mut pending = std::mem::take(&mut *RETRY_QUEUE.lock().unwrap());
+
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    let matcher = get_matcher(log, tx);
+    let mut matched = 0;
+
+    while !pending.is_empty() {
+        let id = pending.remove(0);
+
+        let mfile = match MediaFile::get_one(&conn, id).await {
+            Ok(x) => x,
+            // the file was removed in the meantime.
+            Err(_) => continue,
+        };
+
+        if mfile.media_id.is_some() {
+            continue;
+        }
+
+        let library = Library::get_one(&conn, mfile.library_id).await?;
+
+        let result = match match_type(library.media_type, &mfile) {
+            MediaType::Movie => matcher.match_movie(mfile).await,
+            MediaType::Tv => matcher.match_tv(mfile).await,
+            _ => continue,
+        };
+
+        if RETRY_QUEUE.lock().unwrap().contains(&id) {
+            break;
+        }
+
+        if matches!(result, Ok(Ok(_))) {
+            matched += 1;
+        }
+    }
+
+    for id in pending {
+        queue_for_retry(id);
+    }
+
+    if matched > 0 {
+        info!(
+            log,
+            "Matched files queued while tmdb was unreachable";
+            "mod" => "scanner",
+            "files" => matched,
+        );
+    }
+
+    Ok(matched)
+}
+
+/// Function returns the scanner status of every library passed in. Libraries which are not being
+/// scanned are reported as idle.
+pub fn scan_status(library_ids: impl IntoIterator<Item = i64>) -> Vec<events::LibraryScanStatus> {
+    let progress = SCAN_PROGRESS.lock().unwrap();
+
+    library_ids
+        .into_iter()
+        .map(|library_id| {
+            let entry = progress.get(&library_id).copied();
+            let (processed, total) = entry.unwrap_or_default();
+
+            events::LibraryScanStatus {
+                library_id,
+                scanning: entry.is_some(),
+                processed,
+                total,
+            }
+        })
+        .collect()
+}
+
 pub fn get_extractor(log: &slog::Logger, _tx: &EventTx) -> &'static base::MetadataExtractor {
     let mut handle = xtra::spawn::Tokio::Global;
 
@@ -81,6 +298,67 @@ pub fn get_matcher_unchecked() -> &'static base::MetadataMatcher {
     METADATA_MATCHER.get().unwrap()
 }
 
+/// Returns the media type a mounted file should be matched as. Files in mixed libraries are
+/// matched as episodes if their name carries a season or an episode number and as movies
+/// otherwise.
+pub fn match_type(library_type: MediaType, mfile: &MediaFile) -> MediaType {
+    match library_type {
+        MediaType::Mixed if mfile.season.is_some() || mfile.episode.is_some() => MediaType::Tv,
+        MediaType::Mixed => MediaType::Movie,
+        x => x,
+    }
+}
+
+/// Number of filenames sampled when checking whether the files of a library match its media type.
+const MISMATCH_SAMPLE: usize = 50;
+/// Minimum number of sampled files needed before a library is reported as mismatched.
+const MISMATCH_MIN_FILES: usize = 5;
+
+/// Function samples the filenames of `files` and returns the media type the files look like if
+/// it strongly disagrees with `media_type`, ie when nearly none of the files of a tv library carry
+/// a season or episode number. Only movie and tv libraries are checked.
+pub async fn detect_mismatch(
+    log: &slog::Logger,
+    media_type: MediaType,
+    files: &[PathBuf],
+) -> Option<MediaType> {
+    if !matches!(media_type, MediaType::Movie | MediaType::Tv) || files.len() < MISMATCH_MIN_FILES {
+        return None;
+    }
+
+    let strip_tags = crate::get_global_settings().filename_strip_tags;
+    let step = (files.len() / MISMATCH_SAMPLE).max(1);
+    let mut sampled = 0;
+    let mut episodes = 0;
+
+    for file in files.iter().step_by(step).take(MISMATCH_SAMPLE) {
+        let stem = match file.file_stem().and_then(|x| x.to_str()) {
+            Some(x) => x.to_string(),
+            None => continue,
+        };
+
+        if let Ok(parsed) = base::parse_filename(log, stem, strip_tags.clone(), false).await {
+            sampled += 1;
+
+            if parsed.season.is_some() || parsed.episode.is_some() {
+                episodes += 1;
+            }
+        }
+    }
+
+    if sampled < MISMATCH_MIN_FILES {
+        return None;
+    }
+
+    let ratio = episodes as f64 / sampled as f64;
+
+    match media_type {
+        MediaType::Tv if ratio < 0.1 => Some(MediaType::Movie),
+        MediaType::Movie if ratio > 0.9 => Some(MediaType::Tv),
+        _ => None,
+    }
+}
+
 pub async fn start_custom(
     library_id: i64,
     log: slog::Logger,
@@ -88,6 +366,22 @@ pub async fn start_custom(
     paths: impl Iterator<Item = impl AsRef<Path>>,
     media_type: MediaType,
 ) -> Result<(), self::base::ScannerError> {
+    // scans over the limit wait here until a running scan finishes.
+    let _permit = if crate::get_global_settings().max_concurrent_scans > 0 {
+        if SCAN_PERMITS.available_permits() == 0 {
+            info!(log, "Queued library scan"; "mod" => "scanner", "library_id" => library_id);
+        }
+
+        Some(
+            SCAN_PERMITS
+                .acquire()
+                .await
+                .expect("Scan semaphore was closed"),
+        )
+    } else {
+        None
+    };
+
     info!(log, "Scanning library"; "mod" => "scanner", "library_id" => library_id);
     tx.send(
         events::Message {
@@ -98,39 +392,55 @@ pub async fn start_custom(
     )
     .unwrap();
 
+    SCAN_PROGRESS.lock().unwrap().insert(library_id, (0, 0));
+
     let _conn = get_conn().await.expect("Failed to grab the conn pool");
 
     let extractor = get_extractor(&log, &tx);
     let matcher = get_matcher(&log, &tx);
 
+    let settings = crate::get_global_settings();
+    let network = settings.network_libraries.contains(&library_id);
+    let max_depth = max_scan_depth(library_id);
+    let ignore_hidden = settings.ignore_hidden_files;
+
     let mut files = Vec::with_capacity(2048);
+    let mut stat_cache = HashMap::new();
     for path in paths {
-        let mut subfiles: Vec<PathBuf> = WalkDir::new(path)
+        let root = path.as_ref();
+
+        if network {
+            files.append(&mut walk_network(
+                root,
+                max_depth,
+                ignore_hidden,
+                &mut stat_cache,
+            ));
+            continue;
+        }
+
+        let mut subfiles: Vec<PathBuf> = WalkDir::new(root)
             // we want to follow all symlinks in case of complex dir structures
             .follow_links(true)
+            .max_depth(max_depth)
             .into_iter()
             .filter_map(Result::ok)
-            // ignore all hidden files.
-            .filter(|f| {
-                !f.path()
-                    .iter()
-                    .any(|s| s.to_str().map(|x| x.starts_with('.')).unwrap_or(false))
-            })
-            // check whether `f` has a supported extension
-            .filter(|f| {
-                f.path()
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .map_or(false, |e| SUPPORTED_EXTS.contains(&e))
-            })
+            .filter(|f| !(ignore_hidden && is_hidden(root, f.path())))
+            .filter(|f| is_supported(f.path()))
             .map(|f| f.into_path())
             .collect();
 
         files.append(&mut subfiles);
     }
 
+    let files = dedup_paths(files, settings.path_case);
     let total_files = files.len();
 
+    SCAN_PROGRESS
+        .lock()
+        .unwrap()
+        .insert(library_id, (0, total_files));
+
     info!(
         log,
         "Walked library directory";
@@ -139,26 +449,92 @@ pub async fn start_custom(
         "files" => total_files,
     );
 
-    let mut futures = Vec::new();
+    let media_type = match detect_mismatch(&log, media_type, &files).await {
+        Some(detected) => {
+            warn!(
+                log,
+                "Library content doesnt look like its media type";
+                "mod" => "scanner",
+                "library_id" => library_id,
+                "media_type" => media_type.to_string(),
+                "detected" => detected.to_string(),
+            );
+
+            let event = events::Message {
+                id: library_id,
+                event_type: events::PushEventType::EventMediaTypeMismatch {
+                    library_id,
+                    media_type: media_type.to_string(),
+                    detected: detected.to_string(),
+                },
+            };
+
+            let _ = tx.send(event.to_string());
+
+            match settings.media_type_mismatch {
+                MediaTypeMismatch::Mixed => MediaType::Mixed,
+                MediaTypeMismatch::Warn => media_type,
+            }
+        }
+        None => media_type,
+    };
+
     let now = Instant::now();
+    let batch_size = crate::get_global_settings().scan_batch_size.max(1);
+    let files_before = MediaFile::count_by_lib(&_conn, library_id)
+        .await
+        .unwrap_or_default();
 
-    for file in files {
-        futures.push(async move {
-            if let Ok(mfile) = extractor.mount_file(file, library_id, media_type).await {
-                match media_type {
-                    MediaType::Movie => {
-                        let _ = matcher.match_movie(mfile).await;
-                    }
-                    MediaType::Tv => {
-                        let _ = matcher.match_tv(mfile).await;
-                    }
-                    _ => unreachable!(),
+    // NOTE: The extractor and matcher commit every file on its own as soon as it is processed, the
+    // batch size only bounds how many files are in flight. A transaction per batch would be held
+    // across tmdb requests and block every other writer of the database in the meantime.
+    let tx_ref = &tx;
+    let futures = files.iter().cloned().map(move |file| async move {
+        if let Ok(mfile) = extractor.mount_file(file, library_id, media_type).await {
+            match match_type(media_type, &mfile) {
+                MediaType::Movie => {
+                    let _ = matcher.match_movie(mfile).await;
+                }
+                MediaType::Tv => {
+                    let _ = matcher.match_tv(mfile).await;
                 }
+                // audio files arent matched against tmdb.
+                MediaType::Audio => {}
+                _ => unreachable!(),
             }
-        })
-    }
+        }
+
+        let progress = {
+            let mut lock = SCAN_PROGRESS.lock().unwrap();
+            lock.get_mut(&library_id).map(|(processed, total)| {
+                *processed += 1;
+                (*processed, *total)
+            })
+        };
+
+        if let Some((processed, total)) = progress {
+            if events::is_progress_step(processed, total) {
+                let event = events::Message {
+                    id: library_id,
+                    event_type: events::PushEventType::EventScanProgress {
+                        library_id,
+                        total,
+                        processed,
+                    },
+                };
+
+                let _ = tx_ref.send(event.to_string());
+            }
+        }
+    });
+
+    stream::iter(futures)
+        .buffer_unordered(batch_size)
+        .for_each(|_| async {})
+        .await;
+
+    SCAN_PROGRESS.lock().unwrap().remove(&library_id);
 
-    futures::future::join_all(futures).await;
     info!(
         log,
         "Finished scanning library";
@@ -166,6 +542,69 @@ pub async fn start_custom(
         "files" => total_files,
         "duration" => now.elapsed().as_secs(),
     );
+
+    let last_scan = LastScan {
+        finished_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs() as i64)
+            .unwrap_or_default(),
+        duration: now.elapsed().as_secs() as i64,
+        items: total_files as i64,
+    };
+
+    if let Err(e) = Library::set_last_scan(&_conn, library_id, &last_scan).await {
+        warn!(
+            log,
+            "Failed to store last scan summary";
+            "library_id" => library_id,
+            "reason" => e.to_string(),
+        );
+    }
+
+    let files_after = MediaFile::count_by_lib(&_conn, library_id)
+        .await
+        .unwrap_or(files_before);
+
+    let history = InsertableScanHistory {
+        library_id,
+        finished_at: last_scan.finished_at,
+        duration: last_scan.duration,
+        items: last_scan.items,
+        added: (files_after - files_before).max(0),
+    };
+
+    if let Err(e) = history.insert(&_conn).await {
+        warn!(
+            log,
+            "Failed to store scan history";
+            "library_id" => library_id,
+            "reason" => e.to_string(),
+        );
+    }
+
+    if crate::get_global_settings().duplicate_files == DuplicateFiles::Flag {
+        match MediaFile::flag_cross_library_duplicates(&_conn).await {
+            Ok(0) => {}
+            Ok(flagged) => warn!(
+                log,
+                "Flagged files indexed by more than one library";
+                "library_id" => library_id,
+                "files" => flagged,
+            ),
+            Err(e) => warn!(
+                log,
+                "Failed to flag files indexed by more than one library";
+                "library_id" => library_id,
+                "reason" => e.to_string(),
+            ),
+        }
+    }
+
+    // detection runs ffmpeg over every new episode, so it doesnt hold up the scan.
+    if settings.intro_detection && matches!(media_type, MediaType::Tv | MediaType::Mixed) {
+        tokio::spawn(intro::detect_library(log.clone(), library_id));
+    }
+
     tx.send(
         events::Message {
             id: library_id,
@@ -178,6 +617,180 @@ pub async fn start_custom(
     Ok(())
 }
 
+/// Function returns why a scan of the library `library_id` with `locations` would skip `path`,
+/// `None` means the path passes the filters applied while walking the library. Files without a
+/// recognizable title are skipped later on and arent covered by this.
+pub fn ignore_reason(library_id: i64, locations: &[String], path: &Path) -> Option<String> {
+    let ignore_hidden = crate::get_global_settings().ignore_hidden_files;
+    let max_depth = max_scan_depth(library_id);
+
+    // a file inside overlapping locations is indexed as long as the walk of one of them reaches
+    // it.
+    let reasons = locations
+        .iter()
+        .map(Path::new)
+        .filter(|x| path.starts_with(x))
+        .map(|root| walk_reason(root, path, max_depth, ignore_hidden))
+        .collect::<Vec<_>>();
+
+    if reasons.is_empty() {
+        return Some("the path is not inside any location of the library".into());
+    }
+
+    if reasons.iter().any(Option::is_none) {
+        return None;
+    }
+
+    reasons.into_iter().flatten().next()
+}
+
+/// Function returns why the walk of `root` skips `path`.
+fn walk_reason(root: &Path, path: &Path, max_depth: usize, ignore_hidden: bool) -> Option<String> {
+    if ignore_hidden && is_hidden(root, path) {
+        return Some("hidden files and directories are ignored".into());
+    }
+
+    if path.strip_prefix(root).map_or(0, |x| x.iter().count()) > max_depth {
+        return Some(format!(
+            "the file is nested deeper than `max_scan_depth` ({}) allows",
+            max_depth
+        ));
+    }
+
+    match path.extension().and_then(|e| e.to_str()) {
+        _ if is_supported(path) => None,
+        Some(ext) => Some(format!("the extension `{}` is not supported", ext)),
+        None => Some("files without an extension are ignored".into()),
+    }
+}
+
+/// Function returns how deep the scanner descends below the locations of the library
+/// `library_id`.
+fn max_scan_depth(library_id: i64) -> usize {
+    crate::get_global_settings()
+        .max_scan_depth
+        .get(&library_id)
+        .copied()
+        .unwrap_or(usize::MAX)
+}
+
+/// Function returns whether `path` or any directory between it and `root` is hidden. Hidden
+/// directories `root` itself lives in dont count.
+fn is_hidden(root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .iter()
+        .any(|s| s.to_str().map_or(false, |x| x.starts_with('.')))
+}
+
+/// Function returns whether `path` has an extension the scanner indexes.
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| SUPPORTED_EXTS.contains(&e))
+}
+
+/// Function walks a directory that lives on a network filesystem like SMB or NFS. Unlike the
+/// default walk this relies on the file types returned by the directory reads instead of calling
+/// `stat` on every entry, only symlinks are resolved and their targets are kept in `stat_cache`
+/// for the rest of the scan. With `ignore_hidden` set hidden files and directories are pruned before
+/// they are read. Nothing deeper than `max_depth` below `root` is walked.
+fn walk_network(
+    root: &Path,
+    max_depth: usize,
+    ignore_hidden: bool,
+    stat_cache: &mut HashMap<PathBuf, bool>,
+) -> Vec<PathBuf> {
+    let mut files = vec![];
+    // directories left to walk and their depth below `root`.
+    let mut pending = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        let entries = WalkDir::new(&dir)
+            .follow_links(false)
+            .max_depth(max_depth - depth)
+            .into_iter()
+            .filter_entry(|f| !(ignore_hidden && is_hidden(&dir, f.path())))
+            .filter_map(Result::ok);
+
+        for entry in entries {
+            let file_type = entry.file_type();
+            let entry_depth = depth + entry.depth();
+            let path = entry.into_path();
+
+            if file_type.is_dir() {
+                continue;
+            }
+
+            if file_type.is_symlink() {
+                let target = match std::fs::canonicalize(&path) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+
+                // a symlinked directory is walked on its own, the cache stops us from resolving
+                // the same target twice and from following symlink loops.
+                if stat_cache.contains_key(&target) {
+                    continue;
+                }
+
+                let is_dir = std::fs::metadata(&target)
+                    .map(|x| x.is_dir())
+                    .unwrap_or(false);
+                stat_cache.insert(target, is_dir);
+
+                if is_dir {
+                    if entry_depth < max_depth {
+                        pending.push((path, entry_depth));
+                    }
+                    continue;
+                }
+            }
+
+            if is_supported(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files
+}
+
+/// Function removes the paths that point at a file that is already in `files`, ie because two
+/// locations of a library overlap. With [`PathCase::Insensitive`] paths that only differ in case
+/// point at the same file, only ASCII letters are folded to match how sqlite compares paths with
+/// `COLLATE NOCASE`. The first path of every file is kept.
+fn dedup_paths(files: Vec<PathBuf>, case: PathCase) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+
+    files
+        .into_iter()
+        .filter(|x| {
+            let path = x.to_string_lossy();
+            seen.insert(match case {
+                PathCase::Sensitive => path.into_owned(),
+                PathCase::Insensitive => path.to_ascii_lowercase(),
+            })
+        })
+        .collect()
+}
+
+/// Function returns the mediafile indexed under `path`. With [`PathCase::Insensitive`] paths that
+/// only differ in case match, ie `Movie.mkv` and `movie.mkv`.
+///
+/// # Arguments
+/// * `conn` - database connection
+/// * `path` - path of the file
+pub(crate) async fn get_mediafile_by_path(
+    conn: &DbConnection,
+    path: &str,
+) -> Result<MediaFile, DatabaseError> {
+    match crate::get_global_settings().path_case {
+        PathCase::Sensitive => MediaFile::get_by_file(conn, path).await,
+        PathCase::Insensitive => MediaFile::get_by_file_nocase(conn, path).await,
+    }
+}
+
 pub async fn start(
     library_id: i64,
     log: slog::Logger,
@@ -194,3 +807,516 @@ pub async fn start(
     )
     .await
 }
+
+/// Function probes every file of a library that is missing technical metadata such as the
+/// resolution, codec or duration and fills it in. Unlike a scan this neither walks the filesystem
+/// nor fetches metadata from any provider. Returns the number of files that were updated.
+pub async fn analyze(
+    library_id: i64,
+    log: slog::Logger,
+    tx: EventTx,
+) -> Result<usize, self::base::ScannerError> {
+    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    let extractor = get_extractor(&log, &tx);
+
+    let files = MediaFile::get_by_lib(&conn, library_id)
+        .await?
+        .into_iter()
+        .filter(|x| {
+            x.quality.is_none()
+                || x.codec.is_none()
+                || x.container.is_none()
+                || x.audio.is_none()
+                || x.original_resolution.is_none()
+                || x.duration.is_none()
+        })
+        .collect::<Vec<_>>();
+
+    info!(
+        log,
+        "Analyzing library";
+        "mod" => "scanner",
+        "library_id" => library_id,
+        "files" => files.len(),
+    );
+
+    let now = Instant::now();
+    let batch_size = crate::get_global_settings().scan_batch_size.max(1);
+
+    let futures = files
+        .iter()
+        .cloned()
+        .map(move |file| async move { extractor.analyze_file(file).await });
+
+    let updated = stream::iter(futures)
+        .buffer_unordered(batch_size)
+        .filter(|x| futures::future::ready(matches!(x, Ok(true))))
+        .count()
+        .await;
+
+    info!(
+        log,
+        "Finished analyzing library";
+        "library_id" => library_id,
+        "updated" => updated,
+        "duration" => now.elapsed().as_secs(),
+    );
+
+    Ok(updated)
+}
+
+/// Source of the metadata [`refresh_ongoing_shows`] refreshes tv shows with. Implemented by
+/// [`Tmdb`](tmdb::Tmdb), the trait exists so that the refresh can be run against other providers.
+#[async_trait]
+pub trait ShowProvider {
+    /// Returns the best search result for a show, used for shows that were never matched to an id.
+    async fn search(
+        &mut self,
+        title: String,
+        year: Option<i32>,
+    ) -> Result<ApiMedia, tmdb::TmdbError>;
+    /// Returns whether the show with the id is still airing.
+    async fn is_airing(&mut self, id: u64) -> Result<bool, tmdb::TmdbError>;
+    /// Returns the seasons of the show with the id.
+    async fn get_seasons_for(&mut self, id: u64) -> Result<Vec<tmdb::Season>, tmdb::TmdbError>;
+    /// Returns the episodes of a season of the show with the id.
+    async fn get_episodes_for(
+        &mut self,
+        id: u64,
+        season: u64,
+    ) -> Result<Vec<tmdb::Episode>, tmdb::TmdbError>;
+}
+
+#[async_trait]
+impl ShowProvider for Tmdb {
+    async fn search(
+        &mut self,
+        title: String,
+        year: Option<i32>,
+    ) -> Result<ApiMedia, tmdb::TmdbError> {
+        Tmdb::search(self, title, year).await
+    }
+
+    async fn is_airing(&mut self, id: u64) -> Result<bool, tmdb::TmdbError> {
+        Tmdb::is_airing(self, id).await
+    }
+
+    async fn get_seasons_for(&mut self, id: u64) -> Result<Vec<tmdb::Season>, tmdb::TmdbError> {
+        Tmdb::get_seasons_for(self, id).await
+    }
+
+    async fn get_episodes_for(
+        &mut self,
+        id: u64,
+        season: u64,
+    ) -> Result<Vec<tmdb::Episode>, tmdb::TmdbError> {
+        Tmdb::get_episodes_for(self, id, season).await
+    }
+}
+
+/// Function fetches fresh metadata for every tv show that is still airing from tmdb, see
+/// [`refresh_shows`].
+pub async fn refresh_ongoing_shows(
+    log: &slog::Logger,
+    tx: &EventTx,
+) -> Result<usize, self::base::ScannerError> {
+    let conn = get_conn().await.expect("Failed to grab the conn pool");
+    let mut tmdb = Tmdb::new("38c372f5bc572c8aadde7a802638534e".into(), MediaType::Tv);
+
+    refresh_shows(&conn, log, tx, &mut tmdb).await
+}
+
+/// Function fetches fresh metadata for every tv show that is still airing. Shows are fetched by
+/// the tmdb id they were matched to, shows without one are searched for by name once and the id
+/// found is stored. Names and descriptions of episodes that were scanned before the provider had
+/// metadata for them are filled in, and seasons and episodes the provider lists that arent in the
+/// library yet are added. Shows that have ended are skipped.
+///
+/// Returns the number of episodes that were updated or added. A `EventUpdateCard` event is
+/// dispatched for every updated episode and a new card for every added one.
+pub async fn refresh_shows(
+    conn: &DbConnection,
+    log: &slog::Logger,
+    tx: &EventTx,
+    provider: &mut (impl ShowProvider + Send),
+) -> Result<usize, self::base::ScannerError> {
+    let mut updated = 0;
+
+    for show in TVShow::get_all(conn).await? {
+        let tmdb_id = match Media::get_tmdb_id(conn, show.id).await? {
+            Some(x) => x as u64,
+            None => match provider
+                .search(show.name.clone(), show.year.map(|x| x as i32))
+                .await
+            {
+                Ok(x) => {
+                    Media::set_tmdb_id(conn, show.id, x.id as i64).await?;
+                    x.id
+                }
+                Err(e) => {
+                    debug!(
+                        log,
+                        "Could not find show to refresh";
+                        "media_id" => show.id,
+                        "reason" => e.to_string(),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        match provider.is_airing(tmdb_id).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Could not fetch airing status of show";
+                    "media_id" => show.id,
+                    "reason" => e.to_string(),
+                );
+                continue;
+            }
+        }
+
+        let seasons = match provider.get_seasons_for(tmdb_id).await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!(
+                    log,
+                    "Could not fetch seasons of show";
+                    "media_id" => show.id,
+                    "reason" => e.to_string(),
+                );
+                continue;
+            }
+        };
+
+        let local = Season::get_all(conn, show.id).await?;
+
+        for number in seasons.iter().filter_map(|x| x.season_number) {
+            let fresh = match provider.get_episodes_for(tmdb_id, number).await {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            let season_id = match local.iter().find(|x| x.season_number == number as i64) {
+                Some(x) => x.id,
+                None => {
+                    InsertableSeason {
+                        season_number: number as i64,
+                        added: Utc::now().to_string(),
+                        poster: None,
+                    }
+                    .insert(conn, show.id)
+                    .await?
+                }
+            };
+
+            let episodes = Episode::get_all_of_season(conn, season_id).await?;
+
+            for fresh in fresh.iter() {
+                let number = match fresh.episode_number {
+                    Some(x) => x as i64,
+                    None => continue,
+                };
+
+                let episode = match episodes.iter().find(|x| x.episode == number) {
+                    Some(x) => x,
+                    None => {
+                        let id = InsertableEpisode {
+                            episode: number,
+                            seasonid: season_id,
+                            media: InsertableMedia {
+                                library_id: show.library_id,
+                                name: fresh.name.clone().unwrap_or_else(|| number.to_string()),
+                                description: fresh.overview.clone().filter(|x| !x.is_empty()),
+                                added: Utc::now().to_string(),
+                                media_type: MediaType::Episode,
+                                ..Default::default()
+                            },
+                        }
+                        .insert(conn)
+                        .await?;
+
+                        updated += 1;
+                        push_new_card(tx, show.library_id, id);
+                        continue;
+                    }
+                };
+
+                let update = UpdateMedia {
+                    name: fresh.name.clone().filter(|x| *x != episode.media.name),
+                    description: fresh
+                        .overview
+                        .clone()
+                        .filter(|x| !x.is_empty() && Some(x) != episode.media.description.as_ref()),
+                    ..Default::default()
+                };
+
+                if update.name.is_none() && update.description.is_none() {
+                    continue;
+                }
+
+                update.update(conn, episode.media.id).await?;
+                updated += 1;
+
+                let event = events::Message {
+                    id: episode.media.id,
+                    event_type: events::PushEventType::EventUpdateCard,
+                };
+
+                let _ = tx.send(event.to_string());
+            }
+        }
+    }
+
+    info!(
+        log,
+        "Refreshed ongoing shows";
+        "mod" => "scanner",
+        "updated" => updated,
+    );
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use database::library::InsertableLibrary;
+
+    #[test]
+    fn test_ignore_reason() {
+        let locations = vec![
+            "/home/user/.media/movies".to_string(),
+            "/srv/movies".to_string(),
+        ];
+        let hidden = Some("hidden files and directories are ignored");
+        let cases = &[
+            ("/srv/movies/Movie.mkv", None),
+            ("/srv/movies/a/b/Movie.webm", None),
+            // hidden directories the location lives in dont count.
+            ("/home/user/.media/movies/Movie.mkv", None),
+            ("/home/user/.media/movies/.trash/Movie.mkv", hidden),
+            ("/srv/movies/.Movie.mkv", hidden),
+            (
+                "/srv/movies/Movie.txt",
+                Some("the extension `txt` is not supported"),
+            ),
+            (
+                "/srv/movies/Movie",
+                Some("files without an extension are ignored"),
+            ),
+            (
+                "/srv/other/Movie.mkv",
+                Some("the path is not inside any location of the library"),
+            ),
+        ];
+
+        for (path, reason) in cases {
+            assert_eq!(
+                ignore_reason(1, &locations, Path::new(path)),
+                reason.map(ToString::to_string),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_walk_reason() {
+        let root = Path::new("/srv/movies");
+
+        assert!(walk_reason(root, Path::new("/srv/movies/a/Movie.mkv"), 2, true).is_none());
+        assert!(walk_reason(root, Path::new("/srv/movies/a/Movie.mkv"), 1, true).is_some());
+        assert!(walk_reason(root, Path::new("/srv/movies/.a/Movie.mkv"), 2, true).is_some());
+        assert!(walk_reason(root, Path::new("/srv/movies/.a/Movie.mkv"), 2, false).is_none());
+    }
+
+    #[test]
+    fn test_dedup_paths() {
+        let files = || {
+            vec![
+                PathBuf::from("/media/Movie.mkv"),
+                PathBuf::from("/media/movie.mkv"),
+                PathBuf::from("/media/Été.mkv"),
+                PathBuf::from("/media/été.mkv"),
+            ]
+        };
+
+        assert_eq!(dedup_paths(files(), PathCase::Sensitive), files());
+        // only ASCII letters are folded, like sqlite does with `COLLATE NOCASE`.
+        assert_eq!(
+            dedup_paths(files(), PathCase::Insensitive),
+            vec![
+                PathBuf::from("/media/Movie.mkv"),
+                PathBuf::from("/media/Été.mkv"),
+                PathBuf::from("/media/été.mkv"),
+            ]
+        );
+    }
+
+    /// Provider serving two seasons with two episodes each for the show with the id 1399.
+    struct MockProvider {
+        searches: usize,
+    }
+
+    #[async_trait]
+    impl ShowProvider for MockProvider {
+        async fn search(
+            &mut self,
+            title: String,
+            _year: Option<i32>,
+        ) -> Result<ApiMedia, tmdb::TmdbError> {
+            self.searches += 1;
+
+            Ok(ApiMedia {
+                id: 1399,
+                title,
+                release_date: None,
+                overview: None,
+                poster_path: None,
+                backdrop_path: None,
+                poster_file: None,
+                backdrop_file: None,
+                genres: vec![],
+                rating: None,
+                seasons: vec![],
+                trailers: vec![],
+            })
+        }
+
+        async fn is_airing(&mut self, id: u64) -> Result<bool, tmdb::TmdbError> {
+            assert_eq!(id, 1399);
+            Ok(true)
+        }
+
+        async fn get_seasons_for(&mut self, id: u64) -> Result<Vec<tmdb::Season>, tmdb::TmdbError> {
+            assert_eq!(id, 1399);
+
+            Ok((1..=2)
+                .map(|x| tmdb::Season {
+                    id: x,
+                    air_date: None,
+                    episode_count: Some(2),
+                    name: None,
+                    overview: None,
+                    poster_path: None,
+                    season_number: Some(x),
+                })
+                .collect())
+        }
+
+        async fn get_episodes_for(
+            &mut self,
+            id: u64,
+            season: u64,
+        ) -> Result<Vec<tmdb::Episode>, tmdb::TmdbError> {
+            assert_eq!(id, 1399);
+
+            Ok((1..=2)
+                .map(|x| tmdb::Episode {
+                    id: season * 10 + x,
+                    name: Some(format!("S{}E{}", season, x)),
+                    overview: Some("Fresh".into()),
+                    episode_number: Some(x),
+                    still_path: None,
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_shows() {
+        let conn = database::get_conn_memory().await.unwrap();
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let library_id = InsertableLibrary {
+            name: "Shows".into(),
+            locations: vec![],
+            media_type: MediaType::Tv,
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+
+        let show = InsertableMedia {
+            library_id,
+            name: "Show".into(),
+            media_type: MediaType::Tv,
+            ..Default::default()
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+        TVShow::insert(&conn, show).await.unwrap();
+
+        let season = InsertableSeason {
+            season_number: 1,
+            ..Default::default()
+        }
+        .insert(&conn, show)
+        .await
+        .unwrap();
+
+        // scanned before the provider had metadata for it.
+        let episode = InsertableEpisode {
+            episode: 1,
+            seasonid: season,
+            media: InsertableMedia {
+                library_id,
+                name: "1".into(),
+                media_type: MediaType::Episode,
+                ..Default::default()
+            },
+        }
+        .insert(&conn)
+        .await
+        .unwrap();
+
+        let mut provider = MockProvider { searches: 0 };
+
+        // the existing episode is updated, the rest of season 1 and all of season 2 are added.
+        let updated = refresh_shows(&conn, &log, &tx, &mut provider)
+            .await
+            .unwrap();
+        assert_eq!(updated, 4);
+        assert_eq!(provider.searches, 1);
+        assert_eq!(Media::get_tmdb_id(&conn, show).await.unwrap(), Some(1399));
+
+        let result = Episode::get_by_id(&conn, episode).await.unwrap();
+        assert_eq!(result.media.name, "S1E1");
+        assert_eq!(result.media.description.as_deref(), Some("Fresh"));
+
+        let seasons = Season::get_all(&conn, show).await.unwrap();
+        assert_eq!(seasons.len(), 2);
+
+        for season in seasons {
+            let mut names = Episode::get_all_of_season(&conn, season.id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|x| x.media.name)
+                .collect::<Vec<_>>();
+            names.sort();
+
+            assert_eq!(
+                names,
+                &[
+                    format!("S{}E1", season.season_number),
+                    format!("S{}E2", season.season_number)
+                ]
+            );
+        }
+
+        // the show is fetched by its stored id now and everything is up to date.
+        let updated = refresh_shows(&conn, &log, &tx, &mut provider)
+            .await
+            .unwrap();
+        assert_eq!(updated, 0);
+        assert_eq!(provider.searches, 1);
+    }
+}
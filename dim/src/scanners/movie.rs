@@ -6,8 +6,10 @@ use database::DbConnection;
 
 use database::library::MediaType;
 use database::media::InsertableMedia;
+use database::media::Media;
 use database::mediafile::MediaFile;
 use database::mediafile::UpdateMediaFile;
+use database::trailer::InsertableTrailer;
 
 use chrono::prelude::Utc;
 use chrono::Datelike;
@@ -16,11 +18,9 @@ use chrono::NaiveDate;
 use slog::warn;
 use slog::Logger;
 
-use events::Message;
-use events::PushEventType;
-
 use crate::core::EventTx;
 use crate::fetcher::insert_into_queue;
+use crate::routes::settings::DuplicateProviderIds;
 
 pub struct MovieMatcher<'a> {
     pub conn: &'a DbConnection,
@@ -145,7 +145,43 @@ impl<'a> MovieMatcher<'a> {
         media: InsertableMedia,
         result: super::ApiMedia,
     ) -> Result<(), super::base::ScannerError> {
-        let media_id = media.insert(&self.conn).await?;
+        let tmdb_id = result.id as i64;
+        let existing =
+            Media::get_id_by_tmdb_id(&self.conn, media.library_id, tmdb_id, MediaType::Movie)
+                .await?;
+
+        // a movie matched to an id another movie has already is merged, kept separate or flagged.
+        let duplicates = crate::get_global_settings().duplicate_provider_ids;
+        let media_id = match (existing, duplicates) {
+            (Some(id), DuplicateProviderIds::Merge) => {
+                let updated_mediafile = UpdateMediaFile {
+                    media_id: Some(id),
+                    needs_review: Some(false),
+                    ..Default::default()
+                };
+
+                updated_mediafile.update(&self.conn, orphan.id).await?;
+                return Ok(());
+            }
+            (Some(_), DuplicateProviderIds::Flag) => {
+                let updated_mediafile = UpdateMediaFile {
+                    needs_review: Some(true),
+                    ..Default::default()
+                };
+
+                updated_mediafile.update(&self.conn, orphan.id).await?;
+                return Ok(());
+            }
+            (Some(_), DuplicateProviderIds::Separate) => {
+                media.insert_with_tmdb_id(&self.conn, tmdb_id).await?
+            }
+            (None, _) => {
+                let media_id = media.insert(&self.conn).await?;
+                Media::set_tmdb_id(&self.conn, media_id, tmdb_id).await?;
+                media_id
+            }
+        };
+
         // the reason we ignore the result here is that in some cases this can fail. Specifically when there are multiple mediafiles for a movie.
         let _ = InsertableMovie::insert(&self.conn, media_id).await;
 
@@ -157,8 +193,19 @@ impl<'a> MovieMatcher<'a> {
             }
         }
 
+        for trailer in result.trailers.iter() {
+            let trailer = InsertableTrailer {
+                media_id,
+                name: trailer.name.clone(),
+                url: trailer.url.clone(),
+            };
+
+            let _ = trailer.insert(&self.conn).await;
+        }
+
         let updated_mediafile = UpdateMediaFile {
             media_id: Some(media_id),
+            needs_review: Some(false),
             ..Default::default()
         };
 
@@ -172,11 +219,6 @@ impl<'a> MovieMatcher<'a> {
     async fn push_event(&self, id: i64, lib_id: i64) {
         // TODO: verify if this scanner suffers from the same duplicate top-level media insertion
         // bug.
-        let event = Message {
-            id,
-            event_type: PushEventType::EventNewCard { lib_id },
-        };
-
-        let _ = self.event_tx.send(serde_json::to_string(&event).unwrap());
+        super::push_new_card(&self.event_tx, lib_id, id);
     }
 }
@@ -0,0 +1,484 @@
+//! Module contains a pre-processing pass for filenames which strips release tags such as
+//! `[1080p]`, `{edition-Extended}` or `-PROPER` out of the filename before it is handed to the
+//! filename parser. Tags that carry useful information are captured into [`ReleaseTags`].
+
+/// Tags captured from a filename.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReleaseTags {
+    /// Quality tag, ie `1080p`.
+    pub quality: Option<String>,
+    /// Edition of the release, ie `Extended`.
+    pub edition: Option<String>,
+    /// Name of the group which released the file.
+    pub release_group: Option<String>,
+}
+
+/// Function strips release tags from `name` returning the cleaned up name alongside the tags that
+/// were captured. Words found in `strip` are removed regardless of case.
+///
+/// # Arguments
+/// * `name` - filename without the extension
+/// * `strip` - list of tags that should be removed from the filename
+pub fn extract(name: &str, strip: &[String]) -> (String, ReleaseTags) {
+    let mut tags = ReleaseTags::default();
+    let mut clean = String::with_capacity(name.len());
+    let mut rest = name.trim();
+
+    // leading `[Group]` is how most fansub groups tag their releases.
+    if rest.starts_with('[') {
+        if let Some(end) = rest.find(']') {
+            let group = rest[1..end].trim();
+
+            if !group.is_empty() && !is_quality(group) {
+                tags.release_group = Some(group.to_string());
+                rest = &rest[end + 1..];
+            }
+        }
+    }
+
+    while let Some(start) = rest.find(|c: char| c == '[' || c == '{') {
+        let close = if rest[start..].starts_with('[') {
+            ']'
+        } else {
+            '}'
+        };
+
+        let end = match rest[start..].find(close) {
+            Some(x) => start + x,
+            None => break,
+        };
+
+        clean.push_str(&rest[..start]);
+        capture(&rest[start + 1..end], &mut tags);
+        rest = &rest[end + 1..];
+    }
+
+    clean.push_str(rest);
+
+    let mut clean = strip_words(&clean, strip);
+
+    if tags.release_group.is_none() {
+        if let Some(group) = trailing_group(&clean) {
+            clean.truncate(clean.len() - group.len() - 1);
+            tags.release_group = Some(group);
+        }
+    }
+
+    (clean.trim().to_string(), tags)
+}
+
+/// Function normalizes the separators used between words, ie `Show.Name.S01E02` and
+/// `Show Name - S01E02` both become `Show Name S01E02`. Dots and underscores are always treated as
+/// separators while dashes are only treated as one when they arent between two words, so titles
+/// like `Spider-Man` are kept intact.
+///
+/// # Arguments
+/// * `name` - filename without the extension and release tags
+pub fn normalize_separators(name: &str) -> String {
+    let chars = name
+        .chars()
+        .map(|c| if c == '.' || c == '_' { ' ' } else { c })
+        .collect::<Vec<_>>();
+
+    let mut result = String::with_capacity(name.len());
+
+    for (i, c) in chars.iter().enumerate() {
+        let is_separator = c.is_whitespace()
+            || (*c == '-'
+                && !(i > 0
+                    && chars[i - 1].is_alphanumeric()
+                    && chars.get(i + 1).map_or(false, |x| x.is_alphanumeric())));
+
+        if !is_separator {
+            result.push(*c);
+        } else if !result.is_empty() && !result.ends_with(' ') {
+            result.push(' ');
+        }
+    }
+
+    result.trim_end().to_string()
+}
+
+/// Function returns whether a parsed title is too generic to be matched against a metadata
+/// provider, ie the title of `video1.mp4` or `IMG_1234.mov`. Matching these would only pollute the
+/// library with wrong metadata. Titles without any letters such as `300` or `1917` are real titles
+/// and never generic.
+///
+/// # Arguments
+/// * `title` - title parsed from the filename
+pub fn is_generic_title(title: &str) -> bool {
+    const GENERIC: &[&str] = &[
+        "video", "vid", "clip", "file", "untitled", "img", "mov", "dsc", "dscn", "pxl",
+    ];
+
+    let word = title
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect::<String>()
+        .to_lowercase();
+
+    !word.is_empty() && GENERIC.contains(&word.as_str())
+}
+
+/// Function strips a trailing multi-part tag such as `CD1`, `disc 2` or `pt3` from `name`
+/// returning the cleaned up name alongside the part number. `part` is deliberately not treated as
+/// a tag because titles like `Deathly Hallows Part 1` are separate movies.
+///
+/// # Arguments
+/// * `name` - filename without the extension and release tags
+pub fn extract_part(name: &str) -> (String, Option<i64>) {
+    const PREFIXES: &[&str] = &["cd", "dvd", "disc", "disk", "pt"];
+
+    let words = name
+        .char_indices()
+        .filter(|(i, c)| {
+            c.is_alphanumeric()
+                && name[..*i]
+                    .chars()
+                    .next_back()
+                    .map_or(true, |x| !x.is_alphanumeric())
+        })
+        .map(|(i, _)| {
+            let len = name[i..]
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(name.len() - i);
+            (i, &name[i..i + len])
+        })
+        .collect::<Vec<_>>();
+
+    let number = |x: &str| -> Option<i64> {
+        if x.is_empty() || x.len() > 2 || !x.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        x.parse().ok().filter(|x| *x > 0)
+    };
+
+    let (start, part) = match words.as_slice() {
+        [.., _, (start, word)] => match PREFIXES
+            .iter()
+            .find(|x| word.to_lowercase().starts_with(*x))
+        {
+            Some(prefix) => (*start, number(&word[prefix.len()..])),
+            None => match words.as_slice() {
+                [.., _, (start, prefix), (_, word)]
+                    if PREFIXES.iter().any(|x| prefix.eq_ignore_ascii_case(x)) =>
+                {
+                    (*start, number(word))
+                }
+                _ => return (name.to_string(), None),
+            },
+        },
+        _ => return (name.to_string(), None),
+    };
+
+    match part {
+        Some(part) => (
+            name[..start]
+                .trim_end_matches(|c: char| !c.is_alphanumeric() && c != ')' && c != ']')
+                .to_string(),
+            Some(part),
+        ),
+        None => (name.to_string(), None),
+    }
+}
+
+/// Function strips the tail of an episode range such as `S01E01-E02`, `S01E01E02` or `S01E01-02`
+/// from `name` returning the name with only the first episode left alongside the last episode of
+/// the range.
+///
+/// # Arguments
+/// * `name` - filename without the extension
+pub fn extract_episode_range(name: &str) -> (String, Option<i64>) {
+    let bytes = name.as_bytes();
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|x| x.is_ascii_digit())
+            .count()
+    };
+    let is_e = |at: usize| {
+        bytes
+            .get(at)
+            .map_or(false, |x| x.eq_ignore_ascii_case(&b'e'))
+    };
+
+    for start in 0..bytes.len() {
+        if !bytes[start].eq_ignore_ascii_case(&b's')
+            || (start > 0 && bytes[start - 1].is_ascii_alphanumeric())
+        {
+            continue;
+        }
+
+        let e = start + 1 + digits(start + 1);
+        if e == start + 1 || !is_e(e) || digits(e + 1) == 0 {
+            continue;
+        }
+
+        let first_end = e + 1 + digits(e + 1);
+        let first = match name[e + 1..first_end].parse::<i64>() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        let mut end = first_end;
+        let mut last = None;
+
+        loop {
+            let mut next = end;
+            if bytes.get(next) == Some(&b'-') {
+                next += 1;
+            }
+
+            if is_e(next) {
+                next += 1;
+            }
+
+            let len = digits(next);
+            // the number has to stand on its own so that `S01E01-1080p` isnt read as a range.
+            let bounded = bytes
+                .get(next + len)
+                .map_or(true, |x| !x.is_ascii_alphanumeric() || is_e(next + len));
+
+            if next == end || len == 0 || !bounded {
+                break;
+            }
+
+            match name[next..next + len].parse::<i64>() {
+                Ok(x) if x > last.unwrap_or(first) => {
+                    last = Some(x);
+                    end = next + len;
+                }
+                _ => break,
+            }
+        }
+
+        return match last {
+            Some(last) => (
+                format!("{}{}", &name[..first_end], &name[end..]),
+                Some(last),
+            ),
+            None => (name.to_string(), None),
+        };
+    }
+
+    (name.to_string(), None)
+}
+
+/// Function captures the content of a bracketed tag into `tags` if it is a tag we know.
+fn capture(tag: &str, tags: &mut ReleaseTags) {
+    let tag = tag.trim();
+    let lower = tag.to_lowercase();
+
+    if lower.starts_with("edition-") {
+        match tag.get("edition-".len()..).map(str::trim) {
+            Some(edition) if !edition.is_empty() => tags.edition = Some(edition.to_string()),
+            _ => {}
+        }
+    } else if is_quality(tag) && tags.quality.is_none() {
+        tags.quality = Some(lower);
+    }
+}
+
+fn is_quality(tag: &str) -> bool {
+    let lower = tag.to_lowercase();
+
+    if lower == "4k" || lower == "8k" {
+        return true;
+    }
+
+    match lower.strip_suffix(|c: char| c == 'p' || c == 'i') {
+        Some(x) => !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Function removes all words found in `strip` from `name`. A word is a run of alphanumeric
+/// characters, the separator in front of a removed word is removed as well.
+fn strip_words(name: &str, strip: &[String]) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut separator = String::new();
+    let mut word = String::new();
+
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+
+        if !word.is_empty() {
+            push_word(&mut result, &mut separator, &mut word, strip);
+        }
+
+        separator.push(c);
+    }
+
+    push_word(&mut result, &mut separator, &mut word, strip);
+
+    result
+}
+
+fn push_word(result: &mut String, separator: &mut String, word: &mut String, strip: &[String]) {
+    if word.is_empty() || !strip.iter().any(|x| x.eq_ignore_ascii_case(word)) {
+        result.push_str(separator);
+        result.push_str(word);
+    }
+
+    separator.clear();
+    word.clear();
+}
+
+/// Function returns the release group from names like `Movie.2020.1080p-GROUP`. To avoid
+/// mistaking titles such as `Spider-Man` for a release, the token in front of the dash must
+/// contain a digit or be closed by a bracket.
+fn trailing_group(name: &str) -> Option<String> {
+    let dash = name.rfind('-')?;
+    let group = &name[dash + 1..];
+
+    if group.is_empty() || !group.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let before = name[..dash]
+        .rsplit(|c: char| c == '.' || c == ' ' || c == '_')
+        .next()?;
+
+    if before.ends_with(')') || before.ends_with(']') || before.chars().any(|c| c.is_ascii_digit())
+    {
+        Some(group.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(quality: Option<&str>, edition: Option<&str>, group: Option<&str>) -> ReleaseTags {
+        ReleaseTags {
+            quality: quality.map(ToString::to_string),
+            edition: edition.map(ToString::to_string),
+            release_group: group.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn test_extract() {
+        let strip = vec!["PROPER".to_string(), "REPACK".to_string()];
+        let cases = &[
+            (
+                "Movie (2020) [1080p] {edition-Extended}",
+                ("Movie (2020)", tags(Some("1080p"), Some("Extended"), None)),
+            ),
+            (
+                "[SubGroup] Show - 01 [720p]",
+                ("Show - 01", tags(Some("720p"), None, Some("SubGroup"))),
+            ),
+            ("[1080p] Movie", ("Movie", tags(Some("1080p"), None, None))),
+            ("Movie [4K]", ("Movie", tags(Some("4k"), None, None))),
+            ("Movie {edition-}", ("Movie", tags(None, None, None))),
+            (
+                "Movie.2020.1080p.PROPER-GROUP",
+                ("Movie.2020.1080p", tags(None, None, Some("GROUP"))),
+            ),
+            ("Movie repack", ("Movie", tags(None, None, None))),
+            // dashes within titles are not release groups.
+            ("Spider-Man", ("Spider-Man", tags(None, None, None))),
+            ("Movie [unknown]", ("Movie", tags(None, None, None))),
+        ];
+
+        for (name, (clean, tags)) in cases {
+            assert_eq!(
+                extract(name, &strip),
+                (clean.to_string(), tags.clone()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_quality() {
+        for tag in &["1080p", "720P", "480i", "4K", "8k"] {
+            assert!(is_quality(tag), "{}", tag);
+        }
+
+        for tag in &["p", "1080x", "HDR", "x264", ""] {
+            assert!(!is_quality(tag), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn test_trailing_group() {
+        let cases = &[
+            ("Movie.2020.1080p-GROUP", Some("GROUP")),
+            ("Movie (2020)-GROUP", Some("GROUP")),
+            ("Spider-Man", None),
+            ("Movie.2020-", None),
+            ("Movie.2020-GR.OUP", None),
+        ];
+
+        for (name, group) in cases {
+            assert_eq!(
+                trailing_group(name),
+                group.map(ToString::to_string),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_separators() {
+        let cases = &[
+            ("Show.Name.S01E02", "Show Name S01E02"),
+            ("Show Name - S01E02", "Show Name S01E02"),
+            ("Show_Name_S01E02", "Show Name S01E02"),
+            ("Show.Name.-.S01E02", "Show Name S01E02"),
+            ("Spider-Man.2002", "Spider-Man 2002"),
+            ("-Movie.", "Movie"),
+        ];
+
+        for (name, normalized) in cases {
+            assert_eq!(normalize_separators(name), *normalized, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_is_generic_title() {
+        for title in &["video1", "IMG 1234", "DSC 0042", "Untitled"] {
+            assert!(is_generic_title(title), "{}", title);
+        }
+
+        for title in &["300", "1917", "2012", "21", "Blade Runner"] {
+            assert!(!is_generic_title(title), "{}", title);
+        }
+    }
+
+    #[test]
+    fn test_extract_episode_range() {
+        let cases = &[
+            ("Show S01E01-E02", ("Show S01E01", Some(2))),
+            ("Show S01E01E02 Title", ("Show S01E01 Title", Some(2))),
+            ("Show S01E01-02", ("Show S01E01", Some(2))),
+            ("show s01e01-e02", ("show s01e01", Some(2))),
+            ("Show S01E01E02E03", ("Show S01E01", Some(3))),
+            // not a range
+            ("Show S01E01", ("Show S01E01", None)),
+            ("Show S01E01-1080p", ("Show S01E01-1080p", None)),
+            // ranges have to be increasing
+            ("Show S01E03-E02", ("Show S01E03-E02", None)),
+            ("Show S01E02E02", ("Show S01E02E02", None)),
+        ];
+
+        for (name, (clean, last)) in cases {
+            assert_eq!(
+                extract_episode_range(name),
+                (clean.to_string(), *last),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -107,13 +107,15 @@ impl FsWatcher {
                 .mount_file(path.clone(), self.library_id, self.media_type)
                 .await
             {
-                match self.media_type {
+                match super::match_type(self.media_type, &mfile) {
                     MediaType::Movie => {
                         let _ = matcher.match_movie(mfile).await;
                     }
                     MediaType::Tv => {
                         let _ = matcher.match_tv(mfile).await;
                     }
+                    // audio files arent matched against tmdb.
+                    MediaType::Audio => {}
                     _ => unreachable!(),
                 }
             }
@@ -142,7 +144,7 @@ impl FsWatcher {
             }
         };
 
-        if let Some(media_file) = MediaFile::get_by_file(&self.conn, path).await.ok() {
+        if let Some(media_file) = super::get_mediafile_by_path(&self.conn, path).await.ok() {
             let media = Media::get_of_mediafile(&self.conn, media_file.id).await;
 
             if let Err(e) = MediaFile::delete(&self.conn, media_file.id).await {
@@ -189,7 +191,7 @@ impl FsWatcher {
             }
         };
 
-        if let Some(media_file) = MediaFile::get_by_file(&self.conn, from).await.ok() {
+        if let Some(media_file) = super::get_mediafile_by_path(&self.conn, from).await.ok() {
             let update_query = UpdateMediaFile {
                 target_file: Some(to.into()),
                 ..Default::default()
@@ -6,19 +6,134 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
+use reqwest::header::RETRY_AFTER;
 use reqwest::Client;
 use reqwest::ClientBuilder;
+use reqwest::Response;
 use reqwest::StatusCode;
 
+use chrono::DateTime;
+use chrono::Utc;
+
 use err_derive::Error;
 use futures::stream;
 use futures::StreamExt;
 use tokio::sync::RwLock;
 
-use async_recursion::async_recursion;
-
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 
+type CacheKey = (String, Option<i32>, MediaType);
+type CacheStore = Arc<RwLock<HashMap<CacheKey, Vec<Media>>>>;
+
+lazy_static::lazy_static! {
+    static ref SEARCH_CACHE: CacheStore = Arc::new(RwLock::new(HashMap::new()));
+    static ref GENRE_CACHE: Arc<RwLock<HashMap<MediaType, Vec<Genre>>>> = Arc::new(RwLock::new(HashMap::new()));
+}
+
+/// Function clears the in-memory cache of search results and genres so that subsequent lookups
+/// fetch fresh metadata from tmdb.
+pub async fn clear_cache() {
+    SEARCH_CACHE.write().await.clear();
+    GENRE_CACHE.write().await.clear();
+}
+
+/// Function returns how confident we are that a search result is the media we searched for, from
+/// `0.0` to `1.0`. The score is made up of the similarity of the titles and, when both years are
+/// known, of how close the release year is to the year we searched for.
+///
+/// # Arguments
+/// * `query` - title we searched for
+/// * `year` - year we searched for
+/// * `title` - title of the result
+/// * `release_date` - release date of the result formatted as `YYYY-MM-DD`
+pub fn match_confidence(
+    query: &str,
+    year: Option<i32>,
+    title: &str,
+    release_date: Option<&str>,
+) -> f64 {
+    let title_score = title_similarity(query, title);
+
+    let release_year = release_date
+        .and_then(|x| x.get(..4))
+        .and_then(|x| x.parse::<i32>().ok());
+
+    match year.zip(release_year) {
+        Some((year, release_year)) => {
+            let year_score = match (year - release_year).abs() {
+                0 => 1.0,
+                1 => 0.5,
+                _ => 0.0,
+            };
+
+            title_score * 0.75 + year_score * 0.25
+        }
+        None => title_score,
+    }
+}
+
+/// Function returns the dice coefficient of the character bigrams of two titles. Titles are
+/// compared case insensitively and ignoring punctuation.
+fn title_similarity(a: &str, b: &str) -> f64 {
+    let normalize = |x: &str| {
+        x.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<Vec<_>>()
+    };
+
+    let (a, b) = (normalize(a), normalize(b));
+
+    if a == b {
+        return 1.0;
+    }
+
+    if a.len() < 2 || b.len() < 2 {
+        return 0.0;
+    }
+
+    let mut bigrams = a.windows(2).collect::<Vec<_>>();
+    let total = bigrams.len() + b.len() - 1;
+    let mut matches = 0;
+
+    for bigram in b.windows(2) {
+        if let Some(i) = bigrams.iter().position(|x| *x == bigram) {
+            bigrams.swap_remove(i);
+            matches += 1;
+        }
+    }
+
+    (2 * matches) as f64 / total as f64
+}
+
+/// Function returns how long to wait before retrying a request tmdb rejected because of its rate
+/// limit. `Retry-After` holds either a number of seconds or a http date, without it the delay
+/// doubles with every attempt starting at one second. The delay never exceeds `max_wait`.
+///
+/// # Arguments
+/// * `retry_after` - value of the `Retry-After` header
+/// * `attempt` - number of times the request was retried already
+/// * `max_wait` - longest we are willing to wait
+/// * `now` - current time, dates in `Retry-After` are relative to it
+pub fn retry_delay(
+    retry_after: Option<&str>,
+    attempt: u32,
+    max_wait: Duration,
+    now: DateTime<Utc>,
+) -> Duration {
+    let requested = retry_after.map(str::trim).and_then(|x| {
+        x.parse::<u64>().ok().map(Duration::from_secs).or_else(|| {
+            DateTime::parse_from_rfc2822(x)
+                .ok()
+                .map(|at| (at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+        })
+    });
+
+    requested
+        .unwrap_or_else(|| Duration::from_secs(1 << attempt.min(16)))
+        .min(max_wait)
+}
+
 #[derive(Debug, Error, Serialize)]
 pub enum TmdbError {
     #[error(display = "The request timeouted")]
@@ -31,6 +146,27 @@ pub enum TmdbError {
     DeserializationError,
     #[error(display = "No results are found")]
     NoResults,
+    #[error(display = "The best result had a match confidence of {}", _0)]
+    LowConfidence(f64),
+    #[error(display = "Tmdb responded with a server error")]
+    Unavailable,
+    #[error(display = "Tmdb kept rejecting requests because of its rate limit")]
+    RateLimited,
+}
+
+impl TmdbError {
+    /// Returns whether the error was caused by tmdb being unreachable rather than by the query,
+    /// requests that failed this way can succeed later on.
+    pub fn is_outage(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout
+                | Self::ReachedMaxTries
+                | Self::ReqwestError
+                | Self::Unavailable
+                | Self::RateLimited
+        )
+    }
 }
 
 #[derive(Clone)]
@@ -53,17 +189,62 @@ impl Tmdb {
         }
     }
 
+    /// Method sends a get request to `url`. Requests tmdb rejects because of its rate limit are
+    /// retried after the delay it asks for, up to `provider_rate_limit_retries` times.
+    async fn get(&self, url: String, args: &[(String, String)]) -> Result<Response, TmdbError> {
+        let settings = crate::get_global_settings();
+        let max_wait = Duration::from_secs(settings.provider_rate_limit_max_wait.max(1));
+        let mut attempt = 0;
+
+        loop {
+            let resp = self
+                .client
+                .get(&url)
+                .query(args)
+                .send()
+                .await
+                .map_err(|_| TmdbError::ReqwestError)?;
+
+            if resp.status() != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(resp);
+            }
+
+            if attempt >= settings.provider_rate_limit_retries {
+                return Err(TmdbError::RateLimited);
+            }
+
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|x| x.to_str().ok());
+
+            tokio::time::sleep(retry_delay(retry_after, attempt, max_wait, Utc::now())).await;
+            attempt += 1;
+        }
+    }
+
+    /// Method returns the best search result for `title`. Results with a match confidence below
+    /// `min_match_confidence` are rejected.
     pub async fn search(
         &mut self,
         title: String,
         year: Option<i32>,
     ) -> Result<super::ApiMedia, TmdbError> {
-        self.search_by_name(title, year, None)
+        let result = self
+            .search_by_name(title.clone(), year)
             .await?
             .first()
             .cloned()
-            .map(Into::into)
-            .ok_or(TmdbError::NoResults)
+            .ok_or(TmdbError::NoResults)?;
+
+        let confidence =
+            match_confidence(&title, year, &result.title, result.release_date.as_deref());
+
+        if confidence < crate::get_global_settings().min_match_confidence {
+            return Err(TmdbError::LowConfidence(confidence));
+        }
+
+        Ok(result.into())
     }
 
     pub async fn search_by_id(&mut self, id: i32) -> Result<Media, TmdbError> {
@@ -72,13 +253,7 @@ impl Tmdb {
         args.push(("language".into(), "en-US".into()));
 
         let url = format!("{}/{}/{}", self.base, self.media_type.to_string(), id);
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.get(url, &args).await?;
 
         #[derive(Deserialize, Clone, Debug)]
         struct WMedia {
@@ -123,22 +298,13 @@ impl Tmdb {
         })
     }
 
-    #[async_recursion]
     pub async fn search_by_name(
         &mut self,
         title: String,
         year: Option<i32>,
-        max_tries: Option<usize>,
     ) -> Result<Vec<Media>, TmdbError> {
-        type CacheKey = (String, Option<i32>, MediaType);
-        type CacheStore = Arc<RwLock<HashMap<CacheKey, Vec<Media>>>>;
-
-        lazy_static::lazy_static! {
-            static ref __CACHE: CacheStore = Arc::new(RwLock::new(HashMap::new()));
-        }
-
         {
-            let lock = (*__CACHE).read().await;
+            let lock = (*SEARCH_CACHE).read().await;
             let key = (title.clone(), year, self.media_type);
 
             if let Some(x) = lock.get(&key) {
@@ -146,12 +312,6 @@ impl Tmdb {
             }
         }
 
-        let max_tries = max_tries.unwrap_or(10);
-
-        if max_tries == 0 {
-            return Err(TmdbError::ReachedMaxTries);
-        }
-
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
         args.push(("language".into(), "en-US".into()));
@@ -165,17 +325,10 @@ impl Tmdb {
 
         let url = format!("{}/search/{}", self.base, self.media_type.to_string(),);
 
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.get(url, &args).await?;
 
-        if matches!(req.status(), StatusCode::TOO_MANY_REQUESTS) {
-            tokio::time::sleep(Duration::from_millis(1000)).await;
-            return self.search_by_name(title, year, Some(max_tries - 1)).await;
+        if req.status().is_server_error() {
+            return Err(TmdbError::Unavailable);
         }
 
         let mut result: Vec<Media> = req
@@ -206,7 +359,7 @@ impl Tmdb {
         }
 
         {
-            let mut lock = (*__CACHE).write().await;
+            let mut lock = (*SEARCH_CACHE).write().await;
             let key = (title.clone(), year, self.media_type);
             lock.insert(key, result.clone());
         }
@@ -218,13 +371,7 @@ impl Tmdb {
         let mut args: Vec<(String, String)> = Vec::new();
         args.push(("api_key".into(), self.api_key.clone()));
 
-        let req = self
-            .client
-            .get(format!("{}/tv/{}", self.base, id))
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.get(format!("{}/tv/{}", self.base, id), &args).await?;
 
         #[derive(Deserialize)]
         struct Wrapper {
@@ -238,6 +385,30 @@ impl Tmdb {
             .ok_or(TmdbError::NoResults)
     }
 
+    /// Method returns whether a tv show is still airing. Shows that tmdb lists as ended or canceled
+    /// are not expected to get new episodes.
+    pub async fn is_airing(&mut self, id: u64) -> Result<bool, TmdbError> {
+        let mut args: Vec<(String, String)> = Vec::new();
+        args.push(("api_key".into(), self.api_key.clone()));
+
+        let req = self.get(format!("{}/tv/{}", self.base, id), &args).await?;
+
+        #[derive(Deserialize)]
+        struct Wrapper {
+            status: Option<String>,
+            in_production: Option<bool>,
+        }
+
+        let result = req
+            .json::<Wrapper>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?;
+
+        let ended = matches!(result.status.as_deref(), Some("Ended") | Some("Canceled"));
+
+        Ok(!ended && result.in_production.unwrap_or(true))
+    }
+
     pub async fn get_episodes_for(
         &mut self,
         id: u64,
@@ -247,12 +418,8 @@ impl Tmdb {
         args.push(("api_key".into(), self.api_key.clone()));
 
         let req = self
-            .client
-            .get(format!("{}/tv/{}/season/{}", self.base, id, season))
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+            .get(format!("{}/tv/{}/season/{}", self.base, id, season), &args)
+            .await?;
 
         #[derive(Deserialize)]
         struct Wrapper {
@@ -266,13 +433,34 @@ impl Tmdb {
             .ok_or(TmdbError::NoResults)
     }
 
-    pub async fn get_genre_detail(&mut self, genre_id: u64) -> Result<Genre, TmdbError> {
-        lazy_static::lazy_static! {
-            static ref __CACHE: Arc<RwLock<HashMap<MediaType, Vec<Genre>>>> = Arc::new(RwLock::new(HashMap::new()));
+    /// Method returns the trailers tmdb has for a media. Only trailers hosted on youtube or vimeo
+    /// are returned as we cant build links for other sites.
+    pub async fn get_trailers_for(&mut self, id: u64) -> Result<Vec<Trailer>, TmdbError> {
+        let mut args: Vec<(String, String)> = Vec::new();
+        args.push(("api_key".into(), self.api_key.clone()));
+        args.push(("language".into(), "en-US".into()));
+
+        let url = format!("{}/{}/{}/videos", self.base, self.media_type, id);
+        let req = self.get(url, &args).await?;
+
+        #[derive(Deserialize)]
+        struct Wrapper {
+            results: Vec<Trailer>,
         }
 
+        Ok(req
+            .json::<Wrapper>()
+            .await
+            .map_err(|_| TmdbError::DeserializationError)?
+            .results
+            .into_iter()
+            .filter(|x| x.kind == "Trailer" && x.get_url().is_some())
+            .collect())
+    }
+
+    pub async fn get_genre_detail(&mut self, genre_id: u64) -> Result<Genre, TmdbError> {
         {
-            let lock = (*__CACHE).read().await;
+            let lock = (*GENRE_CACHE).read().await;
             if let Some(x) = lock.get(&self.media_type) {
                 if let Some(x) = x.iter().find(|x| x.id == genre_id) {
                     return Ok(x.clone());
@@ -284,13 +472,7 @@ impl Tmdb {
         args.push(("api_key".into(), self.api_key.clone()));
 
         let url = format!("{}/genre/{}/list", self.base.clone(), self.media_type);
-        let req = self
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await
-            .map_err(|_| TmdbError::ReqwestError)?;
+        let req = self.get(url, &args).await?;
 
         #[derive(Deserialize)]
         struct Wrapper {
@@ -304,7 +486,7 @@ impl Tmdb {
             .genres;
 
         {
-            let mut lock = (*__CACHE).write().await;
+            let mut lock = (*GENRE_CACHE).write().await;
             lock.insert(self.media_type.clone(), genres.clone());
         }
 
@@ -350,6 +532,22 @@ struct SearchResult {
     results: Vec<Option<Media>>,
 }
 
+/// Size of posters and stills fetched from tmdb.
+const POSTER_SIZE: &str = "w600_and_h900_bestv2";
+
+/// Function returns the url of a tmdb image at `size`, ie `original`. Images are fetched from the
+/// `tmdb_image_base_url` setting so a mirror or a regional CDN can be used.
+fn image_url(size: &str, path: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        crate::get_global_settings()
+            .tmdb_image_base_url
+            .trim_end_matches('/'),
+        size,
+        path.trim_start_matches('/')
+    )
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct Media {
     pub id: u64,
@@ -368,13 +566,10 @@ pub struct Media {
 
 impl From<Media> for super::ApiMedia {
     fn from(this: Media) -> Self {
-        let backdrop_path = this.backdrop_path.clone().map(|bp| {
-            if bp.starts_with('/') {
-                format!("https://image.tmdb.org/t/p/original/{}", bp)
-            } else {
-                format!("https://image.tmdb.org/t/p/original{}", bp)
-            }
-        });
+        let backdrop_path = this
+            .backdrop_path
+            .as_deref()
+            .map(|bp| image_url("original", bp));
 
         Self {
             id: this.id,
@@ -383,14 +578,43 @@ impl From<Media> for super::ApiMedia {
             overview: this.overview,
             poster_path: this
                 .poster_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|s| image_url(POSTER_SIZE, s)),
             poster_file: this.poster_path,
             backdrop_path,
             backdrop_file: this.backdrop_path,
             genres: this.genres,
             rating: this.vote_average.map(|x| x as i32),
             seasons: Vec::new(),
+            trailers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Trailer {
+    pub name: Option<String>,
+    pub key: String,
+    pub site: String,
+    #[serde(rename(deserialize = "type"))]
+    pub kind: String,
+}
+
+impl Trailer {
+    pub fn get_url(&self) -> Option<String> {
+        match self.site.as_str() {
+            "YouTube" => Some(format!("https://www.youtube.com/watch?v={}", self.key)),
+            "Vimeo" => Some(format!("https://vimeo.com/{}", self.key)),
+            _ => None,
+        }
+    }
+}
+
+impl From<Trailer> for super::ApiTrailer {
+    fn from(this: Trailer) -> Self {
+        Self {
+            url: this.get_url().unwrap_or_default(),
+            name: this.name,
         }
     }
 }
@@ -419,8 +643,8 @@ impl From<Season> for super::ApiSeason {
             name: this.name,
             poster_path: this
                 .poster_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|s| image_url(POSTER_SIZE, s)),
             poster_file: this.poster_path.clone(),
             season_number: this.season_number.unwrap_or(1),
             episodes: Vec::new(),
@@ -446,8 +670,8 @@ impl From<Episode> for super::ApiEpisode {
             episode: other.episode_number,
             still: other
                 .still_path
-                .clone()
-                .map(|s| format!("https://image.tmdb.org/t/p/w600_and_h900_bestv2{}", s)),
+                .as_deref()
+                .map(|s| image_url(POSTER_SIZE, s)),
             still_file: other.still_path,
         }
     }
@@ -459,11 +683,98 @@ mod tests {
 
     const API_KEY: &str = "38c372f5bc572c8aadde7a802638534e";
 
+    #[test]
+    fn test_title_similarity() {
+        let cases = &[
+            ("Blade Runner", "blade runner!", 1.0),
+            ("", "", 1.0),
+            ("night", "nacht", 0.25),
+            ("abc", "xyz", 0.0),
+            ("a", "b", 0.0),
+        ];
+
+        for (a, b, score) in cases {
+            assert_eq!(title_similarity(a, b), *score, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_match_confidence() {
+        let cases = &[
+            (Some(1982), Some("1982-06-25"), 1.0),
+            (Some(1982), Some("1983-01-01"), 0.875),
+            (Some(1982), Some("1985-01-01"), 0.75),
+            // without both years only the title counts.
+            (None, Some("1982-06-25"), 1.0),
+            (Some(1982), None, 1.0),
+            (Some(1982), Some("soon"), 1.0),
+        ];
+
+        for (year, release_date, score) in cases {
+            assert_eq!(
+                match_confidence("Blade Runner", *year, "Blade Runner", *release_date),
+                *score,
+                "{:?} {:?}",
+                year,
+                release_date
+            );
+        }
+
+        assert_eq!(
+            match_confidence("night", Some(2000), "nacht", Some("2000-01-01")),
+            0.4375
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let now = DateTime::parse_from_rfc2822("Thu, 19 Aug 2021 12:00:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        let max_wait = Duration::from_secs(60);
+
+        // seconds
+        assert_eq!(
+            retry_delay(Some("5"), 0, max_wait, now),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            retry_delay(Some(" 5 "), 0, max_wait, now),
+            Duration::from_secs(5)
+        );
+
+        // http dates
+        assert_eq!(
+            retry_delay(Some("Thu, 19 Aug 2021 12:00:30 GMT"), 0, max_wait, now),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            retry_delay(Some("Thu, 19 Aug 2021 11:59:00 GMT"), 0, max_wait, now),
+            Duration::from_secs(0)
+        );
+
+        // missing or garbage header
+        assert_eq!(retry_delay(None, 0, max_wait, now), Duration::from_secs(1));
+        assert_eq!(retry_delay(None, 3, max_wait, now), Duration::from_secs(8));
+        assert_eq!(
+            retry_delay(Some("soon"), 2, max_wait, now),
+            Duration::from_secs(4)
+        );
+
+        // max_wait clamping
+        assert_eq!(retry_delay(Some("120"), 0, max_wait, now), max_wait);
+        assert_eq!(
+            retry_delay(Some("Thu, 19 Aug 2021 13:00:00 GMT"), 0, max_wait, now),
+            max_wait
+        );
+        assert_eq!(retry_delay(None, 20, max_wait, now), max_wait);
+    }
+
     // #[test]
     // fn test_search_by_name() {
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Movie);
     //     let result = tmdb
-    //         .search_by_name("Blade Runner 2049".into(), None, None)
+    //         .search_by_name("Blade Runner 2049".into(), None)
     //         .unwrap();
 
     //     let result = result.first().unwrap();
@@ -471,7 +782,7 @@ mod tests {
     //     assert_eq!(result.release_date, Some("2017-10-04".into()));
 
     //     let result = tmdb
-    //         .search_by_name("Blade Runner 2049".into(), Some(2017), None)
+    //         .search_by_name("Blade Runner 2049".into(), Some(2017))
     //         .unwrap();
 
     //     let result = result.first().unwrap();
@@ -481,7 +792,7 @@ mod tests {
 
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Tv);
     //     let result = tmdb
-    //         .search_by_name("The expanse".into(), None, None)
+    //         .search_by_name("The expanse".into(), None)
     //         .unwrap();
 
     //     let result = result.first().unwrap();
@@ -495,7 +806,7 @@ mod tests {
     // fn test_get_seasons_for() {
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Tv);
     //     let result = tmdb
-    //         .search_by_name("The expanse".into(), None, None)
+    //         .search_by_name("The expanse".into(), None)
     //         .unwrap();
 
     //     let result = result.first().unwrap();
@@ -515,7 +826,7 @@ mod tests {
     // fn test_get_episodes_for() {
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Tv);
     //     let result = tmdb
-    //         .search_by_name("The expanse".into(), None, None)
+    //         .search_by_name("The expanse".into(), None)
     //         .unwrap();
 
     //     let result = result.first().unwrap();
@@ -535,7 +846,7 @@ mod tests {
     // fn test_get_genre_detail() {
     //     let mut tmdb = Tmdb::new(API_KEY.to_string(), MediaType::Tv);
     //     let result = tmdb
-    //         .search_by_name("The expanse".into(), None, None)
+    //         .search_by_name("The expanse".into(), None)
     //         .unwrap();
 
     //     let genres = result.first().unwrap().genre_ids.as_ref().unwrap();
@@ -6,10 +6,12 @@ use database::DbConnection;
 use database::episode::InsertableEpisode;
 use database::library::MediaType;
 use database::media::InsertableMedia;
+use database::media::Media;
 use database::mediafile::MediaFile;
 use database::mediafile::UpdateMediaFile;
 use database::movie::InsertableMovie;
 use database::season::InsertableSeason;
+use database::trailer::InsertableTrailer;
 use database::tv::TVShow;
 
 use chrono::prelude::Utc;
@@ -21,9 +23,6 @@ use slog::error;
 use slog::warn;
 use slog::Logger;
 
-use events::Message;
-use events::PushEventType;
-
 use crate::core::EventTx;
 use crate::fetcher::insert_into_queue;
 
@@ -147,9 +146,21 @@ impl<'a> TvShowMatcher<'a> {
     ) -> Result<(), super::base::ScannerError> {
         let media_id = media.insert(&self.conn).await?;
         let _ = TVShow::insert(&self.conn, media_id).await;
+        // the tmdb id lets `refresh_ongoing_shows` fetch the show without searching for it again.
+        let _ = Media::set_tmdb_id(&self.conn, media_id, result.id as i64).await;
 
         self.push_event(media_id, media.library_id).await;
 
+        for trailer in result.trailers.iter() {
+            let trailer = InsertableTrailer {
+                media_id,
+                name: trailer.name.clone(),
+                url: trailer.url.clone(),
+            };
+
+            let _ = trailer.insert(&self.conn).await;
+        }
+
         for name in result.genres {
             let genre = InsertableGenre { name };
 
@@ -216,8 +227,42 @@ impl<'a> TvShowMatcher<'a> {
             }
         };
 
+        let episode = orphan.episode.unwrap_or(0);
+        let episode_id = self
+            .insert_episode(orphan, season, seasonid, episode)
+            .await?;
+
+        // a file like `S01E01-E02` spans several episodes, every episode of the range gets a row
+        // so that the season lists them all, the mediafile itself stays linked to the first one.
+        if let Some(episode_end) = orphan.episode_end {
+            for number in episode + 1..=episode_end {
+                self.insert_episode(orphan, season, seasonid, number)
+                    .await?;
+            }
+        }
+
+        let updated_mediafile = UpdateMediaFile {
+            media_id: Some(episode_id),
+            needs_review: Some(false),
+            ..Default::default()
+        };
+
+        updated_mediafile.update(&self.conn, orphan.id).await?;
+
+        Ok(())
+    }
+
+    /// Method inserts episode `number` of a season using the episode metadata found in `season`
+    /// and returns the id of the episode.
+    async fn insert_episode(
+        &self,
+        orphan: &MediaFile,
+        season: Option<&super::ApiSeason>,
+        seasonid: i64,
+        number: i64,
+    ) -> Result<i64, super::base::ScannerError> {
         let search_ep = {
-            let orphan_episode = orphan.episode.unwrap_or(0) as u64;
+            let orphan_episode = number as u64;
             season.and_then(|x| {
                 x.episodes
                     .iter()
@@ -265,19 +310,19 @@ impl<'a> TvShowMatcher<'a> {
             self.log,
             "Inserting new episode";
             "seasonid" => seasonid,
-            "episode" => orphan.episode.unwrap_or(0),
+            "episode" => number,
             "target_file" => &orphan.target_file,
         );
 
         let episode = InsertableEpisode {
-            episode: orphan.episode.unwrap_or(0),
+            episode: number,
             seasonid,
             media: InsertableMedia {
                 library_id: orphan.library_id,
                 name: search_ep
                     .as_ref()
                     .and_then(|x| x.name.clone())
-                    .unwrap_or_else(|| orphan.episode.unwrap_or(0).to_string()),
+                    .unwrap_or_else(|| number.to_string()),
                 added: Utc::now().to_string(),
                 media_type: MediaType::Episode,
                 description: search_ep
@@ -301,16 +346,7 @@ impl<'a> TvShowMatcher<'a> {
             );
         }
 
-        let episode_id = episode.insert(&self.conn).await?;
-
-        let updated_mediafile = UpdateMediaFile {
-            media_id: Some(episode_id),
-            ..Default::default()
-        };
-
-        updated_mediafile.update(&self.conn, orphan.id).await?;
-
-        Ok(())
+        Ok(episode.insert(&self.conn).await?)
     }
 
     async fn push_event(&self, id: i64, lib_id: i64) {
@@ -327,11 +363,6 @@ impl<'a> TvShowMatcher<'a> {
             lock.push((lib_id, id));
         }
 
-        let event = Message {
-            id,
-            event_type: PushEventType::EventNewCard { lib_id },
-        };
-
-        let _ = self.event_tx.send(serde_json::to_string(&event).unwrap());
+        super::push_new_card(&self.event_tx, lib_id, id);
     }
 }
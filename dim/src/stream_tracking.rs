@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::num::NonZeroU64;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
 
 use crate::core::StateManager;
 use crate::utils::ts_to_xml;
@@ -10,6 +12,9 @@ use uuid::Uuid;
 use serde::Serialize;
 use xmlwriter::*;
 
+/// Length of a media segment in seconds.
+const SEGMENT_DURATION: u64 = 5;
+
 #[derive(Debug, Clone, Copy, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ContentType {
@@ -23,6 +28,9 @@ pub struct VirtualManifest {
     pub content_type: ContentType,
     pub id: String,
     pub set_id: NonZeroU64,
+    /// Index of the period the track is compiled into, ie the part of a movie split over several
+    /// files the track belongs to.
+    pub period: u64,
     pub is_direct: bool,
     pub mime: String,
     pub codecs: String,
@@ -86,11 +94,25 @@ impl VirtualManifest {
         // write segment template
         w.start_element("SegmentTemplate");
         w.write_attribute("timescale", &1000);
-        w.write_attribute("duration", &5000);
+        if !self.is_timed() {
+            w.write_attribute("duration", &(SEGMENT_DURATION * 1000));
+        }
         w.write_attribute("initialization", &init);
         w.write_attribute("media", &chunk_path);
         w.write_attribute("startNumber", &start_num);
 
+        // `$Time$` templates need a timeline, every segment has the same length so a single entry
+        // repeated until the end of the period covers the stream.
+        if self.is_timed() {
+            w.start_element("SegmentTimeline");
+            w.start_element("S");
+            w.write_attribute("t", &(start_num * SEGMENT_DURATION * 1000));
+            w.write_attribute("d", &(SEGMENT_DURATION * 1000));
+            w.write_attribute("r", &-1);
+            w.end_element();
+            w.end_element();
+        }
+
         // close SegmentTemplate and Representation
         w.end_element();
         w.end_element();
@@ -100,6 +122,92 @@ impl VirtualManifest {
         }
     }
 
+    /// Method compiles a HLS media playlist listing every segment of a audio or video stream.
+    /// Returns `None` for subtitles and streams with an unknown duration.
+    pub fn compile_hls(&self) -> Option<String> {
+        if matches!(self.content_type, ContentType::Subtitle) {
+            return None;
+        }
+
+        let duration = self.duration? as u64;
+        let segments = (duration + SEGMENT_DURATION - 1) / SEGMENT_DURATION;
+
+        let mut out = String::new();
+        out.push_str("#EXTM3U\n");
+        out.push_str("#EXT-X-VERSION:7\n");
+        out.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", SEGMENT_DURATION));
+        out.push_str("#EXT-X-MEDIA-SEQUENCE:0\n");
+        out.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n");
+        out.push_str(&format!(
+            "#EXT-X-MAP:URI=\"/api/v1/stream/{}?start_num=0\"\n",
+            self.init_seg.as_ref()?
+        ));
+
+        for number in 0..segments {
+            let length = SEGMENT_DURATION.min(duration - number * SEGMENT_DURATION);
+            out.push_str(&format!("#EXTINF:{}.0,\n", length));
+            out.push_str(&format!("/api/v1/stream/{}\n", self.segment_uri(number)));
+        }
+
+        out.push_str("#EXT-X-ENDLIST\n");
+
+        Some(out)
+    }
+
+    /// Method returns the id the urls of the stream are built from, see
+    /// [`stream_path_id`](crate::streaming::stream_path_id).
+    pub fn path_id(&self) -> &str {
+        self.chunk_path.split('/').next().unwrap_or_default()
+    }
+
+    /// Method returns whether segments of the stream are named after their start time rather than
+    /// their number.
+    pub fn is_timed(&self) -> bool {
+        self.chunk_path.contains("$Time")
+    }
+
+    /// Method returns the number of the segment named `name`, ie `42` for `00042` or for
+    /// `210000` when segments are named after their start time.
+    pub fn segment_number(&self, name: u64) -> u64 {
+        if self.is_timed() {
+            name / (SEGMENT_DURATION * 1000)
+        } else {
+            name
+        }
+    }
+
+    /// Method fills the segment number or start time into the `$Number$`/`$Number%05d$` or
+    /// `$Time$` template of the chunk path.
+    fn segment_uri(&self, number: u64) -> String {
+        let (start, value) = match self.chunk_path.find("$Number") {
+            Some(x) => (x, number),
+            None => match self.chunk_path.find("$Time") {
+                Some(x) => (x, number * SEGMENT_DURATION * 1000),
+                None => return self.chunk_path.clone(),
+            },
+        };
+
+        let end = match self.chunk_path[start + 1..].find('$') {
+            Some(x) => start + 1 + x,
+            None => return self.chunk_path.clone(),
+        };
+
+        let width = self.chunk_path[start + 1..end]
+            .trim_start_matches(char::is_alphabetic)
+            .trim_start_matches("%0")
+            .trim_end_matches('d')
+            .parse::<usize>()
+            .unwrap_or(0);
+
+        format!(
+            "{}{:0width$}{}",
+            &self.chunk_path[..start],
+            value,
+            &self.chunk_path[end + 1..],
+            width = width
+        )
+    }
+
     fn compile_sub(&self, w: &mut XmlWriter) {
         w.start_element("AdapationSet");
         w.write_attribute("mimeType", &self.mime);
@@ -127,12 +235,228 @@ impl VirtualManifest {
 
 pub struct StreamTracking {
     streaming_sessions: Arc<RwLock<HashMap<Uuid, Vec<VirtualManifest>>>>,
+    /// Maps a session to the user that created it.
+    session_owners: Arc<RwLock<HashMap<Uuid, String>>>,
+    /// Last time a session was requested by a client.
+    last_activity: Arc<RwLock<HashMap<Uuid, Instant>>>,
+    /// Last time a segment of a session was requested, or when the session was created if no
+    /// segment was requested yet.
+    last_segment: Arc<RwLock<HashMap<Uuid, Instant>>>,
+    /// Maps a session to the library of the file it streams.
+    session_libraries: Arc<RwLock<HashMap<Uuid, i64>>>,
+    /// Maps a session to the websocket connection of the client that started it.
+    session_connections: Arc<RwLock<HashMap<Uuid, Uuid>>>,
+    /// Number of open websockets for every connection id.
+    connections: Arc<RwLock<HashMap<Uuid, usize>>>,
 }
 
 impl StreamTracking {
     pub async fn insert(&self, id: &Uuid, manifest: VirtualManifest) {
         let mut lock = self.streaming_sessions.write().await;
         lock.entry(*id).or_default().push(manifest);
+
+        self.touch(id).await;
+        self.last_segment
+            .write()
+            .await
+            .entry(*id)
+            .or_insert_with(Instant::now);
+    }
+
+    /// Method marks the session `gid` as active.
+    pub async fn touch(&self, gid: &Uuid) {
+        let mut lock = self.last_activity.write().await;
+        lock.insert(*gid, Instant::now());
+    }
+
+    /// Method returns the id of the session which contains the stream `id`.
+    async fn session_of(&self, id: &str) -> Option<Uuid> {
+        let lock = self.streaming_sessions.read().await;
+        lock.iter()
+            .find(|(_, v)| v.iter().any(|x| x.id == id || x.path_id() == id))
+            .map(|(k, _)| *k)
+    }
+
+    /// Method marks the session which contains the stream `id` as active.
+    pub async fn touch_stream(&self, id: &str) {
+        if let Some(gid) = self.session_of(id).await {
+            self.touch(&gid).await;
+        }
+    }
+
+    /// Method marks that a segment of the stream `id` was requested, this also marks its session
+    /// as active.
+    pub async fn touch_segment(&self, id: &str) {
+        if let Some(gid) = self.session_of(id).await {
+            self.touch(&gid).await;
+            self.last_segment.write().await.insert(gid, Instant::now());
+        }
+    }
+
+    /// Method kills and stops tracking every session that hasnt been requested for longer than
+    /// `timeout`. Returns the ids of the sessions that were reaped.
+    pub async fn reap_inactive(&self, state: &StateManager, timeout: Duration) -> Vec<Uuid> {
+        let inactive = {
+            let lock = self.last_activity.read().await;
+            idle_since(&lock, Instant::now(), timeout)
+        };
+
+        for gid in inactive.iter() {
+            self.kill_all(state, gid, true).await;
+            self.remove(gid).await;
+        }
+
+        inactive
+    }
+
+    /// Method kills and stops tracking every session none of whose segments were requested in the
+    /// `timeout` before `now`, ie because the client vanished without closing its websocket.
+    /// Returns the ids of the sessions that were reaped.
+    pub async fn reap_idle_transcodes(
+        &self,
+        state: &StateManager,
+        now: Instant,
+        timeout: Duration,
+    ) -> Vec<Uuid> {
+        let idle = {
+            let lock = self.last_segment.read().await;
+            idle_since(&lock, now, timeout)
+        };
+
+        for gid in idle.iter() {
+            self.kill_all(state, gid, true).await;
+            self.remove(gid).await;
+        }
+
+        idle
+    }
+
+    /// Method marks `user` as the owner of the session `gid`.
+    pub async fn set_owner(&self, gid: &Uuid, user: String) {
+        let mut lock = self.session_owners.write().await;
+        lock.insert(*gid, user);
+    }
+
+    /// Method marks the session `gid` as streaming a file of the library `library_id`.
+    pub async fn set_library(&self, gid: &Uuid, library_id: i64) {
+        let mut lock = self.session_libraries.write().await;
+        lock.insert(*gid, library_id);
+    }
+
+    /// Method kills and stops tracking every session streaming a file of the library
+    /// `library_id`. Returns the ids of the sessions that were killed.
+    pub async fn kill_library(&self, state: &StateManager, library_id: i64) -> Vec<Uuid> {
+        let sessions = {
+            let lock = self.session_libraries.read().await;
+            lock.iter()
+                .filter(|(_, x)| **x == library_id)
+                .map(|(k, _)| *k)
+                .collect::<Vec<_>>()
+        };
+
+        for gid in sessions.iter() {
+            self.kill_all(state, gid, true).await;
+            self.remove(gid).await;
+        }
+
+        sessions
+    }
+
+    /// Method ties the session `gid` to the websocket connection `connection`. The session is
+    /// killed once the connection drops unless the client reconnects in time.
+    pub async fn set_connection(&self, gid: &Uuid, connection: Uuid) {
+        let mut lock = self.session_connections.write().await;
+        lock.insert(*gid, connection);
+    }
+
+    /// Method marks a websocket of `user` as open and returns its connection id. A client that
+    /// reconnects passes the id it was handed before as `reclaim` to keep the sessions of the
+    /// connection alive, a new id is handed out if `reclaim` is `None` or sessions of another user
+    /// are tied to it.
+    pub async fn open_connection(&self, reclaim: Option<Uuid>, user: &str) -> Uuid {
+        let connection = match reclaim {
+            Some(id) => {
+                let sessions = self.sessions_of_connection(&id).await;
+                let owners = self.session_owners.read().await;
+
+                if sessions
+                    .iter()
+                    .all(|gid| owners.get(gid).map_or(true, |x| x.as_str() == user))
+                {
+                    id
+                } else {
+                    Uuid::new_v4()
+                }
+            }
+            None => Uuid::new_v4(),
+        };
+
+        let mut lock = self.connections.write().await;
+        *lock.entry(connection).or_default() += 1;
+
+        connection
+    }
+
+    /// Method marks a websocket of `connection` as closed. Returns `true` if it was the last one,
+    /// in which case [`kill_connection`](Self::kill_connection) should be called once the grace
+    /// period for reconnects is over.
+    pub async fn detach_connection(&self, connection: &Uuid) -> bool {
+        let mut lock = self.connections.write().await;
+
+        match lock.get_mut(connection) {
+            Some(x) if *x > 1 => {
+                *x -= 1;
+                false
+            }
+            Some(_) => {
+                lock.remove(connection);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Method kills and stops tracking every session tied to `connection` unless a websocket
+    /// reclaimed the connection. Connections without sessions are left alone. Returns the ids of
+    /// the sessions that were killed.
+    pub async fn kill_connection(&self, state: &StateManager, connection: &Uuid) -> Vec<Uuid> {
+        if self.connections.read().await.contains_key(connection) {
+            return vec![];
+        }
+
+        let sessions = self.sessions_of_connection(connection).await;
+
+        for gid in sessions.iter() {
+            self.kill_all(state, gid, true).await;
+            self.remove(gid).await;
+        }
+
+        sessions
+    }
+
+    async fn sessions_of_connection(&self, connection: &Uuid) -> Vec<Uuid> {
+        let lock = self.session_connections.read().await;
+        lock.iter()
+            .filter(|(_, x)| *x == connection)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// Method returns the number of sessions currently owned by `user`.
+    pub async fn count_for_user(&self, user: &str) -> usize {
+        let lock = self.session_owners.read().await;
+        lock.values().filter(|x| x.as_str() == user).count()
+    }
+
+    /// Method stops tracking the session `gid`. This should be called once all the streams of a
+    /// session have been killed.
+    pub async fn remove(&self, gid: &Uuid) {
+        self.streaming_sessions.write().await.remove(gid);
+        self.session_owners.write().await.remove(gid);
+        self.last_activity.write().await.remove(gid);
+        self.last_segment.write().await.remove(gid);
+        self.session_libraries.write().await.remove(gid);
+        self.session_connections.write().await.remove(gid);
     }
 
     pub async fn kill_all(&self, state: &StateManager, id: &Uuid, ignore_gc: bool) {
@@ -175,7 +499,23 @@ impl StreamTracking {
     pub async fn compile(&self, gid: &Uuid, start_num: u64) -> Option<String> {
         let lock = self.streaming_sessions.read().await;
         let manifests = lock.get(gid)?;
-        let duration = ts_to_xml(manifests.first().and_then(|x| x.duration)? as u64);
+
+        let mut periods: Vec<(u64, u64)> = Vec::new();
+        for track in manifests {
+            if let (Some(duration), false) = (
+                track.duration,
+                periods.iter().any(|(id, _)| *id == track.period),
+            ) {
+                periods.push((track.period, duration as u64));
+            }
+        }
+        periods.sort_by_key(|(id, _)| *id);
+
+        if periods.is_empty() {
+            return None;
+        }
+
+        let total = periods.iter().map(|(_, duration)| duration).sum::<u64>();
 
         let mut w = XmlWriter::new(Default::default());
         w.write_declaration();
@@ -186,41 +526,174 @@ impl StreamTracking {
         w.write_attribute("xsi:schemaLocation", "urn:mpeg:dash:schema:mpd:2011 http://standards.iso.org/ittf/PubliclyAvailableStandards/MPEG-DASH_schema_files/DASH-MPD.xsd");
         w.write_attribute("profiles", "urn:mpeg:dash:profile:full:2011");
         w.write_attribute("type", "static");
-        w.write_attribute("mediaPresentationDuration", &duration);
+        w.write_attribute("mediaPresentationDuration", &ts_to_xml(total));
         w.write_attribute("minBufferTime", "PT20S");
         w.write_attribute("maxSegmentDuration", "PT20S");
 
-        w.start_element("Period");
-        w.write_attribute("duration", &duration);
-        w.start_element("BaseURL");
-        w.write_text("/api/v1/stream/");
-        w.end_element();
+        // each part of a movie split over several files is its own period, the periods are
+        // played back to back.
+        let mut start = 0;
+        for (period, duration) in periods {
+            // `start_num` only applies to the first period, the others are fetched from the
+            // beginning.
+            let start_num = if period == 0 { start_num } else { 0 };
+
+            w.start_element("Period");
+            w.write_attribute("id", &period);
+            w.write_attribute("start", &ts_to_xml(start));
+            w.write_attribute("duration", &ts_to_xml(duration));
+            w.start_element("BaseURL");
+            w.write_text("/api/v1/stream/");
+            w.end_element();
 
-        // write video tracks within the first adaptation set.
-        w.start_element("AdaptationSet");
-        w.write_attribute("contentType", "video");
-        w.write_attribute("id", "0");
+            let tracks = manifests
+                .iter()
+                .filter(|x| x.period == period)
+                .collect::<Vec<_>>();
 
-        for track in manifests {
-            if matches!(track.content_type, ContentType::Video) {
-                track.compile(&mut w, start_num);
+            // write video tracks within the first adaptation set.
+            w.start_element("AdaptationSet");
+            w.write_attribute("contentType", "video");
+            w.write_attribute("id", "0");
+
+            for track in tracks.iter() {
+                if matches!(track.content_type, ContentType::Video) {
+                    track.compile(&mut w, start_num);
+                }
             }
-        }
-        w.end_element();
+            w.end_element();
 
-        // write the audio and subtitle tracks.
-        for track in manifests {
-            if matches!(
-                track.content_type,
-                ContentType::Audio | ContentType::Subtitle
-            ) {
-                track.compile(&mut w, start_num);
+            // write the audio and subtitle tracks.
+            for track in tracks.iter() {
+                if matches!(
+                    track.content_type,
+                    ContentType::Audio | ContentType::Subtitle
+                ) {
+                    track.compile(&mut w, start_num);
+                }
             }
+
+            w.end_element();
+            start += duration;
         }
 
         Some(w.end_document())
     }
 
+    /// Method returns the stream `id` if it is part of a session thats tracked. `id` is either
+    /// the id nightfall assigned to the stream or the [`path_id`](VirtualManifest::path_id) of it,
+    /// in which case any live stream of the same file and profile is returned.
+    pub async fn get_stream(&self, id: &str) -> Option<VirtualManifest> {
+        let lock = self.streaming_sessions.read().await;
+        lock.values()
+            .flatten()
+            .find(|x| x.id == id || x.path_id() == id)
+            .cloned()
+    }
+
+    /// Method compiles a HLS master playlist for the session `gid`. Every video stream is listed
+    /// as a variant so that the player can switch between them and audio streams are listed as
+    /// alternative renditions. Sessions without a video stream list their audio streams as the
+    /// variants instead.
+    ///
+    /// HLS sessions only play the first part of a movie split over several files.
+    pub async fn compile_hls(&self, gid: &Uuid) -> Option<String> {
+        let lock = self.streaming_sessions.read().await;
+        let manifests = lock
+            .get(gid)?
+            .iter()
+            .filter(|x| x.period == 0)
+            .collect::<Vec<_>>();
+
+        let video = manifests
+            .iter()
+            .filter(|x| matches!(x.content_type, ContentType::Video))
+            .collect::<Vec<_>>();
+        let audio = manifests
+            .iter()
+            .filter(|x| matches!(x.content_type, ContentType::Audio))
+            .collect::<Vec<_>>();
+
+        let mut out = String::new();
+        out.push_str("#EXTM3U\n");
+        out.push_str("#EXT-X-VERSION:7\n");
+        out.push_str("#EXT-X-INDEPENDENT-SEGMENTS\n");
+
+        if video.is_empty() {
+            for track in audio {
+                out.push_str(&format!(
+                    "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"\n",
+                    track.bandwidth, track.codecs
+                ));
+                out.push_str(&format!(
+                    "/api/v1/stream/{}/playlist.m3u8\n",
+                    track.path_id()
+                ));
+            }
+
+            return Some(out);
+        }
+
+        for track in audio.iter() {
+            let name = if track.label.is_empty() {
+                &track.id
+            } else {
+                &track.label
+            };
+
+            out.push_str(&format!(
+                "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES",
+                name.replace('"', "'"),
+                if track.is_default { "YES" } else { "NO" }
+            ));
+
+            if let Some(lang) = track.lang.as_ref() {
+                out.push_str(&format!(",LANGUAGE=\"{}\"", lang));
+            }
+
+            out.push_str(&format!(
+                ",URI=\"/api/v1/stream/{}/playlist.m3u8\"\n",
+                track.path_id()
+            ));
+        }
+
+        let audio_codec = audio
+            .iter()
+            .find(|x| x.is_default)
+            .or_else(|| audio.first())
+            .map(|x| x.codecs.clone());
+        let audio_bandwidth = audio.iter().map(|x| x.bandwidth).max().unwrap_or(0);
+
+        for track in video {
+            let codecs = match audio_codec.as_ref() {
+                Some(audio) => format!("{},{}", track.codecs, audio),
+                None => track.codecs.clone(),
+            };
+
+            out.push_str(&format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={},CODECS=\"{}\"",
+                track.bandwidth + audio_bandwidth,
+                codecs
+            ));
+
+            if let (Some(width), Some(height)) = (track.args.get("width"), track.args.get("height"))
+            {
+                out.push_str(&format!(",RESOLUTION={}x{}", width, height));
+            }
+
+            if !audio.is_empty() {
+                out.push_str(",AUDIO=\"audio\"");
+            }
+
+            out.push_str(&format!(
+                "\n/api/v1/stream/{}/playlist.m3u8\n",
+                track.path_id()
+            ));
+        }
+
+        Some(out)
+    }
+
     pub async fn compile_only(
         &self,
         gid: &Uuid,
@@ -231,10 +704,26 @@ impl StreamTracking {
     }
 }
 
+/// Function returns the sessions whose last activity in `activity` lies more than `timeout` before
+/// `now`.
+fn idle_since(activity: &HashMap<Uuid, Instant>, now: Instant, timeout: Duration) -> Vec<Uuid> {
+    activity
+        .iter()
+        .filter(|(_, x)| now.saturating_duration_since(**x) > timeout)
+        .map(|(k, _)| *k)
+        .collect()
+}
+
 impl Default for StreamTracking {
     fn default() -> Self {
         Self {
             streaming_sessions: Arc::new(RwLock::new(HashMap::new())),
+            session_owners: Arc::new(RwLock::new(HashMap::new())),
+            last_activity: Arc::new(RwLock::new(HashMap::new())),
+            last_segment: Arc::new(RwLock::new(HashMap::new())),
+            session_libraries: Arc::new(RwLock::new(HashMap::new())),
+            session_connections: Arc::new(RwLock::new(HashMap::new())),
+            connections: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 }
@@ -243,6 +732,151 @@ impl Clone for StreamTracking {
     fn clone(&self) -> Self {
         Self {
             streaming_sessions: Arc::clone(&self.streaming_sessions),
+            session_owners: Arc::clone(&self.session_owners),
+            last_activity: Arc::clone(&self.last_activity),
+            last_segment: Arc::clone(&self.last_segment),
+            session_libraries: Arc::clone(&self.session_libraries),
+            session_connections: Arc::clone(&self.session_connections),
+            connections: Arc::clone(&self.connections),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_since() {
+        let now = Instant::now();
+        let (idle, active) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut activity = HashMap::new();
+        activity.insert(idle, now);
+        activity.insert(active, now + Duration::from_secs(50));
+
+        let timeout = Duration::from_secs(30);
+        assert!(idle_since(&activity, now, timeout).is_empty());
+        assert_eq!(
+            idle_since(&activity, now + Duration::from_secs(60), timeout),
+            vec![idle]
+        );
+        assert_eq!(
+            idle_since(&activity, now + Duration::from_secs(100), timeout).len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reap_idle_transcodes() {
+        let state = StateManager::new(
+            &mut xtra::spawn::Tokio::Global,
+            std::env::temp_dir().to_string_lossy().into_owned(),
+            "ffmpeg".into(),
+            slog::Logger::root(slog::Discard, slog::o!()),
+        );
+
+        let tracking = StreamTracking::default();
+        let now = Instant::now();
+        let (idle, active) = (Uuid::new_v4(), Uuid::new_v4());
+
+        {
+            let mut sessions = tracking.streaming_sessions.write().await;
+            let mut segments = tracking.last_segment.write().await;
+
+            for (gid, last_segment) in &[(idle, now), (active, now + Duration::from_secs(50))] {
+                sessions.insert(*gid, vec![]);
+                segments.insert(*gid, *last_segment);
+            }
+        }
+
+        let timeout = Duration::from_secs(30);
+        let reaped = tracking
+            .reap_idle_transcodes(&state, now + Duration::from_secs(60), timeout)
+            .await;
+
+        assert_eq!(reaped, vec![idle]);
+
+        let sessions = tracking.streaming_sessions.read().await;
+        assert!(!sessions.contains_key(&idle));
+        assert!(sessions.contains_key(&active));
+        assert!(!tracking.last_segment.read().await.contains_key(&idle));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sessions() {
+        let tracking = StreamTracking::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // both sessions play the same file with the same profile.
+        for (gid, id) in &[(a, "stream-a"), (b, "stream-b")] {
+            let path_id = crate::streaming::stream_path_id(gid, 12, "video-native");
+            let track = VirtualManifest {
+                content_type: ContentType::Video,
+                id: id.to_string(),
+                set_id: NonZeroU64::new(1).unwrap(),
+                period: 0,
+                is_direct: true,
+                mime: "video/mp4".into(),
+                codecs: "avc1.64001f".into(),
+                bandwidth: 1_000_000,
+                args: HashMap::new(),
+                duration: Some(600),
+                chunk_path: format!("{}/data/$Number$.m4s", path_id),
+                init_seg: Some(format!("{}/data/init.mp4", path_id)),
+                is_default: true,
+                label: String::new(),
+                lang: None,
+            };
+
+            tracking.insert(gid, track).await;
         }
+
+        for (gid, id) in &[(a, "stream-a"), (b, "stream-b")] {
+            let path_id = crate::streaming::stream_path_id(gid, 12, "video-native");
+
+            assert_eq!(tracking.get_stream(&path_id).await.unwrap().id, *id);
+            assert_eq!(tracking.session_of(&path_id).await, Some(*gid));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compile_periods() {
+        let tracking = StreamTracking::default();
+        let gid = Uuid::new_v4();
+
+        for (period, duration) in &[(0, 600), (1, 300)] {
+            let track = VirtualManifest {
+                content_type: ContentType::Video,
+                id: format!("part-{}", period),
+                set_id: NonZeroU64::new(1).unwrap(),
+                period: *period,
+                is_direct: true,
+                mime: "video/mp4".into(),
+                codecs: "avc1.64001f".into(),
+                bandwidth: 1_000_000,
+                args: HashMap::new(),
+                duration: Some(*duration),
+                chunk_path: format!("part-{}/data/$Number$.m4s", period),
+                init_seg: Some(format!("part-{}/data/init.mp4", period)),
+                is_default: true,
+                label: String::new(),
+                lang: None,
+            };
+
+            tracking.insert(&gid, track).await;
+        }
+
+        let manifest = tracking.compile(&gid, 10).await.unwrap();
+
+        assert_eq!(manifest.matches("<Period").count(), 2);
+        assert!(manifest.contains(&format!("mediaPresentationDuration=\"{}\"", ts_to_xml(900))));
+        assert!(manifest.contains(&format!("start=\"{}\"", ts_to_xml(600))));
+        assert!(manifest.contains("part-0/data/init.mp4?start_num=10"));
+        assert!(manifest.contains("part-1/data/init.mp4?start_num=0"));
+
+        let playlist = tracking.compile_hls(&gid).await.unwrap();
+        assert!(playlist.contains("part-0"));
+        assert!(!playlist.contains("part-1"));
     }
 }
@@ -0,0 +1,103 @@
+//! Roku style BIF trick-play files. A BIF file is a header followed by an index of frame
+//! timestamps and offsets and the JPEG frames themselves, clients use it to show thumbnails while
+//! seeking.
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+use super::FFMPEG_BIN;
+
+/// Magic number every BIF file starts with.
+pub const MAGIC: [u8; 8] = [0x89, 0x42, 0x49, 0x46, 0x0d, 0x0a, 0x1a, 0x0a];
+/// Size of the BIF header, the index starts right after it.
+const HEADER_SIZE: usize = 64;
+/// Width frames are scaled to, the height keeps the aspect ratio of the video.
+const FRAME_WIDTH: u32 = 320;
+
+/// Function packs JPEG frames taken every `interval` seconds into a BIF file.
+pub fn encode(frames: &[Vec<u8>], interval: u32) -> Vec<u8> {
+    let index_size = (frames.len() + 1) * 8;
+    let data_size = frames.iter().map(Vec::len).sum::<usize>();
+    let mut out = Vec::with_capacity(HEADER_SIZE + index_size + data_size);
+
+    out.extend_from_slice(&MAGIC);
+    // version
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+    // timestamps in the index are multiplied by this many milliseconds.
+    out.extend_from_slice(&(interval * 1000).to_le_bytes());
+    out.resize(HEADER_SIZE, 0);
+
+    let mut offset = HEADER_SIZE + index_size;
+    for (i, frame) in frames.iter().enumerate() {
+        out.extend_from_slice(&(i as u32).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += frame.len();
+    }
+
+    // the index is terminated with a sentinel pointing at the end of the last frame.
+    out.extend_from_slice(&u32::MAX.to_le_bytes());
+    out.extend_from_slice(&(offset as u32).to_le_bytes());
+
+    for frame in frames {
+        out.extend_from_slice(frame);
+    }
+
+    out
+}
+
+/// Function extracts a frame every `interval` seconds from `file` with ffmpeg and writes them to
+/// `out` as a BIF file. This blocks until ffmpeg exits.
+///
+/// Every call extracts frames into its own directory and writes the BIF file to a temporary file
+/// that is renamed to `out` once complete, so concurrent calls for the same file never read a
+/// partial BIF file or each others frames.
+pub fn generate(file: &Path, out: &Path, interval: u32) -> io::Result<()> {
+    let interval = interval.max(1);
+    let id = uuid::Uuid::new_v4().to_simple().to_string();
+    let frames_dir = out.with_extension(format!("{}.frames", id));
+    let partial = out.with_extension(format!("{}.part", id));
+    fs::create_dir_all(&frames_dir)?;
+
+    let status = Command::new(*FFMPEG_BIN)
+        .arg("-v")
+        .arg("quiet")
+        .arg("-i")
+        .arg(file)
+        .arg("-an")
+        .arg("-sn")
+        .arg("-vf")
+        .arg(format!("fps=1/{},scale={}:-2", interval, FRAME_WIDTH))
+        .arg("-q:v")
+        .arg("5")
+        .arg(frames_dir.join("%06d.jpg"))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    let result = status.and_then(|status| {
+        if !status.success() {
+            return Err(io::Error::new(io::ErrorKind::Other, "ffmpeg failed"));
+        }
+
+        let mut paths = fs::read_dir(&frames_dir)?
+            .filter_map(Result::ok)
+            .map(|x| x.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let frames = paths.iter().map(fs::read).collect::<io::Result<Vec<_>>>()?;
+
+        fs::write(&partial, encode(&frames, interval))?;
+        fs::rename(&partial, out)
+    });
+
+    let _ = fs::remove_dir_all(&frames_dir);
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+
+    result
+}
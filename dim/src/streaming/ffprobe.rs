@@ -1,6 +1,33 @@
+use err_derive::Error;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::VecDeque;
-use std::{path::Path, process::Command, str};
+use std::io::{self, Read};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{path::Path, process::Command, str, thread};
+
+/// Errors ffprobe can fail with while probing a file.
+#[derive(Debug, Error)]
+pub enum FFProbeError {
+    #[error(display = "The ffprobe binary could not be found")]
+    NotFound,
+    #[error(display = "ffprobe timed out after {:?}", _0)]
+    TimedOut(Duration),
+    #[error(display = "ffprobe returned output that could not be parsed: {}", _0)]
+    Unparseable(String),
+    #[error(display = "Failed to run ffprobe: {}", _0)]
+    Io(#[source] io::Error),
+}
+
+impl From<io::Error> for FFProbeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            _ => Self::Io(e),
+        }
+    }
+}
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct FFPWrapper {
@@ -104,52 +131,82 @@ struct Format {
     pub nb_programs: i64,
     pub format_name: String,
     pub format_long_name: String,
-    pub start_time: String,
-    pub duration: String,
-    pub size: String,
-    pub bit_rate: String,
+    pub start_time: Option<String>,
+    pub duration: Option<String>,
+    pub size: Option<String>,
+    pub bit_rate: Option<String>,
 }
 
 pub struct FFProbeCtx {
     ffprobe_bin: String,
+    timeout: Duration,
 }
 
 impl FFProbeCtx {
     pub fn new(ffprobe_bin: &'static str) -> Self {
         Self {
             ffprobe_bin: ffprobe_bin.to_owned(),
+            timeout: Duration::from_secs(crate::get_global_settings().ffprobe_timeout.max(1)),
         }
     }
 
-    pub fn get_meta(&self, file: &Path) -> Result<FFPWrapper, std::io::Error> {
-        let probe = Command::new(self.ffprobe_bin.clone())
-            .arg(file.to_str().unwrap())
+    /// Method runs ffprobe on `file` and parses its output. ffprobe is killed if it runs for
+    /// longer than the configured timeout.
+    pub fn get_meta(&self, file: &Path) -> Result<FFPWrapper, FFProbeError> {
+        let mut child = Command::new(self.ffprobe_bin.clone())
+            .arg(file)
             .arg("-v")
             .arg("quiet")
             .arg("-print_format")
             .arg("json")
             .arg("-show_streams")
             .arg("-show_format")
-            .output()?;
-
-        let json = String::from_utf8_lossy(probe.stdout.as_slice());
-
-        let de: FFPWrapper = serde_json::from_str(&json).map_or_else(
-            |_| FFPWrapper {
-                ffpstream: None,
-                corrupt: Some(true),
-            },
-            |x| FFPWrapper {
-                ffpstream: Some(x),
-                corrupt: None,
-            },
-        );
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // stdout is read on a separate thread so that a probe which never exits cant block us.
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut out = Vec::new();
+            let _ = tx.send(stdout.read_to_end(&mut out).map(|_| out));
+        });
 
-        Ok(de)
+        match rx.recv_timeout(self.timeout) {
+            Ok(out) => {
+                let _ = child.wait();
+                FFPWrapper::from_json(&out?)
+            }
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(FFProbeError::TimedOut(self.timeout))
+            }
+        }
     }
 }
 
 impl FFPWrapper {
+    /// Method parses the json ffprobe printed for a file.
+    pub fn from_json(json: &[u8]) -> Result<Self, FFProbeError> {
+        let stream = serde_json::from_slice::<FFPStream>(json)
+            .map_err(|e| FFProbeError::Unparseable(e.to_string()))?;
+
+        Ok(Self {
+            ffpstream: Some(stream),
+            corrupt: None,
+        })
+    }
+
+    /// Method returns a wrapper for a file ffprobe couldnt make sense of.
+    pub fn corrupt() -> Self {
+        Self {
+            ffpstream: None,
+            corrupt: Some(true),
+        }
+    }
+
     pub fn get_container(&self) -> Option<String> {
         if let Some(ctx) = self.ffpstream.clone() {
             Some(ctx.format.format_name)
@@ -159,7 +216,13 @@ impl FFPWrapper {
     }
 
     pub fn get_container_bitrate(&self) -> Option<u64> {
-        self.ffpstream.as_ref()?.format.bit_rate.parse::<u64>().ok()
+        self.ffpstream
+            .as_ref()?
+            .format
+            .bit_rate
+            .as_ref()?
+            .parse::<u64>()
+            .ok()
     }
 
     pub fn get_video_codec(&self) -> Option<String> {
@@ -206,16 +269,45 @@ impl FFPWrapper {
                 .as_ref()?
                 .format
                 .duration
+                .as_ref()?
                 .parse::<f64>()
                 .ok()? as i32,
         )
     }
 
+    /// Method estimates the duration of a file in seconds from its size and bitrate. This should
+    /// only be used when ffprobe cant report the duration of a file. If the container doesnt
+    /// report a bitrate, the bitrates of the individual streams are summed up. Bitrates of `0` are
+    /// treated as missing.
+    pub fn estimate_duration(&self) -> Option<i32> {
+        let format = &self.ffpstream.as_ref()?.format;
+        let size = format.size.as_ref()?.parse::<u64>().ok()?;
+
+        let bitrate = self
+            .get_container_bitrate()
+            .filter(|x| *x > 0)
+            .or_else(|| {
+                let sum = self
+                    .ffpstream
+                    .as_ref()?
+                    .streams
+                    .iter()
+                    .filter_map(|x| x.get_bitrate())
+                    .filter(|x| *x > 0)
+                    .sum::<u64>();
+
+                Some(sum).filter(|x| *x > 0)
+            })?;
+
+        Some((size * 8 / bitrate) as i32)
+    }
+
     pub fn get_ms(&self) -> Option<u128> {
         self.ffpstream
             .as_ref()?
             .format
             .duration
+            .as_ref()?
             .parse::<f64>()
             .map(|x| (x.trunc() * 1_000_000.0) as u128)
             .ok()
@@ -253,3 +345,49 @@ pub struct Disposition {
     pub hearing_impaired: i64,
     pub visual_impaired: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json_garbage() {
+        for json in &[&b"\x00\xff\xfe garbage"[..], b"", b"{\"streams\": 1}"] {
+            assert!(matches!(
+                FFPWrapper::from_json(json),
+                Err(FFProbeError::Unparseable(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_estimate_duration_zero_bitrate() {
+        let probe = |bit_rate: &str| {
+            let json = format!(
+                r#"{{
+                    "streams": [{{
+                        "index": 0,
+                        "codec_name": "h264",
+                        "codec_type": "video",
+                        "tags": {{ "BPS-eng": "8000" }}
+                    }}],
+                    "format": {{
+                        "filename": "a.mkv",
+                        "nb_streams": 1,
+                        "nb_programs": 0,
+                        "format_name": "matroska",
+                        "format_long_name": "Matroska",
+                        "size": "80000",
+                        "bit_rate": "{}"
+                    }}
+                }}"#,
+                bit_rate
+            );
+
+            FFPWrapper::from_json(json.as_bytes()).unwrap()
+        };
+
+        assert_eq!(probe("16000").estimate_duration(), Some(40));
+        assert_eq!(probe("0").estimate_duration(), Some(80));
+    }
+}
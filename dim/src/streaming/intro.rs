@@ -0,0 +1,203 @@
+//! Intro detection through audio fingerprints. Episodes of a season share their intro, so the
+//! longest stretch of audio two neighbouring episodes have in common is taken as the intro of
+//! both. Fingerprints are computed by ffmpeg's chromaprint muxer.
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+use super::FFMPEG_BIN;
+
+/// Milliseconds of audio covered by a single fingerprint item. Chromaprint analyzes audio at
+/// 11025Hz in frames of 4096 samples which overlap by two thirds.
+pub const ITEM_MS: f64 = 4096.0 / 3.0 / 11025.0 * 1000.0;
+/// Number of bits two items may differ in and still count as the same audio.
+const MAX_BIT_ERRORS: u32 = 8;
+/// Number of differing items tolerated inside a match, ie when a title card is mixed into the
+/// intro of only one of the episodes.
+const MAX_GAP: usize = 8;
+/// Shortest stretch of audio accepted as a intro.
+pub const MIN_INTRO_MS: i64 = 15_000;
+/// Longest stretch of audio accepted as a intro, longer matches are usually recaps.
+pub const MAX_INTRO_MS: i64 = 150_000;
+
+/// Audio both episodes have in common, offsets and length are in fingerprint items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Offset at which the match starts in the first fingerprint.
+    pub start_a: usize,
+    /// Offset at which the match starts in the second fingerprint.
+    pub start_b: usize,
+    pub len: usize,
+}
+
+impl Match {
+    /// Method returns the start and end in milliseconds of the match in the first fingerprint.
+    pub fn range_a(&self) -> (i64, i64) {
+        to_ms(self.start_a, self.len)
+    }
+
+    /// Method returns the start and end in milliseconds of the match in the second fingerprint.
+    pub fn range_b(&self) -> (i64, i64) {
+        to_ms(self.start_b, self.len)
+    }
+
+    /// Method returns whether the match is long enough to be a intro without being longer than
+    /// any intro would be.
+    pub fn is_intro(&self) -> bool {
+        let (start, end) = self.range_a();
+        (MIN_INTRO_MS..=MAX_INTRO_MS).contains(&(end - start))
+    }
+}
+
+fn to_ms(start: usize, len: usize) -> (i64, i64) {
+    (
+        (start as f64 * ITEM_MS) as i64,
+        ((start + len) as f64 * ITEM_MS) as i64,
+    )
+}
+
+/// Function computes the raw chromaprint fingerprint of the first `seconds` of audio of `file`
+/// with ffmpeg. This blocks until ffmpeg exits.
+pub fn fingerprint(file: &Path, seconds: u64) -> io::Result<Vec<u32>> {
+    let output = Command::new(*FFMPEG_BIN)
+        .arg("-v")
+        .arg("quiet")
+        .arg("-t")
+        .arg(seconds.to_string())
+        .arg("-i")
+        .arg(file)
+        .arg("-map")
+        .arg("0:a:0")
+        .arg("-ac")
+        .arg("1")
+        .arg("-f")
+        .arg("chromaprint")
+        .arg("-fp_format")
+        .arg("raw")
+        .arg("-")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    // ffmpeg builds without chromaprint fail here too.
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, "ffmpeg failed"));
+    }
+
+    Ok(output
+        .stdout
+        .chunks_exact(4)
+        .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        .collect())
+}
+
+/// Function returns the longest stretch of audio two fingerprints have in common. Every
+/// alignment of the fingerprints is tried, so the intro can start at a different offset in each
+/// of them.
+pub fn find_common(a: &[u32], b: &[u32]) -> Option<Match> {
+    let mut best: Option<Match> = None;
+
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    for offset in -(b.len() as isize - 1)..a.len() as isize {
+        let (start_a, start_b) = if offset >= 0 {
+            (offset as usize, 0)
+        } else {
+            (0, (-offset) as usize)
+        };
+        let overlap = (a.len() - start_a).min(b.len() - start_b);
+
+        let mut run_start = None;
+        let mut last_match = 0;
+
+        for k in 0..overlap {
+            if (a[start_a + k] ^ b[start_b + k]).count_ones() <= MAX_BIT_ERRORS {
+                let start = *run_start.get_or_insert(k);
+                last_match = k;
+
+                let len = k + 1 - start;
+                if best.map_or(true, |x| len > x.len) {
+                    best = Some(Match {
+                        start_a: start_a + start,
+                        start_b: start_b + start,
+                        len,
+                    });
+                }
+            } else if run_start.is_some() && k - last_match > MAX_GAP {
+                run_start = None;
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Function returns `len` items that differ from each other in far more than
+    /// `MAX_BIT_ERRORS` bits.
+    fn noise(seed: u32, len: usize) -> Vec<u32> {
+        (0..len as u32)
+            .map(|x| (x ^ seed).wrapping_mul(0x9e37_79b9).rotate_left(x % 32))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_common_shifted() {
+        let intro = noise(1, 40);
+
+        let mut a = noise(2, 10);
+        a.extend_from_slice(&intro);
+        a.extend(noise(3, 20));
+
+        let mut b = noise(4, 25);
+        b.extend_from_slice(&intro);
+        b.extend(noise(5, 5));
+
+        let result = find_common(&a, &b).unwrap();
+        assert_eq!(result.start_a, 10);
+        assert_eq!(result.start_b, 25);
+        assert_eq!(result.len, 40);
+    }
+
+    #[test]
+    fn test_find_common_gaps() {
+        let a = noise(1, 60);
+
+        // a title card covering part of the intro in one of the episodes, and a few bit errors.
+        let mut b = a.clone();
+        for x in b[20..20 + MAX_GAP].iter_mut() {
+            *x = !*x;
+        }
+        b[40] ^= 0b111;
+
+        let result = find_common(&a, &b).unwrap();
+        assert_eq!((result.start_a, result.start_b, result.len), (0, 0, 60));
+
+        // gaps longer than `MAX_GAP` split the match.
+        let mut b = a.clone();
+        for x in b[20..21 + MAX_GAP].iter_mut() {
+            *x = !*x;
+        }
+
+        let result = find_common(&a, &b).unwrap();
+        assert_eq!(
+            (result.start_a, result.start_b),
+            (21 + MAX_GAP, 21 + MAX_GAP)
+        );
+    }
+
+    #[test]
+    fn test_find_common_no_match() {
+        assert_eq!(find_common(&[], &noise(1, 10)), None);
+
+        let a = vec![0u32; 20];
+        let b = vec![u32::MAX; 20];
+        assert_eq!(find_common(&a, &b), None);
+    }
+}
@@ -1,11 +1,17 @@
+pub mod bif;
 pub mod ffprobe;
+pub mod intro;
+pub mod vtt;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::RwLock;
 
+use crate::routes::settings::SegmentNaming;
 use crate::utils::ffpath;
 
+use uuid::Uuid;
+
 lazy_static::lazy_static! {
     pub static ref STREAMING_SESSION: Arc<RwLock<HashMap<String, HashMap<String, String>>>> = Arc::new(RwLock::new(HashMap::new()));
     pub static ref FFMPEG_BIN: &'static str = ffpath("utils/ffmpeg");
@@ -49,14 +55,118 @@ pub fn ffcheck() -> Vec<Result<Box<str>, &'static str>> {
     results
 }
 
-#[derive(Clone, Copy)]
+/// Function picks the codec an audio stream encoded with `source` should be output as. Streams
+/// whose codec is the target already or is listed in `passthrough` keep their codec, everything
+/// else is transcoded to `target`.
+pub fn pick_audio_codec(source: &str, target: &str, passthrough: &[String]) -> String {
+    if source == target || passthrough.iter().any(|x| x.eq_ignore_ascii_case(source)) {
+        source.to_string()
+    } else {
+        target.to_string()
+    }
+}
+
+/// Function returns the RFC 6381 codec string for an audio codec, ie `mp4a.40.2` for `aac`.
+pub fn audio_codec_tag(codec: &str) -> &'static str {
+    match codec {
+        "ac3" => "ac-3",
+        "eac3" => "ec-3",
+        "mp3" => "mp4a.40.34",
+        "opus" => "opus",
+        "flac" => "fLaC",
+        _ => "mp4a.40.2",
+    }
+}
+
+/// Function returns the maximum video bitrate for a stream. The ceiling is the lower of the
+/// bitrate the client declared and the cap configured for its network type, `None` means the
+/// stream is not capped.
+pub fn bitrate_ceiling(
+    network: Option<&str>,
+    max_bitrate: Option<u64>,
+    caps: &HashMap<String, u64>,
+) -> Option<u64> {
+    let cap = network.and_then(|x| caps.get(&x.to_lowercase()).copied());
+
+    match (cap, max_bitrate) {
+        (Some(x), Some(y)) => Some(x.min(y)),
+        (x, y) => x.or(y),
+    }
+}
+
+/// Function returns the id the urls of a stream are built from, ie `<gid>-12-video-720p`. Unlike
+/// the ids nightfall generates for every stream it only depends on the session, the file and the
+/// profile of the stream, so urls stay the same when a stream is restarted. Two sessions playing
+/// the same file never share a url.
+pub fn stream_path_id(gid: &Uuid, mediafile_id: i64, profile: &str) -> String {
+    format!("{}-{}-{}", gid.to_hyphenated(), mediafile_id, profile)
+}
+
+/// Function returns the DASH segment template of a stream, ie `<id>/data/$Number%05d$.m4s` when
+/// `segment_number_padding` is set to `5` or `<id>/data/$Time$.m4s` when segments are named after
+/// their start time. Clients and caches only ever see segment names built from this template.
+pub fn segment_template(path_id: &str) -> String {
+    let settings = crate::get_global_settings();
+    let var = match settings.segment_naming {
+        SegmentNaming::Number => "Number",
+        SegmentNaming::Time => "Time",
+    };
+
+    match settings.segment_number_padding {
+        0 => format!("{}/data/${}$.m4s", path_id, var),
+        x => format!("{}/data/${}%0{}d$.m4s", path_id, var, x),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Quality {
     pub height: u64,
     pub bitrate: u64,
 }
 
-pub fn get_qualities(height: u64, bitrate: u64) -> Vec<&'static Quality> {
-    VIDEO_QUALITIES.iter().collect()
+/// Function picks the renditions of the bitrate ladder a video with a height of `height` pixels
+/// and a bitrate of `bitrate` should be transcoded to. Renditions are never taller than the source
+/// and never use a higher bitrate than it. Sources below the lowest rung get no renditions, they
+/// are only passed through.
+pub fn get_qualities(height: u64, bitrate: u64) -> Vec<Quality> {
+    VIDEO_QUALITIES
+        .iter()
+        .filter(|x| x.height <= height)
+        .map(|x| Quality {
+            height: x.height,
+            bitrate: x.bitrate.min(bitrate),
+        })
+        .collect()
+}
+
+/// Function picks the renditions a video is transcoded to when it is streamed under `ceiling`.
+/// Sources below the lowest rung are only passed through, unless the source is over the ceiling in
+/// which case a single rendition at the source height is transcoded. Renditions over the ceiling
+/// are left out, if none are left the lowest one is kept. Bitrates arent clamped to the ceiling.
+pub fn get_ladder(source: Quality, ceiling: Option<u64>) -> Vec<Quality> {
+    let native = ceiling.map_or(true, |x| source.bitrate <= x);
+    let mut qualities = get_qualities(source.height, source.bitrate);
+
+    if qualities.is_empty() && !native {
+        qualities.push(source);
+    }
+
+    match ceiling {
+        Some(ceiling) => {
+            let mut capped = qualities
+                .iter()
+                .filter(|x| x.bitrate <= ceiling)
+                .copied()
+                .collect::<Vec<_>>();
+
+            if capped.is_empty() {
+                capped.extend(qualities.iter().min_by_key(|x| x.bitrate));
+            }
+
+            capped
+        }
+        None => qualities,
+    }
 }
 
 pub const VIDEO_QUALITIES: [Quality; 3] = [
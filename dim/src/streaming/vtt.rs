@@ -0,0 +1,107 @@
+//! Helpers for WebVTT subtitles.
+
+/// Function shifts the timings of every cue of a WebVTT file by `offset` milliseconds, other lines
+/// are left untouched. Cues shifted before the start of the file are clamped to `00:00:00.000`.
+pub fn shift(vtt: &str, offset: i64) -> String {
+    vtt.split('\n')
+        .map(|line| shift_timing(line, offset).unwrap_or_else(|| line.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Function shifts a cue timing line like `00:00:01.000 --> 00:00:04.000 align:start`, returns
+/// `None` if `line` isnt a cue timing line.
+fn shift_timing(line: &str, offset: i64) -> Option<String> {
+    let (start, rest) = line.split_once(" --> ")?;
+    let (rest, cr) = match rest.strip_suffix('\r') {
+        Some(x) => (x, "\r"),
+        None => (rest, ""),
+    };
+    let (end, settings) = match rest.find(char::is_whitespace) {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, ""),
+    };
+
+    let start = parse_timestamp(start.trim())? + offset;
+    let end = parse_timestamp(end)? + offset;
+
+    Some(format!(
+        "{} --> {}{}{}",
+        format_timestamp(start),
+        format_timestamp(end),
+        settings,
+        cr
+    ))
+}
+
+/// Function parses a timestamp in the form of `hh:mm:ss.ttt` or `mm:ss.ttt` into milliseconds.
+fn parse_timestamp(ts: &str) -> Option<i64> {
+    let (rest, millis) = ts.split_once('.')?;
+    let mut parts = rest.rsplit(':');
+
+    let seconds = parts.next()?.parse::<i64>().ok()?;
+    let minutes = parts.next()?.parse::<i64>().ok()?;
+    let hours = match parts.next() {
+        Some(x) => x.parse::<i64>().ok()?,
+        None => 0,
+    };
+
+    if parts.next().is_some() || millis.len() != 3 {
+        return None;
+    }
+
+    Some(((hours * 60 + minutes) * 60 + seconds) * 1000 + millis.parse::<i64>().ok()?)
+}
+
+fn format_timestamp(ms: i64) -> String {
+    let ms = ms.max(0);
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        let cases = &[
+            ("00:00:01.000", Some(1000)),
+            ("01:02:03.004", Some(3_723_004)),
+            ("02:03.004", Some(123_004)),
+            ("1.000", None),
+            ("00:00:01,000", None),
+            ("00:00:01.00", None),
+            ("0:00:00:01.000", None),
+            ("aa:00.000", None),
+        ];
+
+        for (ts, ms) in cases {
+            assert_eq!(parse_timestamp(ts), *ms, "{}", ts);
+        }
+    }
+
+    #[test]
+    fn test_shift() {
+        let vtt = "WEBVTT\n\n00:00:01.000 --> 00:00:04.000 align:start\nHello --> World\n";
+
+        assert_eq!(
+            shift(vtt, 2500),
+            "WEBVTT\n\n00:00:03.500 --> 00:00:06.500 align:start\nHello --> World\n"
+        );
+        assert_eq!(
+            shift(vtt, -2000),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:02.000 align:start\nHello --> World\n"
+        );
+        assert_eq!(
+            shift("00:59:59.500 --> 01:00:00.000\r\nHello\r\n", 1000),
+            "01:00:00.500 --> 01:00:01.000\r\nHello\r\n"
+        );
+    }
+}
@@ -419,7 +419,7 @@ macro_rules! json_expect_expr_comma {
 
 use crate::streaming::Quality;
 
-pub fn quality_to_label(quality: &'static Quality) -> String {
+pub fn quality_to_label(quality: &Quality) -> String {
     let bandwidth_ident = if quality.bitrate > 1_000_000 {
         "MB"
     } else {
@@ -435,6 +435,19 @@ pub fn quality_to_label(quality: &'static Quality) -> String {
     format!("{}p@{}{}", quality.height, bandwidth_norm, bandwidth_ident)
 }
 
+/// Function resolves the page size a paginated route should use. If the client didnt request a
+/// page size the configured default is used, otherwise the requested size is clamped to the
+/// configured maximum.
+pub fn page_size(requested: Option<i64>) -> i64 {
+    let settings = crate::get_global_settings();
+    let max = settings.max_page_size.max(1);
+
+    requested
+        .unwrap_or(settings.default_page_size)
+        .max(1)
+        .min(max)
+}
+
 pub fn ts_to_xml(t: u64) -> String {
     let h = t / 3600;
     let m = t % 3600 / 60;
@@ -457,6 +470,138 @@ pub fn ts_to_xml(t: u64) -> String {
     tag
 }
 
+/// Function parses a `Range` header like `bytes=0-499` against a file of `len` bytes and returns
+/// the inclusive byte range requested. Open ended (`bytes=500-`) and suffix (`bytes=-500`) ranges
+/// are supported, multiple ranges are not.
+pub fn parse_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = header.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if len == 0 {
+        return None;
+    }
+
+    let (start, end) = if start.is_empty() {
+        let suffix = end.parse::<u64>().ok().filter(|x| *x > 0)?;
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start = start.parse::<u64>().ok()?;
+        let end = if end.is_empty() {
+            len - 1
+        } else {
+            end.parse::<u64>().ok()?.min(len - 1)
+        };
+
+        (start, end)
+    };
+
+    if start > end || start >= len {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+/// Function replies with the file at `path`, streaming it instead of buffering it in memory. If
+/// `range` holds a `Range` header only the requested bytes are sent back with `206 Partial
+/// Content`. If `attachment` is set the file is sent as a download with that filename.
+pub async fn reply_with_range(
+    path: impl AsRef<std::path::Path>,
+    range: Option<String>,
+    content_type: &str,
+    attachment: Option<String>,
+) -> warp::http::Response<warp::hyper::Body> {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncSeekExt;
+    use warp::http::Response;
+    use warp::http::StatusCode;
+    use warp::hyper::Body;
+
+    let mut file = match tokio::fs::File::open(path).await {
+        Ok(x) => x,
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    let len = match file.metadata().await {
+        Ok(x) => x.len(),
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    let mut builder = Response::builder()
+        .header("Content-Type", content_type)
+        .header("Accept-Ranges", "bytes");
+
+    if let Some(name) = attachment {
+        builder = builder.header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", name.replace('"', "")),
+        );
+    }
+
+    let (start, end) = match range {
+        Some(range) => match parse_range(&range, len) {
+            Some((start, end)) => {
+                builder = builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+                (start, end)
+            }
+            None => {
+                return builder
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header("Content-Range", format!("bytes */{}", len))
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        },
+        None if len == 0 => return builder.body(Body::empty()).unwrap(),
+        None => (0, len - 1),
+    };
+
+    if start > 0 && file.seek(std::io::SeekFrom::Start(start)).await.is_err() {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let remaining = end - start + 1;
+    let stream = futures::stream::unfold((file, remaining), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+
+        let mut buf = vec![0; remaining.min(64 * 1024) as usize];
+
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((
+                    Ok::<_, std::io::Error>(bytes::Bytes::from(buf)),
+                    (file, remaining - n as u64),
+                ))
+            }
+            Err(e) => Some((Err(e), (file, 0))),
+        }
+    });
+
+    builder
+        .header("Content-Length", remaining)
+        .body(Body::wrap_stream(stream))
+        .unwrap()
+}
+
 #[cfg(not(debug_assertions))]
 pub fn ffpath(bin: impl AsRef<str>) -> &'static str {
     let mut path = std::env::current_exe().expect("Failed to grab path to the `dim` binary.");
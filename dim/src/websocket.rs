@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
@@ -12,7 +13,14 @@ use warp::Filter;
 use futures::prelude::*;
 use futures::stream::SplitSink;
 
+use uuid::Uuid;
+
+use crate::core::StateManager;
 use crate::routes;
+use crate::scanners;
+use crate::stream_tracking::StreamTracking;
+
+use database::library::Library;
 
 pub enum CtrlEvent<A, M>
 where
@@ -106,12 +114,54 @@ where
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
 pub enum ClientActions {
-    Authenticate { token: String },
+    /// `connection_id` is the id a previous connection was handed, reconnecting clients pass it
+    /// to keep the stream sessions they started.
+    Authenticate {
+        token: String,
+        #[serde(default)]
+        connection_id: Option<String>,
+    },
+}
+
+/// Function builds the scanner status messages that get sent to every client after it
+/// authenticates. The status is split over several messages if it exceeds `ws_max_message_size`.
+async fn scan_status_messages() -> Vec<String> {
+    let library_ids = match database::get_conn().await {
+        Ok(conn) => Library::get_all(&conn)
+            .await
+            .into_iter()
+            .map(|x| x.id)
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    events::Message {
+        id: -1,
+        event_type: events::PushEventType::EventScanStatus {
+            libraries: scanners::scan_status(library_ids),
+        },
+    }
+    .to_chunked_strings(crate::get_global_settings().ws_max_message_size)
+}
+
+/// Function waits for the reconnect grace period and then kills the stream sessions of
+/// `connection` if no websocket reclaimed it in the meantime.
+async fn kill_dropped_connection(
+    state: StateManager,
+    stream_tracking: StreamTracking,
+    connection: Uuid,
+) {
+    let grace = crate::get_global_settings().stream_reconnect_grace;
+    tokio::time::sleep(Duration::from_secs(grace)).await;
+
+    stream_tracking.kill_connection(&state, &connection).await;
 }
 
 pub fn event_socket(
     rt_handle: Handle,
     mut event_rx: UnboundedReceiver<String>,
+    state: StateManager,
+    stream_tracking: StreamTracking,
 ) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     let (i_tx, i_rx) = unbounded_channel::<CtrlEvent<SocketAddr, String>>();
 
@@ -130,14 +180,18 @@ pub fn event_socket(
     let _forwarder = rt_handle.spawn(forwarder_fut);
 
     warp::path("ws")
-        .and(warp::filters::addr::remote())
+        .and(routes::global_filters::with_remote_addr())
         .and(routes::global_filters::with_state(i_tx))
         .and(routes::global_filters::with_state(rt_handle))
+        .and(routes::global_filters::with_state(state))
+        .and(routes::global_filters::with_state(stream_tracking))
         .and(warp::ws())
         .map(
             |addr: Option<SocketAddr>,
              i_tx: UnboundedSender<CtrlEvent<SocketAddr, String>>,
              rt_handle: Handle,
+             state: StateManager,
+             stream_tracking: StreamTracking,
              ws: warp::ws::Ws| {
                 ws.on_upgrade(move |websocket| async move {
                     let addr = match addr {
@@ -147,13 +201,27 @@ pub fn event_socket(
 
                     let (m_tx, mut m_rx) = unbounded_channel::<(SocketAddr, Message)>();
                     let (ws_tx, mut ws_rx) = websocket.split();
+                    let mut connection = None;
 
                     'auth_loop: while let Some(Ok(x)) = ws_rx.next().await {
                         if x.is_text() {
-                            if let Ok(ClientActions::Authenticate { token }) =
-                                serde_json::from_slice(x.as_bytes())
+                            if let Ok(ClientActions::Authenticate {
+                                token,
+                                connection_id,
+                            }) = serde_json::from_slice(x.as_bytes())
                             {
                                 if let Ok(token_data) = auth::jwt_check(token) {
+                                    let user = token_data.claims.get_user();
+
+                                    let connection_id = stream_tracking
+                                        .open_connection(
+                                            connection_id.and_then(|x| Uuid::parse_str(&x).ok()),
+                                            &user,
+                                        )
+                                        .await;
+
+                                    connection = Some(connection_id);
+
                                     let _ = i_tx.send(CtrlEvent::Track {
                                         addr,
                                         sink: ws_tx,
@@ -164,11 +232,19 @@ pub fn event_socket(
                                         addr,
                                         message: events::Message {
                                             id: -1,
-                                            event_type: events::PushEventType::EventAuthOk,
+                                            event_type: events::PushEventType::EventAuthOk {
+                                                connection_id: connection_id
+                                                    .to_hyphenated()
+                                                    .to_string(),
+                                            },
                                         }
                                         .to_string(),
                                     });
 
+                                    for message in scan_status_messages().await {
+                                        let _ = i_tx.send(CtrlEvent::SendTo { addr, message });
+                                    }
+
                                     break 'auth_loop;
                                 }
                             }
@@ -193,7 +269,13 @@ pub fn event_socket(
                             }
                         }
 
-                        i_tx.send(CtrlEvent::Forget { addr })
+                        let _ = i_tx.send(CtrlEvent::Forget { addr });
+
+                        if let Some(connection) = connection {
+                            if stream_tracking.detach_connection(&connection).await {
+                                kill_dropped_connection(state, stream_tracking, connection).await;
+                            }
+                        }
                     });
 
                     'outer: loop {
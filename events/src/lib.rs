@@ -1,5 +1,8 @@
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
 
 /// Struct encompasses a message we are trying to relay to a client from somehwere within dim. It
 /// holds an id and a event_type field.
@@ -20,18 +23,61 @@ impl ToString for Message {
     }
 }
 
+impl Message {
+    /// Method serializes the message into one or more messages that are at most `max_size` bytes
+    /// long. Batch events are split into several events of the same type when they dont fit,
+    /// other events are returned as a single message regardless of their size. A `max_size` of `0`
+    /// disables splitting.
+    pub fn to_chunked_strings(&self, max_size: usize) -> Vec<String> {
+        let body = self.to_string();
+
+        if max_size == 0 || body.len() <= max_size {
+            return vec![body];
+        }
+
+        match self.event_type.split() {
+            Some((left, right)) => {
+                let mut messages = Self {
+                    id: self.id,
+                    event_type: left,
+                }
+                .to_chunked_strings(max_size);
+
+                messages.extend(
+                    Self {
+                        id: self.id,
+                        event_type: right,
+                    }
+                    .to_chunked_strings(max_size),
+                );
+
+                messages
+            }
+            None => vec![body],
+        }
+    }
+}
+
 /// Enum holds all event types used within dim that are dispatched over ws.
 #[derive(Serialize)]
 #[serde(tag = "type")]
 pub enum PushEventType {
     /// A new media card has been added to the database
     EventNewCard { lib_id: i64 },
+    /// Several new media cards have been added to a library, `ids` holds the ids of their media.
+    /// The `id` of the message is the id of the library. See [`CardBatcher`].
+    EventBatchNewCard { lib_id: i64, ids: Vec<i64> },
     /// A card has been removed from the database
     EventRemoveCard,
+    /// The metadata of a card changed, ie its poster, name or description. The `id` of the
+    /// message is the id of the media whose card should be refreshed.
+    EventUpdateCard,
     /// A new library has been added to the database
     EventNewLibrary,
     /// A library has been removed from the database
     EventRemoveLibrary,
+    /// A library has been renamed, the `id` of the message is the id of the library.
+    EventUpdateLibrary,
     /// A stream is ready to be streamed.
     EventStreamIsReady,
     /// Holds a hashmap of stats collected from ffmpeg over stdout.
@@ -40,8 +86,293 @@ pub enum PushEventType {
     EventStartedScanning,
     /// A library has finished scanning.
     EventStoppedScanning,
-    /// Tell client auth is ok
-    EventAuthOk,
+    /// Snapshot of the scanner state of every library, sent to a client right after it
+    /// authenticates.
+    EventScanStatus { libraries: Vec<LibraryScanStatus> },
+    /// Progress of a running scan, sent every time another percent of the files of the library
+    /// has been processed. See [`is_progress_step`].
+    EventScanProgress {
+        library_id: i64,
+        total: usize,
+        processed: usize,
+    },
+    /// The files of a library dont look like its media type, ie a tv library pointed at movies.
+    /// `detected` is the media type the files look like.
+    EventMediaTypeMismatch {
+        library_id: i64,
+        media_type: String,
+        detected: String,
+    },
+    /// Tell client auth is ok. `connection_id` identifies the websocket connection, stream
+    /// sessions started with it are killed when the connection drops. Clients reconnecting should
+    /// authenticate with it to keep their streams alive.
+    EventAuthOk { connection_id: String },
     /// Tell client their token is wrong or missing
     EventAuthErr,
 }
+
+impl PushEventType {
+    /// Method splits a batch event into two halves. Returns `None` if the event isnt a batch or
+    /// holds a single item.
+    fn split(&self) -> Option<(Self, Self)> {
+        match self {
+            Self::EventScanStatus { libraries } if libraries.len() > 1 => {
+                let (left, right) = libraries.split_at(libraries.len() / 2);
+
+                Some((
+                    Self::EventScanStatus {
+                        libraries: left.to_vec(),
+                    },
+                    Self::EventScanStatus {
+                        libraries: right.to_vec(),
+                    },
+                ))
+            }
+            Self::EventBatchNewCard { lib_id, ids } if ids.len() > 1 => {
+                let (left, right) = ids.split_at(ids.len() / 2);
+
+                Some((
+                    Self::EventBatchNewCard {
+                        lib_id: *lib_id,
+                        ids: left.to_vec(),
+                    },
+                    Self::EventBatchNewCard {
+                        lib_id: *lib_id,
+                        ids: right.to_vec(),
+                    },
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Struct coalesces new card events so that large scans dont flood clients with one message per
+/// media. Cards are collected from the moment the first one is pushed until `window` elapsed and
+/// are then flushed as a single `EventBatchNewCard` per library. A library with a single new
+/// card gets a plain `EventNewCard` instead.
+#[derive(Debug)]
+pub struct CardBatcher {
+    /// How long cards are collected before they are flushed.
+    pub window: Duration,
+    pending: BTreeMap<i64, Vec<i64>>,
+    opened: Option<Instant>,
+}
+
+impl CardBatcher {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: BTreeMap::new(),
+            opened: None,
+        }
+    }
+
+    /// Method queues a new card of library `lib_id` for media `id`. Returns the messages to send
+    /// if the window elapsed.
+    pub fn push(&mut self, lib_id: i64, id: i64, now: Instant) -> Vec<Message> {
+        self.opened.get_or_insert(now);
+        self.pending.entry(lib_id).or_default().push(id);
+        self.poll(now)
+    }
+
+    /// Method returns the messages to send if the window elapsed, otherwise nothing.
+    pub fn poll(&mut self, now: Instant) -> Vec<Message> {
+        match self.opened {
+            Some(opened) if now.duration_since(opened) >= self.window => self.flush(),
+            _ => vec![],
+        }
+    }
+
+    /// Method returns the messages for every queued card regardless of the window.
+    pub fn flush(&mut self) -> Vec<Message> {
+        self.opened = None;
+
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(lib_id, mut ids)| {
+                if ids.len() == 1 {
+                    Message {
+                        id: ids.remove(0),
+                        event_type: PushEventType::EventNewCard { lib_id },
+                    }
+                } else {
+                    Message {
+                        id: lib_id,
+                        event_type: PushEventType::EventBatchNewCard { lib_id, ids },
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Scanner state of a single library.
+#[derive(Clone, Debug, Serialize)]
+pub struct LibraryScanStatus {
+    pub library_id: i64,
+    /// Whether a scan is currently running for this library.
+    pub scanning: bool,
+    /// Number of files that have been processed so far.
+    pub processed: usize,
+    /// Total number of files found by the current scan. This is `0` while the library directory
+    /// is still being walked.
+    pub total: usize,
+}
+
+/// Function returns whether processing the `processed`th file of `total` files completes another
+/// percent of a scan, so that progress is reported at most a hundred times per scan.
+pub fn is_progress_step(processed: usize, total: usize) -> bool {
+    if total == 0 || processed == 0 || processed > total {
+        return false;
+    }
+
+    processed * 100 / total != (processed - 1) * 100 / total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_card_batcher() {
+        let start = Instant::now();
+        let mut batcher = CardBatcher::new(Duration::from_millis(500));
+        let mut messages = vec![];
+
+        // 1000 cards arriving a millisecond apart.
+        for id in 0..1000 {
+            let now = start + Duration::from_millis(id as u64);
+            messages.extend(batcher.push(1, id, now));
+        }
+        messages.extend(batcher.flush());
+
+        assert!(messages.len() <= 3, "got {} messages", messages.len());
+
+        let mut ids = vec![];
+        for message in messages.iter() {
+            let value: serde_json::Value = serde_json::from_str(&message.to_string()).unwrap();
+            assert_eq!(value["type"], "EventBatchNewCard");
+            assert_eq!(value["lib_id"], 1);
+
+            match &message.event_type {
+                PushEventType::EventBatchNewCard { ids: batch, .. } => ids.extend(batch),
+                _ => panic!("expected a batch"),
+            }
+        }
+
+        assert_eq!(ids, (0..1000).collect::<Vec<_>>());
+        assert!(batcher.flush().is_empty());
+    }
+
+    #[test]
+    fn test_card_batcher_single() {
+        let now = Instant::now();
+        let mut batcher = CardBatcher::new(Duration::from_millis(500));
+
+        assert!(batcher.push(1, 7, now).is_empty());
+        assert!(batcher.poll(now + Duration::from_millis(100)).is_empty());
+
+        let messages = batcher.poll(now + Duration::from_millis(500));
+        assert_eq!(messages.len(), 1);
+
+        let value: serde_json::Value = serde_json::from_str(&messages[0].to_string()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "type": "EventNewCard", "lib_id": 1, "id": 7 })
+        );
+    }
+
+    #[test]
+    fn test_scan_progress() {
+        let steps = (1..=12345).filter(|x| is_progress_step(*x, 12345)).count();
+        assert_eq!(steps, 100);
+        assert!(is_progress_step(12345, 12345));
+
+        // small libraries report every file.
+        assert_eq!((1..=10).filter(|x| is_progress_step(*x, 10)).count(), 10);
+
+        let message = Message {
+            id: 3,
+            event_type: PushEventType::EventScanProgress {
+                library_id: 3,
+                total: 200,
+                processed: 50,
+            },
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&message.to_string()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "type": "EventScanProgress",
+                "id": 3,
+                "library_id": 3,
+                "total": 200,
+                "processed": 50,
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_card_serialization() {
+        let message = Message {
+            id: 42,
+            event_type: PushEventType::EventUpdateCard,
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&message.to_string()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "type": "EventUpdateCard", "id": 42 })
+        );
+    }
+
+    #[test]
+    fn test_to_chunked_strings() {
+        let ids = (1000..1200).collect::<Vec<i64>>();
+        let message = Message {
+            id: 7,
+            event_type: PushEventType::EventBatchNewCard {
+                lib_id: 7,
+                ids: ids.clone(),
+            },
+        };
+        let body = message.to_string();
+
+        // a payload exactly at the limit isnt split.
+        assert_eq!(message.to_chunked_strings(body.len()), vec![body.clone()]);
+        assert_eq!(message.to_chunked_strings(0), vec![body.clone()]);
+
+        for max_size in [body.len() - 1, body.len() / 3, 64] {
+            let chunks = message.to_chunked_strings(max_size);
+            assert!(chunks.len() > 1);
+            assert!(chunks.iter().all(|x| x.len() <= max_size));
+
+            let mut reassembled = vec![];
+            for chunk in chunks {
+                let value: serde_json::Value = serde_json::from_str(&chunk).unwrap();
+                assert_eq!(value["type"], "EventBatchNewCard");
+                assert_eq!(value["id"], 7);
+                assert_eq!(value["lib_id"], 7);
+
+                reassembled.extend(
+                    value["ids"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|x| x.as_i64().unwrap()),
+                );
+            }
+
+            assert_eq!(reassembled, ids);
+        }
+
+        // events that arent batches are never split.
+        let message = Message {
+            id: 7,
+            event_type: PushEventType::EventUpdateCard,
+        };
+        assert_eq!(message.to_chunked_strings(1), vec![message.to_string()]);
+    }
+}